@@ -0,0 +1,41 @@
+use anyhow::{Result, anyhow};
+use m87_shared::users::{AdminUser, UserStatus};
+
+use crate::{auth::AuthManager, config::Config, server, util::servers_parallel::fanout_servers};
+
+pub async fn list_users(status: Option<UserStatus>) -> Result<Vec<AdminUser>> {
+    let token = AuthManager::get_cli_token().await?;
+    let config = Config::load()?;
+    let trust = config.trust_invalid_server_cert;
+
+    let results = fanout_servers(config.manager_server_urls, 4, true, |server_url| {
+        let token = token.clone();
+        async move { server::list_admin_users(&server_url, &token, trust, status).await }
+    })
+    .await?;
+
+    Ok(results.into_iter().map(|(_, user)| user).collect())
+}
+
+/// Approve, reject, disable or enable a user (by id or email) on every
+/// server that knows them.
+pub async fn update_user(user: &str, action: &str) -> Result<AdminUser> {
+    let token = AuthManager::get_cli_token().await?;
+    let config = Config::load()?;
+    let trust = config.trust_invalid_server_cert;
+
+    let results = fanout_servers(config.manager_server_urls, 4, false, |server_url| {
+        let token = token.clone();
+        async move {
+            let user = server::update_admin_user(&server_url, &token, trust, user, action).await?;
+            Ok(vec![user])
+        }
+    })
+    .await?;
+
+    results
+        .into_iter()
+        .next()
+        .map(|(_, user)| user)
+        .ok_or_else(|| anyhow!("Could not {action} user {user}"))
+}
@@ -0,0 +1,165 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Result, anyhow};
+use m87_shared::alerts::{
+    Alert, AlertCondition, AlertRule, CreateAlertRuleBody, SilenceAlertRuleBody,
+};
+
+use crate::{
+    auth::AuthManager, config::Config, org::get_or_resolve_default_org_id, server,
+    util::servers_parallel::fanout_servers,
+};
+
+pub async fn list_alerts(org_id: Option<String>, all: bool) -> Result<Vec<Alert>> {
+    let token = AuthManager::get_cli_token().await?;
+    let config = Config::load()?;
+    let trust = config.trust_invalid_server_cert;
+
+    let org_id = get_or_resolve_default_org_id(org_id).await?;
+
+    let results = fanout_servers(config.manager_server_urls, 4, false, |server_url| {
+        let token = token.clone();
+        let org_id = org_id.clone();
+        async move { server::list_org_alerts(&server_url, &token, trust, &org_id, all).await }
+    })
+    .await?;
+
+    let mut alerts: Vec<Alert> = results.into_iter().map(|(_, alert)| alert).collect();
+    // RFC 3339 strings sort chronologically; newest first.
+    alerts.sort_by(|a, b| b.fired_at.cmp(&a.fired_at));
+    Ok(alerts)
+}
+
+pub async fn ack_alert(org_id: Option<String>, alert_id: &str) -> Result<Alert> {
+    let token = AuthManager::get_cli_token().await?;
+    let config = Config::load()?;
+    let trust = config.trust_invalid_server_cert;
+
+    let org_id = get_or_resolve_default_org_id(org_id).await?;
+
+    let results = fanout_servers(config.manager_server_urls, 4, false, |server_url| {
+        let token = token.clone();
+        let org_id = org_id.clone();
+        async move {
+            let alert =
+                server::ack_org_alert(&server_url, &token, trust, &org_id, alert_id).await?;
+            Ok(vec![alert])
+        }
+    })
+    .await?;
+
+    results
+        .into_iter()
+        .next()
+        .map(|(_, alert)| alert)
+        .ok_or_else(|| anyhow!("Alert {alert_id} not found"))
+}
+
+pub async fn list_rules(org_id: Option<String>) -> Result<Vec<AlertRule>> {
+    let token = AuthManager::get_cli_token().await?;
+    let config = Config::load()?;
+    let trust = config.trust_invalid_server_cert;
+
+    let org_id = get_or_resolve_default_org_id(org_id).await?;
+
+    let results = fanout_servers(config.manager_server_urls, 4, false, |server_url| {
+        let token = token.clone();
+        let org_id = org_id.clone();
+        async move { server::list_org_alert_rules(&server_url, &token, trust, &org_id).await }
+    })
+    .await?;
+
+    Ok(results.into_iter().map(|(_, rule)| rule).collect())
+}
+
+pub async fn add_rule(
+    org_id: Option<String>,
+    name: &str,
+    condition: AlertCondition,
+    for_secs: u64,
+    labels: BTreeMap<String, String>,
+) -> Result<AlertRule> {
+    let token = AuthManager::get_cli_token().await?;
+    let config = Config::load()?;
+    let trust = config.trust_invalid_server_cert;
+
+    let org_id = get_or_resolve_default_org_id(org_id).await?;
+    let body = &CreateAlertRuleBody {
+        name: name.to_string(),
+        labels,
+        condition,
+        for_secs,
+    };
+
+    let results = fanout_servers(config.manager_server_urls, 4, false, |server_url| {
+        let token = token.clone();
+        let org_id = org_id.clone();
+        async move {
+            let rule =
+                server::create_org_alert_rule(&server_url, &token, trust, &org_id, body).await?;
+            Ok(vec![rule])
+        }
+    })
+    .await?;
+
+    results
+        .into_iter()
+        .next()
+        .map(|(_, rule)| rule)
+        .ok_or_else(|| anyhow!("Could not create alert rule for organization {org_id}"))
+}
+
+pub async fn remove_rule(org_id: Option<String>, rule_id: &str) -> Result<()> {
+    let token = AuthManager::get_cli_token().await?;
+    let config = Config::load()?;
+    let trust = config.trust_invalid_server_cert;
+
+    let org_id = get_or_resolve_default_org_id(org_id).await?;
+
+    let results = fanout_servers(config.manager_server_urls, 4, false, |server_url| {
+        let token = token.clone();
+        let org_id = org_id.clone();
+        async move {
+            server::delete_org_alert_rule(&server_url, &token, trust, &org_id, rule_id).await?;
+            Ok(vec![()])
+        }
+    })
+    .await?;
+
+    if results.is_empty() {
+        return Err(anyhow!("Alert rule {rule_id} not found"));
+    }
+    Ok(())
+}
+
+/// Silence a rule for `duration_secs`, or lift its silence with `None`.
+pub async fn silence_rule(
+    org_id: Option<String>,
+    rule_id: &str,
+    duration_secs: Option<u64>,
+) -> Result<AlertRule> {
+    let token = AuthManager::get_cli_token().await?;
+    let config = Config::load()?;
+    let trust = config.trust_invalid_server_cert;
+
+    let org_id = get_or_resolve_default_org_id(org_id).await?;
+    let body = &SilenceAlertRuleBody { duration_secs };
+
+    let results = fanout_servers(config.manager_server_urls, 4, false, |server_url| {
+        let token = token.clone();
+        let org_id = org_id.clone();
+        async move {
+            let rule =
+                server::silence_org_alert_rule(&server_url, &token, trust, &org_id, rule_id, body)
+                    .await?;
+            Ok(vec![rule])
+        }
+    })
+    .await?;
+
+    results
+        .into_iter()
+        .next()
+        .map(|(_, rule)| rule)
+        .ok_or_else(|| anyhow!("Alert rule {rule_id} not found"))
+}
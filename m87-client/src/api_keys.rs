@@ -0,0 +1,81 @@
+use anyhow::{Result, anyhow};
+use m87_shared::auth::{ApiKeyInfo, CreateApiKeyBody, CreatedApiKey};
+use m87_shared::roles::Role;
+
+use crate::{
+    auth::AuthManager, config::Config, devices, server, util::servers_parallel::fanout_servers,
+};
+
+pub struct CreateApiKeyArgs {
+    pub name: String,
+    pub role: Role,
+    pub org_id: Option<String>,
+    /// Device name; the key is created on the server the device lives on.
+    pub device: Option<String>,
+    pub ttl_secs: Option<u64>,
+    /// Server for keys without a device; defaults to the first manager server.
+    pub server_url: Option<String>,
+}
+
+/// Create a key and return it together with the server it is valid on.
+pub async fn create_api_key(args: CreateApiKeyArgs) -> Result<(String, CreatedApiKey)> {
+    let token = AuthManager::get_cli_token().await?;
+    let config = Config::load()?;
+    let trust = config.trust_invalid_server_cert;
+
+    let (server_url, device_id) = match &args.device {
+        Some(name) => {
+            let resolved = devices::resolve_device_cached(name).await?;
+            (resolved.url, Some(resolved.id))
+        }
+        None => {
+            let url = args
+                .server_url
+                .or_else(|| config.manager_server_urls.first().cloned())
+                .ok_or_else(|| anyhow!("No server configured. Run `m87 login` first"))?;
+            (url, None)
+        }
+    };
+
+    let body = CreateApiKeyBody {
+        name: args.name,
+        role: args.role,
+        org_id: args.org_id,
+        device_id,
+        ttl_secs: args.ttl_secs,
+    };
+    let created = server::create_api_key(&server_url, &token, trust, &body).await?;
+    Ok((server_url, created))
+}
+
+pub async fn list_api_keys() -> Result<Vec<(String, ApiKeyInfo)>> {
+    let token = AuthManager::get_cli_token().await?;
+    let config = Config::load()?;
+    let trust = config.trust_invalid_server_cert;
+
+    fanout_servers(config.manager_server_urls, 4, true, |server_url| {
+        let token = token.clone();
+        async move { server::list_api_keys(&server_url, &token, trust).await }
+    })
+    .await
+}
+
+pub async fn revoke_api_key(key_id: &str) -> Result<()> {
+    let token = AuthManager::get_cli_token().await?;
+    let config = Config::load()?;
+    let trust = config.trust_invalid_server_cert;
+
+    let results = fanout_servers(config.manager_server_urls, 4, false, |server_url| {
+        let token = token.clone();
+        async move {
+            server::revoke_api_key(&server_url, &token, trust, key_id).await?;
+            Ok(vec![()])
+        }
+    })
+    .await?;
+
+    if results.is_empty() {
+        return Err(anyhow!("API key {key_id} not found"));
+    }
+    Ok(())
+}
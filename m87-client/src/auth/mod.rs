@@ -86,16 +86,19 @@ impl APIConfig {
         Ok(api_config)
     }
 
+    /// Written to a temp file and renamed into place, so a crash mid-write
+    /// (e.g. while storing a rotated device key) never leaves a torn file.
     pub fn save(&self) -> Result<()> {
         let file_path = Self::default_credentials_path()?;
         if let Some(parent) = file_path.parent() {
             fs::create_dir_all(parent)?;
         }
+        let tmp_path = file_path.with_extension("json.tmp");
         let file = OpenOptions::new()
             .write(true)
             .create(true)
             .truncate(true)
-            .open(file_path)?;
+            .open(&tmp_path)?;
 
         #[cfg(unix)]
         {
@@ -103,8 +106,11 @@ impl APIConfig {
             file.set_permissions(Permissions::from_mode(0o600))?;
         }
 
-        let writer = BufWriter::new(file);
-        serde_json::to_writer_pretty(writer, self)?;
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        let file = writer.into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, &file_path)?;
         Ok(())
     }
 
@@ -219,6 +225,9 @@ impl AuthManager {
     }
 
     pub async fn get_cli_token() -> Result<String> {
+        if let Some(api_key) = cli_api_key_from_env() {
+            return Ok(api_key);
+        }
         let mut config = APIConfig::load_or_create()?;
         let credentials = config
             .credentials
@@ -237,6 +246,17 @@ impl AuthManager {
         Ok(token)
     }
 
+    /// Replace the stored device key, e.g. after a rotation.
+    pub fn save_device_credentials(api_key: String) -> Result<()> {
+        APIConfig::save_device_credentials(api_key)
+    }
+
+    /// True if the device key comes from `M87_API_KEY`, which would put it
+    /// back on every start; such keys are not rotated.
+    pub fn device_key_pinned() -> bool {
+        cli_api_key_from_env().is_some()
+    }
+
     pub fn get_device_token() -> Result<String> {
         Ok(APIConfig::load_or_create()?
             .device_credentials
@@ -245,6 +265,9 @@ impl AuthManager {
     }
 
     pub fn has_cli_credentials() -> Result<bool> {
+        if cli_api_key_from_env().is_some() {
+            return Ok(true);
+        }
         Ok(APIConfig::load_or_create()?.credentials.is_some())
     }
 
@@ -253,6 +276,15 @@ impl AuthManager {
     }
 }
 
+/// A personal or service key from `M87_API_KEY` replaces the stored login,
+/// e.g. for CI. The runtime only takes it as its initial device key.
+fn cli_api_key_from_env() -> Option<String> {
+    std::env::var(API_KEY_ENV_VAR)
+        .ok()
+        .map(|key| key.trim().to_string())
+        .filter(|key| !key.is_empty())
+}
+
 // m87 command line: OAuth2 login for device management
 pub async fn login_cli() -> Result<()> {
     if AuthManager::has_cli_credentials()? {
@@ -3,18 +3,30 @@ use std::path::PathBuf;
 use anyhow::Context;
 use anyhow::bail;
 use clap::{CommandFactory, Parser, Subcommand};
+use m87_shared::alerts::AlertCondition;
+use m87_shared::deploy_spec::{JournalPriority, JournalSpec};
+use m87_shared::device::DeviceLogQuery;
+use m87_shared::org::{UpdateOrgSettingsBody, WebhookEvent};
 use m87_shared::roles::Role;
+use m87_shared::users::UserStatus;
 
+use crate::admin;
+use crate::alerts;
+use crate::api_keys;
 use crate::auth;
 use crate::config::Config;
 use crate::device;
 use crate::device::deploy::DeploymentUpdateArgs;
 use crate::device::deploy::SpecType;
 use crate::device::forward;
+use crate::device::progress::{ProgressSink, TransferProgress};
 use crate::device::serial;
 use crate::devices;
+use crate::logs;
 use crate::org;
+use crate::streams::compress::{STREAM_STATS, disable_compression};
 use crate::tui;
+use crate::tui::process::ProcessSort;
 use crate::update;
 #[cfg(feature = "runtime")]
 use crate::util;
@@ -74,6 +86,14 @@ struct Cli {
     #[arg(short, long, global = true)]
     verbose: bool,
 
+    /// Print payload vs on-the-wire bytes of device streams on exit
+    #[arg(long, global = true)]
+    stats: bool,
+
+    /// Don't compress device streams (for already-compressed data or slow devices)
+    #[arg(long, global = true)]
+    no_compress: bool,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -113,6 +133,10 @@ enum Commands {
 
         /// Destination path (<path> for local, <device>:<path> for remote)
         dest: String,
+
+        /// Print progress as NDJSON events instead of a status line
+        #[arg(long)]
+        json: bool,
     },
 
     /// Sync files between local and remote devices (rsync-style)
@@ -124,13 +148,27 @@ enum Commands {
         dest: String,
 
         /// Delete files from destination that are not present in source
-        #[arg(long, default_value_t = false)]
+        #[arg(long, default_value_t = false, conflicts_with = "bidirectional")]
         delete: bool,
 
+        /// Propagate changes (including deletions) in both directions, based
+        /// on the state recorded by the previous bidirectional sync
+        #[arg(long, default_value_t = false)]
+        bidirectional: bool,
+
+        /// How to resolve files changed on both sides (with --bidirectional)
+        #[arg(long, value_enum, default_value_t = device::fs::ConflictPolicy::Abort, requires = "bidirectional")]
+        on_conflict: device::fs::ConflictPolicy,
+
         /// Watch for changes and sync automatically
         #[arg(long, default_value_t = false)]
         watch: bool,
 
+        /// Command to run on the device after each batch of synced changes
+        /// (with --watch)
+        #[arg(long, requires = "watch")]
+        on_change: Option<String>,
+
         /// Show what would be done without making changes
         #[arg(long, short = 'n', default_value_t = false)]
         dry_run: bool,
@@ -138,6 +176,14 @@ enum Commands {
         /// Exclude files matching pattern (can be used multiple times)
         #[arg(long, short = 'e', action = clap::ArgAction::Append)]
         exclude: Vec<String>,
+
+        /// Don't read .gitignore / .m87ignore files from the synced trees
+        #[arg(long, default_value_t = false)]
+        no_ignore_files: bool,
+
+        /// Print progress as NDJSON events instead of a status line
+        #[arg(long)]
+        json: bool,
     },
 
     Ls {
@@ -156,6 +202,49 @@ enum Commands {
     #[command(subcommand)]
     Org(OrgCommands),
 
+    /// Show and acknowledge alerts raised by org alert rules
+    #[command(subcommand)]
+    Alerts(AlertsCommands),
+
+    /// Search logs shipped by the org's devices (`log_shipping` in the
+    /// runtime config)
+    Logs {
+        #[arg(long, alias = "org-id")]
+        org: Option<String>,
+        /// Regex the lines must match
+        #[arg(long)]
+        grep: Option<String>,
+        /// Only lines of this device
+        #[arg(long)]
+        device: Option<String>,
+        /// Only lines of this unit, or `m87` for the runtime's own log
+        #[arg(long)]
+        source: Option<String>,
+        /// Show lines since (RFC3339, unix ms, or relative like 1h)
+        #[arg(long)]
+        since: Option<String>,
+        /// Show lines until (RFC3339, unix ms, or relative like 1h)
+        #[arg(long)]
+        until: Option<String>,
+        /// Number of newest lines to show
+        #[arg(short = 'n', long, default_value_t = 200)]
+        tail: u32,
+        /// Output as NDJSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Manage API keys for non-interactive use, e.g. in CI
+    ///
+    /// Export a key as `M87_API_KEY` and the CLI uses it instead of the
+    /// login.
+    #[command(subcommand)]
+    Apikey(ApiKeyCommands),
+
+    /// Server administration (requires an admin email or the admin key)
+    #[command(subcommand)]
+    Admin(AdminCommands),
+
     /// Manage login profiles to switch between accounts
     ///
     /// Each profile keeps its own config and credentials, so you can stay
@@ -190,6 +279,18 @@ enum OrgCommands {
         new_id: String,
     },
     List,
+    /// Manage webhooks that notify external systems about org events
+    #[clap(subcommand)]
+    Webhooks(WebhookAction),
+    /// Show or change org policy
+    Settings {
+        #[arg(long)]
+        org_id: Option<String>,
+        /// Record shell and `exec` sessions on the org's devices. Recordings
+        /// are kept with the audit log (see `m87 <device> audit --session`).
+        #[arg(long)]
+        record_sessions: Option<bool>,
+    },
     //     Invites {
     //         #[clap(subcommand)]
     //         action: InviteAction,
@@ -221,6 +322,187 @@ enum OrgDeviceAction {
     },
 }
 
+#[derive(Subcommand)]
+enum WebhookAction {
+    List {
+        #[arg(long)]
+        org_id: Option<String>,
+    },
+    /// Subscribe a URL to org events. Requests are signed with the returned
+    /// secret (`X-M87-Signature: sha256=HMAC(secret, "<timestamp>.<body>")`).
+    Add {
+        url: String,
+        /// Event to deliver (repeatable); all events if omitted. One of
+        /// device_online, device_offline, observe_unhealthy, step_failed,
+        /// job_failed, device_auth_request, alert_firing, alert_resolved
+        #[arg(long = "event")]
+        events: Vec<WebhookEvent>,
+        #[arg(long)]
+        org_id: Option<String>,
+    },
+    Remove {
+        webhook_id: String,
+        #[arg(long)]
+        org_id: Option<String>,
+    },
+    /// Show recent deliveries and their outcome
+    Deliveries {
+        webhook_id: String,
+        #[arg(long)]
+        org_id: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ApiKeyCommands {
+    /// Create a key, e.g. `create --role editor --org acme --expires 30d`
+    ///
+    /// Without --org or --device the key acts within your own scope. The
+    /// key never gets more than the role you hold on its scope.
+    Create {
+        #[arg(long, value_parser = parse_role)]
+        role: Role,
+        #[arg(long, default_value = "cli")]
+        name: String,
+        /// Scope the key to an org
+        #[arg(long, conflicts_with = "device")]
+        org: Option<String>,
+        /// Scope the key to a single device
+        #[arg(long)]
+        device: Option<String>,
+        /// Lifetime of the key (e.g. 30d); keys without one don't expire
+        #[arg(long, value_parser = parse_duration)]
+        expires: Option<u64>,
+        /// Server to create the key on (defaults to the first configured one)
+        #[arg(long, conflicts_with = "device")]
+        server: Option<String>,
+    },
+    /// List the keys you created
+    List,
+    /// Revoke a key
+    Revoke { key_id: String },
+}
+
+#[derive(Subcommand)]
+enum AdminCommands {
+    /// Approve, reject and disable user accounts
+    #[clap(subcommand)]
+    Users(UserAdminAction),
+}
+
+#[derive(Subcommand)]
+enum UserAdminAction {
+    /// List users of the server
+    List {
+        /// Only users waiting for approval
+        #[arg(long, conflicts_with = "status")]
+        pending: bool,
+        /// Only users with this status (pending, active or disabled)
+        #[arg(long)]
+        status: Option<UserStatus>,
+    },
+    /// Let a pending user sign in
+    Approve {
+        /// User id or email
+        user: String,
+    },
+    /// Turn down a pending user
+    Reject {
+        /// User id or email
+        user: String,
+    },
+    /// Block a user and close their open sessions
+    Disable {
+        /// User id or email
+        user: String,
+    },
+    /// Lift a previous disable or reject
+    Enable {
+        /// User id or email
+        user: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum AlertsCommands {
+    /// List firing alerts
+    List {
+        /// Include resolved alerts
+        #[arg(long)]
+        all: bool,
+        #[arg(long)]
+        org_id: Option<String>,
+    },
+    /// Acknowledge an alert
+    Ack {
+        alert_id: String,
+        #[arg(long)]
+        org_id: Option<String>,
+    },
+    /// Stop notifications of a rule for a while (e.g. 2h); alerts still show
+    Silence {
+        rule_id: String,
+        #[arg(value_parser = parse_duration)]
+        duration: u64,
+        #[arg(long)]
+        org_id: Option<String>,
+    },
+    /// Lift the silence of a rule
+    Unsilence {
+        rule_id: String,
+        #[arg(long)]
+        org_id: Option<String>,
+    },
+    /// Manage the rules alerts are raised from
+    #[clap(subcommand)]
+    Rules(AlertRuleAction),
+}
+
+#[derive(Subcommand)]
+enum AlertRuleAction {
+    List {
+        #[arg(long)]
+        org_id: Option<String>,
+    },
+    /// Add a rule, e.g. `add disk-full 'disk.usage_percent>90' --for 5m`
+    Add {
+        name: String,
+        /// One of `offline`, `version_mismatch`, `unhealthy:<checks>`,
+        /// `unhealthy:<unit>:<checks>` or `<metric><op><threshold>` with
+        /// metric cpu.usage_percent, memory.usage_percent,
+        /// disk.usage_percent, thermal.max_temp_c, thermal.min_fan_rpm,
+        /// cpu.freq_mhz, power.undervoltage, power.throttled (0 or 1) or
+        /// containers.unhealthy and op >, >=, < or <=
+        condition: AlertCondition,
+        /// How long the condition must hold before the alert fires
+        #[arg(long = "for", value_parser = parse_duration, default_value = "0")]
+        for_secs: u64,
+        /// Only devices with this label (repeatable, `key=value`)
+        #[arg(long = "label", value_parser = parse_label)]
+        labels: Vec<(String, String)>,
+        #[arg(long)]
+        org_id: Option<String>,
+    },
+    Remove {
+        rule_id: String,
+        #[arg(long)]
+        org_id: Option<String>,
+    },
+}
+
+fn parse_duration(s: &str) -> Result<u64, String> {
+    crate::util::time::parse_duration_secs(s).map_err(|e| e.to_string())
+}
+
+fn parse_label(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => {
+            Ok((key.trim().to_string(), value.trim().to_string()))
+        }
+        _ => Err(format!("expected key=value, got '{s}'")),
+    }
+}
+
 #[derive(Subcommand)]
 enum MemberAction {
     Add {
@@ -271,6 +553,11 @@ enum ConfigCommands {
 
         #[arg(long)]
         trust_invalid_server_cert: Option<bool>,
+
+        /// Servers to manage devices on, replacing the ones from login
+        /// (repeatable)
+        #[arg(long = "manager-server-url")]
+        manager_server_urls: Vec<String>,
     },
 
     Show,
@@ -325,6 +612,14 @@ pub struct DeviceRoot {
     /// Device name or ID
     pub device: String,
 
+    /// Print payload vs on-the-wire bytes of device streams on exit
+    #[arg(long, global = true)]
+    pub stats: bool,
+
+    /// Don't compress device streams (for already-compressed data or slow devices)
+    #[arg(long, global = true)]
+    pub no_compress: bool,
+
     #[command(subcommand)]
     pub command: DeviceCommand,
 }
@@ -356,9 +651,63 @@ pub enum DeviceCommand {
     /// a specific job run-id. Use `--follow` to switch to a live observe
     /// stream instead of history.
     Logs(LogsArgs),
+    /// Read the systemd journal (kernel, NetworkManager, docker.service, ...)
+    Journal {
+        /// Only entries of this systemd unit; repeatable
+        #[arg(short = 'u', long = "unit")]
+        units: Vec<String>,
+        /// Kernel messages
+        #[arg(short = 'k', long)]
+        kernel: bool,
+        /// Only entries at this priority or more severe, e.g. err or 3
+        #[arg(short = 'p', long, value_parser = str::parse::<JournalPriority>)]
+        priority: Option<JournalPriority>,
+        /// Start of the window: `30m`, `1h`, `7d`, or an absolute timestamp
+        #[arg(long)]
+        since: Option<String>,
+        /// End of the window. Same formats as --since.
+        #[arg(long)]
+        until: Option<String>,
+        /// Last N entries; 0 for all
+        #[arg(short = 'n', long, default_value = "200")]
+        tail: usize,
+        /// Keep printing new entries
+        #[arg(short = 'f', long, conflicts_with = "until")]
+        follow: bool,
+        /// Output as NDJSON, one entry per line.
+        #[arg(long)]
+        json: bool,
+    },
     /// Show device system metrics
     #[clap(alias = "stats")]
-    Metrics,
+    Metrics {
+        /// Per-container CPU, memory, restarts and health instead
+        #[arg(long)]
+        containers: bool,
+        /// Chart the values of a unit's `metrics` observe hook instead
+        #[arg(long, value_name = "UNIT_ID", conflicts_with = "containers")]
+        unit: Option<String>,
+    },
+    /// List the device's processes
+    Ps {
+        #[arg(long, value_enum, default_value_t)]
+        sort: ProcessSort,
+        /// Only the first N processes; 0 for all
+        #[arg(short = 'n', long, default_value = "0")]
+        tail: usize,
+        /// Output as NDJSON, one process per line.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Live process view (like top), with sending signals
+    Top,
+    /// Send a signal to a process on the device
+    Kill {
+        pid: u32,
+        /// Signal name, e.g. TERM, KILL, HUP, INT, USR1
+        #[arg(short = 's', long, default_value = "TERM")]
+        signal: String,
+    },
     /// Execute a command on the device
     Exec {
         /// Keep stdin open (for responding to prompts)
@@ -372,10 +721,26 @@ pub enum DeviceCommand {
     },
     /// Connect to a serial device
     Serial {
-        /// path to serial device (e.g., "/dev/ttyUSB0")
-        path: String,
+        /// path to serial device (e.g., "/dev/ttyUSB0"), or a stable alias
+        /// from --list (e.g., "by-id/usb-FTDI_FT232R_A50285BI-if00-port0")
+        #[arg(required_unless_present = "list")]
+        path: Option<String>,
         /// Optional baud rate (defaults to 115200)
         baud: Option<u32>,
+        /// Serve the port over RFC 2217 instead of a local PTY, for tools
+        /// that toggle DTR/RTS or change baud (esptool, avrdude, pyserial).
+        /// Takes `[host]:port`, e.g. `:4000`; the host defaults to localhost
+        #[arg(long, value_name = "ADDR")]
+        rfc2217: Option<String>,
+        /// Print what a captured port recorded over this long (e.g. 1h)
+        #[arg(long, value_parser = parse_duration, conflicts_with = "rfc2217")]
+        since: Option<u64>,
+        /// Print a captured port's output as it arrives
+        #[arg(short = 'f', long, conflicts_with = "rfc2217")]
+        follow: bool,
+        /// List serial ports with USB IDs, serial numbers and aliases
+        #[arg(long, conflicts_with_all = ["path", "baud", "rfc2217", "since", "follow"])]
+        list: bool,
     },
 
     /// Show device health.
@@ -397,6 +762,23 @@ pub enum DeviceCommand {
         max: u32,
         #[arg(long, default_value = "false")]
         details: bool,
+        /// Only entries by this user (email or name)
+        #[arg(long)]
+        user: Option<String>,
+        /// Only relayed-traffic entries of this stream type
+        /// (terminal, exec, forward, logs, serial, ...)
+        #[arg(long)]
+        r#type: Option<String>,
+        /// Output format; ndjson and csv include the structured stream fields
+        #[arg(long, value_enum, default_value_t = tui::device::AuditFormat::Table)]
+        format: tui::device::AuditFormat,
+        /// Recorded shell/exec session (id from a "Recorded ... session" entry).
+        /// Prints the asciicast v2 file unless --replay is given.
+        #[arg(long)]
+        session: Option<String>,
+        /// Play the session back in this terminal with its original timing
+        #[arg(long, requires = "session")]
+        replay: bool,
     },
 
     /// Deploy a service, observer, or job definition (upsert by id).
@@ -604,6 +986,25 @@ pub struct LogsArgs {
     /// Output as NDJSON, one event per line.
     #[arg(long)]
     pub json: bool,
+
+    /// Read the unit's persisted output (`observe.logs.persist`) instead of
+    /// its events. Honors --since, --until and --tail.
+    #[arg(
+        long,
+        requires = "id",
+        conflicts_with_all = ["services", "jobs", "failed", "follow", "logs"]
+    )]
+    pub persisted: bool,
+
+    /// Only persisted lines matching this regex, evaluated on the device.
+    /// Implies --persisted.
+    #[arg(
+        long,
+        value_name = "REGEX",
+        requires = "id",
+        conflicts_with_all = ["services", "jobs", "failed", "follow", "logs"]
+    )]
+    pub grep: Option<String>,
 }
 
 #[derive(Parser, Debug)]
@@ -629,6 +1030,11 @@ pub struct StatusArgs {
     /// Output the full summary as JSON.
     #[arg(long)]
     pub json: bool,
+
+    /// Also report which data path (direct or relay) a new connection would
+    /// get. Opens a connection to the device, so it takes a few seconds.
+    #[arg(long, conflicts_with_all = ["short", "quiet"])]
+    pub probe_path: bool,
 }
 
 #[derive(Parser, Debug)]
@@ -803,6 +1209,39 @@ enum DevicesCommands {
         /// Device name or ID
         device: String,
     },
+
+    /// Set or remove device labels used by alert rule selectors
+    Label {
+        /// Device name or ID
+        device: String,
+        /// Labels to set, as `key=value`
+        #[arg(value_parser = parse_label)]
+        labels: Vec<(String, String)>,
+        /// Label key to remove (repeatable)
+        #[arg(long = "remove")]
+        remove: Vec<String>,
+    },
+
+    /// Revoke a device's credentials, e.g. when it was stolen
+    ///
+    /// Its key stops working and its connection is closed. The device keeps
+    /// its history but has to register and be approved again to reconnect.
+    Revoke {
+        /// Device name or ID
+        device: String,
+        /// Skip the confirmation prompt
+        #[arg(long, short)]
+        force: bool,
+    },
+}
+
+/// Prints `--stats` when `cli()` returns, whichever way it returns.
+struct StatsReport;
+
+impl Drop for StatsReport {
+    fn drop(&mut self) {
+        STREAM_STATS.report();
+    }
 }
 
 pub async fn cli() -> anyhow::Result<()> {
@@ -827,6 +1266,14 @@ pub async fn cli() -> anyhow::Result<()> {
     }
     set_tls_provider();
 
+    if cli.stats {
+        STREAM_STATS.enable();
+    }
+    if cli.no_compress {
+        disable_compression();
+    }
+    let _stats = StatsReport;
+
     match cli.command {
         Commands::Login => {
             tracing::info!("Logging in...");
@@ -987,6 +1434,35 @@ pub async fn cli() -> anyhow::Result<()> {
                 auth::reject_auth_request(&device).await?;
                 tracing::info!("Device rejected successfully");
             }
+            DevicesCommands::Label {
+                device,
+                labels,
+                remove,
+            } => {
+                let labels =
+                    devices::update_labels(&device, labels.into_iter().collect(), remove).await?;
+                if labels.is_empty() {
+                    println!("No labels");
+                }
+                for (key, value) in labels {
+                    println!("{key}={value}");
+                }
+            }
+            DevicesCommands::Revoke { device, force } => {
+                if !force {
+                    println!("Revoke the credentials of device {}?", device);
+                    println!("It disconnects and has to be approved again to reconnect.");
+                    println!("Type 'y' to confirm:");
+                    let mut input = String::new();
+                    std::io::stdin().read_line(&mut input)?;
+                    if input.trim() != "y" {
+                        println!("Aborted.");
+                        return Ok(());
+                    }
+                }
+                devices::revoke(&device).await?;
+                println!("Device {} revoked", device);
+            }
         },
 
         Commands::Version => {
@@ -1005,25 +1481,46 @@ pub async fn cli() -> anyhow::Result<()> {
             update::update(true).await?;
         }
 
-        Commands::Cp { source, dest } => {
-            let _ = device::fs::copy(&source, &dest).await?;
+        Commands::Cp { source, dest, json } => {
+            let progress = TransferProgress::new(ProgressSink::for_cli(json));
+            device::fs::copy(&source, &dest, &progress).await?;
         }
 
         Commands::Sync {
             source,
             dest,
             delete,
+            bidirectional,
+            on_conflict,
             watch,
+            on_change,
             dry_run,
             exclude,
+            no_ignore_files,
+            json,
         } => {
+            let bidirectional = bidirectional.then_some(on_conflict);
+            let filter = device::fs::SyncFilter::new(exclude, !no_ignore_files);
+            let progress = TransferProgress::new(ProgressSink::for_cli(json));
             if watch {
                 if dry_run {
                     anyhow::bail!("--dry-run cannot be used with --watch");
                 }
-                device::fs::watch_sync(&source, &dest, delete, &exclude).await?;
+                device::fs::watch_sync(
+                    &source,
+                    &dest,
+                    delete,
+                    &filter,
+                    bidirectional,
+                    on_change.as_deref(),
+                    &progress,
+                )
+                .await?;
+            } else if let Some(policy) = bidirectional {
+                device::fs::sync_bidirectional(&source, &dest, policy, dry_run, &filter, &progress)
+                    .await?;
             } else {
-                device::fs::sync(&source, &dest, delete, dry_run, &exclude).await?;
+                device::fs::sync(&source, &dest, delete, dry_run, &filter, &progress).await?;
             }
         }
         Commands::Ls { path } => {
@@ -1037,6 +1534,12 @@ pub async fn cli() -> anyhow::Result<()> {
                 Ok(p) => p,
                 Err(e) => e.exit(), // Clean exit for help/version, error message for parse errors
             };
+            if parsed.stats {
+                STREAM_STATS.enable();
+            }
+            if parsed.no_compress {
+                disable_compression();
+            }
             handle_device_command(parsed).await?;
         }
 
@@ -1047,6 +1550,7 @@ pub async fn cli() -> anyhow::Result<()> {
                 make87_api_url,
                 make87_app_url,
                 trust_invalid_server_cert,
+                manager_server_urls,
             } => {
                 let mut cfg = Config::load().context("Failed to load config")?;
 
@@ -1070,6 +1574,10 @@ pub async fn cli() -> anyhow::Result<()> {
                     cfg.trust_invalid_server_cert = trust;
                 }
 
+                if !manager_server_urls.is_empty() {
+                    cfg.manager_server_urls = manager_server_urls;
+                }
+
                 cfg.save().context("Failed to save config")?;
                 tracing::info!("Config updated");
             }
@@ -1085,6 +1593,156 @@ pub async fn cli() -> anyhow::Result<()> {
             }
         },
 
+        Commands::Apikey(cmd) => match cmd {
+            ApiKeyCommands::Create {
+                role,
+                name,
+                org,
+                device,
+                expires,
+                server,
+            } => {
+                let (server_url, created) = api_keys::create_api_key(api_keys::CreateApiKeyArgs {
+                    name,
+                    role,
+                    org_id: org,
+                    device,
+                    ttl_secs: expires,
+                    server_url: server,
+                })
+                .await?;
+                println!(
+                    "API key {} created for {} ({})",
+                    created.key.key_id,
+                    created.key.scope,
+                    created.key.role.to_string()
+                );
+                println!("It is shown only once and is valid on {server_url}:\n");
+                println!("  {}\n", created.api_key);
+                println!("Use it with:");
+                println!("  m87 config set --manager-server-url {server_url}");
+                println!("  export M87_API_KEY=<key>");
+            }
+            ApiKeyCommands::List => {
+                let keys = api_keys::list_api_keys().await?;
+                tui::api_key::print_api_keys(&keys);
+            }
+            ApiKeyCommands::Revoke { key_id } => {
+                api_keys::revoke_api_key(&key_id).await?;
+                println!("API key revoked");
+            }
+        },
+
+        Commands::Admin(AdminCommands::Users(action)) => match action {
+            UserAdminAction::List { pending, status } => {
+                let status = if pending {
+                    Some(UserStatus::Pending)
+                } else {
+                    status
+                };
+                let users = admin::list_users(status).await?;
+                tui::user::print_admin_users(&users);
+            }
+            UserAdminAction::Approve { user } => {
+                let user = admin::update_user(&user, "approve").await?;
+                println!("User {} approved", user.email);
+            }
+            UserAdminAction::Reject { user } => {
+                let user = admin::update_user(&user, "reject").await?;
+                println!("User {} rejected", user.email);
+            }
+            UserAdminAction::Disable { user } => {
+                let user = admin::update_user(&user, "disable").await?;
+                println!("User {} disabled", user.email);
+            }
+            UserAdminAction::Enable { user } => {
+                let user = admin::update_user(&user, "enable").await?;
+                println!("User {} enabled", user.email);
+            }
+        },
+
+        Commands::Logs {
+            org,
+            grep,
+            device,
+            source,
+            since,
+            until,
+            tail,
+            json,
+        } => {
+            use crate::util::time::{now_ms, parse_time};
+
+            let now = now_ms();
+            let device = match device {
+                Some(name) => Some(devices::resolve_device_cached(&name).await?.short_id),
+                None => None,
+            };
+            let query = DeviceLogQuery {
+                grep,
+                device,
+                source,
+                since_ms: since.as_deref().map(|s| parse_time(s, now)).transpose()?,
+                until_ms: until.as_deref().map(|s| parse_time(s, now)).transpose()?,
+                limit: Some(tail),
+            };
+            let lines = logs::query_org_logs(org, query).await?;
+            tui::log::print_shipped_logs(&lines, json);
+        }
+
+        Commands::Alerts(cmd) => match cmd {
+            AlertsCommands::List { all, org_id } => {
+                let list = alerts::list_alerts(org_id, all).await?;
+                tui::alerts::print_alerts(&list);
+            }
+            AlertsCommands::Ack { alert_id, org_id } => {
+                let _ = alerts::ack_alert(org_id, &alert_id).await?;
+                println!("Alert acknowledged");
+            }
+            AlertsCommands::Silence {
+                rule_id,
+                duration,
+                org_id,
+            } => {
+                let rule = alerts::silence_rule(org_id, &rule_id, Some(duration)).await?;
+                println!(
+                    "Rule silenced until {}",
+                    rule.silenced_until.unwrap_or_default()
+                );
+            }
+            AlertsCommands::Unsilence { rule_id, org_id } => {
+                let _ = alerts::silence_rule(org_id, &rule_id, None).await?;
+                println!("Rule unsilenced");
+            }
+            AlertsCommands::Rules(action) => match action {
+                AlertRuleAction::List { org_id } => {
+                    let rules = alerts::list_rules(org_id).await?;
+                    tui::alerts::print_alert_rules(&rules);
+                }
+                AlertRuleAction::Add {
+                    name,
+                    condition,
+                    for_secs,
+                    labels,
+                    org_id,
+                } => {
+                    let rule = alerts::add_rule(
+                        org_id,
+                        &name,
+                        condition,
+                        for_secs,
+                        labels.into_iter().collect(),
+                    )
+                    .await?;
+                    println!("Alert rule created: {}", rule.id);
+                }
+                AlertRuleAction::Remove { rule_id, org_id } => {
+                    alerts::remove_rule(org_id, &rule_id).await?;
+                    println!("Alert rule removed");
+                }
+            },
+        },
+
         Commands::Org(cmd) => match cmd {
             OrgCommands::List => {
                 let orgs = org::list_organizations().await?;
@@ -1102,6 +1760,37 @@ pub async fn cli() -> anyhow::Result<()> {
                 let _ = org::update_organization(&id, &new_id).await?;
                 println!("Organization updated");
             }
+            OrgCommands::Settings {
+                org_id,
+                record_sessions,
+            } => {
+                let settings =
+                    org::settings(org_id, UpdateOrgSettingsBody { record_sessions }).await?;
+                println!("record_sessions: {}", settings.record_sessions);
+            }
+            OrgCommands::Webhooks(action) => match action {
+                WebhookAction::List { org_id } => {
+                    let hooks = org::list_webhooks(org_id).await?;
+                    tui::org::print_webhooks(&hooks);
+                }
+                WebhookAction::Add {
+                    url,
+                    events,
+                    org_id,
+                } => {
+                    let created = org::add_webhook(org_id, &url, events).await?;
+                    println!("Webhook created: {}", created.webhook.id);
+                    println!("Signing secret (shown only once): {}", created.secret);
+                }
+                WebhookAction::Remove { webhook_id, org_id } => {
+                    org::remove_webhook(org_id, &webhook_id).await?;
+                    println!("Webhook removed");
+                }
+                WebhookAction::Deliveries { webhook_id, org_id } => {
+                    let deliveries = org::list_webhook_deliveries(org_id, &webhook_id).await?;
+                    tui::org::print_webhook_deliveries(&deliveries);
+                }
+            },
             OrgCommands::Members(action) => match action {
                 MemberAction::List { org_id } => {
                     let members = org::list_members(org_id).await?;
@@ -1255,6 +1944,7 @@ async fn handle_device_command(cmd: DeviceRoot) -> anyhow::Result<()> {
 
         DeviceCommand::Logs(args) => {
             // Mode resolution:
+            //   --persisted / --grep           → unit's persisted output
             //   --follow                       → live stream
             //   any selector / filter / id /   → history (default 200 events)
             //     --json / --logs
@@ -1269,6 +1959,28 @@ async fn handle_device_command(cmd: DeviceRoot) -> anyhow::Result<()> {
             // tails to expand. `--tail` is intentionally treated as bare-live
             // compatible so older `m87 dev logs --tail 10` invocations on a
             // device with no spec continue to succeed (return empty stream).
+            if args.persisted || args.grep.is_some() {
+                use crate::util::time::{now_ms, parse_time};
+
+                let now = now_ms();
+                let since_ms = args
+                    .since
+                    .as_deref()
+                    .map(|s| parse_time(s, now))
+                    .transpose()?;
+                let until_ms = args
+                    .until
+                    .as_deref()
+                    .map(|s| parse_time(s, now))
+                    .transpose()?;
+                let unit = args.id.as_deref().unwrap_or_default();
+                tui::log::print_persisted_logs(
+                    &device, unit, since_ms, until_ms, args.grep, args.tail, args.json,
+                )
+                .await?;
+                return Ok(());
+            }
+
             let bare_invocation = args.id.is_none()
                 && !args.services
                 && !args.jobs
@@ -1335,8 +2047,50 @@ async fn handle_device_command(cmd: DeviceRoot) -> anyhow::Result<()> {
             Ok(())
         }
 
-        DeviceCommand::Metrics => {
-            tui::metric::run_metrics(&device).await?;
+        DeviceCommand::Journal {
+            units,
+            kernel,
+            priority,
+            since,
+            until,
+            tail,
+            follow,
+            json,
+        } => {
+            use crate::util::time::{now_ms, parse_time};
+
+            let now = now_ms();
+            let since_ms = since.as_deref().map(|s| parse_time(s, now)).transpose()?;
+            let until_ms = until.as_deref().map(|s| parse_time(s, now)).transpose()?;
+            let filter = JournalSpec {
+                units,
+                kernel,
+                priority,
+            };
+            tui::log::print_journal(&device, filter, since_ms, until_ms, tail, follow, json)
+                .await?;
+            Ok(())
+        }
+
+        DeviceCommand::Metrics { containers, unit } => {
+            tui::metric::run_metrics(&device, containers, unit.as_deref()).await?;
+            Ok(())
+        }
+
+        DeviceCommand::Ps { sort, tail, json } => {
+            let list = device::ps::list_processes(&device).await?;
+            tui::process::print_processes(list, sort, tail, json);
+            Ok(())
+        }
+
+        DeviceCommand::Top => {
+            tui::process::run_top(&device).await?;
+            Ok(())
+        }
+
+        DeviceCommand::Kill { pid, signal } => {
+            device::ps::send_signal(&device, pid, &signal).await?;
+            println!("Sent {signal} to {pid}");
             Ok(())
         }
 
@@ -1350,24 +2104,74 @@ async fn handle_device_command(cmd: DeviceRoot) -> anyhow::Result<()> {
         }
 
         #[cfg(unix)]
-        DeviceCommand::Serial { path, baud } => {
+        DeviceCommand::Serial {
+            path,
+            baud,
+            rfc2217,
+            since,
+            follow,
+            ..
+        } => {
+            // clap only lets `path` be missing together with --list.
+            let Some(path) = path else {
+                let ports = serial::list_ports(&device).await?;
+                tui::serial::print_serial_ports(&ports);
+                return Ok(());
+            };
             let baud = baud.unwrap_or(115200);
-            serial::open_serial(&device, &path, baud).await?;
+            match rfc2217 {
+                Some(listen) => serial::serve_rfc2217(&device, &path, baud, &listen).await?,
+                None if since.is_some() || follow => {
+                    serial::read_capture(&device, &path, since, follow).await?
+                }
+                None => serial::open_serial(&device, &path, baud).await?,
+            }
             Ok(())
         }
 
         DeviceCommand::Status(args) => run_status(&device, args).await,
 
+        DeviceCommand::Audit {
+            session: Some(session_id),
+            replay,
+            ..
+        } => {
+            let recording = devices::get_session_recording(&device, &session_id).await?;
+            if replay {
+                tui::replay::replay_session(&recording).await?;
+            } else {
+                print!("{}", recording.cast);
+            }
+            Ok(())
+        }
+
         DeviceCommand::Audit {
             until,
             since,
             max,
             details,
+            user,
+            r#type,
+            format,
+            ..
         } => {
-            let logs = devices::get_audit_logs(&device, until, since, max).await?;
+            let query = devices::AuditLogQuery {
+                since,
+                until,
+                max,
+                user,
+                stream_type: r#type,
+            };
+            let logs = devices::get_audit_logs(&device, query).await?;
 
             tracing::info!("Received audit logs");
-            tui::device::print_deployment_reports(&logs, details);
+            match format {
+                tui::device::AuditFormat::Table => {
+                    tui::device::print_deployment_reports(&logs, details)
+                }
+                tui::device::AuditFormat::Ndjson => tui::device::print_audit_logs_ndjson(&logs),
+                tui::device::AuditFormat::Csv => tui::device::print_audit_logs_csv(&logs),
+            }
             Ok(())
         }
 
@@ -1788,6 +2592,18 @@ async fn run_status(device: &str, args: StatusArgs) -> anyhow::Result<()> {
         }
     }
 
+    // Which data path a connection gets right now; opt-in since it costs a
+    // full connection attempt.
+    if args.probe_path {
+        summary.connection = Some(match device::forward::probe_connection_path(device).await {
+            Ok(path) => path.to_string(),
+            Err(e) => {
+                tracing::debug!("connection probe failed: {e:#}");
+                "unreachable".to_string()
+            }
+        });
+    }
+
     let healthy = summary.is_healthy();
 
     if args.quiet {
@@ -1803,6 +2619,9 @@ async fn run_status(device: &str, args: StatusArgs) -> anyhow::Result<()> {
         // window section when present. The existing renderer takes the raw
         // server `DeviceStatus`, not our summary, so we hand it through.
         tui::device::print_device_status(device, &status);
+        if let Some(connection) = &summary.connection {
+            println!("Connection  {connection}");
+        }
         if let Some(w) = &summary.window {
             use crate::util::time::format_ms;
             println!();
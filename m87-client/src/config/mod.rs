@@ -24,6 +24,52 @@ fn default_make87_app_url() -> String {
     "https://app.make87.com".to_string()
 }
 
+fn default_direct_connections() -> bool {
+    false
+}
+
+fn default_capture_baud() -> u32 {
+    115200
+}
+
+fn default_capture_max_bytes() -> u64 {
+    4 * 1024 * 1024
+}
+
+fn default_ship_runtime_log() -> bool {
+    true
+}
+
+fn default_log_spool_bytes() -> u64 {
+    64 * 1024 * 1024
+}
+
+/// Upload logs to the server, which keeps them for `m87 logs --org`.
+/// Lines of units with `observe.logs.persist` are shipped.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct LogShippingConfig {
+    /// Also ship the runtime's own log.
+    #[serde(default = "default_ship_runtime_log")]
+    pub runtime: bool,
+    /// Disk budget for batches waiting to be uploaded; the oldest are
+    /// dropped first.
+    #[serde(default = "default_log_spool_bytes")]
+    pub max_spool_bytes: u64,
+}
+
+/// A serial port the runtime keeps open and records to disk from startup,
+/// so output is kept even when nobody is attached.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct SerialCaptureConfig {
+    /// Port name under /dev, e.g. "ttyUSB0".
+    pub port: String,
+    #[serde(default = "default_capture_baud")]
+    pub baud: u32,
+    /// Disk budget for the rotating buffer; the oldest output is dropped first.
+    #[serde(default = "default_capture_max_bytes")]
+    pub max_bytes: u64,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Config {
     #[serde(default, alias = "agent_server_url", alias = "api_url")]
@@ -47,12 +93,25 @@ pub struct Config {
     pub auth_client_id: String,
     #[serde(default)]
     pub trust_invalid_server_cert: bool,
+    /// Try a direct peer-to-peer path for forwards and file sync before
+    /// relaying through the server. On a device, `false` (the default) refuses
+    /// direct sessions.
+    #[serde(default = "default_direct_connections")]
+    pub direct_connections: bool,
 
     #[serde(default)]
     pub manager_server_urls: Vec<String>,
 
     #[serde(default)]
     pub organization_id: Option<String>,
+
+    /// Serial ports captured on the device (see `m87 <device> serial --since`).
+    #[serde(default)]
+    pub serial_captures: Vec<SerialCaptureConfig>,
+
+    /// Off unless set.
+    #[serde(default)]
+    pub log_shipping: Option<LogShippingConfig>,
 }
 
 impl Default for Config {
@@ -70,8 +129,11 @@ impl Default for Config {
             auth_audience: "https://auth.make87.com".to_string(),
             auth_client_id: "E2J7xfFLgexzvhHhz4YqaJBy8Ys82SmM".to_string(),
             trust_invalid_server_cert: false,
+            direct_connections: default_direct_connections(),
             manager_server_urls: vec![],
             organization_id: None,
+            serial_captures: vec![],
+            log_shipping: None,
         }
     }
 }
@@ -0,0 +1,358 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Duration,
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use futures::future::join_all;
+use m87_shared::metrics::{ContainerInfo, ContainerMetrics};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, de::DeserializeOwned};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::UnixStream,
+};
+
+use crate::device::deployment_manager::RevisionStore;
+
+const DOCKER_SOCKET: &str = "/var/run/docker.sock";
+/// A wedged daemon must not hold up the heartbeat.
+const DOCKER_TIMEOUT: Duration = Duration::from_secs(5);
+const COMPOSE_PROJECT: &str = "com.docker.compose.project";
+const COMPOSE_WORKING_DIR: &str = "com.docker.compose.project.working_dir";
+
+/// `(total_usage, system_cpu_usage)` of each container's last stats, which
+/// the next CPU percentage is measured against.
+static PREV_CPU: Lazy<Mutex<HashMap<String, (u64, u64)>>> = Lazy::new(Default::default);
+/// Registry digest by image id; images don't change under their id.
+static IMAGE_DIGESTS: Lazy<Mutex<HashMap<String, Option<String>>>> = Lazy::new(Default::default);
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ListedContainer {
+    id: String,
+    #[serde(default)]
+    names: Vec<String>,
+    image: String,
+    #[serde(rename = "ImageID", default)]
+    image_id: String,
+    state: String,
+    #[serde(default)]
+    labels: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct InspectedContainer {
+    #[serde(default)]
+    restart_count: u64,
+    state: InspectedState,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct InspectedState {
+    health: Option<InspectedHealth>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct InspectedHealth {
+    status: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct InspectedImage {
+    #[serde(default)]
+    repo_digests: Vec<String>,
+}
+
+#[derive(Deserialize, Default)]
+struct Stats {
+    #[serde(default)]
+    cpu_stats: CpuStats,
+    #[serde(default)]
+    memory_stats: MemoryStats,
+}
+
+#[derive(Deserialize, Default)]
+struct CpuStats {
+    #[serde(default)]
+    cpu_usage: CpuUsage,
+    system_cpu_usage: Option<u64>,
+    online_cpus: Option<u32>,
+}
+
+#[derive(Deserialize, Default)]
+struct CpuUsage {
+    #[serde(default)]
+    total_usage: u64,
+}
+
+#[derive(Deserialize, Default)]
+struct MemoryStats {
+    usage: Option<u64>,
+    limit: Option<u64>,
+    #[serde(default)]
+    stats: HashMap<String, u64>,
+}
+
+/// Containers from the local Docker daemon, or `None` when it can't be
+/// reached.
+pub async fn collect_container_metrics() -> Option<ContainerMetrics> {
+    let listed: Vec<ListedContainer> = docker_get("/containers/json?all=1").await.ok()?;
+    let services: Vec<(String, PathBuf)> = RevisionStore::unit_workdirs(None)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(unit, workdir)| (unit.id, workdir))
+        .collect();
+
+    let containers: Vec<ContainerInfo> = join_all(
+        listed
+            .iter()
+            .map(|container| container_info(container, &services)),
+    )
+    .await;
+    // Forget removed containers.
+    PREV_CPU
+        .lock()
+        .unwrap()
+        .retain(|id, _| listed.iter().any(|c| &c.id == id));
+
+    Some(ContainerMetrics {
+        total: containers.len() as u32,
+        running: containers.iter().filter(|c| c.state == "running").count() as u32,
+        unhealthy: containers
+            .iter()
+            .filter(|c| c.health.as_deref() == Some("unhealthy"))
+            .count() as u32,
+        restarts: containers.iter().map(|c| c.restart_count).sum(),
+        containers,
+    })
+}
+
+/// One container; details that fail to load are left at their defaults.
+async fn container_info(
+    container: &ListedContainer,
+    services: &[(String, PathBuf)],
+) -> ContainerInfo {
+    let id = &container.id;
+    let inspected: Option<InspectedContainer> =
+        docker_get(&format!("/containers/{id}/json")).await.ok();
+    let stats: Option<Stats> = if container.state == "running" {
+        docker_get(&format!(
+            "/containers/{id}/stats?stream=false&one-shot=true"
+        ))
+        .await
+        .ok()
+    } else {
+        None
+    };
+
+    let cpu_percent = match &stats {
+        Some(Stats { cpu_stats: cpu, .. }) => {
+            let current = (cpu.cpu_usage.total_usage, cpu.system_cpu_usage.unwrap_or(0));
+            let previous = PREV_CPU.lock().unwrap().insert(id.clone(), current);
+            previous.map_or(0.0, |previous| {
+                cpu_percent(previous, current, cpu.online_cpus.unwrap_or(1))
+            })
+        }
+        None => {
+            PREV_CPU.lock().unwrap().remove(id);
+            0.0
+        }
+    };
+    let memory = stats.map(|s| s.memory_stats).unwrap_or_default();
+
+    ContainerInfo {
+        id: id.chars().take(12).collect(),
+        name: container
+            .names
+            .first()
+            .map(|n| n.trim_start_matches('/').to_string())
+            .unwrap_or_default(),
+        image: container.image.clone(),
+        image_digest: image_digest(&container.image_id).await,
+        state: container.state.clone(),
+        restart_count: inspected.as_ref().map_or(0, |c| c.restart_count),
+        health: inspected.and_then(|c| c.state.health).map(|h| h.status),
+        cpu_percent,
+        memory_bytes: memory_used(&memory),
+        memory_limit_bytes: memory.limit.unwrap_or(0),
+        service: service_for(&container.labels, services),
+    }
+}
+
+async fn image_digest(image_id: &str) -> Option<String> {
+    if image_id.is_empty() {
+        return None;
+    }
+    if let Some(digest) = IMAGE_DIGESTS.lock().unwrap().get(image_id) {
+        return digest.clone();
+    }
+    let image: InspectedImage = docker_get(&format!("/images/{image_id}/json")).await.ok()?;
+    let digest = image
+        .repo_digests
+        .first()
+        .and_then(|d| d.split_once('@'))
+        .map(|(_, digest)| digest.to_string());
+    IMAGE_DIGESTS
+        .lock()
+        .unwrap()
+        .insert(image_id.to_string(), digest.clone());
+    digest
+}
+
+/// Percentage of one core between two stats readings, like `docker stats`.
+fn cpu_percent(previous: (u64, u64), current: (u64, u64), online_cpus: u32) -> f32 {
+    let cpu_delta = current.0.saturating_sub(previous.0);
+    let system_delta = current.1.saturating_sub(previous.1);
+    if system_delta == 0 {
+        return 0.0;
+    }
+    (cpu_delta as f64 / system_delta as f64 * online_cpus as f64 * 100.0) as f32
+}
+
+/// Usage without the page cache, like `docker stats` (cgroup v2 reports
+/// `inactive_file`, v1 `total_inactive_file`).
+fn memory_used(memory: &MemoryStats) -> u64 {
+    let cache = memory
+        .stats
+        .get("inactive_file")
+        .or_else(|| memory.stats.get("total_inactive_file"))
+        .copied()
+        .unwrap_or(0);
+    memory.usage.unwrap_or(0).saturating_sub(cache)
+}
+
+/// The service whose workdir a compose container was started from: by the
+/// compose working dir label, or else by the project name compose derives
+/// from the workdir's name.
+fn service_for(labels: &HashMap<String, String>, services: &[(String, PathBuf)]) -> Option<String> {
+    if let Some(dir) = labels.get(COMPOSE_WORKING_DIR) {
+        let dir = Path::new(dir);
+        if let Some((id, _)) = services.iter().find(|(_, workdir)| workdir == dir) {
+            return Some(id.clone());
+        }
+    }
+    let project = labels.get(COMPOSE_PROJECT)?;
+    services
+        .iter()
+        .find(|(_, workdir)| {
+            workdir
+                .file_name()
+                .is_some_and(|name| compose_project_name(&name.to_string_lossy()) == *project)
+        })
+        .map(|(id, _)| id.clone())
+}
+
+/// Compose's default project name for a directory: lowercased, with only
+/// `a-z`, `0-9`, `_` and `-` kept.
+fn compose_project_name(dir_name: &str) -> String {
+    dir_name
+        .to_lowercase()
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '-')
+        .collect()
+}
+
+async fn docker_get<T: DeserializeOwned>(path: &str) -> Result<T> {
+    let raw = tokio::time::timeout(DOCKER_TIMEOUT, async {
+        let mut socket = UnixStream::connect(DOCKER_SOCKET).await?;
+        // HTTP/1.0 keeps the body unchunked, and the daemon closes after it.
+        let request = format!("GET {path} HTTP/1.0\r\nHost: docker\r\n\r\n");
+        socket.write_all(request.as_bytes()).await?;
+        let mut raw = Vec::new();
+        socket.read_to_end(&mut raw).await?;
+        Ok::<_, std::io::Error>(raw)
+    })
+    .await
+    .map_err(|_| anyhow!("docker {path} timed out"))??;
+    parse_response(&raw).with_context(|| format!("docker {path}"))
+}
+
+fn parse_response<T: DeserializeOwned>(raw: &[u8]) -> Result<T> {
+    let end = raw
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or_else(|| anyhow!("malformed HTTP response"))?;
+    let head = String::from_utf8_lossy(&raw[..end]);
+    let status = head.split_whitespace().nth(1).unwrap_or_default();
+    let body = &raw[end + 4..];
+    if status != "200" {
+        bail!("HTTP {status}: {}", String::from_utf8_lossy(body).trim());
+    }
+    Ok(serde_json::from_slice(body)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_docker_responses() {
+        let raw = b"HTTP/1.0 200 OK\r\nContent-Type: application/json\r\n\r\n\
+            [{\"Id\":\"3f4e9a1b2c7d8e0f\",\"Names\":[\"/web-1\"],\"Image\":\"nginx:1.27\",\
+            \"ImageID\":\"sha256:ab\",\"State\":\"running\",\"Labels\":{}}]";
+        let listed: Vec<ListedContainer> = parse_response(raw).unwrap();
+        assert_eq!(listed[0].names, ["/web-1"]);
+        assert_eq!(listed[0].image_id, "sha256:ab");
+
+        let raw = b"HTTP/1.0 404 Not Found\r\n\r\n{\"message\":\"No such container\"}";
+        let Err(err) = parse_response::<InspectedImage>(raw) else {
+            panic!("404 parsed as an image");
+        };
+        assert!(err.to_string().contains("404"));
+    }
+
+    #[test]
+    fn measures_cpu_and_memory_like_docker_stats() {
+        // A quarter of the system's time on a 4-core box is one full core.
+        assert_eq!(cpu_percent((100, 1_000), (350, 2_000), 4), 100.0);
+        assert_eq!(cpu_percent((100, 1_000), (100, 1_000), 4), 0.0);
+
+        let memory = MemoryStats {
+            usage: Some(500),
+            limit: Some(1_000),
+            stats: [("inactive_file".to_string(), 120)].into(),
+        };
+        assert_eq!(memory_used(&memory), 380);
+    }
+
+    #[test]
+    fn correlates_compose_containers_with_services() {
+        let services = vec![
+            (
+                "web-server".to_string(),
+                PathBuf::from("/var/lib/m87/workspaces/web-server"),
+            ),
+            (
+                "Camera.Bridge".to_string(),
+                PathBuf::from("/opt/camera.Bridge"),
+            ),
+        ];
+        let labels = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+
+        let by_dir = labels(&[(COMPOSE_WORKING_DIR, "/var/lib/m87/workspaces/web-server")]);
+        assert_eq!(
+            service_for(&by_dir, &services).as_deref(),
+            Some("web-server")
+        );
+        let by_project = labels(&[(COMPOSE_PROJECT, "camerabridge")]);
+        assert_eq!(
+            service_for(&by_project, &services).as_deref(),
+            Some("Camera.Bridge")
+        );
+        let unrelated = labels(&[(COMPOSE_PROJECT, "monitoring")]);
+        assert_eq!(service_for(&unrelated, &services), None);
+        assert_eq!(service_for(&HashMap::new(), &services), None);
+    }
+}
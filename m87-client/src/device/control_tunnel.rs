@@ -11,17 +11,24 @@ use tracing::error;
 use tracing::{debug, warn};
 
 #[cfg(feature = "runtime")]
-use crate::{auth::AuthManager, config::Config, device::deployment_manager::DeploymentManager};
+use crate::{
+    auth::AuthManager, config::Config, device::deployment_manager::DeploymentManager,
+    device::log_shipper,
+};
 
 #[cfg(feature = "runtime")]
 pub use m87_shared::heartbeat::{HeartbeatRequest, HeartbeatResponse};
 
+use crate::device::system_metrics::collect_system_metrics;
 use crate::util::system_info::get_system_info;
+use m87_shared::metrics::SystemMetrics;
 
 pub struct HeartbeatState {
     last_instruction_hash: String,
     heartbeat_interval: u64,
     first_heartbeat: bool,
+    /// Id of the stored device key; `None` opts out of key rotation.
+    api_key_id: Option<String>,
 }
 
 // Runtime-specific: Maintain persistent control tunnel connection
@@ -30,8 +37,6 @@ pub async fn connect_control_tunnel(unit_manager: Arc<DeploymentManager>) -> Res
     use std::sync::Arc;
 
     use crate::streams::quic::get_quic_connection;
-    use crate::streams::udp_manager::UdpChannelManager;
-    use bytes::{BufMut, Bytes, BytesMut};
     use m87_shared::{
         config::DeviceClientConfig, deploy_spec::build_instruction_hash, device::short_device_id,
     };
@@ -68,6 +73,7 @@ pub async fn connect_control_tunnel(unit_manager: Arc<DeploymentManager>) -> Res
 
     //  SHUTDOWN SIGNAL
     let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    log_shipper::reconnected();
 
     // thread to send periodoic health reports to server
 
@@ -88,6 +94,7 @@ pub async fn connect_control_tunnel(unit_manager: Arc<DeploymentManager>) -> Res
         last_instruction_hash: last_deploy_hash,
         heartbeat_interval: config.heartbeat_interval_secs,
         first_heartbeat: true,
+        api_key_id: (!AuthManager::device_key_pinned()).then(|| api_key_id(&token)),
     }));
 
     let manager_clone = unit_manager.clone();
@@ -102,6 +109,9 @@ pub async fn connect_control_tunnel(unit_manager: Arc<DeploymentManager>) -> Res
                     msg = read_msg::<HeartbeatResponse>(&mut recv) => {
                         let resp = msg?;
                         tracing::debug!("Received heartbeat response");
+                        if let Some(ack) = &resp.log_ack {
+                            log_shipper::ack(ack);
+                        }
 
                         // Don't hold `state` across `set_desired_units` /
                         // `apply_lifecycle_updates` / `ack_event` — those can
@@ -153,6 +163,19 @@ pub async fn connect_control_tunnel(unit_manager: Arc<DeploymentManager>) -> Res
                                 .await;
                         }
 
+                        if let Some(new_key) = resp.new_api_key {
+                            // Confirmed on the next heartbeat; until then the
+                            // server keeps the old key valid.
+                            let key_id = api_key_id(&new_key);
+                            match AuthManager::save_device_credentials(new_key) {
+                                Ok(()) => {
+                                    tracing::info!("Stored rotated device API key {key_id}");
+                                    state.lock().await.api_key_id = Some(key_id);
+                                }
+                                Err(e) => tracing::error!("Failed to store rotated API key: {e}"),
+                            }
+                        }
+
                         if let Some(received_report_hashes) = resp.received_report_hashes {
                             for hash in received_report_hashes {
                                 if let Err(e) = ack_event(&hash, None).await {
@@ -162,8 +185,12 @@ pub async fn connect_control_tunnel(unit_manager: Arc<DeploymentManager>) -> Res
                             }
                         }
 
-                        // Final lock — small + fast.
-                        state.lock().await.last_instruction_hash = resp.instruction_hash;
+                        // Final lock — small + fast. Up-to-date responses
+                        // (including log batch acks) echo the hash of their
+                        // request, which may predate a newer revision.
+                        if !resp.up_to_date {
+                            state.lock().await.last_instruction_hash = resp.instruction_hash;
+                        }
                     }
                 }
             }
@@ -175,6 +202,7 @@ pub async fn connect_control_tunnel(unit_manager: Arc<DeploymentManager>) -> Res
 
     let _sender = tokio::spawn({
         let state = state.clone();
+        let shutdown_tx = shutdown_tx.clone();
         async move {
             use std::time::Duration;
 
@@ -217,7 +245,11 @@ pub async fn connect_control_tunnel(unit_manager: Arc<DeploymentManager>) -> Res
                         };
 
                         tracing::debug!("Sending heartbeat with event update");
-                        let _ = write_msg(&mut send, &req).await;
+                        if let Err(e) = write_msg(&mut send, &req).await {
+                            warn!("failed to send event update, reconnecting: {e:?}");
+                            let _ = shutdown_tx.send(true);
+                            break;
+                        }
 
                         // Rate-limit event emission so a large `pending/`
                         // backlog can't pin a core. `on_new_event` returns
@@ -232,14 +264,42 @@ pub async fn connect_control_tunnel(unit_manager: Arc<DeploymentManager>) -> Res
                         tokio::time::sleep(Duration::from_millis(25)).await;
                     },
 
+                    // Waits while a batch is unacknowledged, which keeps
+                    // uploads to the pace the server stores them at.
+                    batch = log_shipper::next_batch() => {
+                        let req = {
+                            let st = state.lock().await;
+                            HeartbeatRequest {
+                                last_instruction_hash: st.last_instruction_hash.clone(),
+                                supported_revision_format: Some(2),
+                                log_batch: Some(batch),
+                                ..Default::default()
+                            }
+                        };
+                        tracing::debug!("Sending shipped logs");
+                        if let Err(e) = write_msg(&mut send, &req).await {
+                            warn!("failed to send shipped logs, reconnecting: {e:?}");
+                            let _ = shutdown_tx.send(true);
+                            break;
+                        }
+                    },
+
 
                     _ = tokio::time::sleep_until(next_heartbeat) => {
+                        // Sampled before taking the lock: CPU usage needs a
+                        // short pause between two readings.
+                        let metrics = collect_system_metrics()
+                            .await
+                            .ok()
+                            .map(SystemMetrics::for_heartbeat);
                         let req = {
                             let mut st = state.lock().await;
 
                             let mut req = HeartbeatRequest {
                                 last_instruction_hash: st.last_instruction_hash.clone(),
                                 supported_revision_format: Some(2),
+                                metrics,
+                                api_key_id: st.api_key_id.clone(),
                                 ..Default::default()
                             };
 
@@ -254,7 +314,11 @@ pub async fn connect_control_tunnel(unit_manager: Arc<DeploymentManager>) -> Res
 
                         tracing::info!("Sending heartbeat request");
 
-                        let _ = write_msg(&mut send, &req).await;
+                        if let Err(e) = write_msg(&mut send, &req).await {
+                            warn!("failed to send heartbeat, reconnecting: {e:?}");
+                            let _ = shutdown_tx.send(true);
+                            break;
+                        }
                         // Advance the deadline only after firing, so the
                         // interval is honored regardless of select cancellation.
                         next_heartbeat =
@@ -265,6 +329,36 @@ pub async fn connect_control_tunnel(unit_manager: Arc<DeploymentManager>) -> Res
         }
     });
 
+    serve_connection(&quic_conn, unit_manager, shutdown_tx.clone(), None).await;
+
+    let _ = shutdown_tx.send(true);
+    debug!("control tunnel terminated");
+    Ok(())
+}
+
+/// Public part of an API key (`<key_id>.<secret>`).
+#[cfg(feature = "runtime")]
+fn api_key_id(api_key: &str) -> String {
+    api_key
+        .split_once('.')
+        .map_or(api_key, |(key_id, _)| key_id)
+        .to_string()
+}
+
+/// Route incoming streams and UDP-forward datagrams of a device-side
+/// connection (the control tunnel or, with `direct_usage`, a direct session)
+/// until it closes or `shutdown_tx` fires.
+#[cfg(feature = "runtime")]
+pub async fn serve_connection(
+    quic_conn: &quinn::Connection,
+    unit_manager: Arc<DeploymentManager>,
+    shutdown_tx: tokio::sync::watch::Sender<bool>,
+    direct_usage: Option<tokio::sync::mpsc::UnboundedSender<m87_shared::p2p::DirectStreamUsage>>,
+) {
+    use crate::streams::router::StreamOrigin;
+    use crate::streams::udp_manager::UdpChannelManager;
+    use bytes::{BufMut, Bytes, BytesMut};
+
     let udp_channels = UdpChannelManager::new();
 
     let (datagram_tx, mut datagram_rx) = tokio::sync::mpsc::channel::<(u32, Bytes)>(2048);
@@ -272,7 +366,7 @@ pub async fn connect_control_tunnel(unit_manager: Arc<DeploymentManager>) -> Res
     // This task frames datagrams and sends via QUIC
     {
         let conn = quic_conn.clone();
-        let mut shutdown = shutdown_rx.clone();
+        let mut shutdown = shutdown_tx.subscribe();
         let shutdown_tx = shutdown_tx.clone();
         tokio::spawn(async move {
             loop {
@@ -301,7 +395,7 @@ pub async fn connect_control_tunnel(unit_manager: Arc<DeploymentManager>) -> Res
     {
         let udp_channels_clone = udp_channels.clone();
         let conn = quic_conn.clone();
-        let mut shutdown = shutdown_rx.clone();
+        let mut shutdown = shutdown_tx.subscribe();
         let shutdown_tx = shutdown_tx.clone();
 
         tokio::spawn(async move {
@@ -336,7 +430,7 @@ pub async fn connect_control_tunnel(unit_manager: Arc<DeploymentManager>) -> Res
         });
     }
 
-    let mut shutdown = shutdown_rx.clone();
+    let mut shutdown = shutdown_tx.subscribe();
     //  CONTROL STREAM ACCEPT LOOP
     loop {
         use crate::streams::{self, quic::QuicIo};
@@ -352,15 +446,19 @@ pub async fn connect_control_tunnel(unit_manager: Arc<DeploymentManager>) -> Res
                     Ok((send, recv)) => {
                         debug!("QUIC: new control stream accepted");
 
-                        let io = QuicIo { recv, send };
+                        let io = QuicIo::new(recv, send);
                         let udp_channels_clone = udp_channels.clone();
                         let datagram_tx_clone = datagram_tx.clone();
                         let unit_manager_clone = unit_manager.clone();
+                        let origin = match &direct_usage {
+                            Some(usage) => StreamOrigin::Direct { usage: usage.clone() },
+                            None => StreamOrigin::ControlTunnel { shutdown: shutdown_tx.subscribe() },
+                        };
 
                         tokio::spawn(async move {
                             if let Err(e) =
                                 streams::router::handle_incoming_stream(
-                                    io, udp_channels_clone, datagram_tx_clone, unit_manager_clone
+                                    io, udp_channels_clone, datagram_tx_clone, unit_manager_clone, origin
                                 ).await
                             {
                                 warn!("control stream error: {:?}", e);
@@ -387,8 +485,6 @@ pub async fn connect_control_tunnel(unit_manager: Arc<DeploymentManager>) -> Res
 
     let _ = shutdown_tx.send(true);
     udp_channels.remove_all().await;
-    debug!("control tunnel terminated");
-    Ok(())
 }
 
 pub async fn write_msg<T: Serialize>(io: &mut quinn::SendStream, msg: &T) -> Result<()> {
@@ -414,3 +510,14 @@ pub async fn read_msg<T: DeserializeOwned>(io: &mut quinn::RecvStream) -> Result
 
     Ok(msg)
 }
+
+#[cfg(all(test, feature = "runtime"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn api_key_id_is_the_public_prefix() {
+        assert_eq!(api_key_id("m87_abcdefgh.s3cret.with.dots"), "m87_abcdefgh");
+        assert_eq!(api_key_id("m87_abcdefgh"), "m87_abcdefgh");
+    }
+}
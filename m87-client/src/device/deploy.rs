@@ -682,6 +682,8 @@ pub async fn compose_file_to_service_spec(file: &Path, name: Option<&str>) -> Re
                 "docker compose -f {} logs -f --timestamps -n 50",
                 file_name
             ))),
+            journal: None,
+            persist: None,
         }),
         liveness: Some(ObserveHooks {
             every: Duration::from_secs(5),
@@ -708,6 +710,7 @@ pub async fn compose_file_to_service_spec(file: &Path, name: Option<&str>) -> Re
             fails_after: Some(3),
             ..Default::default()
         }),
+        metrics: None,
     };
 
     Ok(ServiceSpec {
@@ -1,8 +1,8 @@
 use anyhow::{Context, Result, anyhow};
 use m87_shared::deploy_spec::{
     DeployReportKind, DeploymentRevision, DeploymentRevisionReport, JobDef, JobRun, JobRunReport,
-    JobRunStatus, Lifecycle, LifecycleUpdate, ObserveHooks, OnFailure, Outcome, RestartPolicy,
-    RunReport, RunState, ServiceSpec, Step, StepReport, UndoMode, WorkdirMode,
+    JobRunStatus, Lifecycle, LifecycleUpdate, MetricsHook, ObserveHooks, OnFailure, Outcome,
+    RestartPolicy, RunReport, RunState, ServiceSpec, Step, StepReport, UndoMode, WorkdirMode,
 };
 use std::{
     collections::{BTreeMap, HashMap, HashSet, VecDeque},
@@ -14,10 +14,16 @@ use std::{
     },
     time::{Duration, Instant, SystemTime},
 };
-use tokio::{fs, io::AsyncWriteExt, sync::RwLock, time::sleep};
+use tokio::{
+    fs,
+    io::AsyncWriteExt,
+    sync::{RwLock, mpsc},
+    time::sleep,
+};
 
 use crate::{
-    device::log_manager::LogManager,
+    device::log_manager::{LogManager, PersistedUnit},
+    device::unit_metrics::run_metrics_hook,
     util::{
         command::{RunCommandError, run_command},
         shutdown::SHUTDOWN,
@@ -195,6 +201,15 @@ async fn maybe_prune_events(dir_path: Option<PathBuf>) {
     }
 }
 
+/// A finished `metrics` hook run, on its way back to the observe loop.
+struct UnitMetricsRun {
+    /// Spec hash of the unit; at most one run per hash is in flight.
+    hash: String,
+    revision_id: String,
+    spec: ServiceSpec,
+    values: Result<BTreeMap<String, f64>>,
+}
+
 // ---------------------------------------------------------------------------
 // LocalRunState – per-unit persistent state stored in the workdir
 // ---------------------------------------------------------------------------
@@ -229,6 +244,13 @@ pub struct LocalRunState {
     #[serde(default)]
     pub last_alive: bool,
 
+    /// Values of the last successful `metrics` hook run.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metrics: BTreeMap<String, f64>,
+    /// Timestamp (ms since epoch) of that run.
+    #[serde(default)]
+    pub metrics_at_ms: Option<u64>,
+
     /// Runtime lifecycle override sent from the server via heartbeat.
     #[serde(default)]
     pub lifecycle: Lifecycle,
@@ -425,6 +447,7 @@ impl ObserveKind {
                 alive: Some(true),
                 report_time: t,
                 log_tail: None,
+                metrics: BTreeMap::new(),
             },
             (ObserveKind::Liveness, false) => RunState {
                 run_id: run_id.to_string(),
@@ -433,6 +456,7 @@ impl ObserveKind {
                 alive: Some(false),
                 report_time: t,
                 log_tail,
+                metrics: BTreeMap::new(),
             },
             (ObserveKind::Health, true) => RunState {
                 run_id: run_id.to_string(),
@@ -441,6 +465,7 @@ impl ObserveKind {
                 alive: Some(true),
                 report_time: t,
                 log_tail: None,
+                metrics: BTreeMap::new(),
             },
             (ObserveKind::Health, false) => RunState {
                 run_id: run_id.to_string(),
@@ -449,6 +474,7 @@ impl ObserveKind {
                 alive: None,
                 report_time: t,
                 log_tail,
+                metrics: BTreeMap::new(),
             },
         }
     }
@@ -477,6 +503,25 @@ impl RevisionStore {
         Self::read_revision_file(&Self::desired_path(dir_path)?)
     }
 
+    /// Each active service and observer of the desired revision, with its
+    /// workdir.
+    pub fn unit_workdirs(dir_path: Option<PathBuf>) -> Result<Vec<(ServiceSpec, PathBuf)>> {
+        let root_dir = data_dir(dir_path.clone())?;
+        let Some(revision) = Self::get_desired_config(dir_path)? else {
+            return Ok(Vec::new());
+        };
+        Ok(revision
+            .get_service_map()
+            .into_values()
+            .chain(revision.get_observer_map().into_values())
+            .map(|unit| {
+                let path =
+                    DeploymentManager::workspace_path(&root_dir, &unit.id, unit.workdir.as_ref());
+                (unit, path)
+            })
+            .collect())
+    }
+
     /// Read and parse a stored revision file. A corrupt or incompatible-schema
     /// file (e.g. written by an older client) is NOT propagated as an error —
     /// that would wedge every reconcile cycle forever, forcing a manual file
@@ -581,6 +626,49 @@ impl DeploymentManager {
         Ok(())
     }
 
+    /// Where a unit's `observe.logs.persist` output is stored.
+    pub fn persisted_logs_dir(&self, unit_id: &str) -> PathBuf {
+        self.root_dir.join("logs").join(unit_id.replace('/', "_"))
+    }
+
+    /// Keep the follow command of every unit with `observe.logs.persist`
+    /// running, and stop those no longer in the revision.
+    async fn sync_log_persist(&self, spec: Option<&DeploymentRevision>) {
+        let mut units = Vec::new();
+        let specs = match spec {
+            Some(spec) => spec.services.iter().chain(spec.observers.iter()).collect(),
+            None => Vec::new(),
+        };
+        for svc in specs {
+            let Some(log_spec) = svc.observe.as_ref().and_then(|obs| obs.logs.as_ref()) else {
+                continue;
+            };
+            let (Some(persist), Some(follow)) = (&log_spec.persist, log_spec.follow_command())
+            else {
+                continue;
+            };
+            let workdir = match self
+                .resolve_workdir_for(&svc.id, svc.workdir.as_ref())
+                .await
+            {
+                Ok(wd) => wd,
+                Err(e) => {
+                    tracing::error!("log persist: no workdir for {}: {e}", svc.id);
+                    continue;
+                }
+            };
+            units.push(PersistedUnit {
+                run_id: svc.id.clone(),
+                follow,
+                persist: persist.clone(),
+                env: svc.env.clone(),
+                workdir,
+                store_dir: self.persisted_logs_dir(&svc.id),
+            });
+        }
+        self.log_manager.sync_persist(units).await;
+    }
+
     // -----------------------------------------------------------------------
     // set_desired_units – called when a new revision arrives via heartbeat
     // -----------------------------------------------------------------------
@@ -969,6 +1057,13 @@ impl DeploymentManager {
         tokio::spawn(async move {
             let mut next_health: HashMap<String, Instant> = HashMap::new();
             let mut next_liveness: HashMap<String, Instant> = HashMap::new();
+            let mut next_metrics: HashMap<String, Instant> = HashMap::new();
+            // Metrics hooks run in their own tasks, at most one per unit, and
+            // hand their values back here to be stored.
+            let (metrics_tx, mut metrics_rx) = mpsc::unbounded_channel::<UnitMetricsRun>();
+            let mut metrics_running: HashSet<String> = HashSet::new();
+            // Revision the persisted logs were last synced to.
+            let mut log_persist_rev: Option<Option<String>> = None;
             let tick = Duration::from_millis(250);
 
             // Reap 0.7.x-era orphaned compose projects before reconciling, so an
@@ -1034,7 +1129,15 @@ impl DeploymentManager {
                     }
                 }
 
-                // 3) Schedule observe checks for services + observers
+                // 3) Store finished metrics hook runs
+                while let Ok(run) = metrics_rx.try_recv() {
+                    metrics_running.remove(&run.hash);
+                    if let Err(e) = self.store_unit_metrics(&run).await {
+                        tracing::warn!("metrics hook failed for '{}': {e:#}", run.spec.id);
+                    }
+                }
+
+                // 4) Schedule observe checks for services + observers
                 let now = Instant::now();
                 let desired_spec =
                     match RevisionStore::get_desired_config(Some(self.root_dir.clone())) {
@@ -1046,6 +1149,12 @@ impl DeploymentManager {
                         }
                     };
 
+                let rev_hash = desired_spec.as_ref().map(|spec| spec.get_hash());
+                if log_persist_rev.as_ref() != Some(&rev_hash) {
+                    self.sync_log_persist(desired_spec.as_ref()).await;
+                    log_persist_rev = Some(rev_hash);
+                }
+
                 if let Some(spec) = desired_spec {
                     let revision_id = spec.id.clone().unwrap_or_default();
 
@@ -1104,6 +1213,25 @@ impl DeploymentManager {
                                     .await;
                             }
                         }
+                        if let Some(hook) = &obs.metrics {
+                            let due = next_metrics.get(&hash).copied().unwrap_or(now);
+                            if now >= due && metrics_running.insert(hash.clone()) {
+                                next_metrics.insert(hash.clone(), now + hook.every);
+                                let mgr = self.clone();
+                                let tx = metrics_tx.clone();
+                                let (spec, hook) = (svc.clone(), hook.clone());
+                                let (hash, revision_id) = (hash.clone(), revision_id.clone());
+                                tokio::spawn(async move {
+                                    let values = mgr.run_unit_metrics_hook(&spec, &hook).await;
+                                    let _ = tx.send(UnitMetricsRun {
+                                        hash,
+                                        revision_id,
+                                        spec,
+                                        values,
+                                    });
+                                });
+                            }
+                        }
                     }
                 }
 
@@ -1519,6 +1647,57 @@ impl DeploymentManager {
         }
     }
 
+    async fn run_unit_metrics_hook(
+        &self,
+        spec: &ServiceSpec,
+        hook: &MetricsHook,
+    ) -> Result<BTreeMap<String, f64>> {
+        let wd = self
+            .resolve_workdir_for(&spec.id, spec.workdir.as_ref())
+            .await?;
+        run_metrics_hook(&wd, &spec.env, hook).await
+    }
+
+    /// Store the values of a `metrics` hook run in the unit's run state, from
+    /// where heartbeats pick them up, and report them to the server when they
+    /// changed. A failed run clears the old values. Runs on the observe loop,
+    /// which owns the run state files.
+    async fn store_unit_metrics(&self, run: &UnitMetricsRun) -> Result<()> {
+        let spec = &run.spec;
+        let wd = self
+            .resolve_workdir_for(&spec.id, spec.workdir.as_ref())
+            .await?;
+        let mut st = LocalRunState::load(&wd)?;
+        let (metrics, metrics_at_ms) = match &run.values {
+            Ok(values) => (values.clone(), Some(now_ms_u64())),
+            Err(_) => (BTreeMap::new(), None),
+        };
+        let changed = st.metrics != metrics;
+        st.metrics = metrics;
+        st.metrics_at_ms = metrics_at_ms;
+        LocalRunState::save(&wd, &st)?;
+
+        if changed && !st.metrics.is_empty() {
+            let _ = enqueue_event(
+                DeployReportKind::RunState(RunState {
+                    run_id: spec.id.clone(),
+                    revision_id: run.revision_id.clone(),
+                    healthy: None,
+                    alive: None,
+                    report_time: now_ms_u64(),
+                    log_tail: None,
+                    metrics: st.metrics,
+                }),
+                Some(self.root_dir.clone()),
+            )
+            .await;
+        }
+        match &run.values {
+            Ok(_) => Ok(()),
+            Err(e) => Err(anyhow!("{e:#}")),
+        }
+    }
+
     async fn run_observe(
         &self,
         kind: ObserveKind,
@@ -1867,12 +2046,20 @@ impl DeploymentManager {
         id: &str,
         workdir: Option<&m87_shared::deploy_spec::Workdir>,
     ) -> Result<PathBuf> {
+        Ok(Self::workspace_path(&self.root_dir, id, workdir))
+    }
+
+    fn workspace_path(
+        root_dir: &Path,
+        id: &str,
+        workdir: Option<&m87_shared::deploy_spec::Workdir>,
+    ) -> PathBuf {
         if let Some(wd) = workdir {
             if let Some(path) = &wd.path {
-                return Ok(PathBuf::from(path));
+                return PathBuf::from(path);
             }
         }
-        Ok(self.root_dir.join("workspaces").join(id))
+        root_dir.join("workspaces").join(id)
     }
 
     pub(crate) async fn resolve_workdir_for(
@@ -2101,6 +2288,7 @@ mod tests {
                     report_timeout: None,
                     fails_after: None,
                 }),
+                metrics: None,
             }),
             stop: None,
             reboot: RebootMode::None,
@@ -325,6 +325,7 @@ mod tests {
                 alive: None,
                 report_time: ts,
                 log_tail: if healthy { None } else { Some("curl: connection refused".into()) },
+                metrics: Default::default(),
             }),
             expires_at: None,
             created_at: ts,
@@ -1,7 +1,8 @@
 use std::sync::Arc;
 
 use crate::devices;
-use crate::streams::quic::connect_quic_only;
+use crate::streams::quic::connect_preferring_direct;
+use crate::streams::p2p::ConnectionPath;
 use crate::streams::quic::open_quic_stream;
 use crate::streams::stream_type::{ForwardTarget, SocketTarget, TcpTarget, UdpTarget};
 use crate::util::shutdown::SHUTDOWN;
@@ -28,6 +29,22 @@ pub async fn open_local_forward(device_name: &str, forward_specs: Vec<String>) -
     Ok(())
 }
 
+/// Which path (direct or relay) a data connection to the device gets right now.
+pub async fn probe_connection_path(device_name: &str) -> Result<ConnectionPath> {
+    let config = Config::load()?;
+    let resolved = devices::resolve_device_cached(device_name).await?;
+    let token = AuthManager::get_cli_token().await?;
+    let (_endpoint, conn, path) = connect_preferring_direct(
+        &resolved.host,
+        &token,
+        &resolved.short_id,
+        config.trust_invalid_server_cert,
+    )
+    .await?;
+    conn.close(0u32.into(), b"probe");
+    Ok(path)
+}
+
 /// Non-blocking variant: spawns forwards and returns parsed targets immediately.
 /// Caller controls lifetime via the `cancel` token.
 pub async fn start_forward(
@@ -124,8 +141,10 @@ async fn forward_device_port_tcp(
     let remote_host = forward_spec.remote_host.clone();
 
     debug!("Connecting to QUIC server...");
-    let (_endpoint, conn) =
-        connect_quic_only(host_name, token, device_short_id, trust_invalid_server_cert).await?;
+    let (_endpoint, conn, path) =
+        connect_preferring_direct(host_name, token, device_short_id, trust_invalid_server_cert)
+            .await?;
+    info!("Connected to {device_short_id} via {path}");
     debug!("QUIC connection established, entering accept loop");
 
     println!(
@@ -179,8 +198,10 @@ async fn forward_device_port_udp(
     trust_invalid_server_cert: bool,
     cancel: CancellationToken,
 ) -> Result<()> {
-    let (_endpoint, conn) =
-        connect_quic_only(host_name, token, device_short_id, trust_invalid_server_cert).await?;
+    let (_endpoint, conn, path) =
+        connect_preferring_direct(host_name, token, device_short_id, trust_invalid_server_cert)
+            .await?;
+    info!("Connected to {device_short_id} via {path}");
 
     // Send StreamType::Forward over a QUIC stream
     let stream_type = forward_spec.to_stream_type(token);
@@ -330,8 +351,10 @@ async fn forward_device_socket(
         local_path, device_short_id, target.remote_path
     );
 
-    let (_endpoint, conn) =
-        connect_quic_only(host_name, token, device_short_id, trust_invalid_server_cert).await?;
+    let (_endpoint, conn, path) =
+        connect_preferring_direct(host_name, token, device_short_id, trust_invalid_server_cert)
+            .await?;
+    info!("Connected to {device_short_id} via {path}");
 
     loop {
         tokio::select! {
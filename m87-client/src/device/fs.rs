@@ -1,3 +1,4 @@
+use std::borrow::Cow;
 use std::collections::{HashMap, HashSet};
 use std::future::Future;
 use std::path::{Path, PathBuf};
@@ -6,17 +7,24 @@ use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use anyhow::{Context, Result, bail};
 use filetime::{FileTime, set_file_times};
+use ignore::Match;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
 use russh::keys::ssh_key;
 use russh_sftp::client::fs::{DirEntry, Metadata};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::Notify;
 use tokio::time::sleep;
-use tracing::error;
+use tracing::{debug, error, warn};
 
 use russh::client::{Config as ClientConfig, Handler};
 use russh_sftp::client::SftpSession;
+use russh_sftp::client::error::Error as SftpError;
+use russh_sftp::protocol::StatusCode;
 
+use crate::device::progress::TransferProgress;
 use crate::devices;
-use crate::streams::quic::open_quic_io;
+use crate::streams::compress::{Counted, STREAM_STATS, compression_enabled};
+use crate::streams::quic::{connect_preferring_direct, open_quic_stream};
 use crate::streams::stream_type::StreamType;
 use crate::util::shutdown::SHUTDOWN;
 use crate::{auth::AuthManager, config::Config};
@@ -60,15 +68,16 @@ pub async fn open_sftp_session(device_name: &str) -> anyhow::Result<SftpSession>
     let stream_type = StreamType::Ssh {
         token: token.to_string(),
     };
-    let (_, io) = open_quic_io(
+    let (_endpoint, conn, path) = connect_preferring_direct(
         &resolved.host,
         &token,
         &resolved.short_id,
-        stream_type,
         cfg.trust_invalid_server_cert,
     )
     .await
-    .context("Failed to connect to RAW metrics stream")?;
+    .context("Failed to connect to device")?;
+    debug!("sftp session to {} via {path}", resolved.short_id);
+    let io = open_quic_stream(&conn, stream_type).await?;
 
     // minimal ssh client config
     let mut config = ClientConfig::default();
@@ -77,10 +86,22 @@ pub async fn open_sftp_session(device_name: &str) -> anyhow::Result<SftpSession>
     config.window_size = 4 * 1024 * 1024; // OK > 1MB
     config.channel_buffer_size = 4 * 1024 * 1024; // OK > 1MB
     config.maximum_packet_size = 65535; // MUST stay <= 65535
+    // SSH traffic is encrypted, so stream-level compression can't shrink it;
+    // compress inside SSH instead (the device's russh server supports zlib).
+    config.preferred.compression = if compression_enabled() {
+        Cow::Borrowed(&[
+            russh::compression::ZLIB_LEGACY,
+            russh::compression::ZLIB,
+            russh::compression::NONE,
+        ])
+    } else {
+        Cow::Borrowed(&[russh::compression::NONE])
+    };
     let config = Arc::new(config);
     let sh = DummyHandler {};
 
     // connect SSH over the raw IO
+    let io = Counted::new(io, &STREAM_STATS.sftp_wire);
     let mut session = russh::client::connect_stream(config, io, sh).await?;
 
     // authenticate with "none" (your SSH server already trusts RBAC via tunnel)
@@ -88,7 +109,8 @@ pub async fn open_sftp_session(device_name: &str) -> anyhow::Result<SftpSession>
 
     let channel = session.channel_open_session().await.unwrap();
     channel.request_subsystem(true, "sftp").await.unwrap();
-    let sftp = SftpSession::new(channel.into_stream()).await.unwrap();
+    let stream = Counted::new(channel.into_stream(), &STREAM_STATS.sftp_payload);
+    let sftp = SftpSession::new(stream).await.unwrap();
     Ok(sftp)
 }
 
@@ -121,14 +143,33 @@ pub async fn list(path: &str) -> Result<Vec<DirEntry>> {
     Ok(files)
 }
 
-pub async fn copy(src: &str, dst: &str) -> Result<()> {
+pub async fn copy(src: &str, dst: &str, progress: &TransferProgress) -> Result<()> {
     let src_path = LocalOrRemotePath::parse(src);
     let dst_path = LocalOrRemotePath::parse(dst);
 
     let mut sftp_src = maybe_open_sftp(&src_path).await?;
     let mut sftp_dst = maybe_open_sftp(&dst_path).await?;
 
-    copy_file(&src_path, &dst_path, &mut sftp_src, &mut sftp_dst).await
+    progress.start_file(src, file_size(&src_path, &sftp_src).await);
+    copy_file(&src_path, &dst_path, &mut sftp_src, &mut sftp_dst, progress).await?;
+    progress.finish_file();
+    progress.finish();
+    Ok(())
+}
+
+/// Best-effort size of a single file (0 if it can't be determined).
+async fn file_size(path: &LocalOrRemotePath, sftp: &Option<SftpSession>) -> u64 {
+    match (path, sftp) {
+        (LocalOrRemotePath::Local(p), _) => {
+            tokio::fs::metadata(p).await.map(|m| m.len()).unwrap_or(0)
+        }
+        (LocalOrRemotePath::Remote { path, .. }, Some(sftp)) => sftp
+            .metadata(path.clone())
+            .await
+            .map(|m| m.len())
+            .unwrap_or(0),
+        (LocalOrRemotePath::Remote { .. }, None) => 0,
+    }
 }
 
 async fn copy_file(
@@ -136,6 +177,7 @@ async fn copy_file(
     dst: &LocalOrRemotePath,
     sftp_src: &mut Option<SftpSession>,
     sftp_dst: &mut Option<SftpSession>,
+    progress: &TransferProgress,
 ) -> Result<()> {
     match (src, dst) {
         (LocalOrRemotePath::Local(src), LocalOrRemotePath::Remote { path: dst, .. }) => {
@@ -158,7 +200,7 @@ async fn copy_file(
 
             let mut remote_file = sftp.create(dst.clone()).await?;
 
-            copy_chunked(&mut local_file, &mut remote_file).await?;
+            copy_chunked(&mut local_file, &mut remote_file, progress).await?;
             sync_remote_mtime(sftp, dst, mtime).await;
         }
 
@@ -175,7 +217,7 @@ async fn copy_file(
                 .await
                 .with_context(|| format!("create local file {dst:?}"))?;
 
-            copy_chunked(&mut remote_file, &mut local_file).await?;
+            copy_chunked(&mut remote_file, &mut local_file, progress).await?;
             sync_local_mtime(dst, &remote_meta).await;
         }
 
@@ -195,7 +237,7 @@ async fn copy_file(
             }
 
             let mut to_file = to.create(dst.clone()).await?;
-            copy_chunked(&mut from_file, &mut to_file).await?;
+            copy_chunked(&mut from_file, &mut to_file, progress).await?;
             sync_remote_mtime(to, dst, mtime).await;
         }
 
@@ -206,7 +248,7 @@ async fn copy_file(
 
             let mut src_file = tokio::fs::File::open(src).await?;
             let mut dst_file = tokio::fs::File::create(dst).await?;
-            copy_chunked(&mut src_file, &mut dst_file).await?;
+            copy_chunked(&mut src_file, &mut dst_file, progress).await?;
         }
     }
 
@@ -248,8 +290,21 @@ async fn maybe_open_sftp(p: &LocalOrRemotePath) -> Result<Option<SftpSession>> {
 
 #[derive(Debug, Clone)]
 struct FileInfo {
+    size: u64,
     /// Cheap fingerprint: "<size>:<mtime_secs>".
     fingerprint: String,
+    /// Modification time in unix seconds (0 if unknown).
+    mtime: u64,
+}
+
+impl FileInfo {
+    fn new(size: u64, mtime: Option<SystemTime>) -> Self {
+        Self {
+            size,
+            fingerprint: fingerprint(size, mtime),
+            mtime: mtime_secs(mtime),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -259,12 +314,15 @@ struct FileTree {
     files: HashMap<String, FileInfo>,
 }
 
-fn fingerprint(size: u64, mtime: Option<SystemTime>) -> String {
-    let secs = mtime
+fn mtime_secs(mtime: Option<SystemTime>) -> u64 {
+    mtime
         .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
         .map(|d| d.as_secs())
-        .unwrap_or(0);
-    format!("{size}:{secs}")
+        .unwrap_or(0)
+}
+
+fn fingerprint(size: u64, mtime: Option<SystemTime>) -> String {
+    format!("{size}:{}", mtime_secs(mtime))
 }
 
 /// Check if a relative path matches any exclusion pattern.
@@ -308,15 +366,125 @@ fn matches_exclude(rel_path: &str, excludes: &[String]) -> bool {
     false
 }
 
-async fn read_local_tree(root: &Path, excludes: &[String]) -> Result<FileTree> {
+/// Per-directory ignore files, read hierarchically while walking a tree.
+/// `.m87ignore` is applied after `.gitignore`, so it can override it.
+const IGNORE_FILES: [&str; 2] = [".gitignore", ".m87ignore"];
+
+/// Which paths sync and watch skip: `-e` patterns plus, unless disabled, the
+/// `.gitignore` / `.m87ignore` files found in the tree being read.
+#[derive(Debug, Clone)]
+pub struct SyncFilter {
+    pub excludes: Vec<String>,
+    pub ignore_files: bool,
+}
+
+impl Default for SyncFilter {
+    fn default() -> Self {
+        Self {
+            excludes: Vec::new(),
+            ignore_files: true,
+        }
+    }
+}
+
+impl SyncFilter {
+    pub fn new(excludes: Vec<String>, ignore_files: bool) -> Self {
+        Self {
+            excludes,
+            ignore_files,
+        }
+    }
+
+    fn skips(&self, rel: &str, is_dir: bool, ignores: &IgnoreChain) -> bool {
+        if matches_exclude(rel, &self.excludes) {
+            return true;
+        }
+        if !self.ignore_files {
+            return false;
+        }
+        // git never tracks its own directory, so neither do we
+        if is_dir && Path::new(rel).file_name().is_some_and(|n| n == ".git") {
+            return true;
+        }
+        ignores.is_ignored(rel, is_dir)
+    }
+}
+
+/// The ignore matchers of a directory and all of its ancestors, outermost first.
+#[derive(Clone, Default)]
+struct IgnoreChain(Vec<Arc<Gitignore>>);
+
+impl IgnoreChain {
+    /// Extend the chain with the ignore file contents found in `dir_rel`.
+    fn with_dir(&self, dir_rel: &str, sources: &[String]) -> Self {
+        if sources.is_empty() {
+            return self.clone();
+        }
+
+        let mut builder = GitignoreBuilder::new(Path::new("/").join(dir_rel));
+        for source in sources {
+            for line in source.lines() {
+                if let Err(e) = builder.add_line(None, line) {
+                    warn!("Ignoring invalid pattern in {}: {}", dir_rel, e);
+                }
+            }
+        }
+
+        let mut chain = self.clone();
+        match builder.build() {
+            Ok(gi) => chain.0.push(Arc::new(gi)),
+            Err(e) => warn!("Failed to build ignore rules for {}: {}", dir_rel, e),
+        }
+        chain
+    }
+
+    /// The deepest ignore file with a matching rule decides, like git.
+    fn is_ignored(&self, rel: &str, is_dir: bool) -> bool {
+        let path = Path::new("/").join(rel);
+        for gi in self.0.iter().rev() {
+            // a matcher treats paths outside its directory as relative to it
+            if !path.starts_with(gi.path()) {
+                continue;
+            }
+            match gi.matched(&path, is_dir) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => {}
+            }
+        }
+        false
+    }
+}
+
+async fn read_local_tree(root: &Path, filter: &SyncFilter) -> Result<FileTree> {
     let root = root.to_path_buf();
     let mut files = HashMap::new();
-    let mut stack = vec![root.clone()];
+    let mut stack = vec![(root.clone(), IgnoreChain::default())];
 
-    while let Some(dir) = stack.pop() {
+    while let Some((dir, parent_ignores)) = stack.pop() {
+        let mut entries = Vec::new();
         let mut rd = tokio::fs::read_dir(&dir).await?;
-
         while let Some(entry) = rd.next_entry().await? {
+            entries.push(entry);
+        }
+
+        let dir_rel = dir.strip_prefix(&root).unwrap().to_string_lossy().to_string();
+        let ignores = if filter.ignore_files {
+            let mut sources = Vec::new();
+            for name in IGNORE_FILES {
+                if entries.iter().any(|e| e.file_name() == name) {
+                    match tokio::fs::read_to_string(dir.join(name)).await {
+                        Ok(s) => sources.push(s),
+                        Err(e) => warn!("Failed to read {}: {}", dir.join(name).display(), e),
+                    }
+                }
+            }
+            parent_ignores.with_dir(&dir_rel, &sources)
+        } else {
+            parent_ignores
+        };
+
+        for entry in entries {
             let path = entry.path();
             let rel = path
                 .strip_prefix(&root)
@@ -324,17 +492,17 @@ async fn read_local_tree(root: &Path, excludes: &[String]) -> Result<FileTree> {
                 .to_string_lossy()
                 .to_string();
 
+            let meta = entry.metadata().await?;
+
             // Skip excluded paths
-            if matches_exclude(&rel, excludes) {
+            if filter.skips(&rel, meta.is_dir(), &ignores) {
                 continue;
             }
 
-            let meta = entry.metadata().await?;
             if meta.is_dir() {
-                stack.push(path);
+                stack.push((path, ignores.clone()));
             } else if meta.is_file() {
-                let fp = fingerprint(meta.len(), meta.modified().ok());
-                files.insert(rel, FileInfo { fingerprint: fp });
+                files.insert(rel, FileInfo::new(meta.len(), meta.modified().ok()));
             }
         }
     }
@@ -342,64 +510,71 @@ async fn read_local_tree(root: &Path, excludes: &[String]) -> Result<FileTree> {
     Ok(FileTree { root, files })
 }
 
-async fn read_remote_tree(sftp: &SftpSession, root: &str, excludes: &[String]) -> Result<FileTree> {
+async fn read_remote_tree(sftp: &SftpSession, root: &str, filter: &SyncFilter) -> Result<FileTree> {
     let mut files = HashMap::new();
     let root_path = PathBuf::from(root);
 
-    // Stack holds (base, rel)
-    let mut stack = vec![(root.to_string(), "".to_string())];
-
-    while let Some((base, rel)) = stack.pop() {
-        // Skip excluded paths
-        if !rel.is_empty() && matches_exclude(&rel, excludes) {
-            continue;
-        }
+    // Stack holds (rel, ignore rules inherited from parent directories)
+    let mut stack = vec![("".to_string(), IgnoreChain::default())];
 
+    while let Some((rel, parent_ignores)) = stack.pop() {
         // Construct full path
         let path = if rel.is_empty() {
-            base.clone()
+            root.to_string()
         } else {
-            format!("{base}/{rel}")
+            format!("{root}/{rel}")
         };
 
         // Try reading as directory
-        let mut dir = match sftp.read_dir(path.clone()).await {
+        let dir = match sftp.read_dir(path.clone()).await {
             Ok(d) => d,
             Err(_) => {
                 // Not a directory → treat as file
                 let meta = sftp.metadata(path.clone()).await?;
                 if !meta.is_dir() {
-                    let fp = fingerprint(meta.len(), meta.modified().ok());
-                    files.insert(rel.clone(), FileInfo { fingerprint: fp });
+                    files.insert(rel.clone(), FileInfo::new(meta.len(), meta.modified().ok()));
                 }
                 continue;
             }
         };
-
-        while let Some(entry) = dir.next() {
-            let name = entry.file_name();
-            if name == "." || name == ".." {
-                continue;
+        let entries: Vec<_> = dir
+            .filter(|e| e.file_name() != "." && e.file_name() != "..")
+            .collect();
+
+        let ignores = if filter.ignore_files {
+            let mut sources = Vec::new();
+            for name in IGNORE_FILES {
+                if entries.iter().any(|e| e.file_name() == name) {
+                    match sftp.read(format!("{path}/{name}")).await {
+                        Ok(data) => sources.push(String::from_utf8_lossy(&data).into_owned()),
+                        Err(e) => warn!("Failed to read {}/{}: {}", path, name, e),
+                    }
+                }
             }
+            parent_ignores.with_dir(&rel, &sources)
+        } else {
+            parent_ignores
+        };
 
+        for entry in entries {
+            let name = entry.file_name();
             let child_rel = if rel.is_empty() {
                 name.clone()
             } else {
                 format!("{rel}/{name}")
             };
 
+            let meta = entry.metadata();
+
             // Skip excluded paths
-            if matches_exclude(&child_rel, excludes) {
+            if filter.skips(&child_rel, meta.is_dir(), &ignores) {
                 continue;
             }
 
-            let meta = entry.metadata();
-
             if meta.is_dir() {
-                stack.push((base.clone(), child_rel));
+                stack.push((child_rel, ignores.clone()));
             } else {
-                let fp = fingerprint(meta.len(), meta.modified().ok());
-                files.insert(child_rel, FileInfo { fingerprint: fp });
+                files.insert(child_rel, FileInfo::new(meta.len(), meta.modified().ok()));
             }
         }
     }
@@ -410,13 +585,93 @@ async fn read_remote_tree(sftp: &SftpSession, root: &str, excludes: &[String]) -
     })
 }
 
+/// Read the tree at `path`, or `None` if its root doesn't exist. Any other
+/// error (permission denied, a dropped SFTP channel, a timeout) is returned,
+/// so it can't be mistaken for an empty tree.
+async fn read_existing_tree(
+    path: &LocalOrRemotePath,
+    sftp: &Option<SftpSession>,
+    filter: &SyncFilter,
+) -> Result<Option<FileTree>> {
+    match path {
+        LocalOrRemotePath::Local(p) => match tokio::fs::metadata(p).await {
+            Ok(_) => Ok(Some(read_local_tree(p, filter).await?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("read {}", p.display())),
+        },
+        LocalOrRemotePath::Remote { path, .. } => {
+            let sftp = sftp
+                .as_ref()
+                .context("SFTP session required for remote sync")?;
+            match sftp.metadata(path.clone()).await {
+                Ok(_) => Ok(Some(read_remote_tree(sftp, path, filter).await?)),
+                Err(SftpError::Status(status)) if status.status_code == StatusCode::NoSuchFile => {
+                    Ok(None)
+                }
+                Err(e) => Err(e).with_context(|| format!("read {path}")),
+            }
+        }
+    }
+}
+
+/// Create the missing root `path` (unless `dry_run`) and return it as an
+/// empty tree.
+async fn create_root(
+    path: &LocalOrRemotePath,
+    sftp: &Option<SftpSession>,
+    dry_run: bool,
+) -> Result<FileTree> {
+    let root = match path {
+        LocalOrRemotePath::Local(p) => {
+            if !dry_run {
+                tokio::fs::create_dir_all(p)
+                    .await
+                    .with_context(|| format!("create {}", p.display()))?;
+            }
+            p.clone()
+        }
+        LocalOrRemotePath::Remote { path, .. } => {
+            let sftp = sftp
+                .as_ref()
+                .context("SFTP session required for remote sync")?;
+            if !dry_run {
+                sftp.create_dir(path.clone())
+                    .await
+                    .with_context(|| format!("create {path}"))?;
+            }
+            PathBuf::from(path)
+        }
+    };
+    Ok(FileTree {
+        root,
+        files: HashMap::new(),
+    })
+}
+
+/// Read the tree at `path`, creating the root (and treating it as empty) if it
+/// does not exist yet. Used for sync destinations.
+async fn read_tree_or_create(
+    path: &LocalOrRemotePath,
+    sftp: &Option<SftpSession>,
+    filter: &SyncFilter,
+    dry_run: bool,
+) -> Result<FileTree> {
+    match read_existing_tree(path, sftp, filter).await? {
+        Some(tree) => Ok(tree),
+        None => create_root(path, sftp, dry_run).await,
+    }
+}
+
+/// One-way sync from `src` to `dst`. Returns the number of files uploaded or
+/// deleted (or that would be, on a dry run).
 pub async fn sync(
     src: &str,
     dst: &str,
     delete: bool,
     dry_run: bool,
-    excludes: &[String],
-) -> Result<()> {
+    filter: &SyncFilter,
+    progress: &TransferProgress,
+) -> Result<usize> {
     let src_path = LocalOrRemotePath::parse(src);
     let dst_path = LocalOrRemotePath::parse(dst);
 
@@ -425,113 +680,519 @@ pub async fn sync(
     tracing::info!("Connected");
 
     let src_tree = match &src_path {
-        LocalOrRemotePath::Local(p) => read_local_tree(p, excludes).await?,
+        LocalOrRemotePath::Local(p) => read_local_tree(p, filter).await?,
         LocalOrRemotePath::Remote { path, .. } => {
             let sftp = sftp_src
                 .as_ref()
                 .context("SFTP src required for remote sync")?;
-            read_remote_tree(sftp, path, excludes).await?
+            read_remote_tree(sftp, path, filter).await?
         }
     };
 
-    let dst_tree = match &dst_path {
-        LocalOrRemotePath::Local(p) => {
-            if !p.exists() {
-                if !dry_run {
-                    tokio::fs::create_dir_all(p).await?;
-                }
-                FileTree {
-                    root: p.clone(),
-                    files: HashMap::new(),
-                }
-            } else {
-                read_local_tree(p, excludes).await?
-            }
+    let dst_tree = read_tree_or_create(&dst_path, &sftp_dst, filter, dry_run).await?;
+
+    // Missing/changed on dst
+    let uploads: Vec<(&String, &FileInfo)> = src_tree
+        .files
+        .iter()
+        .filter(|(rel, src_info)| {
+            dst_tree
+                .files
+                .get(*rel)
+                .is_none_or(|dst_info| dst_info.fingerprint != src_info.fingerprint)
+        })
+        .collect();
+
+    // Extra files on dst
+    let deletes: Vec<&String> = if delete {
+        dst_tree
+            .files
+            .keys()
+            .filter(|rel| !src_tree.files.contains_key(*rel))
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    if !dry_run && !uploads.is_empty() {
+        progress.begin(uploads.len(), uploads.iter().map(|(_, info)| info.size).sum());
+    }
+
+    for (rel, src_info) in &uploads {
+        if dry_run {
+            progress.message(format!("[dry-run] would upload {}", rel));
+            continue;
         }
-        LocalOrRemotePath::Remote { path, .. } => {
-            let sftp = sftp_dst
-                .as_ref()
-                .context("SFTP dst required for remote sync")?;
-            match read_remote_tree(sftp, path, excludes).await {
-                Ok(tree) => tree,
-                Err(_) => {
-                    // Destination doesn't exist - create it and treat as empty
-                    if !dry_run {
-                        sftp.create_dir(path.clone()).await.ok();
-                    }
-                    FileTree {
-                        root: PathBuf::from(path),
-                        files: HashMap::new(),
-                    }
-                }
-            }
+        progress.message(format!("uploading {}", rel));
+        progress.start_file(rel, src_info.size);
+        copy_file(
+            &LocalOrRemotePath::from_path(&src_path, &src_tree.root.join(rel)),
+            &LocalOrRemotePath::from_path(&dst_path, &dst_tree.root.join(rel)),
+            &mut sftp_src,
+            &mut sftp_dst,
+            progress,
+        )
+        .await?;
+        progress.finish_file();
+    }
+
+    for rel in &deletes {
+        if dry_run {
+            progress.message(format!("[dry-run] would delete {}", rel));
+            continue;
         }
-    };
+        progress.message(format!("deleting {}", rel));
+        delete_file(
+            &LocalOrRemotePath::from_path(&dst_path, &dst_tree.root.join(rel)),
+            &mut sftp_dst,
+        )
+        .await?;
+    }
 
-    // Copy missing/changed
-    for (rel, src_info) in &src_tree.files {
-        match dst_tree.files.get(rel) {
-            Some(dst_info) if dst_info.fingerprint == src_info.fingerprint => {
-                // unchanged
-            }
-            _ => {
-                let src_full = src_tree.root.join(rel);
-                let dst_full = dst_tree.root.join(rel);
+    progress.finish();
+    Ok(uploads.len() + deletes.len())
+}
 
-                if dry_run {
-                    println!("[dry-run] would upload {}", rel);
-                } else {
-                    println!("uploading {}", rel);
-                    copy_file(
-                        &LocalOrRemotePath::from_path(&src_path, &src_full),
-                        &LocalOrRemotePath::from_path(&dst_path, &dst_full),
-                        &mut sftp_src,
-                        &mut sftp_dst,
-                    )
-                    .await?;
+// ---------------------------------------------------------------------------
+// Bidirectional sync
+// ---------------------------------------------------------------------------
+
+/// How to resolve a file that changed on both sides since the last sync.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConflictPolicy {
+    /// Keep the version with the newer mtime (a modification beats a deletion)
+    Newer,
+    /// Keep the source version and store the destination version next to it
+    /// as `<file>.conflict-<side>`
+    KeepBoth,
+    /// Report conflicts and exit without changing anything
+    #[default]
+    Abort,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SyncSide {
+    Source,
+    Dest,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum SyncAction {
+    /// Copy `rel` from `from` to the other side, stored there as `to_rel`.
+    Copy {
+        from: SyncSide,
+        rel: String,
+        to_rel: String,
+    },
+    /// Delete `rel` on `side`.
+    Delete { side: SyncSide, rel: String },
+}
+
+#[derive(Debug)]
+struct SyncConflict {
+    rel: String,
+    source: Option<FileInfo>,
+    dest: Option<FileInfo>,
+}
+
+impl SyncConflict {
+    fn describe(&self) -> &'static str {
+        match (&self.source, &self.dest) {
+            (Some(_), Some(_)) => "modified on both sides",
+            (Some(_), None) => "modified on source, deleted on destination",
+            (None, Some(_)) => "deleted on source, modified on destination",
+            (None, None) => "deleted on both sides",
+        }
+    }
+}
+
+/// Fingerprints of both sides at the end of the last successful bidirectional
+/// sync. Stored per (source, dest) pair in the cache dir, so the working trees
+/// stay free of bookkeeping files.
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+struct SyncState {
+    /// Relative path -> (source fingerprint, dest fingerprint)
+    files: HashMap<String, (String, String)>,
+}
+
+impl SyncState {
+    fn path(src: &LocalOrRemotePath, dst: &LocalOrRemotePath) -> Result<PathBuf> {
+        use sha1::{Digest, Sha1};
+
+        let mut hasher = Sha1::new();
+        hasher.update(state_key(src).as_bytes());
+        hasher.update(b"\n");
+        hasher.update(state_key(dst).as_bytes());
+        let name: String = hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+
+        let mut base = dirs::cache_dir().context("Could not determine cache directory")?;
+        base.push("m87");
+        base.push("sync-state");
+        base.push(format!("{name}.json"));
+        Ok(base)
+    }
+
+    fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let data = std::fs::read(path)?;
+        serde_json::from_slice(&data)
+            .with_context(|| format!("corrupt sync state {}", path.display()))
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec(self)?)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Record every file present on both sides. Files that exist on one side
+    /// only were not synced (e.g. dry-run or a failed copy) and are left out,
+    /// so they are treated as new next time.
+    fn from_trees(a: &FileTree, b: &FileTree) -> Self {
+        let files = a
+            .files
+            .iter()
+            .filter_map(|(rel, ai)| {
+                b.files
+                    .get(rel)
+                    .map(|bi| (rel.clone(), (ai.fingerprint.clone(), bi.fingerprint.clone())))
+            })
+            .collect();
+        Self { files }
+    }
+}
+
+fn state_key(p: &LocalOrRemotePath) -> String {
+    match p {
+        LocalOrRemotePath::Local(p) => std::fs::canonicalize(p)
+            .unwrap_or_else(|_| p.clone())
+            .to_string_lossy()
+            .into_owned(),
+        LocalOrRemotePath::Remote { device, path } => format!("{device}:{path}"),
+    }
+}
+
+fn side_label(p: &LocalOrRemotePath) -> String {
+    match p {
+        LocalOrRemotePath::Local(_) => "local".to_string(),
+        LocalOrRemotePath::Remote { device, .. } => device.clone(),
+    }
+}
+
+/// A root that is gone after a previous sync recorded files under it would
+/// plan the deletion of every file on the other side, so it is refused. That
+/// is almost always an unmounted disk or a mistyped path rather than an
+/// intended wipe.
+fn check_missing_roots(
+    state: &SyncState,
+    roots: [(&str, bool); 2],
+    state_path: &Path,
+) -> Result<()> {
+    if state.files.is_empty() {
+        return Ok(());
+    }
+    if let Some((root, _)) = roots.iter().find(|(_, missing)| *missing) {
+        bail!(
+            "{root} does not exist, but the last sync recorded {} file(s) there; \
+             refusing to delete them on the other side. Restore it, or remove {} \
+             to sync from scratch",
+            state.files.len(),
+            state_path.display()
+        );
+    }
+    Ok(())
+}
+
+/// Decide what to do for every path, given both current trees and the state
+/// of the last sync. Pure so it can be tested without any I/O.
+fn plan_bidirectional(
+    src: &FileTree,
+    dst: &FileTree,
+    state: &SyncState,
+    policy: ConflictPolicy,
+    dst_label: &str,
+) -> (Vec<SyncAction>, Vec<SyncConflict>) {
+    let mut paths: Vec<&String> = src
+        .files
+        .keys()
+        .chain(dst.files.keys())
+        .chain(state.files.keys())
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    paths.sort();
+
+    let mut actions = Vec::new();
+    let mut conflicts = Vec::new();
+
+    for rel in paths {
+        let s = src.files.get(rel);
+        let d = dst.files.get(rel);
+        let prev = state.files.get(rel);
+
+        let s_changed = s.map(|i| &i.fingerprint) != prev.map(|(fs, _)| fs);
+        let d_changed = d.map(|i| &i.fingerprint) != prev.map(|(_, fd)| fd);
+
+        let copy = |from| SyncAction::Copy {
+            from,
+            rel: rel.clone(),
+            to_rel: rel.clone(),
+        };
+        let delete = |side| SyncAction::Delete {
+            side,
+            rel: rel.clone(),
+        };
+
+        match (s_changed, d_changed) {
+            (false, false) => {}
+            (true, false) => match s {
+                Some(_) => actions.push(copy(SyncSide::Source)),
+                None if d.is_some() => actions.push(delete(SyncSide::Dest)),
+                None => {}
+            },
+            (false, true) => match d {
+                Some(_) => actions.push(copy(SyncSide::Dest)),
+                None if s.is_some() => actions.push(delete(SyncSide::Source)),
+                None => {}
+            },
+            (true, true) => {
+                // Same content on both sides (or gone on both) is convergence,
+                // not a conflict.
+                match (s, d) {
+                    (None, None) => continue,
+                    (Some(si), Some(di)) if si.fingerprint == di.fingerprint => continue,
+                    _ => {}
+                }
+
+                conflicts.push(SyncConflict {
+                    rel: rel.clone(),
+                    source: s.cloned(),
+                    dest: d.cloned(),
+                });
+
+                match policy {
+                    ConflictPolicy::Abort => {}
+                    ConflictPolicy::Newer => match (s, d) {
+                        (Some(si), Some(di)) if di.mtime > si.mtime => {
+                            actions.push(copy(SyncSide::Dest))
+                        }
+                        (Some(_), _) => actions.push(copy(SyncSide::Source)),
+                        (None, _) => actions.push(copy(SyncSide::Dest)),
+                    },
+                    ConflictPolicy::KeepBoth => match (s, d) {
+                        (Some(_), Some(_)) => {
+                            actions.push(SyncAction::Copy {
+                                from: SyncSide::Dest,
+                                rel: rel.clone(),
+                                to_rel: format!("{rel}.conflict-{dst_label}"),
+                            });
+                            actions.push(copy(SyncSide::Source));
+                        }
+                        // A deletion against a modification: restore the
+                        // modified file rather than lose it.
+                        (Some(_), None) => actions.push(copy(SyncSide::Source)),
+                        (None, _) => actions.push(copy(SyncSide::Dest)),
+                    },
                 }
             }
         }
     }
 
-    // Delete extra files on dst
-    if delete {
-        let src_keys: HashSet<_> = src_tree.files.keys().cloned().collect();
+    (actions, conflicts)
+}
+
+/// Two-way sync: propagate changes made on either side since the last run,
+/// including deletions, and resolve files changed on both sides according to
+/// `policy`. The first run has no state, so it only copies files missing on
+/// one side and treats differing files as conflicts. Returns the number of
+/// copies and deletions performed (or planned, on a dry run).
+pub async fn sync_bidirectional(
+    src: &str,
+    dst: &str,
+    policy: ConflictPolicy,
+    dry_run: bool,
+    filter: &SyncFilter,
+    progress: &TransferProgress,
+) -> Result<usize> {
+    let src_path = LocalOrRemotePath::parse(src);
+    let dst_path = LocalOrRemotePath::parse(dst);
+
+    let mut sftp_src = maybe_open_sftp(&src_path).await?;
+    let mut sftp_dst = maybe_open_sftp(&dst_path).await?;
+    tracing::info!("Connected");
+
+    let state_path = SyncState::path(&src_path, &dst_path)?;
+    let state = SyncState::load(&state_path)?;
+
+    let src_tree = read_existing_tree(&src_path, &sftp_src, filter).await?;
+    let dst_tree = read_existing_tree(&dst_path, &sftp_dst, filter).await?;
+    check_missing_roots(
+        &state,
+        [(src, src_tree.is_none()), (dst, dst_tree.is_none())],
+        &state_path,
+    )?;
+    let src_tree = match src_tree {
+        Some(tree) => tree,
+        None => create_root(&src_path, &sftp_src, dry_run).await?,
+    };
+    let dst_tree = match dst_tree {
+        Some(tree) => tree,
+        None => create_root(&dst_path, &sftp_dst, dry_run).await?,
+    };
 
-        for (rel, _) in &dst_tree.files {
-            if !src_keys.contains(rel) {
-                let dst_full = dst_tree.root.join(rel);
+    let (actions, conflicts) = plan_bidirectional(
+        &src_tree,
+        &dst_tree,
+        &state,
+        policy,
+        &side_label(&dst_path),
+    );
 
+    for c in &conflicts {
+        progress.message(format!("conflict: {} ({})", c.rel, c.describe()));
+    }
+    if policy == ConflictPolicy::Abort && !conflicts.is_empty() {
+        bail!(
+            "{} conflict(s); nothing was changed. Re-run with --on-conflict newer or keep-both",
+            conflicts.len()
+        );
+    }
+
+    let copies: Vec<(&str, u64)> = actions
+        .iter()
+        .filter_map(|action| match action {
+            SyncAction::Copy { from, rel, to_rel } => {
+                let tree = match from {
+                    SyncSide::Source => &src_tree,
+                    SyncSide::Dest => &dst_tree,
+                };
+                Some((to_rel.as_str(), tree.files.get(rel).map_or(0, |info| info.size)))
+            }
+            SyncAction::Delete { .. } => None,
+        })
+        .collect();
+    if !dry_run && !copies.is_empty() {
+        progress.begin(copies.len(), copies.iter().map(|(_, size)| size).sum());
+    }
+
+    for action in &actions {
+        match action {
+            SyncAction::Copy { from, rel, to_rel } => {
+                let (arrow, from_tree, from_path, to_tree, to_path) = match from {
+                    SyncSide::Source => ("->", &src_tree, &src_path, &dst_tree, &dst_path),
+                    SyncSide::Dest => ("<-", &dst_tree, &dst_path, &src_tree, &src_path),
+                };
                 if dry_run {
-                    println!("[dry-run] would delete {}", rel);
-                } else {
-                    println!("deleting {}", rel);
-                    delete_file(
-                        &LocalOrRemotePath::from_path(&dst_path, &dst_full),
-                        &mut sftp_dst,
-                    )
-                    .await?;
+                    progress.message(format!("[dry-run] would copy {arrow} {to_rel}"));
+                    continue;
+                }
+                progress.message(format!("copying {arrow} {to_rel}"));
+                progress.start_file(to_rel, from_tree.files.get(rel).map_or(0, |info| info.size));
+                let from_full = LocalOrRemotePath::from_path(from_path, &from_tree.root.join(rel));
+                let to_full = LocalOrRemotePath::from_path(to_path, &to_tree.root.join(to_rel));
+                match from {
+                    SyncSide::Source => {
+                        copy_file(&from_full, &to_full, &mut sftp_src, &mut sftp_dst, progress)
+                            .await?
+                    }
+                    SyncSide::Dest => {
+                        copy_file(&from_full, &to_full, &mut sftp_dst, &mut sftp_src, progress)
+                            .await?
+                    }
                 }
+                progress.finish_file();
+            }
+            SyncAction::Delete { side, rel } => {
+                let (arrow, tree, path, sftp) = match side {
+                    SyncSide::Source => ("<-", &src_tree, &src_path, &mut sftp_src),
+                    SyncSide::Dest => ("->", &dst_tree, &dst_path, &mut sftp_dst),
+                };
+                if dry_run {
+                    progress.message(format!("[dry-run] would delete {arrow} {rel}"));
+                    continue;
+                }
+                progress.message(format!("deleting {arrow} {rel}"));
+                delete_file(
+                    &LocalOrRemotePath::from_path(path, &tree.root.join(rel)),
+                    sftp,
+                )
+                .await?;
             }
         }
     }
 
-    Ok(())
+    progress.finish();
+    if dry_run {
+        return Ok(actions.len());
+    }
+
+    // Re-read both sides so the recorded fingerprints reflect what is on disk
+    // now (local copies don't preserve mtime, remote ones do).
+    let src_tree = read_tree_or_create(&src_path, &sftp_src, filter, false).await?;
+    let dst_tree = read_tree_or_create(&dst_path, &sftp_dst, filter, false).await?;
+    SyncState::from_trees(&src_tree, &dst_tree).save(&state_path)?;
+
+    Ok(actions.len())
 }
 
-pub async fn watch_sync(src: &str, dst: &str, delete: bool, excludes: &[String]) -> Result<()> {
+/// `bidirectional` switches each pass to a two-way sync with the given
+/// conflict policy; `None` keeps the one-way source -> destination sync.
+///
+/// `on_change` is a shell command run on the device (the remote side of the
+/// sync) once a batch of changes has settled, i.e. after the first pass that
+/// finds nothing new to sync. It runs on its own task so a slow command
+/// doesn't hold up the next pass; batches that settle while it runs queue a
+/// single rerun. Its output is streamed inline.
+pub async fn watch_sync(
+    src: &str,
+    dst: &str,
+    delete: bool,
+    filter: &SyncFilter,
+    bidirectional: Option<ConflictPolicy>,
+    on_change: Option<&str>,
+    progress: &TransferProgress,
+) -> Result<()> {
+    let on_change = match on_change {
+        Some(cmd) => Some(spawn_on_change(hook_device(src, dst)?, cmd.to_string())),
+        None => None,
+    };
+
     println!("Starting periodic watch-sync…");
 
+    let run = || async {
+        match bidirectional {
+            Some(policy) => sync_bidirectional(src, dst, policy, false, filter, progress).await,
+            None => sync(src, dst, delete, false, filter, progress).await,
+        }
+    };
+
     // Initial run (never dry-run for watch mode)
-    sync(src, dst, delete, false, excludes).await?;
+    let mut pending = run().await? > 0;
 
     let interval = Duration::from_secs(2);
 
     loop {
         tokio::select! {
             _ = sleep(interval) => {
-                if let Err(e) = sync(src, dst, delete, false, excludes).await {
-                    error!("sync failed: {e:#}");
+                match run().await {
+                    Ok(0) => {
+                        if let (true, Some(hook)) = (pending, &on_change) {
+                            pending = false;
+                            hook.notify_one();
+                        }
+                    }
+                    Ok(_) => pending = true,
+                    Err(e) => error!("sync failed: {e:#}"),
                 }
             }
 
@@ -543,6 +1204,41 @@ pub async fn watch_sync(src: &str, dst: &str, delete: bool, excludes: &[String])
     }
 }
 
+/// The device an `--on-change` command runs on: the destination if it is
+/// remote, otherwise the source.
+fn hook_device(src: &str, dst: &str) -> Result<String> {
+    match (LocalOrRemotePath::parse(dst), LocalOrRemotePath::parse(src)) {
+        (LocalOrRemotePath::Remote { device, .. }, _)
+        | (_, LocalOrRemotePath::Remote { device, .. }) => Ok(device),
+        _ => bail!("--on-change requires a remote source or destination"),
+    }
+}
+
+/// Run `cmd` on `device` each time the returned handle is notified, one run at
+/// a time. Notifications while a run is in flight coalesce into one rerun.
+fn spawn_on_change(device: String, cmd: String) -> Arc<Notify> {
+    let notify = Arc::new(Notify::new());
+    let trigger = notify.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                _ = trigger.notified() => run_on_change(&device, &cmd).await,
+                _ = SHUTDOWN.cancelled() => break,
+            }
+        }
+    });
+    notify
+}
+
+async fn run_on_change(device: &str, cmd: &str) {
+    println!("on-change [{device}]: {cmd}");
+    match crate::tui::exec::run_exec_streaming(device, cmd.to_string()).await {
+        Ok(0) => {}
+        Ok(code) => println!("on-change exited with code {code}"),
+        Err(e) => error!("on-change failed: {e:#}"),
+    }
+}
+
 async fn sync_remote_mtime(sftp: &SftpSession, remote_path: &str, src_mtime: u64) {
     let mut attrs = Metadata::default();
     attrs.mtime = Some(src_mtime as u32);
@@ -559,7 +1255,7 @@ async fn sync_local_mtime(local_path: &Path, remote_meta: &Metadata) {
     }
 }
 
-async fn copy_chunked<R, W>(mut reader: R, mut writer: W) -> Result<()>
+async fn copy_chunked<R, W>(mut reader: R, mut writer: W, progress: &TransferProgress) -> Result<()>
 where
     R: tokio::io::AsyncRead + Unpin,
     W: tokio::io::AsyncWrite + Unpin,
@@ -578,6 +1274,7 @@ where
                 }
 
                 writer.write_all(&buf[..n]).await?;
+                progress.advance(n as u64);
             }
         }
     }
@@ -747,4 +1444,246 @@ mod tests {
         assert!(matches_exclude(".git", &excludes));
         assert!(!matches_exclude("src/main.rs", &excludes));
     }
+
+    #[test]
+    fn test_hook_device_prefers_remote_destination() {
+        assert_eq!(hook_device("./src", "pi:/app").unwrap(), "pi");
+        assert_eq!(hook_device("pi:/app", "./out").unwrap(), "pi");
+        assert_eq!(hook_device("a:/x", "b:/y").unwrap(), "b");
+        assert!(hook_device("./a", "./b").is_err());
+    }
+
+    // --- ignore file tests ---
+
+    #[test]
+    fn test_ignore_chain_gitignore_semantics() {
+        let chain = IgnoreChain::default().with_dir(
+            "",
+            &["target/\n/build\n**/cache/*.tmp\n*.log\n!keep.log\n".to_string()],
+        );
+        assert!(chain.is_ignored("target", true));
+        assert!(!chain.is_ignored("target", false));
+        assert!(chain.is_ignored("build", true));
+        assert!(!chain.is_ignored("src/build", true));
+        assert!(chain.is_ignored("a/b/cache/x.tmp", false));
+        assert!(chain.is_ignored("app.log", false));
+        assert!(!chain.is_ignored("keep.log", false));
+        assert!(!chain.is_ignored("src/main.rs", false));
+    }
+
+    #[test]
+    fn test_ignore_chain_deeper_file_wins() {
+        let chain = IgnoreChain::default()
+            .with_dir("", &["*.bin\n".to_string()])
+            .with_dir("assets", &["!*.bin\n/local.txt\n".to_string()]);
+        assert!(chain.is_ignored("firmware.bin", false));
+        assert!(!chain.is_ignored("assets/logo.bin", false));
+        assert!(chain.is_ignored("assets/local.txt", false));
+        assert!(!chain.is_ignored("local.txt", false));
+    }
+
+    #[test]
+    fn test_ignore_chain_m87ignore_overrides_gitignore() {
+        let chain = IgnoreChain::default()
+            .with_dir("", &["dist/\n".to_string(), "!dist/\n".to_string()]);
+        assert!(!chain.is_ignored("dist", true));
+    }
+
+    #[tokio::test]
+    async fn test_read_local_tree_respects_ignore_files() {
+        let td = tempfile::TempDir::new().unwrap();
+        let root = td.path();
+        for (rel, content) in [
+            (".gitignore", "target/\n*.log\n"),
+            ("src/main.rs", ""),
+            ("src/.m87ignore", "!debug.log\n"),
+            ("src/debug.log", ""),
+            ("app.log", ""),
+            ("target/out", ""),
+            (".git/HEAD", ""),
+        ] {
+            let path = root.join(rel);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+
+        let tree = read_local_tree(root, &SyncFilter::default()).await.unwrap();
+        let mut rels: Vec<_> = tree.files.keys().cloned().collect();
+        rels.sort();
+        assert_eq!(
+            rels,
+            vec![".gitignore", "src/.m87ignore", "src/debug.log", "src/main.rs"]
+        );
+
+        let tree = read_local_tree(root, &SyncFilter::new(vec![], false))
+            .await
+            .unwrap();
+        assert_eq!(tree.files.len(), 7);
+    }
+
+    // --- plan_bidirectional tests ---
+
+    fn tree(files: &[(&str, u64, u64)]) -> FileTree {
+        FileTree {
+            root: PathBuf::from("/root"),
+            files: files
+                .iter()
+                .map(|(rel, size, mtime)| {
+                    let t = SystemTime::UNIX_EPOCH + Duration::from_secs(*mtime);
+                    (rel.to_string(), FileInfo::new(*size, Some(t)))
+                })
+                .collect(),
+        }
+    }
+
+    fn plan(
+        src: &FileTree,
+        dst: &FileTree,
+        state: &SyncState,
+        policy: ConflictPolicy,
+    ) -> (Vec<SyncAction>, Vec<SyncConflict>) {
+        plan_bidirectional(src, dst, state, policy, "dev")
+    }
+
+    fn copy_action(from: SyncSide, rel: &str, to_rel: &str) -> SyncAction {
+        SyncAction::Copy {
+            from,
+            rel: rel.to_string(),
+            to_rel: to_rel.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_bidirectional_first_run_copies_missing_both_ways() {
+        let src = tree(&[("a.txt", 1, 10)]);
+        let dst = tree(&[("b.txt", 2, 20)]);
+        let (actions, conflicts) = plan(&src, &dst, &SyncState::default(), ConflictPolicy::Abort);
+        assert!(conflicts.is_empty());
+        assert_eq!(
+            actions,
+            vec![
+                copy_action(SyncSide::Source, "a.txt", "a.txt"),
+                copy_action(SyncSide::Dest, "b.txt", "b.txt"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_bidirectional_propagates_deletions() {
+        let src = tree(&[("keep", 1, 10)]);
+        let dst = tree(&[("keep", 1, 10), ("gone", 2, 20)]);
+        let state = SyncState::from_trees(&dst, &dst);
+        let (actions, conflicts) = plan(&src, &dst, &state, ConflictPolicy::Abort);
+        assert!(conflicts.is_empty());
+        assert_eq!(
+            actions,
+            vec![SyncAction::Delete {
+                side: SyncSide::Dest,
+                rel: "gone".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_bidirectional_refuses_missing_root_with_state() {
+        let dst = tree(&[("a.txt", 1, 10)]);
+        let state_path = Path::new("/state.json");
+        let roots = |src_missing| [("src", src_missing), ("dev:/dst", false)];
+
+        // Nothing synced yet: a missing root is just created.
+        assert!(check_missing_roots(&SyncState::default(), roots(true), state_path).is_ok());
+
+        let state = SyncState::from_trees(&dst, &dst);
+        assert!(check_missing_roots(&state, roots(false), state_path).is_ok());
+        let err = check_missing_roots(&state, roots(true), state_path).unwrap_err();
+        assert!(err.to_string().starts_with("src does not exist"));
+    }
+
+    #[tokio::test]
+    async fn test_read_existing_tree_only_treats_not_found_as_missing() {
+        let dir = tempfile::tempdir().unwrap();
+        let filter = SyncFilter::new(vec![], false);
+        let missing = LocalOrRemotePath::Local(dir.path().join("nope"));
+        let tree = read_existing_tree(&missing, &None, &filter).await.unwrap();
+        assert!(tree.is_none());
+
+        std::fs::write(dir.path().join("a.txt"), "a").unwrap();
+        let present = LocalOrRemotePath::Local(dir.path().to_path_buf());
+        let tree = read_existing_tree(&present, &None, &filter).await.unwrap();
+        assert_eq!(tree.unwrap().files.len(), 1);
+    }
+
+    #[test]
+    fn test_bidirectional_same_change_on_both_sides_is_not_a_conflict() {
+        let before = tree(&[("f", 1, 10)]);
+        let state = SyncState::from_trees(&before, &before);
+        let after = tree(&[("f", 5, 50)]);
+        let (actions, conflicts) = plan(&after, &after, &state, ConflictPolicy::Abort);
+        assert!(actions.is_empty());
+        assert!(conflicts.is_empty());
+    }
+
+    #[test]
+    fn test_bidirectional_conflict_abort_plans_nothing() {
+        let before = tree(&[("f", 1, 10)]);
+        let state = SyncState::from_trees(&before, &before);
+        let src = tree(&[("f", 2, 20)]);
+        let dst = tree(&[("f", 3, 30)]);
+        let (actions, conflicts) = plan(&src, &dst, &state, ConflictPolicy::Abort);
+        assert!(actions.is_empty());
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].describe(), "modified on both sides");
+    }
+
+    #[test]
+    fn test_bidirectional_conflict_newer_wins() {
+        let before = tree(&[("f", 1, 10)]);
+        let state = SyncState::from_trees(&before, &before);
+        let src = tree(&[("f", 2, 20)]);
+        let dst = tree(&[("f", 3, 30)]);
+        let (actions, conflicts) = plan(&src, &dst, &state, ConflictPolicy::Newer);
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(actions, vec![copy_action(SyncSide::Dest, "f", "f")]);
+    }
+
+    #[test]
+    fn test_bidirectional_conflict_modification_beats_deletion() {
+        let before = tree(&[("f", 1, 10)]);
+        let state = SyncState::from_trees(&before, &before);
+        let src = tree(&[]);
+        let dst = tree(&[("f", 3, 30)]);
+        for policy in [ConflictPolicy::Newer, ConflictPolicy::KeepBoth] {
+            let (actions, conflicts) = plan(&src, &dst, &state, policy);
+            assert_eq!(conflicts[0].describe(), "deleted on source, modified on destination");
+            assert_eq!(actions, vec![copy_action(SyncSide::Dest, "f", "f")]);
+        }
+    }
+
+    #[test]
+    fn test_bidirectional_conflict_keep_both() {
+        let before = tree(&[("f", 1, 10)]);
+        let state = SyncState::from_trees(&before, &before);
+        let src = tree(&[("f", 2, 20)]);
+        let dst = tree(&[("f", 3, 30)]);
+        let (actions, _) = plan(&src, &dst, &state, ConflictPolicy::KeepBoth);
+        assert_eq!(
+            actions,
+            vec![
+                copy_action(SyncSide::Dest, "f", "f.conflict-dev"),
+                copy_action(SyncSide::Source, "f", "f"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sync_state_only_records_files_on_both_sides() {
+        let a = tree(&[("both", 1, 10), ("only_a", 2, 20)]);
+        let b = tree(&[("both", 1, 11)]);
+        let state = SyncState::from_trees(&a, &b);
+        assert_eq!(state.files.len(), 1);
+        assert_eq!(
+            state.files.get("both"),
+            Some(&("1:10".to_string(), "1:11".to_string()))
+        );
+    }
 }
@@ -1,25 +1,36 @@
 use anyhow::{Context, Result, anyhow};
-use m87_shared::deploy_spec::{CommandSpec, LogSpec, ObserveHooks};
+use m87_shared::deploy_spec::{CommandSpec, LogPersistSpec, LogSpec, ObserveHooks};
 use std::{
-    collections::{BTreeMap, HashMap},
+    collections::{BTreeMap, HashMap, HashSet},
     path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
     time::Duration,
 };
 use tokio::{
     io::{AsyncBufReadExt, BufReader},
     sync::mpsc,
+    task::JoinHandle,
     time::timeout,
 };
 use tokio_util::sync::CancellationToken;
 
+use crate::device::log_shipper;
+use crate::device::log_store::LogStore;
 use crate::util::command::build_command;
 use crate::util::format::format_log;
+use crate::util::time::now_ms;
+
+/// Delay before a persisted unit's follow command is started again.
+const PERSIST_RESTART_DELAY: Duration = Duration::from_secs(5);
 
-/// On-demand logs only:
+/// Logs are on-demand unless a unit opts into persistence:
 /// - Snapshot: run once, capture bounded output (for `m87 <device> logs` and incident evidence)
 /// - Follow: stream while a client is connected (`m87 <device> logs -f`)
-///
-/// No always-on processes.
+/// - Persist: units with `logs.persist` keep their follow command running and
+///   store its output (`m87 <device> logs <unit> --persisted`)
 #[derive(Clone)]
 pub struct LogManager {
     tx: mpsc::Sender<LogCmd>,
@@ -45,12 +56,72 @@ enum LogCmd {
     FollowStop {
         run_id: String,
     },
+    /// Persist exactly these units from now on.
+    SyncPersist {
+        units: Vec<PersistedUnit>,
+    },
     StopAll,
 }
 
+/// A unit whose follow output is kept on disk.
+pub struct PersistedUnit {
+    pub run_id: String,
+    pub follow: CommandSpec,
+    pub persist: LogPersistSpec,
+    pub env: BTreeMap<String, String>,
+    pub workdir: PathBuf,
+    pub store_dir: PathBuf,
+}
+
+impl PersistedUnit {
+    /// Changes to any of these restart the follow command.
+    fn hash(&self) -> String {
+        format!(
+            "{}|{:?}|{:?}|{:?}|{}",
+            serde_json::to_string(&self.follow).unwrap_or_default(),
+            self.persist,
+            self.env,
+            self.workdir,
+            self.store_dir.display()
+        )
+    }
+}
+
 struct FollowStream {
     cancel: CancellationToken,
     followers: u64,
+    /// Whether lines go to the live log stream; persisted units keep running
+    /// without followers.
+    emit: Arc<AtomicBool>,
+    /// Set for persisted units.
+    persist_hash: Option<String>,
+}
+
+/// Where a follow command's lines go.
+#[derive(Clone)]
+struct LineSink {
+    unit: String,
+    emit: Arc<AtomicBool>,
+    store: Option<Arc<Mutex<LogStore>>>,
+}
+
+impl LineSink {
+    fn push(&self, line: &str) {
+        if self.emit.load(Ordering::Relaxed) {
+            tracing::info!(
+                target: "observe",
+                "[observe]{}",
+                format_log(&self.unit, line, true)
+            );
+        }
+        if let Some(store) = &self.store {
+            let at_ms = now_ms();
+            if let Err(e) = store.lock().unwrap().append(at_ms, line) {
+                tracing::debug!("failed to persist log line for {}: {e}", self.unit);
+            }
+            log_shipper::ship(&self.unit, at_ms, line);
+        }
+    }
 }
 
 impl LogManager {
@@ -90,6 +161,7 @@ impl LogManager {
                         if let Some(stream) = follows.get_mut(&run_id) {
                             // Increment follower count
                             stream.followers += 1;
+                            stream.emit.store(true, Ordering::Relaxed);
                             tracing::debug!(
                                 "Added follower to {} (total: {})",
                                 run_id,
@@ -98,7 +170,7 @@ impl LogManager {
                             continue;
                         }
 
-                        let Some(follow) = spec.follow.as_ref() else {
+                        let Some(follow) = spec.follow_command() else {
                             tracing::info!(
                                 "Skipping follow for {} since there is no follow spec",
                                 run_id
@@ -107,13 +179,21 @@ impl LogManager {
                         };
 
                         let cancel = CancellationToken::new();
-                        match spawn_follow(&run_id, &follow, &env, &workdir, cancel.clone()).await {
-                            Ok(()) => {
+                        let emit = Arc::new(AtomicBool::new(true));
+                        let sink = LineSink {
+                            unit: run_id.clone(),
+                            emit: emit.clone(),
+                            store: None,
+                        };
+                        match spawn_follow(&follow, &env, &workdir, sink, cancel.clone()) {
+                            Ok(_) => {
                                 follows.insert(
                                     run_id.clone(),
                                     FollowStream {
                                         cancel,
                                         followers: 1,
+                                        emit,
+                                        persist_hash: None,
                                     },
                                 );
                                 tracing::debug!("Started follow stream for {}", run_id);
@@ -133,7 +213,9 @@ impl LogManager {
                                 stream.followers
                             );
 
-                            if stream.followers == 0 {
+                            if stream.followers == 0 && stream.persist_hash.is_some() {
+                                stream.emit.store(false, Ordering::Relaxed);
+                            } else if stream.followers == 0 {
                                 tracing::debug!(
                                     "No more followers for {}, cancelling stream",
                                     run_id
@@ -144,6 +226,10 @@ impl LogManager {
                         }
                     }
 
+                    Some(LogCmd::SyncPersist { units }) => {
+                        sync_persist(&mut follows, units);
+                    }
+
                     Some(LogCmd::StopAll) => {
                         for (_, s) in follows.drain() {
                             s.cancel.cancel();
@@ -226,18 +312,121 @@ impl LogManager {
         let _ = self.tx.send(LogCmd::FollowStop { run_id }).await;
     }
 
+    /// Persist the given units' logs, restarting those whose spec changed,
+    /// and stop persisting all others.
+    pub async fn sync_persist(&self, units: Vec<PersistedUnit>) {
+        let _ = self.tx.send(LogCmd::SyncPersist { units }).await;
+    }
+
     pub async fn stop_all(&self) {
         let _ = self.tx.send(LogCmd::StopAll).await;
     }
 }
 
-async fn spawn_follow(
-    run_id: &str,
+fn sync_persist(follows: &mut HashMap<String, FollowStream>, units: Vec<PersistedUnit>) {
+    let wanted: HashMap<String, String> = units
+        .iter()
+        .map(|unit| (unit.run_id.clone(), unit.hash()))
+        .collect();
+
+    // Stop persisting units that were removed or changed; followers of a
+    // changed unit carry over to its new stream below.
+    let mut carried: HashMap<String, u64> = HashMap::new();
+    let stale: Vec<String> = follows
+        .iter()
+        .filter(|(run_id, stream)| {
+            match (&stream.persist_hash, wanted.get(run_id.as_str())) {
+                (Some(current), Some(hash)) => current != hash,
+                (Some(_), None) => true,
+                // An on-demand follow is replaced by the persisted one.
+                (None, Some(_)) => true,
+                (None, None) => false,
+            }
+        })
+        .map(|(run_id, _)| run_id.clone())
+        .collect();
+    for run_id in stale {
+        let stream = follows.remove(&run_id).unwrap();
+        stream.cancel.cancel();
+        if wanted.contains_key(&run_id) {
+            carried.insert(run_id, stream.followers);
+        } else {
+            // Live followers of a unit no longer persisted lose their
+            // stream, as when the unit is undeployed.
+            tracing::info!("Stopped persisting logs for {}", run_id);
+        }
+    }
+
+    let running: HashSet<String> = follows.keys().cloned().collect();
+    for unit in units {
+        if running.contains(&unit.run_id) {
+            continue;
+        }
+        let followers = carried.remove(&unit.run_id).unwrap_or(0);
+        let store = match LogStore::open(&unit.store_dir, &unit.persist) {
+            Ok(store) => Arc::new(Mutex::new(store)),
+            Err(e) => {
+                tracing::error!("failed to open log store for {}: {e}", unit.run_id);
+                continue;
+            }
+        };
+        let cancel = CancellationToken::new();
+        let emit = Arc::new(AtomicBool::new(followers > 0));
+        let sink = LineSink {
+            unit: unit.run_id.clone(),
+            emit: emit.clone(),
+            store: Some(store),
+        };
+        let run_id = unit.run_id.clone();
+        let hash = unit.hash();
+        tracing::info!("Persisting logs for {}", run_id);
+        tokio::spawn(keep_following(unit, sink, cancel.clone()));
+        follows.insert(
+            run_id,
+            FollowStream {
+                cancel,
+                followers,
+                emit,
+                persist_hash: Some(hash),
+            },
+        );
+    }
+}
+
+/// Run a persisted unit's follow command until cancelled, starting it again
+/// whenever it exits.
+async fn keep_following(unit: PersistedUnit, sink: LineSink, cancel: CancellationToken) {
+    loop {
+        let follow = spawn_follow(
+            &unit.follow,
+            &unit.env,
+            &unit.workdir,
+            sink.clone(),
+            cancel.clone(),
+        );
+        match follow {
+            Ok(done) => {
+                let _ = done.await;
+            }
+            Err(e) => tracing::warn!("log follow for {} failed to start: {e}", unit.run_id),
+        }
+        tokio::select! {
+            _ = cancel.cancelled() => break,
+            _ = tokio::time::sleep(PERSIST_RESTART_DELAY) => {}
+        }
+    }
+}
+
+/// Start a follow command, feeding its stdout and stderr lines to `sink`.
+/// The returned task finishes once the command has exited or was killed on
+/// cancellation.
+fn spawn_follow(
     spec: &CommandSpec,
     env: &BTreeMap<String, String>,
     workdir: &Path,
+    sink: LineSink,
     cancel: CancellationToken,
-) -> Result<()> {
+) -> Result<JoinHandle<()>> {
     let mut cmd = build_command(spec)?;
     cmd.current_dir(workdir);
     for (k, v) in env {
@@ -254,7 +443,7 @@ async fn spawn_follow(
 
     async fn follow_lines<R: tokio::io::AsyncRead + Unpin + Send + 'static>(
         reader: R,
-        unit: String,
+        sink: LineSink,
         cancel: CancellationToken,
     ) {
         let mut lines = BufReader::new(reader).lines();
@@ -266,11 +455,7 @@ async fn spawn_follow(
                 }
                 res = lines.next_line() => {
                     match res {
-                        Ok(Some(line)) => tracing::info!(
-                            target: "observe",
-                            "[observe]{}",
-                            format_log(&unit, &line, true)
-                        ),
+                        Ok(Some(line)) => sink.push(&line),
                         Ok(None) => break, // EOF
                         Err(_) => break,   // I/O error; best-effort
                     }
@@ -279,29 +464,23 @@ async fn spawn_follow(
         }
     }
 
-    let unit = run_id.to_string();
-    tokio::spawn(follow_lines(stdout, unit, cancel.clone()));
-
-    let unit = run_id.to_string();
-    tokio::spawn(follow_lines(stderr, unit, cancel.clone()));
+    let out = tokio::spawn(follow_lines(stdout, sink.clone(), cancel.clone()));
+    let err = tokio::spawn(follow_lines(stderr, sink, cancel.clone()));
 
     // Ensure the process is terminated when cancellation is requested.
-    tokio::spawn({
-        let cancel = cancel.clone();
-        async move {
-            tokio::select! {
-                _ = cancel.cancelled() => {
-                    let _ = child.kill().await;   // best-effort
-                    let _ = child.wait().await;   // reap
-                }
-                _ = child.wait() => {
-                    // Process exited normally.
-                }
+    Ok(tokio::spawn(async move {
+        tokio::select! {
+            _ = cancel.cancelled() => {
+                let _ = child.kill().await;   // best-effort
+                let _ = child.wait().await;   // reap
+            }
+            _ = child.wait() => {
+                // Process exited normally.
             }
         }
-    });
-
-    Ok(())
+        let _ = out.await;
+        let _ = err.await;
+    }))
 }
 
 /// Runs the log command once and captures bounded output.
@@ -352,3 +531,43 @@ async fn snapshot_logs(
         .await
         .context("log snapshot timeout")?
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::log_store::{self, LogQuery};
+
+    #[tokio::test]
+    async fn persisted_unit_output_reaches_the_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let store_dir = dir.path().join("logs");
+        let manager = LogManager::start();
+        manager
+            .sync_persist(vec![PersistedUnit {
+                run_id: "web".to_string(),
+                follow: CommandSpec::Sh("echo \"started $GREETING\"".to_string()),
+                persist: LogPersistSpec {
+                    max_size: 1024 * 1024,
+                    max_age: None,
+                },
+                env: BTreeMap::from([("GREETING".to_string(), "hi".to_string())]),
+                workdir: dir.path().to_path_buf(),
+                store_dir: store_dir.clone(),
+            }])
+            .await;
+
+        let mut lines = Vec::new();
+        for _ in 0..50 {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            lines = log_store::read(&store_dir, &LogQuery::default()).unwrap_or_default();
+            if !lines.is_empty() {
+                break;
+            }
+        }
+        manager.sync_persist(Vec::new()).await;
+        assert_eq!(
+            lines.first().map(|(_, line)| line.as_str()),
+            Some("started hi")
+        );
+    }
+}
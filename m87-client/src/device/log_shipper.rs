@@ -0,0 +1,392 @@
+//! Log shipping (`log_shipping` in the runtime config).
+//!
+//! Lines of units with `observe.logs.persist` and, optionally, the runtime's
+//! own log are collected into zstd-compressed NDJSON batches and spooled to
+//! disk, so they outlive a long offline period. The control tunnel uploads
+//! the oldest batch with a heartbeat and deletes it once the server has
+//! acknowledged it; only one batch is in flight at a time.
+
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use m87_shared::heartbeat::{LogBatch, LogBatchAck, ShippedLogLine};
+use once_cell::sync::OnceCell;
+use tokio::sync::{Notify, broadcast, mpsc};
+use tokio::time::Instant;
+use tracing::{debug, warn};
+
+use crate::config::LogShippingConfig;
+use crate::util::logging::get_log_rx;
+use crate::util::time::now_ms;
+
+/// Raw NDJSON bytes at which a batch is closed.
+const BATCH_BYTES: usize = 256 * 1024;
+/// A batch is closed this long after its first line at the latest.
+const BATCH_DELAY: Duration = Duration::from_secs(5);
+/// Resend an unacknowledged batch after this long, e.g. when the server
+/// doesn't know about log shipping.
+const ACK_TIMEOUT: Duration = Duration::from_secs(60);
+/// Lines waiting to be batched; more are dropped.
+const QUEUE_LINES: usize = 16 * 1024;
+const RUNTIME_SOURCE: &str = "m87";
+
+static SHIPPER: OnceCell<Shipper> = OnceCell::new();
+
+struct Shipper {
+    lines: mpsc::Sender<ShippedLogLine>,
+    spool: Mutex<Spool>,
+    changed: Notify,
+}
+
+struct Spool {
+    dir: PathBuf,
+    max_bytes: u64,
+    /// Seq and size of each spooled batch, oldest first.
+    batches: VecDeque<(u64, u64)>,
+    next_seq: u64,
+    in_flight: Option<(u64, Instant)>,
+    paused_until: Option<Instant>,
+}
+
+/// Start shipping with the spool in `dir`. Does nothing when called twice.
+pub fn start(config: &LogShippingConfig, dir: PathBuf) -> Result<()> {
+    let spool = Spool::open(dir, config.max_spool_bytes)
+        .context("Failed to open the log shipping spool")?;
+    let (tx, rx) = mpsc::channel(QUEUE_LINES);
+    let shipper = Shipper {
+        lines: tx,
+        spool: Mutex::new(spool),
+        changed: Notify::new(),
+    };
+    if SHIPPER.set(shipper).is_err() {
+        return Ok(());
+    }
+
+    tokio::spawn(collect(rx));
+    if config.runtime
+        && let Some(runtime_rx) = get_log_rx()
+    {
+        tokio::spawn(ship_runtime_log(runtime_rx));
+    }
+    Ok(())
+}
+
+/// Queue a line for shipping; a no-op unless shipping is on.
+pub fn ship(source: &str, at_ms: u64, line: &str) {
+    let Some(shipper) = SHIPPER.get() else {
+        return;
+    };
+    let _ = shipper.lines.try_send(ShippedLogLine {
+        at_ms,
+        source: source.to_string(),
+        line: line.to_string(),
+    });
+}
+
+/// The next batch to upload, once nothing is in flight. Never resolves
+/// while shipping is off.
+pub async fn next_batch() -> LogBatch {
+    let Some(shipper) = SHIPPER.get() else {
+        return std::future::pending().await;
+    };
+    loop {
+        let changed = shipper.changed.notified();
+        let wait = match shipper.spool.lock().unwrap().take_next() {
+            Ok(Some(batch)) => return batch,
+            Ok(None) => None,
+            Err(Wait(until)) => Some(until),
+        };
+        match wait {
+            Some(until) => {
+                tokio::select! {
+                    _ = changed => {}
+                    _ = tokio::time::sleep_until(until) => {}
+                }
+            }
+            None => changed.await,
+        }
+    }
+}
+
+/// Apply the server's answer to an uploaded batch.
+pub fn ack(ack: &LogBatchAck) {
+    if let Some(shipper) = SHIPPER.get() {
+        shipper.spool.lock().unwrap().ack(ack);
+        shipper.changed.notify_one();
+    }
+}
+
+/// Send the batch in flight again on the new connection.
+pub fn reconnected() {
+    if let Some(shipper) = SHIPPER.get() {
+        shipper.spool.lock().unwrap().in_flight = None;
+        shipper.changed.notify_one();
+    }
+}
+
+/// Gather queued lines into batches and spool them.
+async fn collect(mut rx: mpsc::Receiver<ShippedLogLine>) {
+    let mut batch = Vec::new();
+    let mut deadline = None;
+    loop {
+        let line = match deadline {
+            Some(deadline) => tokio::select! {
+                line = rx.recv() => line,
+                _ = tokio::time::sleep_until(deadline) => None,
+            },
+            None => rx.recv().await,
+        };
+        match line {
+            Some(line) => {
+                serde_json::to_writer(&mut batch, &line).expect("log line serializes");
+                batch.push(b'\n');
+                deadline.get_or_insert_with(|| Instant::now() + BATCH_DELAY);
+                if batch.len() < BATCH_BYTES {
+                    continue;
+                }
+            }
+            None if deadline.is_none() => return,
+            None => {}
+        }
+
+        deadline = None;
+        let raw = std::mem::take(&mut batch);
+        let Some(shipper) = SHIPPER.get() else {
+            return;
+        };
+        let spooled = tokio::task::spawn_blocking(move || {
+            let data = zstd::encode_all(raw.as_slice(), 3)?;
+            shipper.spool.lock().unwrap().push(&data)
+        })
+        .await;
+        match spooled {
+            Ok(Ok(())) => shipper.changed.notify_one(),
+            Ok(Err(e)) => warn!("failed to spool shipped logs: {e}"),
+            Err(e) => warn!("failed to spool shipped logs: {e}"),
+        }
+    }
+}
+
+async fn ship_runtime_log(mut rx: broadcast::Receiver<String>) {
+    loop {
+        match rx.recv().await {
+            // Unit output is shipped from its persisted log instead.
+            Ok(line) if line.contains("[observe]") => {}
+            Ok(line) => ship(RUNTIME_SOURCE, now_ms(), &strip_ansi(&line)),
+            Err(broadcast::error::RecvError::Lagged(n)) => {
+                debug!("log shipping fell behind, skipped {n} runtime lines");
+            }
+            Err(broadcast::error::RecvError::Closed) => return,
+        }
+    }
+}
+
+/// The runtime log colors its level names.
+fn strip_ansi(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // CSI sequences end with a letter.
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Nothing to send before this instant.
+struct Wait(Instant);
+
+impl Spool {
+    fn open(dir: PathBuf, max_bytes: u64) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        let mut batches = Vec::new();
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "batch")
+                && let Some(seq) = path
+                    .file_stem()
+                    .and_then(|s| s.to_str())
+                    .and_then(|s| s.parse::<u64>().ok())
+            {
+                batches.push((seq, fs::metadata(&path)?.len()));
+            }
+        }
+        batches.sort_unstable();
+        let next_seq = batches.last().map_or(0, |(seq, _)| seq + 1);
+        Ok(Self {
+            dir,
+            max_bytes,
+            batches: batches.into(),
+            next_seq,
+            in_flight: None,
+            paused_until: None,
+        })
+    }
+
+    fn push(&mut self, data: &[u8]) -> io::Result<()> {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        let path = batch_path(&self.dir, seq);
+        let tmp = path.with_extension("tmp");
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+        fs::rename(&tmp, &path)?;
+        self.batches.push_back((seq, data.len() as u64));
+
+        let mut total: u64 = self.batches.iter().map(|(_, size)| size).sum();
+        while total > self.max_bytes && self.batches.len() > 1 {
+            let (seq, size) = self.batches.pop_front().unwrap();
+            debug!("log shipping spool full, dropping batch {seq}");
+            remove(&batch_path(&self.dir, seq))?;
+            total -= size;
+        }
+        Ok(())
+    }
+
+    fn take_next(&mut self) -> Result<Option<LogBatch>, Wait> {
+        let now = Instant::now();
+        if let Some(until) = self.paused_until {
+            if now < until {
+                return Err(Wait(until));
+            }
+            self.paused_until = None;
+        }
+        if let Some((_, sent)) = self.in_flight
+            && now < sent + ACK_TIMEOUT
+        {
+            return Err(Wait(sent + ACK_TIMEOUT));
+        }
+
+        while let Some(&(seq, _)) = self.batches.front() {
+            match fs::read(batch_path(&self.dir, seq)) {
+                Ok(data) => {
+                    self.in_flight = Some((seq, now));
+                    return Ok(Some(LogBatch { seq, data }));
+                }
+                Err(e) => {
+                    warn!("dropping unreadable log batch {seq}: {e}");
+                    self.batches.pop_front();
+                }
+            }
+        }
+        self.in_flight = None;
+        Ok(None)
+    }
+
+    fn ack(&mut self, ack: &LogBatchAck) {
+        if self.in_flight.is_none_or(|(seq, _)| seq != ack.seq) {
+            return;
+        }
+        self.in_flight = None;
+        if ack.too_large {
+            warn!("log batch {} exceeds the daily quota, dropping it", ack.seq);
+        } else if let Some(secs) = ack.retry_after_secs {
+            debug!("log shipping quota reached, pausing for {secs}s");
+            self.paused_until = Some(Instant::now() + Duration::from_secs(secs));
+            return;
+        }
+        if let Some(pos) = self.batches.iter().position(|(seq, _)| *seq == ack.seq) {
+            self.batches.remove(pos);
+            if let Err(e) = remove(&batch_path(&self.dir, ack.seq)) {
+                warn!("failed to remove shipped log batch {}: {e}", ack.seq);
+            }
+        }
+    }
+}
+
+fn batch_path(dir: &Path, seq: u64) -> PathBuf {
+    dir.join(format!("{seq:020}.batch"))
+}
+
+fn remove(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_level_colors() {
+        assert_eq!(
+            strip_ansi("\x1b[31mERROR\x1b[0m disk full"),
+            "ERROR disk full"
+        );
+    }
+
+    #[tokio::test]
+    async fn sends_one_batch_at_a_time_and_drops_acked_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut spool = Spool::open(dir.path().to_path_buf(), 1024).unwrap();
+        spool.push(b"first").unwrap();
+        spool.push(b"second").unwrap();
+
+        let batch = spool.take_next().ok().flatten().unwrap();
+        assert_eq!((batch.seq, batch.data.as_slice()), (0, b"first".as_slice()));
+        assert!(spool.take_next().is_err(), "waits for the ack");
+
+        spool.ack(&LogBatchAck {
+            seq: 0,
+            retry_after_secs: None,
+            too_large: false,
+        });
+        let batch = spool.take_next().ok().flatten().unwrap();
+        assert_eq!(batch.seq, 1);
+
+        spool.ack(&LogBatchAck {
+            seq: 1,
+            retry_after_secs: Some(60),
+            too_large: false,
+        });
+        assert!(spool.take_next().is_err(), "paused by the quota");
+
+        // Batches that were not acknowledged survive a restart.
+        let mut spool = Spool::open(dir.path().to_path_buf(), 1024).unwrap();
+        assert_eq!(spool.take_next().ok().flatten().unwrap().seq, 1);
+        assert_eq!(spool.next_seq, 2);
+    }
+
+    #[tokio::test]
+    async fn drops_batches_larger_than_the_quota() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut spool = Spool::open(dir.path().to_path_buf(), 1024).unwrap();
+        spool.push(b"huge").unwrap();
+        spool.push(b"next").unwrap();
+
+        let batch = spool.take_next().ok().flatten().unwrap();
+        spool.ack(&LogBatchAck {
+            seq: batch.seq,
+            retry_after_secs: None,
+            too_large: true,
+        });
+        assert!(!batch_path(dir.path(), 0).exists());
+        assert_eq!(spool.take_next().ok().flatten().unwrap().seq, 1);
+    }
+
+    #[test]
+    fn drops_oldest_batches_over_budget() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut spool = Spool::open(dir.path().to_path_buf(), 10).unwrap();
+        spool.push(b"aaaaaa").unwrap();
+        spool.push(b"bbbbbb").unwrap();
+        let seqs: Vec<_> = spool.batches.iter().map(|(seq, _)| *seq).collect();
+        assert_eq!(seqs, [1]);
+        assert!(!batch_path(dir.path(), 0).exists());
+    }
+}
@@ -0,0 +1,284 @@
+//! Persisted unit logs (`observe.logs.persist`).
+//!
+//! Lines are appended as `<unix ms> <line>\n` to segment files named after
+//! the time of their first line. Once the segments add up to more than
+//! `max_size`, or a whole segment is older than `max_age`, the oldest one is
+//! deleted. Queries read the files directly, so they don't contend with the
+//! writer.
+
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use m87_shared::deploy_spec::LogPersistSpec;
+use regex::Regex;
+
+const MIN_SEGMENT_BYTES: u64 = 64 * 1024;
+
+/// What to read back from a unit's persisted logs.
+#[derive(Debug, Default)]
+pub struct LogQuery {
+    pub since_ms: Option<u64>,
+    pub until_ms: Option<u64>,
+    pub grep: Option<Regex>,
+    /// Only the last `tail` matching lines; 0 keeps all.
+    pub tail: usize,
+}
+
+pub struct LogStore {
+    dir: PathBuf,
+    max_size: u64,
+    max_age_ms: Option<u64>,
+    segment_bytes: u64,
+    /// Start time and size of each segment, oldest first; the last is
+    /// written to.
+    segments: VecDeque<(u64, u64)>,
+    file: Option<File>,
+}
+
+impl LogStore {
+    pub fn open(dir: &Path, spec: &LogPersistSpec) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let mut segments: VecDeque<_> = list_segments(dir)?.into();
+
+        // Drop a line torn by a crash, so the next one starts cleanly.
+        if let Some((start_ms, size)) = segments.back_mut() {
+            let path = dir.join(segment_file(*start_ms));
+            let buf = fs::read(&path)?;
+            let complete = buf.iter().rposition(|b| *b == b'\n').map_or(0, |i| i + 1);
+            if complete < buf.len() {
+                OpenOptions::new()
+                    .write(true)
+                    .open(&path)?
+                    .set_len(complete as u64)?;
+                *size = complete as u64;
+            }
+        }
+
+        let mut store = Self {
+            dir: dir.to_path_buf(),
+            max_size: spec.max_size,
+            max_age_ms: spec.max_age.map(|age| age.as_millis() as u64),
+            segment_bytes: (spec.max_size / 4).max(MIN_SEGMENT_BYTES),
+            segments,
+            file: None,
+        };
+        store.trim(crate::util::time::now_ms())?;
+        Ok(store)
+    }
+
+    pub fn append(&mut self, at_ms: u64, line: &str) -> io::Result<()> {
+        let record = format!("{at_ms} {}\n", line.trim_end_matches(['\r', '\n']));
+        let len = record.len() as u64;
+
+        let rotate = match self.segments.back() {
+            Some((_, size)) => *size > 0 && size + len > self.segment_bytes,
+            None => true,
+        };
+        if rotate {
+            // Names must stay unique and ordered even if the clock stalls.
+            let start_ms = match self.segments.back() {
+                Some((last, _)) => at_ms.max(last + 1),
+                None => at_ms,
+            };
+            self.segments.push_back((start_ms, 0));
+            self.file = None;
+        }
+
+        let (start_ms, size) = self.segments.back_mut().expect("segment just ensured");
+        if self.file.is_none() {
+            self.file = Some(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(self.dir.join(segment_file(*start_ms)))?,
+            );
+        }
+        self.file.as_mut().unwrap().write_all(record.as_bytes())?;
+        *size += len;
+
+        self.trim(at_ms)
+    }
+
+    fn trim(&mut self, now_ms: u64) -> io::Result<()> {
+        let mut total: u64 = self.segments.iter().map(|(_, size)| size).sum();
+        let cutoff = self.max_age_ms.map(|age| now_ms.saturating_sub(age));
+        while self.segments.len() > 1 {
+            // Everything in the oldest segment predates the next one's start.
+            let expired = cutoff.is_some_and(|cutoff| self.segments[1].0 <= cutoff);
+            if total <= self.max_size && !expired {
+                break;
+            }
+            let (start_ms, size) = self.segments.pop_front().unwrap();
+            match fs::remove_file(self.dir.join(segment_file(start_ms))) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+            total -= size;
+        }
+        Ok(())
+    }
+}
+
+/// Matching lines of the store in `dir`, oldest first.
+pub fn read(dir: &Path, query: &LogQuery) -> io::Result<Vec<(u64, String)>> {
+    let segments = list_segments(dir)?;
+    let mut out = VecDeque::new();
+    for (i, (start_ms, _)) in segments.iter().enumerate() {
+        let next_start = segments.get(i + 1).map(|(start, _)| *start);
+        if query
+            .since_ms
+            .is_some_and(|since| next_start.is_some_and(|next| next <= since))
+        {
+            continue;
+        }
+        if query.until_ms.is_some_and(|until| *start_ms > until) {
+            break;
+        }
+
+        let buf = match fs::read(dir.join(segment_file(*start_ms))) {
+            Ok(buf) => buf,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        let text = String::from_utf8_lossy(&buf);
+        // A line still being written has no newline yet.
+        for record in text.split_inclusive('\n').filter(|r| r.ends_with('\n')) {
+            let Some((at_ms, line)) = record
+                .trim_end_matches('\n')
+                .split_once(' ')
+                .and_then(|(at, line)| Some((at.parse::<u64>().ok()?, line)))
+            else {
+                continue;
+            };
+            if query.since_ms.is_some_and(|since| at_ms < since)
+                || query.until_ms.is_some_and(|until| at_ms > until)
+                || query.grep.as_ref().is_some_and(|re| !re.is_match(line))
+            {
+                continue;
+            }
+            out.push_back((at_ms, line.to_string()));
+            if query.tail > 0 && out.len() > query.tail {
+                out.pop_front();
+            }
+        }
+    }
+    Ok(out.into())
+}
+
+fn list_segments(dir: &Path) -> io::Result<Vec<(u64, u64)>> {
+    let mut segments = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "log")
+            && let Some(start_ms) = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .and_then(|s| s.parse::<u64>().ok())
+        {
+            segments.push((start_ms, fs::metadata(&path)?.len()));
+        }
+    }
+    segments.sort_unstable();
+    Ok(segments)
+}
+
+fn segment_file(start_ms: u64) -> String {
+    format!("{start_ms:020}.log")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn spec(max_size: u64, max_age: Option<Duration>) -> LogPersistSpec {
+        LogPersistSpec { max_size, max_age }
+    }
+
+    #[test]
+    fn rotates_within_size_and_filters() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = LogStore::open(dir.path(), &spec(256 * 1024, None)).unwrap();
+        let filler = "x".repeat(1000);
+        for i in 0..1000u64 {
+            let level = if i % 100 == 0 { "ERROR" } else { "INFO" };
+            store
+                .append(1_000 + i, &format!("{level} {i} {filler}"))
+                .unwrap();
+        }
+
+        let on_disk: u64 = list_segments(dir.path()).unwrap().iter().map(|s| s.1).sum();
+        assert!(on_disk <= 256 * 1024, "{on_disk} bytes kept");
+
+        let errors = read(
+            dir.path(),
+            &LogQuery {
+                grep: Some(Regex::new("^ERROR").unwrap()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(errors.last().unwrap().0, 1_900);
+        assert!(errors.iter().all(|(_, line)| line.starts_with("ERROR")));
+
+        let window = read(
+            dir.path(),
+            &LogQuery {
+                since_ms: Some(1_990),
+                until_ms: Some(1_994),
+                tail: 2,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let times: Vec<_> = window.iter().map(|(at, _)| *at).collect();
+        assert_eq!(times, [1_993, 1_994]);
+    }
+
+    #[test]
+    fn expires_segments_past_max_age() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = crate::util::time::now_ms();
+        let day = 24 * 3600 * 1000;
+        let mut store = LogStore::open(
+            dir.path(),
+            &spec(1024 * 1024, Some(Duration::from_secs(24 * 3600))),
+        )
+        .unwrap();
+        let line = "y".repeat(300 * 1024);
+        store.append(now - 3 * day, &line).unwrap();
+        store.append(now - 2 * day, &line).unwrap();
+        store.append(now, "fresh").unwrap();
+
+        let kept = read(dir.path(), &LogQuery::default()).unwrap();
+        assert_eq!(
+            kept.len(),
+            2,
+            "only the segment before the fresh one remains"
+        );
+        assert_eq!(kept.last().unwrap().1, "fresh");
+    }
+
+    #[test]
+    fn torn_line_is_dropped_on_open() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = LogStore::open(dir.path(), &spec(1024 * 1024, None)).unwrap();
+        store.append(5, "whole").unwrap();
+        drop(store);
+
+        let segment = dir.path().join(segment_file(5));
+        let mut file = OpenOptions::new().append(true).open(&segment).unwrap();
+        file.write_all(b"6 half a li").unwrap();
+
+        let mut store = LogStore::open(dir.path(), &spec(1024 * 1024, None)).unwrap();
+        store.append(7, "next").unwrap();
+        let lines: Vec<_> = read(dir.path(), &LogQuery::default())
+            .unwrap()
+            .into_iter()
+            .map(|(_, line)| line)
+            .collect();
+        assert_eq!(lines, ["whole", "next"]);
+    }
+}
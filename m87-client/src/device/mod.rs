@@ -1,13 +1,30 @@
 #[cfg(feature = "runtime")]
+pub mod containers;
+#[cfg(feature = "runtime")]
 pub mod deployment_manager;
 #[cfg(feature = "runtime")]
 pub mod log_manager;
 #[cfg(feature = "runtime")]
+pub mod log_shipper;
+#[cfg(feature = "runtime")]
+pub mod log_store;
+#[cfg(feature = "runtime")]
+pub mod processes;
+#[cfg(feature = "runtime")]
+pub mod serial_capture;
+#[cfg(feature = "runtime")]
 pub mod system_metrics;
+#[cfg(feature = "runtime")]
+pub mod thermal;
+#[cfg(feature = "runtime")]
+pub mod unit_metrics;
 
 pub mod docker;
 pub mod forward;
 pub mod fs;
+pub mod progress;
+pub mod ps;
+pub mod rfc2217;
 
 #[cfg(feature = "runtime")]
 pub mod control_tunnel;
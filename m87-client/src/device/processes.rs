@@ -0,0 +1,173 @@
+use anyhow::{Result, anyhow, bail};
+use sysinfo::{Pid, ProcessRefreshKind, ProcessesToUpdate, Signal, System, UpdateKind, Users};
+
+use m87_shared::metrics::{ProcessInfo, ProcessList};
+
+use crate::util::time::now_ms;
+
+/// Signals `m87 <device> kill` can send, by name without `SIG`.
+const SIGNALS: &[(&str, Signal)] = &[
+    ("HUP", Signal::Hangup),
+    ("INT", Signal::Interrupt),
+    ("QUIT", Signal::Quit),
+    ("KILL", Signal::Kill),
+    ("USR1", Signal::User1),
+    ("USR2", Signal::User2),
+    ("TERM", Signal::Term),
+    ("CONT", Signal::Continue),
+    ("STOP", Signal::Stop),
+    ("TSTP", Signal::TSTP),
+];
+
+/// Keeps the previous refresh around, which CPU usage is measured against.
+pub struct ProcessSampler {
+    sys: System,
+    users: Users,
+}
+
+impl Default for ProcessSampler {
+    fn default() -> Self {
+        Self {
+            sys: System::new(),
+            users: Users::new_with_refreshed_list(),
+        }
+    }
+}
+
+impl ProcessSampler {
+    pub fn refresh(&mut self) {
+        let kind = ProcessRefreshKind::nothing()
+            .without_tasks()
+            .with_cpu()
+            .with_memory()
+            .with_user(UpdateKind::OnlyIfNotSet)
+            .with_cmd(UpdateKind::OnlyIfNotSet);
+        self.sys
+            .refresh_processes_specifics(ProcessesToUpdate::All, true, kind);
+        self.sys.refresh_memory();
+    }
+
+    /// Processes as of the last refresh.
+    pub fn snapshot(&self) -> ProcessList {
+        let processes = self
+            .sys
+            .processes()
+            .values()
+            .filter(|p| p.thread_kind().is_none())
+            .map(|p| {
+                let pid = p.pid().as_u32();
+                let cgroup = read_cgroup(pid);
+                ProcessInfo {
+                    pid,
+                    parent_pid: p.parent().map(|pid| pid.as_u32()),
+                    user: p
+                        .user_id()
+                        .and_then(|uid| self.users.get_user_by_id(uid))
+                        .map(|user| user.name().to_string()),
+                    name: p.name().to_string_lossy().into_owned(),
+                    cmd: p
+                        .cmd()
+                        .iter()
+                        .map(|arg| arg.to_string_lossy())
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                    cpu_percent: p.cpu_usage(),
+                    rss_bytes: p.memory(),
+                    status: p.status().to_string(),
+                    started_at: p.start_time(),
+                    container: cgroup.as_deref().and_then(container_id),
+                    cgroup,
+                }
+            })
+            .collect();
+        ProcessList {
+            timestamp: now_ms(),
+            total_memory_bytes: self.sys.total_memory(),
+            processes,
+        }
+    }
+}
+
+/// Send `signal` (`TERM`, `SIGKILL`, `hup`, ...) to `pid`.
+pub fn send_signal(pid: u32, signal: &str) -> Result<()> {
+    let name = signal.to_ascii_uppercase();
+    let name = name.strip_prefix("SIG").unwrap_or(&name);
+    let Some(&(_, signal)) = SIGNALS.iter().find(|(n, _)| *n == name) else {
+        let names: Vec<_> = SIGNALS.iter().map(|(n, _)| *n).collect();
+        bail!("unknown signal {name}; use one of {}", names.join(", "));
+    };
+
+    let pid = Pid::from_u32(pid);
+    let mut sys = System::new();
+    sys.refresh_processes_specifics(
+        ProcessesToUpdate::Some(&[pid]),
+        true,
+        ProcessRefreshKind::nothing().without_tasks(),
+    );
+    let process = sys
+        .process(pid)
+        .ok_or_else(|| anyhow!("no process with pid {pid}"))?;
+    match process.kill_with(signal) {
+        Some(true) => Ok(()),
+        Some(false) => bail!("failed to signal {pid}; is it owned by another user?"),
+        None => bail!("SIG{name} is not supported on this device"),
+    }
+}
+
+/// The process's cgroup v2 path, or the first v1 hierarchy's.
+fn read_cgroup(pid: u32) -> Option<String> {
+    let content = std::fs::read_to_string(format!("/proc/{pid}/cgroup")).ok()?;
+    let path = content
+        .lines()
+        .find_map(|line| line.strip_prefix("0::"))
+        .or_else(|| content.lines().next()?.splitn(3, ':').nth(2))?;
+    (!path.is_empty() && path != "/").then(|| path.to_string())
+}
+
+/// Container id from a docker, containerd or podman cgroup path, shortened
+/// like `docker ps` does.
+fn container_id(cgroup: &str) -> Option<String> {
+    cgroup.rsplit('/').find_map(|segment| {
+        let id = segment.strip_suffix(".scope").unwrap_or(segment);
+        let id = ["docker-", "cri-containerd-", "crio-", "libpod-"]
+            .iter()
+            .find_map(|prefix| id.strip_prefix(prefix))
+            .unwrap_or(id);
+        (id.len() == 64 && id.bytes().all(|b| b.is_ascii_hexdigit())).then(|| id[..12].to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: &str = "3f4e9a1b2c7d8e0f11223344556677889900aabbccddeeff0011223344556677";
+
+    #[test]
+    fn finds_container_ids_in_cgroups() {
+        let systemd = format!("/system.slice/docker-{ID}.scope");
+        assert_eq!(container_id(&systemd).as_deref(), Some("3f4e9a1b2c7d"));
+        let cgroupfs = format!("/docker/{ID}");
+        assert_eq!(container_id(&cgroupfs).as_deref(), Some("3f4e9a1b2c7d"));
+        assert_eq!(container_id("/system.slice/ssh.service"), None);
+    }
+
+    #[test]
+    fn rejects_unknown_signals() {
+        let err = send_signal(std::process::id(), "SIGBOGUS").unwrap_err();
+        assert!(err.to_string().contains("unknown signal BOGUS"));
+    }
+
+    #[test]
+    fn lists_own_process() {
+        let mut sampler = ProcessSampler::default();
+        sampler.refresh();
+        let list = sampler.snapshot();
+        let me = list
+            .processes
+            .iter()
+            .find(|p| p.pid == std::process::id())
+            .expect("own process listed");
+        assert!(me.rss_bytes > 0);
+    }
+}
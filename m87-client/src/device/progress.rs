@@ -0,0 +1,411 @@
+//! Transfer progress for `cp` and `sync`: per-file and total bytes,
+//! throughput and ETA, rendered as a status line on TTYs, as NDJSON events
+//! with `--json`, or handed to a callback (MCP progress notifications).
+
+use std::io::{IsTerminal, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::tui::fs::human_size;
+
+/// Minimum time between two progress updates for the same file.
+const UPDATE_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ProgressEvent {
+    /// A transfer of `files` files totalling `total_bytes` is starting.
+    Start { files: usize, total_bytes: u64 },
+    /// Progress within the current file (sent throttled and once at its end).
+    File {
+        path: String,
+        index: usize,
+        files: usize,
+        file_bytes: u64,
+        file_size: u64,
+        done_bytes: u64,
+        total_bytes: u64,
+        bytes_per_sec: f64,
+        eta_secs: Option<u64>,
+    },
+    /// A status message, e.g. "uploading foo.txt".
+    Message { text: String },
+    Done {
+        files: usize,
+        bytes: u64,
+        elapsed_secs: f64,
+    },
+}
+
+pub type ProgressCallback = Arc<dyn Fn(&ProgressEvent) + Send + Sync>;
+
+#[derive(Clone)]
+pub enum ProgressSink {
+    /// Print messages only.
+    Quiet,
+    /// Redraw a single status line on stderr.
+    Tty,
+    /// One JSON event per line on stdout.
+    Json,
+    Callback(ProgressCallback),
+}
+
+impl ProgressSink {
+    /// `Json` if requested, otherwise a status line when stderr is a terminal.
+    pub fn for_cli(json: bool) -> Self {
+        if json {
+            ProgressSink::Json
+        } else if std::io::stderr().is_terminal() {
+            ProgressSink::Tty
+        } else {
+            ProgressSink::Quiet
+        }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    started: Option<Instant>,
+    files: usize,
+    total_bytes: u64,
+    done_bytes: u64,
+    index: usize,
+    path: String,
+    file_bytes: u64,
+    file_size: u64,
+    last_update: Option<Instant>,
+    /// Whether a TTY status line is currently drawn.
+    line_drawn: bool,
+}
+
+pub struct TransferProgress {
+    sink: ProgressSink,
+    state: Mutex<State>,
+}
+
+impl TransferProgress {
+    pub fn new(sink: ProgressSink) -> Self {
+        Self {
+            sink,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    pub fn begin(&self, files: usize, total_bytes: u64) {
+        let mut state = self.state.lock().unwrap();
+        *state = State {
+            started: Some(Instant::now()),
+            files,
+            total_bytes,
+            ..State::default()
+        };
+        self.emit(&mut state, ProgressEvent::Start { files, total_bytes });
+    }
+
+    pub fn start_file(&self, path: &str, size: u64) {
+        let mut state = self.state.lock().unwrap();
+        if state.started.is_none() {
+            // Not announced via begin(): a single ad-hoc file.
+            state.started = Some(Instant::now());
+            state.files = 1;
+            state.total_bytes = size;
+        }
+        state.index += 1;
+        state.path = path.to_string();
+        state.file_bytes = 0;
+        state.file_size = size;
+        state.last_update = None;
+    }
+
+    pub fn advance(&self, n: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.file_bytes += n;
+        state.done_bytes += n;
+        let due = state
+            .last_update
+            .is_none_or(|t| t.elapsed() >= UPDATE_INTERVAL);
+        if due {
+            state.last_update = Some(Instant::now());
+            let event = file_event(&state);
+            self.emit(&mut state, event);
+        }
+    }
+
+    pub fn finish_file(&self) {
+        let mut state = self.state.lock().unwrap();
+        let event = file_event(&state);
+        self.emit(&mut state, event);
+    }
+
+    pub fn message(&self, text: impl Into<String>) {
+        let mut state = self.state.lock().unwrap();
+        self.emit(&mut state, ProgressEvent::Message { text: text.into() });
+    }
+
+    /// Report the end of the transfer (no-op if nothing was started).
+    pub fn finish(&self) {
+        let mut state = self.state.lock().unwrap();
+        let Some(started) = state.started.take() else {
+            return;
+        };
+        let event = ProgressEvent::Done {
+            files: state.index,
+            bytes: state.done_bytes,
+            elapsed_secs: started.elapsed().as_secs_f64(),
+        };
+        self.emit(&mut state, event);
+    }
+
+    fn emit(&self, state: &mut State, event: ProgressEvent) {
+        match &self.sink {
+            ProgressSink::Quiet => {
+                if let ProgressEvent::Message { text } = &event {
+                    println!("{text}");
+                }
+            }
+            ProgressSink::Tty => render_tty(state, &event),
+            ProgressSink::Json => {
+                if let Ok(line) = serde_json::to_string(&event) {
+                    println!("{line}");
+                }
+            }
+            ProgressSink::Callback(cb) => cb(&event),
+        }
+    }
+}
+
+fn file_event(state: &State) -> ProgressEvent {
+    let elapsed = state
+        .started
+        .map(|t| t.elapsed().as_secs_f64())
+        .unwrap_or_default();
+    let bytes_per_sec = if elapsed > 0.0 {
+        state.done_bytes as f64 / elapsed
+    } else {
+        0.0
+    };
+    let eta_secs = (bytes_per_sec > 0.0).then(|| {
+        (state.total_bytes.saturating_sub(state.done_bytes) as f64 / bytes_per_sec).ceil() as u64
+    });
+    ProgressEvent::File {
+        path: state.path.clone(),
+        index: state.index,
+        files: state.files,
+        file_bytes: state.file_bytes,
+        file_size: state.file_size,
+        done_bytes: state.done_bytes,
+        total_bytes: state.total_bytes,
+        bytes_per_sec,
+        eta_secs,
+    }
+}
+
+fn render_tty(state: &mut State, event: &ProgressEvent) {
+    let mut stderr = std::io::stderr();
+    const CLEAR_LINE: &str = "\r\x1b[2K";
+
+    match event {
+        ProgressEvent::Start { .. } => {}
+        ProgressEvent::File { .. } => {
+            let width = termion::terminal_size()
+                .map(|(w, _)| w as usize)
+                .unwrap_or(80);
+            let line: String = format_status_line(event)
+                .chars()
+                .take(width.saturating_sub(1))
+                .collect();
+            let _ = write!(stderr, "{CLEAR_LINE}{line}");
+            state.line_drawn = true;
+        }
+        ProgressEvent::Message { text } => {
+            if state.line_drawn {
+                let _ = write!(stderr, "{CLEAR_LINE}");
+                state.line_drawn = false;
+            }
+            println!("{text}");
+        }
+        ProgressEvent::Done {
+            files,
+            bytes,
+            elapsed_secs,
+        } => {
+            if state.line_drawn {
+                let _ = write!(stderr, "{CLEAR_LINE}");
+                state.line_drawn = false;
+            }
+            if *files > 0 {
+                let rate = if *elapsed_secs > 0.0 {
+                    *bytes as f64 / elapsed_secs
+                } else {
+                    0.0
+                };
+                let _ = writeln!(
+                    stderr,
+                    "transferred {} file(s), {} in {} ({}/s)",
+                    files,
+                    human_size(*bytes),
+                    format_duration(elapsed_secs.round() as u64),
+                    human_size(rate as u64)
+                );
+            }
+        }
+    }
+    let _ = stderr.flush();
+}
+
+/// `[2/5] src/main.rs 1.2M/4.0M | total 10.3M/52.1M 850.0K/s ETA 0:51`
+fn format_status_line(event: &ProgressEvent) -> String {
+    let ProgressEvent::File {
+        path,
+        index,
+        files,
+        file_bytes,
+        file_size,
+        done_bytes,
+        total_bytes,
+        bytes_per_sec,
+        eta_secs,
+    } = event
+    else {
+        return String::new();
+    };
+    let eta = eta_secs
+        .map(format_duration)
+        .unwrap_or_else(|| "--:--".to_string());
+    format!(
+        "[{index}/{files}] {path} {}/{} | total {}/{} {}/s ETA {eta}",
+        human_size(*file_bytes),
+        human_size(*file_size),
+        human_size(*done_bytes),
+        human_size(*total_bytes),
+        human_size(*bytes_per_sec as u64),
+    )
+}
+
+/// `m:ss`, or `h:mm:ss` from one hour on.
+fn format_duration(secs: u64) -> String {
+    let (h, m, s) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if h > 0 {
+        format!("{h}:{m:02}:{s:02}")
+    } else {
+        format!("{m}:{s:02}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recording() -> (TransferProgress, Arc<Mutex<Vec<ProgressEvent>>>) {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let progress = TransferProgress::new(ProgressSink::Callback(Arc::new(move |e| {
+            sink.lock().unwrap().push(e.clone())
+        })));
+        (progress, events)
+    }
+
+    #[test]
+    fn test_progress_events_track_file_and_total_bytes() {
+        let (progress, events) = recording();
+        progress.begin(2, 300);
+        progress.start_file("a", 100);
+        progress.advance(100);
+        progress.finish_file();
+        progress.start_file("b", 200);
+        progress.advance(50);
+        progress.advance(150); // throttled
+        progress.finish_file();
+        progress.finish();
+
+        let events = events.lock().unwrap();
+        assert_eq!(
+            events[0],
+            ProgressEvent::Start {
+                files: 2,
+                total_bytes: 300
+            }
+        );
+        let files: Vec<_> = events
+            .iter()
+            .filter_map(|e| match e {
+                ProgressEvent::File {
+                    path,
+                    index,
+                    file_bytes,
+                    done_bytes,
+                    ..
+                } => Some((path.as_str(), *index, *file_bytes, *done_bytes)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            files,
+            vec![
+                ("a", 1, 100, 100),
+                ("a", 1, 100, 100),
+                ("b", 2, 50, 150),
+                ("b", 2, 200, 300),
+            ]
+        );
+        assert!(matches!(
+            events.last(),
+            Some(ProgressEvent::Done {
+                files: 2,
+                bytes: 300,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_single_file_without_begin() {
+        let (progress, events) = recording();
+        progress.start_file("big.img", 1000);
+        progress.advance(1000);
+        progress.finish_file();
+        progress.finish();
+
+        let events = events.lock().unwrap();
+        assert!(matches!(
+            &events[0],
+            ProgressEvent::File {
+                files: 1,
+                total_bytes: 1000,
+                ..
+            }
+        ));
+        assert!(matches!(
+            events.last(),
+            Some(ProgressEvent::Done { files: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_format_status_line() {
+        let line = format_status_line(&ProgressEvent::File {
+            path: "src/main.rs".to_string(),
+            index: 2,
+            files: 5,
+            file_bytes: 512,
+            file_size: 2048,
+            done_bytes: 1024 * 1024,
+            total_bytes: 4 * 1024 * 1024,
+            bytes_per_sec: 1024.0,
+            eta_secs: Some(3 * 60 + 7),
+        });
+        assert_eq!(
+            line,
+            "[2/5] src/main.rs 512B/2.0K | total 1.0M/4.0M 1.0K/s ETA 3:07"
+        );
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(0), "0:00");
+        assert_eq!(format_duration(61), "1:01");
+        assert_eq!(format_duration(3600 + 5), "1:00:05");
+    }
+}
@@ -0,0 +1,85 @@
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use m87_shared::metrics::ProcessList;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+
+use crate::auth::AuthManager;
+use crate::config::Config;
+use crate::devices;
+use crate::streams::quic::{connect_quic_only, open_quic_io, open_quic_stream};
+use crate::streams::stream_type::{SignalResult, StreamType};
+
+/// The device measures CPU usage for a moment before the first snapshot.
+const SNAPSHOT_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// One snapshot of the device's processes.
+pub async fn list_processes(device: &str) -> Result<ProcessList> {
+    let config = Config::load()?;
+    let resolved = devices::resolve_device_cached(device).await?;
+    let token = AuthManager::get_cli_token().await?;
+
+    let stream_type = StreamType::Processes {
+        token: token.to_string(),
+    };
+    let (_conn, io) = open_quic_io(
+        &resolved.host,
+        &token,
+        &resolved.short_id,
+        stream_type,
+        config.trust_invalid_server_cert,
+    )
+    .await
+    .context("Failed to connect to process stream")?;
+
+    let mut lines = BufReader::new(io).lines();
+    let line = tokio::time::timeout(SNAPSHOT_TIMEOUT, lines.next_line())
+        .await
+        .context("Timed out listing processes")??;
+    // Devices that don't know the stream type close it without a word.
+    let Some(line) = line else {
+        bail!("{device} does not support listing processes; update m87 on the device");
+    };
+    serde_json::from_str(&line).context("Invalid process list from device")
+}
+
+/// Send `signal` (e.g. `TERM`) to `pid` on the device.
+pub async fn send_signal(device: &str, pid: u32, signal: &str) -> Result<()> {
+    let config = Config::load()?;
+    let resolved = devices::resolve_device_cached(device).await?;
+    let token = AuthManager::get_cli_token().await?;
+
+    let (_endpoint, conn) = connect_quic_only(
+        &resolved.host,
+        &token,
+        &resolved.short_id,
+        config.trust_invalid_server_cert,
+    )
+    .await
+    .context("Failed to connect to device")?;
+    signal_on(&conn, &token, pid, signal).await
+}
+
+/// Like `send_signal`, on an open connection.
+pub async fn signal_on(
+    conn: &quinn::Connection,
+    token: &str,
+    pid: u32,
+    signal: &str,
+) -> Result<()> {
+    let stream_type = StreamType::Signal {
+        token: token.to_string(),
+        pid,
+        signal: signal.to_string(),
+    };
+    let mut io = open_quic_stream(conn, stream_type).await?;
+    let mut body = Vec::new();
+    io.read_to_end(&mut body).await?;
+    if body.is_empty() {
+        bail!("device does not support sending signals; update m87 on the device");
+    }
+    match serde_json::from_slice(&body).context("Invalid answer from device")? {
+        SignalResult::Sent => Ok(()),
+        SignalResult::Error { message } => bail!("{message}"),
+    }
+}
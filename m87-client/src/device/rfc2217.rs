@@ -0,0 +1,501 @@
+//! RFC 2217 (Telnet Com Port Control) server side.
+//!
+//! [`Rfc2217Session`] turns the telnet byte stream of a local client such as
+//! pyserial, esptool or avrdude into serial data and [`SerialControl`]
+//! messages for the device, and device data and modem line changes back into
+//! telnet. It does no I/O itself; `device::serial` drives it.
+
+use crate::streams::serial_control::{
+    FlowControl, ModemStatus, Parity, SerialControl, SerialSettings,
+};
+
+const IAC: u8 = 255;
+const DONT: u8 = 254;
+const DO: u8 = 253;
+const WONT: u8 = 252;
+const WILL: u8 = 251;
+const SB: u8 = 250;
+const SE: u8 = 240;
+
+const BINARY: u8 = 0;
+const SGA: u8 = 3;
+const COM_PORT_OPTION: u8 = 44;
+
+// Client to server com port commands; replies add `SERVER_OFFSET`.
+const SIGNATURE: u8 = 0;
+const SET_BAUDRATE: u8 = 1;
+const SET_DATASIZE: u8 = 2;
+const SET_PARITY: u8 = 3;
+const SET_STOPSIZE: u8 = 4;
+const SET_CONTROL: u8 = 5;
+const NOTIFY_LINESTATE: u8 = 6;
+const NOTIFY_MODEMSTATE: u8 = 7;
+const FLOWCONTROL_SUSPEND: u8 = 8;
+const FLOWCONTROL_RESUME: u8 = 9;
+const SET_LINESTATE_MASK: u8 = 10;
+const SET_MODEMSTATE_MASK: u8 = 11;
+const PURGE_DATA: u8 = 12;
+const SERVER_OFFSET: u8 = 100;
+
+/// Telnet subnegotiations longer than this are dropped.
+const MAX_SUBNEGOTIATION_LEN: usize = 1024;
+
+/// Output of the session towards the device, in order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ToDevice {
+    Data(Vec<u8>),
+    Control(SerialControl),
+}
+
+#[derive(Debug, Default)]
+pub struct Output {
+    /// Telnet bytes for the local client.
+    pub to_client: Vec<u8>,
+    pub to_device: Vec<ToDevice>,
+}
+
+impl Output {
+    fn data(&mut self, byte: u8) {
+        match self.to_device.last_mut() {
+            Some(ToDevice::Data(data)) => data.push(byte),
+            _ => self.to_device.push(ToDevice::Data(vec![byte])),
+        }
+    }
+
+    fn control(&mut self, ctl: SerialControl) {
+        self.to_device.push(ToDevice::Control(ctl));
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Telnet {
+    Data,
+    Iac,
+    Negotiate(u8),
+    Sub,
+    SubIac,
+}
+
+pub struct Rfc2217Session {
+    signature: String,
+    settings: SerialSettings,
+    dtr: bool,
+    rts: bool,
+    brk: bool,
+    modem: ModemStatus,
+    modemstate_mask: u8,
+    linestate_mask: u8,
+    state: Telnet,
+    sub: Vec<u8>,
+    /// Options enabled on our side (we WILL) and on the client's (they WILL).
+    local: Vec<u8>,
+    remote: Vec<u8>,
+}
+
+impl Rfc2217Session {
+    pub fn new(signature: String, settings: SerialSettings) -> Self {
+        Self {
+            signature,
+            settings,
+            // Opening a port raises both lines on Linux.
+            dtr: true,
+            rts: true,
+            brk: false,
+            modem: ModemStatus::default(),
+            modemstate_mask: 0xff,
+            linestate_mask: 0,
+            state: Telnet::Data,
+            sub: Vec::new(),
+            local: Vec::new(),
+            remote: Vec::new(),
+        }
+    }
+
+    /// Options offered when a client connects.
+    pub fn greeting(&mut self) -> Vec<u8> {
+        self.local.extend([BINARY, SGA]);
+        self.remote.extend([BINARY, SGA, COM_PORT_OPTION]);
+        [
+            [IAC, WILL, BINARY],
+            [IAC, DO, BINARY],
+            [IAC, WILL, SGA],
+            [IAC, DO, SGA],
+            [IAC, DO, COM_PORT_OPTION],
+        ]
+        .concat()
+    }
+
+    /// Handle bytes received from the telnet client.
+    pub fn from_client(&mut self, input: &[u8]) -> Output {
+        let mut out = Output::default();
+        for &b in input {
+            self.state = match (self.state, b) {
+                (Telnet::Data, IAC) => Telnet::Iac,
+                (Telnet::Data, _) => {
+                    out.data(b);
+                    Telnet::Data
+                }
+                (Telnet::Iac, IAC) => {
+                    out.data(IAC);
+                    Telnet::Data
+                }
+                (Telnet::Iac, WILL | WONT | DO | DONT) => Telnet::Negotiate(b),
+                (Telnet::Iac, SB) => {
+                    self.sub.clear();
+                    Telnet::Sub
+                }
+                // NOP, GA and friends carry nothing for a serial port.
+                (Telnet::Iac, _) => Telnet::Data,
+                (Telnet::Negotiate(cmd), option) => {
+                    self.negotiate(cmd, option, &mut out);
+                    Telnet::Data
+                }
+                (Telnet::Sub, IAC) => Telnet::SubIac,
+                (Telnet::Sub, _) => {
+                    if self.sub.len() < MAX_SUBNEGOTIATION_LEN {
+                        self.sub.push(b);
+                    }
+                    Telnet::Sub
+                }
+                (Telnet::SubIac, IAC) => {
+                    self.sub.push(IAC);
+                    Telnet::Sub
+                }
+                (Telnet::SubIac, SE) => {
+                    let sub = std::mem::take(&mut self.sub);
+                    if sub.len() < MAX_SUBNEGOTIATION_LEN {
+                        self.subnegotiation(&sub, &mut out);
+                    }
+                    Telnet::Data
+                }
+                (Telnet::SubIac, _) => Telnet::Data,
+            };
+        }
+        out
+    }
+
+    /// Escape data received from the device for the telnet client.
+    pub fn from_device(&self, data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(data.len());
+        for &b in data {
+            out.push(b);
+            if b == IAC {
+                out.push(IAC);
+            }
+        }
+        out
+    }
+
+    /// Notify the client of changed modem lines, if it asked for them.
+    pub fn modem_changed(&mut self, status: ModemStatus) -> Vec<u8> {
+        let previous = std::mem::replace(&mut self.modem, status);
+        let mut value = modem_bits(status);
+        if status.cd != previous.cd {
+            value |= 0x08;
+        }
+        if previous.ri && !status.ri {
+            value |= 0x04;
+        }
+        if status.dsr != previous.dsr {
+            value |= 0x02;
+        }
+        if status.cts != previous.cts {
+            value |= 0x01;
+        }
+        value &= self.modemstate_mask;
+        if value == 0 || !self.remote.contains(&COM_PORT_OPTION) {
+            return Vec::new();
+        }
+        reply(NOTIFY_MODEMSTATE, &[value])
+    }
+
+    fn negotiate(&mut self, cmd: u8, option: u8, out: &mut Output) {
+        match cmd {
+            WILL if matches!(option, BINARY | SGA | COM_PORT_OPTION) => {
+                enable(&mut self.remote, option, [IAC, DO, option], out)
+            }
+            WILL => out.to_client.extend([IAC, DONT, option]),
+            DO if matches!(option, BINARY | SGA) => {
+                enable(&mut self.local, option, [IAC, WILL, option], out)
+            }
+            DO => out.to_client.extend([IAC, WONT, option]),
+            WONT => {
+                if let Some(i) = self.remote.iter().position(|o| *o == option) {
+                    self.remote.remove(i);
+                    out.to_client.extend([IAC, DONT, option]);
+                }
+            }
+            DONT => {
+                if let Some(i) = self.local.iter().position(|o| *o == option) {
+                    self.local.remove(i);
+                    out.to_client.extend([IAC, WONT, option]);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn subnegotiation(&mut self, sub: &[u8], out: &mut Output) {
+        let [COM_PORT_OPTION, cmd, value @ ..] = sub else {
+            return;
+        };
+        let answer: Vec<u8> = match *cmd {
+            SIGNATURE if value.is_empty() => self.signature.as_bytes().to_vec(),
+            // The client's own signature needs no answer.
+            SIGNATURE => return,
+            SET_BAUDRATE => {
+                let Ok(bytes) = <[u8; 4]>::try_from(value) else {
+                    return;
+                };
+                let baud = u32::from_be_bytes(bytes);
+                if baud != 0 && baud != self.settings.baud {
+                    self.settings.baud = baud;
+                    out.control(SerialControl::Baud { baud });
+                }
+                self.settings.baud.to_be_bytes().to_vec()
+            }
+            SET_DATASIZE => {
+                if let Some(&bits @ 5..=8) = value.first()
+                    && bits != self.settings.data_bits
+                {
+                    self.settings.data_bits = bits;
+                    out.control(SerialControl::DataBits { bits });
+                }
+                vec![self.settings.data_bits]
+            }
+            SET_PARITY => {
+                let parity = match value.first() {
+                    Some(1) => Some(Parity::None),
+                    Some(2) => Some(Parity::Odd),
+                    Some(3) => Some(Parity::Even),
+                    // Mark and space parity aren't supported by the port.
+                    _ => None,
+                };
+                if let Some(parity) = parity
+                    && parity != self.settings.parity
+                {
+                    self.settings.parity = parity;
+                    out.control(SerialControl::Parity { parity });
+                }
+                vec![match self.settings.parity {
+                    Parity::None => 1,
+                    Parity::Odd => 2,
+                    Parity::Even => 3,
+                }]
+            }
+            SET_STOPSIZE => {
+                // 3 is 1.5 stop bits, which the port doesn't support.
+                if let Some(&bits @ 1..=2) = value.first()
+                    && bits != self.settings.stop_bits
+                {
+                    self.settings.stop_bits = bits;
+                    out.control(SerialControl::StopBits { bits });
+                }
+                vec![self.settings.stop_bits]
+            }
+            SET_CONTROL => {
+                let Some(&v) = value.first() else {
+                    return;
+                };
+                vec![self.set_control(v, out)]
+            }
+            NOTIFY_LINESTATE => vec![0],
+            NOTIFY_MODEMSTATE => vec![modem_bits(self.modem) & self.modemstate_mask],
+            // Acknowledged only: device data isn't held back while suspended.
+            FLOWCONTROL_SUSPEND | FLOWCONTROL_RESUME => Vec::new(),
+            SET_LINESTATE_MASK => {
+                self.linestate_mask = value.first().copied().unwrap_or(0);
+                vec![self.linestate_mask]
+            }
+            SET_MODEMSTATE_MASK => {
+                self.modemstate_mask = value.first().copied().unwrap_or(0);
+                vec![self.modemstate_mask]
+            }
+            PURGE_DATA => {
+                let Some(&v @ 1..=3) = value.first() else {
+                    return;
+                };
+                out.control(SerialControl::Purge {
+                    rx: v & 1 != 0,
+                    tx: v & 2 != 0,
+                });
+                vec![v]
+            }
+            _ => return,
+        };
+        out.to_client.extend(reply(*cmd, &answer));
+    }
+
+    /// SET-CONTROL: flow control, break, DTR and RTS. Returns the value to
+    /// answer with.
+    fn set_control(&mut self, v: u8, out: &mut Output) -> u8 {
+        let flow = |f: FlowControl| match f {
+            FlowControl::None => 1,
+            FlowControl::Software => 2,
+            FlowControl::Hardware => 3,
+        };
+        match v {
+            0 => flow(self.settings.flow_control),
+            1..=3 => {
+                let flow_control = match v {
+                    1 => FlowControl::None,
+                    2 => FlowControl::Software,
+                    _ => FlowControl::Hardware,
+                };
+                if flow_control != self.settings.flow_control {
+                    self.settings.flow_control = flow_control;
+                    out.control(SerialControl::FlowControl { flow_control });
+                }
+                v
+            }
+            4 => on_off(self.brk, 5),
+            5 | 6 => {
+                self.brk = v == 5;
+                out.control(SerialControl::Break { on: self.brk });
+                v
+            }
+            7 => on_off(self.dtr, 8),
+            8 | 9 => {
+                self.dtr = v == 8;
+                out.control(SerialControl::Dtr { on: self.dtr });
+                v
+            }
+            10 => on_off(self.rts, 11),
+            11 | 12 => {
+                self.rts = v == 11;
+                out.control(SerialControl::Rts { on: self.rts });
+                v
+            }
+            // Inbound flow control follows the outbound setting.
+            _ => 14,
+        }
+    }
+}
+
+/// Enable an option, answering only if it wasn't already on.
+fn enable(enabled: &mut Vec<u8>, option: u8, answer: [u8; 3], out: &mut Output) {
+    if !enabled.contains(&option) {
+        enabled.push(option);
+        out.to_client.extend(answer);
+    }
+}
+
+fn on_off(on: bool, on_value: u8) -> u8 {
+    if on { on_value } else { on_value + 1 }
+}
+
+fn modem_bits(status: ModemStatus) -> u8 {
+    let mut bits = 0;
+    if status.cd {
+        bits |= 0x80;
+    }
+    if status.ri {
+        bits |= 0x40;
+    }
+    if status.dsr {
+        bits |= 0x20;
+    }
+    if status.cts {
+        bits |= 0x10;
+    }
+    bits
+}
+
+/// `IAC SB COM-PORT-OPTION <cmd + 100> <value> IAC SE`, escaping the value.
+fn reply(cmd: u8, value: &[u8]) -> Vec<u8> {
+    let mut out = vec![IAC, SB, COM_PORT_OPTION, cmd + SERVER_OFFSET];
+    for &b in value {
+        out.push(b);
+        if b == IAC {
+            out.push(IAC);
+        }
+    }
+    out.extend([IAC, SE]);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session() -> Rfc2217Session {
+        let mut s = Rfc2217Session::new("m87".to_string(), SerialSettings::with_baud(115200));
+        s.greeting();
+        s
+    }
+
+    fn sub(cmd: u8, value: &[u8]) -> Vec<u8> {
+        let mut out = vec![IAC, SB, COM_PORT_OPTION, cmd];
+        out.extend_from_slice(value);
+        out.extend([IAC, SE]);
+        out
+    }
+
+    #[test]
+    fn data_is_unescaped_both_ways() {
+        let mut s = session();
+        let out = s.from_client(&[b'a', IAC, IAC, b'b']);
+        assert_eq!(out.to_device, vec![ToDevice::Data(vec![b'a', IAC, b'b'])]);
+        assert!(out.to_client.is_empty());
+        assert_eq!(s.from_device(&[1, IAC, 2]), vec![1, IAC, IAC, 2]);
+    }
+
+    #[test]
+    fn baud_change_is_forwarded_and_echoed() {
+        let mut s = session();
+        // esptool switches to a faster baud rate after syncing.
+        let out = s.from_client(&sub(SET_BAUDRATE, &921600u32.to_be_bytes()));
+        assert_eq!(
+            out.to_device,
+            vec![ToDevice::Control(SerialControl::Baud { baud: 921600 })]
+        );
+        assert_eq!(out.to_client, reply(SET_BAUDRATE, &921600u32.to_be_bytes()));
+
+        // A query (0) answers with the current rate and changes nothing.
+        let out = s.from_client(&sub(SET_BAUDRATE, &[0, 0, 0, 0]));
+        assert!(out.to_device.is_empty());
+        assert_eq!(out.to_client, reply(SET_BAUDRATE, &921600u32.to_be_bytes()));
+    }
+
+    #[test]
+    fn reset_sequence_toggles_dtr_and_rts_in_order() {
+        let mut s = session();
+        let mut input = sub(SET_CONTROL, &[9]);
+        input.extend(sub(SET_CONTROL, &[11]));
+        input.push(0x55);
+        let out = s.from_client(&input);
+        assert_eq!(
+            out.to_device,
+            vec![
+                ToDevice::Control(SerialControl::Dtr { on: false }),
+                ToDevice::Control(SerialControl::Rts { on: true }),
+                ToDevice::Data(vec![0x55]),
+            ]
+        );
+        let out = s.from_client(&sub(SET_CONTROL, &[7]));
+        assert_eq!(out.to_client, reply(SET_CONTROL, &[9]));
+    }
+
+    #[test]
+    fn negotiation_answers_once() {
+        let mut s = Rfc2217Session::new("m87".to_string(), SerialSettings::with_baud(9600));
+        let out = s.from_client(&[IAC, WILL, COM_PORT_OPTION, IAC, WILL, COM_PORT_OPTION]);
+        assert_eq!(out.to_client, vec![IAC, DO, COM_PORT_OPTION]);
+        let out = s.from_client(&[IAC, DO, 1]);
+        assert_eq!(out.to_client, vec![IAC, WONT, 1]);
+    }
+
+    #[test]
+    fn modem_changes_carry_deltas() {
+        let mut s = session();
+        let update = s.modem_changed(ModemStatus {
+            cts: true,
+            ..Default::default()
+        });
+        assert_eq!(update, reply(NOTIFY_MODEMSTATE, &[0x11]));
+
+        s.from_client(&sub(SET_MODEMSTATE_MASK, &[0]));
+        let update = s.modem_changed(ModemStatus::default());
+        assert!(update.is_empty());
+    }
+}
@@ -1,14 +1,25 @@
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, anyhow, bail};
+use chrono::{Local, TimeZone};
 use libc;
 use std::ffi::CStr;
+use std::net::SocketAddr;
 use std::os::fd::{FromRawFd, RawFd};
-use tokio::io::split;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, ReadHalf, WriteHalf, split};
+use tokio::net::{TcpListener, TcpStream};
 use tokio::task;
 
-use crate::streams::quic::open_quic_io;
+use crate::device::rfc2217::{Rfc2217Session, ToDevice};
+use crate::streams::quic::{QuicIo, open_quic_io};
+use crate::streams::serial_control::{
+    self, CapturedChunk, Frame, FrameReader, SerialEvent, SerialPortEntry, SerialSettings,
+};
 use crate::streams::stream_type::StreamType;
 use crate::{auth::AuthManager, config::Config, devices, util::shutdown::SHUTDOWN};
 
+/// How long the device gets to open the port on a controlled stream.
+const OPEN_TIMEOUT: Duration = Duration::from_secs(15);
+
 // Create a PTY pair (master + slave)
 fn open_pty() -> Result<(RawFd, String)> {
     unsafe {
@@ -45,6 +56,7 @@ pub async fn open_serial(device: &str, port: &str, baud: u32) -> Result<()> {
         token: token.to_string(),
         baud: Some(baud),
         name: port.to_string(),
+        control: false,
     };
     let (_, remote_io) = open_quic_io(
         &resolved.host,
@@ -77,3 +89,297 @@ pub async fn open_serial(device: &str, port: &str, baud: u32) -> Result<()> {
 
     Ok(())
 }
+
+/// Serial ports on the device, with USB details and stable aliases.
+pub async fn list_ports(device: &str) -> Result<Vec<SerialPortEntry>> {
+    let cfg = Config::load()?;
+    let token = AuthManager::get_cli_token().await?;
+    let resolved = devices::resolve_device_cached(device).await?;
+
+    let stream_type = StreamType::SerialList {
+        token: token.to_string(),
+    };
+    let (_conn, mut remote_io) = open_quic_io(
+        &resolved.host,
+        &token,
+        &resolved.short_id,
+        stream_type,
+        cfg.trust_invalid_server_cert,
+    )
+    .await
+    .context("Failed to connect to serial stream")?;
+
+    let mut body = Vec::new();
+    tokio::time::timeout(OPEN_TIMEOUT, remote_io.read_to_end(&mut body))
+        .await
+        .context("Timed out listing serial ports")??;
+    // Devices that don't know the stream type close it without a word.
+    if body.is_empty() {
+        bail!("Device does not support listing serial ports; update m87 on the device");
+    }
+    serde_json::from_slice(&body).context("Invalid serial port list from device")
+}
+
+/// Serve the device's port to local RFC 2217 clients (pyserial, esptool,
+/// avrdude, ...) on `listen`. Clients are served one at a time, each on its
+/// own stream, so the port is reopened with default settings per client.
+pub async fn serve_rfc2217(device: &str, port: &str, baud: u32, listen: &str) -> Result<()> {
+    let addr = parse_listen_addr(listen)?;
+    let listener = TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Failed to listen on {addr}"))?;
+
+    println!("Serving {device}:{port} over RFC 2217 on rfc2217://{addr}");
+    println!("e.g. esptool.py --port rfc2217://{addr} flash_id");
+
+    loop {
+        let (tcp, peer) = tokio::select! {
+            accepted = listener.accept() => accepted?,
+            _ = SHUTDOWN.cancelled() => return Ok(()),
+        };
+        println!("Client connected from {peer}");
+        tokio::select! {
+            res = serve_rfc2217_client(device, port, baud, tcp) => {
+                if let Err(e) = res {
+                    eprintln!("Session ended: {e:#}");
+                }
+            }
+            _ = SHUTDOWN.cancelled() => return Ok(()),
+        }
+        println!("Client {peer} disconnected");
+    }
+}
+
+async fn serve_rfc2217_client(device: &str, port: &str, baud: u32, tcp: TcpStream) -> Result<()> {
+    tcp.set_nodelay(true)?;
+    let (_conn, mut frames, mut remote, settings) =
+        open_controlled_serial(device, port, baud).await?;
+    let mut session = Rfc2217Session::new(format!("m87 {device} {port}"), settings);
+
+    let (mut tcp_rx, mut tcp_tx) = tcp.into_split();
+    tcp_tx.write_all(&session.greeting()).await?;
+
+    let mut buf = [0u8; 4096];
+    loop {
+        tokio::select! {
+            n = tcp_rx.read(&mut buf) => {
+                let n = n?;
+                if n == 0 {
+                    return Ok(());
+                }
+                let out = session.from_client(&buf[..n]);
+                if !out.to_client.is_empty() {
+                    tcp_tx.write_all(&out.to_client).await?;
+                }
+                for msg in out.to_device {
+                    match msg {
+                        ToDevice::Data(data) => serial_control::write_data(&mut remote, &data).await?,
+                        ToDevice::Control(ctl) => serial_control::write_control(&mut remote, &ctl).await?,
+                    }
+                }
+            }
+            frame = frames.next() => match frame? {
+                Some(Frame::Data(data)) => tcp_tx.write_all(&session.from_device(&data)).await?,
+                Some(Frame::Control(json)) => match Frame::parse::<SerialEvent>(&json)? {
+                    SerialEvent::Modem { status } => {
+                        let update = session.modem_changed(status);
+                        if !update.is_empty() {
+                            tcp_tx.write_all(&update).await?;
+                        }
+                    }
+                    SerialEvent::Error { message } => eprintln!("Device: {message}"),
+                    SerialEvent::Opened { .. } => {}
+                },
+                None => bail!("Device closed the serial stream"),
+            },
+        }
+    }
+}
+
+/// Open a `Serial` stream with in-band control and wait for the device to
+/// report the port open.
+async fn open_controlled_serial(
+    device: &str,
+    port: &str,
+    baud: u32,
+) -> Result<(
+    quinn::Connection,
+    FrameReader<ReadHalf<QuicIo>>,
+    WriteHalf<QuicIo>,
+    SerialSettings,
+)> {
+    let token = AuthManager::get_cli_token().await?;
+    let stream_type = StreamType::Serial {
+        token: token.to_string(),
+        baud: Some(baud),
+        name: port.to_string(),
+        control: true,
+    };
+    open_framed(
+        device,
+        &token,
+        stream_type,
+        "Device does not support serial line control; update m87 on the device",
+    )
+    .await
+}
+
+/// Open a framed serial stream and wait for [`SerialEvent::Opened`]. Older
+/// devices drop headers they don't know or answer with raw bytes, both of
+/// which are reported as `unsupported`.
+async fn open_framed(
+    device: &str,
+    token: &str,
+    stream_type: StreamType,
+    unsupported: &str,
+) -> Result<(
+    quinn::Connection,
+    FrameReader<ReadHalf<QuicIo>>,
+    WriteHalf<QuicIo>,
+    SerialSettings,
+)> {
+    let cfg = Config::load()?;
+    let resolved = devices::resolve_device_cached(device).await?;
+
+    let (conn, remote_io) = open_quic_io(
+        &resolved.host,
+        token,
+        &resolved.short_id,
+        stream_type,
+        cfg.trust_invalid_server_cert,
+    )
+    .await
+    .context("Failed to connect to serial stream")?;
+
+    let (read, write) = split(remote_io);
+    let mut frames = FrameReader::new(read);
+    let unsupported = || anyhow!("{unsupported}");
+    let first = tokio::time::timeout(OPEN_TIMEOUT, frames.next())
+        .await
+        .map_err(|_| unsupported())?;
+    match first {
+        Ok(Some(Frame::Control(json))) => match Frame::parse::<SerialEvent>(&json) {
+            Ok(SerialEvent::Opened { settings }) => Ok((conn, frames, write, settings)),
+            Ok(SerialEvent::Error { message }) => Err(anyhow!(message)),
+            _ => Err(unsupported()),
+        },
+        Ok(None) => Err(unsupported()),
+        _ => Err(unsupported()),
+    }
+}
+
+/// Print what the device recorded from a captured port over the last
+/// `since_secs`, then live output if `follow` is set. Lines are prefixed
+/// with the local time the device read them.
+pub async fn read_capture(
+    device: &str,
+    port: &str,
+    since_secs: Option<u64>,
+    follow: bool,
+) -> Result<()> {
+    let token = AuthManager::get_cli_token().await?;
+    let stream_type = StreamType::SerialLog {
+        token: token.to_string(),
+        name: port.to_string(),
+        since_secs,
+        follow,
+    };
+    // The write half stays open: the device ends a follow once it closes.
+    let (_conn, mut frames, _remote_tx, _) = open_framed(
+        device,
+        &token,
+        stream_type,
+        "Device does not support serial capture; update m87 on the device",
+    )
+    .await?;
+
+    let mut stdout = tokio::io::stdout();
+    let mut at_line_start = true;
+    loop {
+        let frame = tokio::select! {
+            frame = frames.next() => frame?,
+            _ = SHUTDOWN.cancelled() => break,
+        };
+        match frame {
+            Some(Frame::Data(payload)) => {
+                let chunk = CapturedChunk::decode(&payload)?;
+                let stamp = Local
+                    .timestamp_millis_opt(chunk.at_ms as i64)
+                    .single()
+                    .map(|t| t.format("[%Y-%m-%d %H:%M:%S%.3f] ").to_string())
+                    .unwrap_or_default();
+                let mut out = Vec::with_capacity(chunk.data.len());
+                stamp_lines(&mut out, &chunk.data, stamp.as_bytes(), &mut at_line_start);
+                stdout.write_all(&out).await?;
+                stdout.flush().await?;
+            }
+            Some(Frame::Control(json)) => {
+                if let SerialEvent::Error { message } = Frame::parse::<SerialEvent>(&json)? {
+                    bail!(message);
+                }
+            }
+            None => break,
+        }
+    }
+    if !at_line_start {
+        stdout.write_all(b"\n").await?;
+    }
+    Ok(())
+}
+
+/// Append `data` to `out`, putting `stamp` in front of every line that
+/// starts in it.
+fn stamp_lines(out: &mut Vec<u8>, data: &[u8], stamp: &[u8], at_line_start: &mut bool) {
+    for line in data.split_inclusive(|b| *b == b'\n') {
+        if *at_line_start {
+            out.extend_from_slice(stamp);
+        }
+        out.extend_from_slice(line);
+        *at_line_start = line.ends_with(b"\n");
+    }
+}
+
+/// `:4000` and `4000` listen on localhost; a host can be given explicitly.
+fn parse_listen_addr(listen: &str) -> Result<SocketAddr> {
+    let listen = listen.trim();
+    let with_host = match listen.strip_prefix(':') {
+        Some(port) => format!("127.0.0.1:{port}"),
+        None if listen.parse::<u16>().is_ok() => format!("127.0.0.1:{listen}"),
+        None => listen.to_string(),
+    };
+    with_host
+        .parse()
+        .with_context(|| format!("Invalid listen address: {listen}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stamps_only_line_starts() {
+        let mut out = Vec::new();
+        let mut at_line_start = true;
+        stamp_lines(&mut out, b"boot\r\nrst:0x1 ", b"> ", &mut at_line_start);
+        stamp_lines(&mut out, b"(POWERON)\n\nok", b"> ", &mut at_line_start);
+        assert_eq!(out, b"> boot\r\n> rst:0x1 (POWERON)\n> \n> ok");
+        assert!(!at_line_start);
+    }
+
+    #[test]
+    fn listen_addr_defaults_to_localhost() {
+        assert_eq!(
+            parse_listen_addr(":4000").unwrap(),
+            "127.0.0.1:4000".parse().unwrap()
+        );
+        assert_eq!(
+            parse_listen_addr("4000").unwrap(),
+            "127.0.0.1:4000".parse().unwrap()
+        );
+        assert_eq!(
+            parse_listen_addr("0.0.0.0:4000").unwrap(),
+            "0.0.0.0:4000".parse().unwrap()
+        );
+        assert!(parse_listen_addr("localhost").is_err());
+    }
+}
@@ -0,0 +1,919 @@
+//! Shared serial ports and always-on capture.
+//!
+//! A port is opened once by an owner task and shared by every stream that
+//! attaches to it: output is broadcast to all viewers, and their writes and
+//! line changes go through the owner. Ports listed in `serial_captures` are
+//! opened at startup, kept open for the life of the runtime, reopened when
+//! they go away (e.g. a replugged USB adapter) and recorded into a rotating
+//! on-disk buffer. Other ports close when their last viewer leaves.
+//!
+//! Ports are named like under `/dev` (`ttyUSB0`) or by a stable udev alias
+//! (`serial/by-id/usb-FTDI_FT232R_A50285BI-if00-port0`, or just the link
+//! name). Aliases are resolved on every open, so a captured port follows an
+//! adapter that comes back under a different `ttyUSB` number.
+
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{SyncSender, TrySendError, sync_channel};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use once_cell::sync::Lazy;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::{broadcast, mpsc, oneshot, watch};
+use tokio::time::MissedTickBehavior;
+use tokio_serial::{ClearBuffer, SerialPort, SerialPortBuilderExt, SerialPortType, SerialStream};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+use crate::config::SerialCaptureConfig;
+use crate::streams::serial_control::{
+    CapturedChunk, FlowControl, ModemStatus, Parity, SerialControl, SerialPortEntry, SerialSettings,
+};
+use crate::util::shutdown::SHUTDOWN;
+use crate::util::time::now_ms;
+
+/// How often modem lines are checked while a viewer is watching them.
+const MODEM_POLL_INTERVAL: Duration = Duration::from_millis(100);
+const REOPEN_BACKOFF_MIN: Duration = Duration::from_secs(1);
+const REOPEN_BACKOFF_MAX: Duration = Duration::from_secs(30);
+/// Live chunks queued per viewer before a slow one starts missing output.
+const LIVE_BUFFER: usize = 1024;
+const MIN_SEGMENT_BYTES: u64 = 4096;
+/// Chunks queued for the capture writer before recording starts to skip output.
+const CAPTURE_QUEUE: usize = 1024;
+const SERIAL_ALIAS_DIR: &str = "/dev/serial";
+
+/// Open ports by name; the owner task ends once the last handle is dropped.
+static PORTS: Lazy<Mutex<HashMap<String, Weak<SharedPort>>>> = Lazy::new(Default::default);
+/// Captured ports, held for the life of the runtime.
+static CAPTURED: Lazy<Mutex<HashMap<String, Arc<SharedPort>>>> = Lazy::new(Default::default);
+
+enum PortCmd {
+    Write(Vec<u8>),
+    Control(SerialControl, oneshot::Sender<Result<(), String>>),
+}
+
+/// Handle to a port held open by its owner task.
+pub struct SharedPort {
+    cmd_tx: mpsc::Sender<PortCmd>,
+    live_tx: broadcast::Sender<Arc<CapturedChunk>>,
+    settings: watch::Receiver<SerialSettings>,
+    modem: watch::Receiver<Option<ModemStatus>>,
+    closed: CancellationToken,
+    capture: Option<CaptureHandle>,
+    /// Device node currently open, if any.
+    device: Arc<Mutex<Option<PathBuf>>>,
+}
+
+impl SharedPort {
+    /// Live output from now on. Chunks carry increasing `seq`s, so they can be
+    /// lined up with [`SharedPort::history`].
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<CapturedChunk>> {
+        self.live_tx.subscribe()
+    }
+
+    pub fn settings(&self) -> SerialSettings {
+        *self.settings.borrow()
+    }
+
+    /// Modem lines; `None` while unknown or unsupported by the port.
+    /// Lines are only polled while someone holds one of these.
+    pub fn modem(&self) -> watch::Receiver<Option<ModemStatus>> {
+        self.modem.clone()
+    }
+
+    pub async fn write(&self, data: Vec<u8>) {
+        let _ = self.cmd_tx.send(PortCmd::Write(data)).await;
+    }
+
+    /// Change the port for every viewer.
+    pub async fn control(&self, ctl: SerialControl) -> Result<(), String> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        if self
+            .cmd_tx
+            .send(PortCmd::Control(ctl, reply_tx))
+            .await
+            .is_err()
+        {
+            return Err("port closed".to_string());
+        }
+        reply_rx
+            .await
+            .unwrap_or_else(|_| Err("port closed".to_string()))
+    }
+
+    /// Resolves once the port is gone for good.
+    pub async fn closed(&self) {
+        self.closed.cancelled().await
+    }
+
+    /// Recorded output from `since_ms` on; empty for ports that aren't captured.
+    pub async fn history(&self, since_ms: u64) -> Result<Vec<CapturedChunk>> {
+        let Some(capture) = self.capture.clone() else {
+            return Ok(Vec::new());
+        };
+        tokio::task::spawn_blocking(move || capture.read_since(since_ms))
+            .await?
+            .map_err(Into::into)
+    }
+}
+
+/// Strip a leading `/dev/`, so both `ttyUSB0` and `/dev/ttyUSB0` name a port.
+pub fn port_name(port: &str) -> &str {
+    port.strip_prefix("/dev/").unwrap_or(port)
+}
+
+/// Device node behind a port name: a name under `/dev` or `/dev/serial`, or
+/// the name of a `by-id` / `by-path` link.
+pub fn resolve_port(name: &str) -> io::Result<PathBuf> {
+    let name = port_name(name);
+    let alias_dir = Path::new(SERIAL_ALIAS_DIR);
+    [
+        Path::new("/dev").join(name),
+        alias_dir.join(name),
+        alias_dir.join("by-id").join(name),
+        alias_dir.join("by-path").join(name),
+    ]
+    .into_iter()
+    .find(|path| path.exists())
+    .map(fs::canonicalize)
+    .unwrap_or_else(|| {
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no serial port named {name}"),
+        ))
+    })
+}
+
+/// Serial ports on the device, with USB details and `/dev/serial` aliases.
+pub fn list_ports() -> Vec<SerialPortEntry> {
+    let aliases = collect_aliases(Path::new(SERIAL_ALIAS_DIR));
+    let captured: Vec<PathBuf> = CAPTURED
+        .lock()
+        .unwrap()
+        .iter()
+        .filter_map(|(name, port)| {
+            let open_on = port.device.lock().unwrap().clone();
+            open_on.or_else(|| resolve_port(name).ok())
+        })
+        .collect();
+
+    let ports = tokio_serial::available_ports().unwrap_or_else(|e| {
+        warn!("failed to enumerate serial ports: {e}");
+        Vec::new()
+    });
+    let mut entries: Vec<_> = ports
+        .into_iter()
+        .map(|info| {
+            let device = fs::canonicalize(&info.port_name)
+                .unwrap_or_else(|_| PathBuf::from(&info.port_name));
+            let mut entry = SerialPortEntry {
+                name: port_name(&info.port_name).to_string(),
+                kind: "other".to_string(),
+                vid: None,
+                pid: None,
+                serial_number: None,
+                manufacturer: None,
+                product: None,
+                aliases: aliases.get(&device).cloned().unwrap_or_default(),
+                captured: captured.contains(&device),
+            };
+            match info.port_type {
+                SerialPortType::UsbPort(usb) => {
+                    entry.kind = "usb".to_string();
+                    entry.vid = Some(usb.vid);
+                    entry.pid = Some(usb.pid);
+                    entry.serial_number = usb.serial_number;
+                    entry.manufacturer = usb.manufacturer;
+                    entry.product = usb.product;
+                }
+                SerialPortType::PciPort => entry.kind = "pci".to_string(),
+                SerialPortType::BluetoothPort => entry.kind = "bluetooth".to_string(),
+                SerialPortType::Unknown => {}
+            }
+            entry
+        })
+        .collect();
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    entries
+}
+
+/// `by-id` and `by-path` links under `alias_dir`, keyed by the device node
+/// they point to.
+fn collect_aliases(alias_dir: &Path) -> HashMap<PathBuf, Vec<String>> {
+    let mut aliases: HashMap<PathBuf, Vec<String>> = HashMap::new();
+    for kind in ["by-id", "by-path"] {
+        let Ok(links) = fs::read_dir(alias_dir.join(kind)) else {
+            continue;
+        };
+        for link in links.flatten() {
+            if let Ok(device) = fs::canonicalize(link.path()) {
+                let alias = format!("{kind}/{}", link.file_name().to_string_lossy());
+                aliases.entry(device).or_default().push(alias);
+            }
+        }
+    }
+    for list in aliases.values_mut() {
+        list.sort();
+    }
+    aliases
+}
+
+/// A live port known as `name` or currently open on `device`.
+fn find_port(
+    ports: &HashMap<String, Weak<SharedPort>>,
+    name: &str,
+    device: Option<&Path>,
+) -> Option<Arc<SharedPort>> {
+    let open_on = |port: &SharedPort| {
+        device.is_some_and(|d| port.device.lock().unwrap().as_deref() == Some(d))
+    };
+    ports
+        .iter()
+        .filter_map(|(key, port)| Some((key, port.upgrade()?)))
+        .find(|(key, port)| !port.closed.is_cancelled() && (*key == name || open_on(port)))
+        .map(|(_, port)| port)
+}
+
+/// Share the port if it's already open (under this or another of its
+/// names), open it at `baud` otherwise. An open port keeps its current
+/// settings.
+pub fn attach(name: &str, baud: u32) -> Result<Arc<SharedPort>> {
+    let name = port_name(name);
+    let device = resolve_port(name).with_context(|| format!("Failed to open {name}"))?;
+    let mut ports = PORTS.lock().unwrap();
+    if let Some(port) = find_port(&ports, name, Some(&device)) {
+        return Ok(port);
+    }
+
+    let settings = SerialSettings::with_baud(baud);
+    let serial = open_port(&device, &settings)
+        .with_context(|| format!("Failed to open {}", device.display()))?;
+    let port = spawn_port(name, settings, Some((serial, device)), None);
+    ports.insert(name.to_string(), Arc::downgrade(&port));
+    Ok(port)
+}
+
+/// The port if it's captured, under this or another of its names.
+pub fn captured(name: &str) -> Option<Arc<SharedPort>> {
+    let name = port_name(name);
+    let device = resolve_port(name).ok();
+    let captured = CAPTURED.lock().unwrap();
+    let ports = captured
+        .iter()
+        .map(|(key, port)| (key.clone(), Arc::downgrade(port)))
+        .collect();
+    find_port(&ports, name, device.as_deref())
+}
+
+/// Open and start recording the configured ports. Ports that are missing
+/// now are retried in the background.
+pub fn start_captures(captures: &[SerialCaptureConfig]) {
+    for capture in captures {
+        let name = port_name(&capture.port);
+        match start_capture(name, capture) {
+            Ok(()) => info!("serial capture started on {name} at {} baud", capture.baud),
+            Err(e) => warn!("serial capture on {name} not started: {e:#}"),
+        }
+    }
+}
+
+fn start_capture(name: &str, capture: &SerialCaptureConfig) -> Result<()> {
+    let mut ports = PORTS.lock().unwrap();
+    let mut captured = CAPTURED.lock().unwrap();
+    if captured.contains_key(name) {
+        anyhow::bail!("configured twice");
+    }
+
+    let dir = dirs::data_dir()
+        .context("data_dir missing")?
+        .join("m87")
+        .join("serial-capture")
+        .join(name.replace('/', "_"));
+    let buffer = CaptureBuffer::open(&dir, capture.max_bytes)
+        .with_context(|| format!("Failed to open capture buffer in {}", dir.display()))?;
+
+    let settings = SerialSettings::with_baud(capture.baud);
+    let serial = match open_named(name, &settings) {
+        Ok(serial) => Some(serial),
+        Err(e) => {
+            warn!("serial capture: {name} not available yet: {e}");
+            None
+        }
+    };
+    let port = spawn_port(name, settings, serial, Some(buffer));
+    ports.insert(name.to_string(), Arc::downgrade(&port));
+    captured.insert(name.to_string(), port);
+    Ok(())
+}
+
+fn open_named(name: &str, settings: &SerialSettings) -> io::Result<(SerialStream, PathBuf)> {
+    let device = resolve_port(name)?;
+    let serial = open_port(&device, settings)?;
+    Ok((serial, device))
+}
+
+fn spawn_port(
+    name: &str,
+    settings: SerialSettings,
+    serial: Option<(SerialStream, PathBuf)>,
+    capture: Option<CaptureBuffer>,
+) -> Arc<SharedPort> {
+    let (cmd_tx, cmd_rx) = mpsc::channel(64);
+    let (live_tx, _) = broadcast::channel(LIVE_BUFFER);
+    let (settings_tx, settings_rx) = watch::channel(settings);
+    let (modem_tx, modem_rx) = watch::channel(None);
+    let closed = CancellationToken::new();
+    let next_seq = capture.as_ref().map_or(0, |c| c.next_seq);
+    let capture = capture.map(|c| CaptureHandle::spawn(name, c));
+    let device = Arc::new(Mutex::new(None));
+
+    let owner = PortOwner {
+        name: name.to_string(),
+        cmd_rx,
+        live_tx: live_tx.clone(),
+        settings_tx,
+        modem_tx,
+        capture: capture.as_ref().map(|c| c.writer.clone()),
+        next_seq,
+        capture_lagging: false,
+        device: device.clone(),
+    };
+    let done = closed.clone();
+    tokio::spawn(async move {
+        owner.run(serial).await;
+        done.cancel();
+    });
+
+    Arc::new(SharedPort {
+        cmd_tx,
+        live_tx,
+        settings: settings_rx,
+        modem: modem_rx,
+        closed,
+        capture,
+        device,
+    })
+}
+
+struct PortOwner {
+    name: String,
+    cmd_rx: mpsc::Receiver<PortCmd>,
+    live_tx: broadcast::Sender<Arc<CapturedChunk>>,
+    settings_tx: watch::Sender<SerialSettings>,
+    modem_tx: watch::Sender<Option<ModemStatus>>,
+    capture: Option<SyncSender<CaptureCmd>>,
+    next_seq: u64,
+    capture_lagging: bool,
+    device: Arc<Mutex<Option<PathBuf>>>,
+}
+
+impl PortOwner {
+    async fn run(mut self, mut serial: Option<(SerialStream, PathBuf)>) {
+        let mut backoff = REOPEN_BACKOFF_MIN;
+        loop {
+            if let Some((mut port, device)) = serial.take() {
+                *self.device.lock().unwrap() = Some(device);
+                let result = self.pump(&mut port).await;
+                *self.device.lock().unwrap() = None;
+                match result {
+                    Ok(()) => {
+                        debug!("serial {}: closed", self.name);
+                        return;
+                    }
+                    Err(e) => warn!("serial {}: {e}", self.name),
+                }
+                self.modem_tx.send_replace(None);
+                if self.capture.is_none() {
+                    return;
+                }
+            }
+
+            // Only captured ports get here; keep answering viewers while the
+            // port is away.
+            let retry = tokio::time::sleep(backoff);
+            tokio::pin!(retry);
+            loop {
+                tokio::select! {
+                    _ = &mut retry => break,
+                    cmd = self.cmd_rx.recv() => match cmd {
+                        Some(PortCmd::Control(_, reply)) => {
+                            let _ = reply.send(Err("port is not connected".to_string()));
+                        }
+                        Some(PortCmd::Write(_)) => {}
+                        None => return,
+                    },
+                    _ = SHUTDOWN.cancelled() => return,
+                }
+            }
+
+            let settings = *self.settings_tx.borrow();
+            match open_named(&self.name, &settings) {
+                Ok(port) => {
+                    info!("serial {}: reopened on {}", self.name, port.1.display());
+                    serial = Some(port);
+                    backoff = REOPEN_BACKOFF_MIN;
+                }
+                Err(e) => {
+                    debug!("serial {}: reopen failed: {e}", self.name);
+                    backoff = (backoff * 2).min(REOPEN_BACKOFF_MAX);
+                }
+            }
+        }
+    }
+
+    /// Move data until every handle is gone (`Ok`) or the port fails.
+    async fn pump(&mut self, serial: &mut SerialStream) -> io::Result<()> {
+        let mut buf = [0u8; 4096];
+        let mut modem_poll = tokio::time::interval(MODEM_POLL_INTERVAL);
+        modem_poll.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        let mut has_modem = true;
+
+        loop {
+            // Every handle holds one receiver; more means someone is watching.
+            let watched = has_modem && self.modem_tx.receiver_count() > 1;
+            tokio::select! {
+                cmd = self.cmd_rx.recv() => match cmd {
+                    Some(PortCmd::Write(data)) => serial.write_all(&data).await?,
+                    Some(PortCmd::Control(ctl, reply)) => {
+                        let applied = apply_control(serial, &ctl).map_err(|e| e.to_string());
+                        if applied.is_ok() {
+                            self.settings_tx.send_modify(|s| update_settings(s, &ctl));
+                        }
+                        let _ = reply.send(applied);
+                    }
+                    None => return Ok(()),
+                },
+
+                n = serial.read(&mut buf) => match n? {
+                    0 => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "port closed")),
+                    n => self.publish(&buf[..n]),
+                },
+
+                // Ports without modem lines (e.g. some USB CDC adapters) stop
+                // being polled after the first failed read.
+                _ = modem_poll.tick(), if watched => match read_modem_status(serial) {
+                    Ok(status) => {
+                        self.modem_tx.send_if_modified(|m| {
+                            let changed = *m != Some(status);
+                            *m = Some(status);
+                            changed
+                        });
+                    }
+                    Err(e) => {
+                        debug!("serial {}: modem lines unavailable: {e}", self.name);
+                        has_modem = false;
+                    }
+                },
+
+                _ = SHUTDOWN.cancelled() => return Ok(()),
+            }
+        }
+    }
+
+    /// Never touches the disk: recording is handed to the capture writer.
+    fn publish(&mut self, data: &[u8]) {
+        let chunk = Arc::new(CapturedChunk {
+            seq: self.next_seq,
+            at_ms: now_ms(),
+            data: data.to_vec(),
+        });
+        self.next_seq += 1;
+
+        if let Some(writer) = &self.capture {
+            match writer.try_send(CaptureCmd::Append(chunk.clone())) {
+                Ok(()) => self.capture_lagging = false,
+                Err(TrySendError::Full(_)) if !self.capture_lagging => {
+                    warn!(
+                        "serial {}: capture writer behind, output not recorded",
+                        self.name
+                    );
+                    self.capture_lagging = true;
+                }
+                Err(_) => {}
+            }
+        }
+        let _ = self.live_tx.send(chunk);
+    }
+}
+
+fn open_port(path: &Path, settings: &SerialSettings) -> tokio_serial::Result<SerialStream> {
+    tokio_serial::new(path.to_string_lossy(), settings.baud)
+        .data_bits(tokio_serial::DataBits::Eight)
+        .parity(tokio_serial::Parity::None)
+        .stop_bits(tokio_serial::StopBits::One)
+        .flow_control(tokio_serial::FlowControl::None)
+        .open_native_async()
+}
+
+fn apply_control(serial: &mut SerialStream, ctl: &SerialControl) -> tokio_serial::Result<()> {
+    match *ctl {
+        SerialControl::Baud { baud } => serial.set_baud_rate(baud),
+        SerialControl::DataBits { bits } => {
+            let bits = tokio_serial::DataBits::try_from(bits).map_err(|_| {
+                tokio_serial::Error::new(
+                    tokio_serial::ErrorKind::InvalidInput,
+                    format!("unsupported data bits: {bits}"),
+                )
+            })?;
+            serial.set_data_bits(bits)
+        }
+        SerialControl::Parity { parity } => serial.set_parity(match parity {
+            Parity::None => tokio_serial::Parity::None,
+            Parity::Odd => tokio_serial::Parity::Odd,
+            Parity::Even => tokio_serial::Parity::Even,
+        }),
+        SerialControl::StopBits { bits } => {
+            let bits = tokio_serial::StopBits::try_from(bits).map_err(|_| {
+                tokio_serial::Error::new(
+                    tokio_serial::ErrorKind::InvalidInput,
+                    format!("unsupported stop bits: {bits}"),
+                )
+            })?;
+            serial.set_stop_bits(bits)
+        }
+        SerialControl::FlowControl { flow_control } => {
+            serial.set_flow_control(match flow_control {
+                FlowControl::None => tokio_serial::FlowControl::None,
+                FlowControl::Software => tokio_serial::FlowControl::Software,
+                FlowControl::Hardware => tokio_serial::FlowControl::Hardware,
+            })
+        }
+        SerialControl::Dtr { on } => serial.write_data_terminal_ready(on),
+        SerialControl::Rts { on } => serial.write_request_to_send(on),
+        SerialControl::Break { on: true } => serial.set_break(),
+        SerialControl::Break { on: false } => serial.clear_break(),
+        SerialControl::Purge { rx, tx } => match (rx, tx) {
+            (true, true) => serial.clear(ClearBuffer::All),
+            (true, false) => serial.clear(ClearBuffer::Input),
+            (false, true) => serial.clear(ClearBuffer::Output),
+            (false, false) => Ok(()),
+        },
+    }
+}
+
+/// Track settings changes so a reopened port and new viewers see them.
+fn update_settings(settings: &mut SerialSettings, ctl: &SerialControl) {
+    match *ctl {
+        SerialControl::Baud { baud } => settings.baud = baud,
+        SerialControl::DataBits { bits } => settings.data_bits = bits,
+        SerialControl::Parity { parity } => settings.parity = parity,
+        SerialControl::StopBits { bits } => settings.stop_bits = bits,
+        SerialControl::FlowControl { flow_control } => settings.flow_control = flow_control,
+        _ => {}
+    }
+}
+
+fn read_modem_status(serial: &mut SerialStream) -> tokio_serial::Result<ModemStatus> {
+    Ok(ModemStatus {
+        cts: serial.read_clear_to_send()?,
+        dsr: serial.read_data_set_ready()?,
+        ri: serial.read_ring_indicator()?,
+        cd: serial.read_carrier_detect()?,
+    })
+}
+
+enum CaptureCmd {
+    Append(Arc<CapturedChunk>),
+    /// Answered once everything queued before it is on disk.
+    Flush(std::sync::mpsc::Sender<()>),
+}
+
+/// A port's capture buffer, written by a dedicated thread so that disk I/O
+/// never holds up the port's owner task.
+#[derive(Clone)]
+struct CaptureHandle {
+    buffer: Arc<Mutex<CaptureBuffer>>,
+    writer: SyncSender<CaptureCmd>,
+}
+
+impl CaptureHandle {
+    /// The writer thread ends once every sender is gone.
+    fn spawn(name: &str, buffer: CaptureBuffer) -> Self {
+        let buffer = Arc::new(Mutex::new(buffer));
+        let (writer, commands) = sync_channel(CAPTURE_QUEUE);
+        let name = name.to_string();
+        let target = buffer.clone();
+        let spawned = std::thread::Builder::new()
+            .name("serial-capture".to_string())
+            .spawn(move || {
+                let mut disk_failing = false;
+                for cmd in commands {
+                    match cmd {
+                        CaptureCmd::Append(chunk) => match target.lock().unwrap().append(&chunk) {
+                            Ok(()) => disk_failing = false,
+                            Err(e) if !disk_failing => {
+                                warn!("serial {name}: failed to record output: {e}");
+                                disk_failing = true;
+                            }
+                            Err(_) => {}
+                        },
+                        CaptureCmd::Flush(done) => {
+                            let _ = done.send(());
+                        }
+                    }
+                }
+            });
+        if let Err(e) = spawned {
+            warn!("serial capture writer not started: {e}");
+        }
+        Self { buffer, writer }
+    }
+
+    /// Blocking. Waits for queued output to be written, so history lines up
+    /// with what live viewers have already been sent, then reads the segments
+    /// without holding the buffer lock.
+    fn read_since(&self, since_ms: u64) -> io::Result<Vec<CapturedChunk>> {
+        let (done_tx, done_rx) = std::sync::mpsc::channel();
+        if self.writer.send(CaptureCmd::Flush(done_tx)).is_ok() {
+            let _ = done_rx.recv();
+        }
+        let segments = self.buffer.lock().unwrap().segments();
+        segments.read_since(since_ms)
+    }
+}
+
+/// Rotating on-disk record of a port's output.
+///
+/// Output is appended to segment files named after the `seq` of their first
+/// chunk, each record a `u32` length followed by an encoded
+/// [`CapturedChunk`]. Once the segments add up to more than `max_bytes` the
+/// oldest is deleted. A record torn by a crash is cut off on the next open.
+struct CaptureBuffer {
+    dir: PathBuf,
+    max_bytes: u64,
+    segment_bytes: u64,
+    /// First seq and size of each segment, oldest first; the last is written to.
+    segments: VecDeque<(u64, u64)>,
+    file: Option<File>,
+    next_seq: u64,
+}
+
+impl CaptureBuffer {
+    fn open(dir: &Path, max_bytes: u64) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+
+        let mut segments = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "log")
+                && let Some(first_seq) = path
+                    .file_stem()
+                    .and_then(|s| s.to_str())
+                    .and_then(|s| s.parse::<u64>().ok())
+            {
+                segments.push((first_seq, fs::metadata(&path)?.len()));
+            }
+        }
+        segments.sort_unstable();
+
+        let mut buffer = Self {
+            dir: dir.to_path_buf(),
+            max_bytes,
+            segment_bytes: (max_bytes / 4).max(MIN_SEGMENT_BYTES),
+            segments: segments.into(),
+            file: None,
+            next_seq: 0,
+        };
+
+        if let Some((first_seq, size)) = buffer.segments.back_mut() {
+            let path = buffer.dir.join(segment_file(*first_seq));
+            let (chunks, valid_len) = parse_records(&fs::read(&path)?);
+            if valid_len < *size as usize {
+                OpenOptions::new()
+                    .write(true)
+                    .open(&path)?
+                    .set_len(valid_len as u64)?;
+                *size = valid_len as u64;
+            }
+            buffer.next_seq = chunks.last().map_or(*first_seq, |c| c.seq + 1);
+        }
+        buffer.trim()?;
+        Ok(buffer)
+    }
+
+    fn append(&mut self, chunk: &CapturedChunk) -> io::Result<()> {
+        let encoded = chunk.encode();
+        let mut record = Vec::with_capacity(4 + encoded.len());
+        record.extend_from_slice(&(encoded.len() as u32).to_be_bytes());
+        record.extend_from_slice(&encoded);
+        let len = record.len() as u64;
+
+        let rotate = match self.segments.back() {
+            Some((_, size)) => *size > 0 && size + len > self.segment_bytes,
+            None => true,
+        };
+        if rotate {
+            self.segments.push_back((chunk.seq, 0));
+            self.file = None;
+        }
+
+        let (first_seq, size) = self.segments.back_mut().expect("segment just ensured");
+        if self.file.is_none() {
+            self.file = Some(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(self.dir.join(segment_file(*first_seq)))?,
+            );
+        }
+        io::Write::write_all(self.file.as_mut().unwrap(), &record)?;
+        *size += len;
+        self.next_seq = chunk.seq + 1;
+        self.trim()
+    }
+
+    fn trim(&mut self) -> io::Result<()> {
+        let mut total: u64 = self.segments.iter().map(|(_, size)| size).sum();
+        while total > self.max_bytes && self.segments.len() > 1 {
+            let (first_seq, size) = self.segments.pop_front().unwrap();
+            match fs::remove_file(self.dir.join(segment_file(first_seq))) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+            total -= size;
+        }
+        Ok(())
+    }
+
+    fn segments(&self) -> SegmentList {
+        SegmentList {
+            dir: self.dir.clone(),
+            first_seqs: self
+                .segments
+                .iter()
+                .map(|(first_seq, _)| *first_seq)
+                .collect(),
+        }
+    }
+
+    #[cfg(test)]
+    fn read_since(&self, since_ms: u64) -> io::Result<Vec<CapturedChunk>> {
+        self.segments().read_since(since_ms)
+    }
+}
+
+/// Segments of a [`CaptureBuffer`] at one point in time. Reading them races
+/// with the writer: a segment trimmed meanwhile is skipped and a record
+/// still being appended is cut off, like after a crash.
+struct SegmentList {
+    dir: PathBuf,
+    first_seqs: Vec<u64>,
+}
+
+impl SegmentList {
+    fn read_since(&self, since_ms: u64) -> io::Result<Vec<CapturedChunk>> {
+        let mut chunks = Vec::new();
+        for first_seq in &self.first_seqs {
+            let buf = match fs::read(self.dir.join(segment_file(*first_seq))) {
+                Ok(buf) => buf,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+            let (records, _) = parse_records(&buf);
+            chunks.extend(records.into_iter().filter(|c| c.at_ms >= since_ms));
+        }
+        Ok(chunks)
+    }
+}
+
+fn segment_file(first_seq: u64) -> String {
+    format!("{first_seq:020}.log")
+}
+
+/// Complete records in `buf`, and how many bytes they take up.
+fn parse_records(buf: &[u8]) -> (Vec<CapturedChunk>, usize) {
+    let mut chunks = Vec::new();
+    let mut pos = 0;
+    while let Some(len_bytes) = buf.get(pos..pos + 4) {
+        let len = u32::from_be_bytes(len_bytes.try_into().unwrap()) as usize;
+        let Some(chunk) = buf
+            .get(pos + 4..pos + 4 + len)
+            .and_then(|record| CapturedChunk::decode(record).ok())
+        else {
+            break;
+        };
+        chunks.push(chunk);
+        pos += 4 + len;
+    }
+    (chunks, pos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn chunk(seq: u64, at_ms: u64) -> CapturedChunk {
+        CapturedChunk {
+            seq,
+            at_ms,
+            data: vec![b'x'; 1000],
+        }
+    }
+
+    #[test]
+    fn capture_buffer_rotates_within_budget() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut buffer = CaptureBuffer::open(dir.path(), 16 * 1024).unwrap();
+        for seq in 0..100 {
+            buffer.append(&chunk(seq, 1000 + seq)).unwrap();
+        }
+
+        let on_disk: u64 = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().metadata().unwrap().len())
+            .sum();
+        assert!(on_disk <= 16 * 1024, "{on_disk} bytes kept");
+
+        let kept = buffer.read_since(0).unwrap();
+        assert_eq!(kept.last().unwrap().seq, 99);
+        assert!(kept.windows(2).all(|w| w[1].seq == w[0].seq + 1));
+        assert!(kept.len() > 8, "only {} chunks kept", kept.len());
+
+        let recent = buffer.read_since(1095).unwrap();
+        assert_eq!(
+            recent.iter().map(|c| c.seq).collect::<Vec<_>>(),
+            [95, 96, 97, 98, 99]
+        );
+    }
+
+    #[test]
+    fn capture_buffer_recovers_after_torn_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut buffer = CaptureBuffer::open(dir.path(), 1024 * 1024).unwrap();
+        buffer.append(&chunk(0, 1)).unwrap();
+        buffer.append(&chunk(1, 2)).unwrap();
+        drop(buffer);
+
+        // Half a record, as left behind by a power cut mid-write.
+        let segment = dir.path().join(segment_file(0));
+        let mut file = OpenOptions::new().append(true).open(&segment).unwrap();
+        file.write_all(&[0, 0, 4, 0, 1, 2]).unwrap();
+
+        let mut buffer = CaptureBuffer::open(dir.path(), 1024 * 1024).unwrap();
+        assert_eq!(buffer.next_seq, 2);
+        buffer.append(&chunk(2, 3)).unwrap();
+        let seqs: Vec<_> = buffer
+            .read_since(0)
+            .unwrap()
+            .iter()
+            .map(|c| c.seq)
+            .collect();
+        assert_eq!(seqs, [0, 1, 2]);
+    }
+
+    #[test]
+    fn history_includes_queued_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let buffer = CaptureBuffer::open(dir.path(), 1024 * 1024).unwrap();
+        let capture = CaptureHandle::spawn("ttyTEST", buffer);
+        for seq in 0..3 {
+            capture
+                .writer
+                .send(CaptureCmd::Append(Arc::new(chunk(seq, 10 + seq))))
+                .unwrap();
+        }
+
+        let seqs: Vec<_> = capture
+            .read_since(11)
+            .unwrap()
+            .iter()
+            .map(|c| c.seq)
+            .collect();
+        assert_eq!(seqs, [1, 2]);
+    }
+
+    #[test]
+    fn aliases_are_keyed_by_device() {
+        let dev = tempfile::tempdir().unwrap();
+        let tty = dev.path().join("ttyUSB1");
+        File::create(&tty).unwrap();
+        let by_id = dev.path().join("serial/by-id");
+        let by_path = dev.path().join("serial/by-path");
+        fs::create_dir_all(&by_id).unwrap();
+        fs::create_dir_all(&by_path).unwrap();
+        std::os::unix::fs::symlink("../../ttyUSB1", by_id.join("usb-FTDI_A50285BI-if00-port0"))
+            .unwrap();
+        std::os::unix::fs::symlink(&tty, by_path.join("platform-xhci-usb-0:1:1.0-port0")).unwrap();
+
+        let aliases = collect_aliases(&dev.path().join("serial"));
+        assert_eq!(
+            aliases[&fs::canonicalize(&tty).unwrap()],
+            [
+                "by-id/usb-FTDI_A50285BI-if00-port0",
+                "by-path/platform-xhci-usb-0:1:1.0-port0"
+            ]
+        );
+    }
+
+    #[test]
+    fn port_names_accept_dev_paths() {
+        assert_eq!(port_name("/dev/ttyUSB0"), "ttyUSB0");
+        assert_eq!(port_name("ttyACM1"), "ttyACM1");
+    }
+}
@@ -60,6 +60,10 @@ pub struct StatusSummary {
     /// Populated when the caller passed `--since` (and optionally `--until`).
     #[serde(skip_serializing_if = "Option::is_none")]
     pub window: Option<WindowSummary>,
+    /// Data path to the device (`direct (<addr>)`, `relay` or `unreachable`),
+    /// only with `--probe-path`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connection: Option<String>,
 }
 
 impl StatusSummary {
@@ -184,6 +188,7 @@ pub fn summarize(device_name: &str, status: &DeviceStatus) -> StatusSummary {
         observations,
         open_incident_ids: incident_ids,
         window: None,
+        connection: None,
     }
 }
 
@@ -303,6 +308,7 @@ mod tests {
                 alive: None,
                 report_time: ts,
                 log_tail: None,
+                metrics: Default::default(),
             }),
             expires_at: None,
             created_at: ts,
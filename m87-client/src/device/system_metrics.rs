@@ -8,6 +8,9 @@ use std::{
 use sysinfo::{Disks, Networks, System};
 use tokio::sync::Mutex;
 
+use crate::device::containers::collect_container_metrics;
+use crate::device::thermal::{collect_power_metrics, collect_thermal_metrics};
+use crate::device::unit_metrics::latest_unit_metrics;
 use m87_shared::metrics::{
     CpuCoreMetrics, CpuMetrics, DiskMetrics, GpuMetrics, MemoryMetrics, NetworkInterfaceMetrics,
     NetworkMetrics, SystemMetrics,
@@ -163,6 +166,13 @@ pub async fn collect_system_metrics() -> Result<SystemMetrics> {
     // ---------------- GPU ----------------
     let gpu = collect_gpu_metrics().unwrap_or_default();
 
+    // ---------------- THERMAL / POWER ----------------
+    let thermal = collect_thermal_metrics();
+    let power = collect_power_metrics();
+
+    // ---------------- CONTAINERS ----------------
+    let containers = collect_container_metrics().await;
+
     // ---------------- META ----------------
     let hostname = System::host_name().unwrap_or_else(|| "unknown".into());
     let os = System::name().unwrap_or_else(|| "Unknown".into());
@@ -183,6 +193,10 @@ pub async fn collect_system_metrics() -> Result<SystemMetrics> {
         disk,
         network,
         gpu,
+        thermal,
+        power,
+        containers,
+        units: latest_unit_metrics(),
         timestamp: now as u64,
     })
 }
@@ -0,0 +1,277 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::OnceLock,
+};
+
+use m87_shared::metrics::{
+    FanMetrics, PowerMetrics, ThermalMetrics, ThermalZoneMetrics, ThrottleFlags,
+};
+
+const SYSFS: &str = "/sys";
+/// Raspberry Pi firmware's throttle bitmask, same as `vcgencmd get_throttled`.
+const PI_GET_THROTTLED: &str = "devices/platform/soc/soc:firmware/get_throttled";
+
+pub fn collect_thermal_metrics() -> Option<ThermalMetrics> {
+    collect_thermal(Path::new(SYSFS))
+}
+
+pub fn collect_power_metrics() -> Option<PowerMetrics> {
+    let sysfs = Path::new(SYSFS);
+    let throttle = match read_trimmed(&sysfs.join(PI_GET_THROTTLED)) {
+        Some(raw) => parse_throttled(&raw),
+        None => vcgencmd_throttled(),
+    };
+    collect_power(sysfs, throttle)
+}
+
+/// `thermal_zone*` temperatures and hwmon fan tachometers.
+fn collect_thermal(sysfs: &Path) -> Option<ThermalMetrics> {
+    let zones = sorted_entries(&sysfs.join("class/thermal"), "thermal_zone")
+        .into_iter()
+        .filter_map(|zone| {
+            let millis: i64 = read_trimmed(&zone.join("temp"))?.parse().ok()?;
+            Some(ThermalZoneMetrics {
+                name: read_trimmed(&zone.join("type")).unwrap_or_else(|| file_name(&zone)),
+                temp_c: millis as f32 / 1000.0,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut fans = Vec::new();
+    for hwmon in sorted_entries(&sysfs.join("class/hwmon"), "hwmon") {
+        let chip = read_trimmed(&hwmon.join("name")).unwrap_or_else(|| file_name(&hwmon));
+        for input in sorted_entries(&hwmon, "fan") {
+            let name = file_name(&input);
+            let Some(fan) = name.strip_suffix("_input") else {
+                continue;
+            };
+            if let Some(rpm) = read_trimmed(&input).and_then(|v| v.parse().ok()) {
+                fans.push(FanMetrics {
+                    name: format!("{chip}/{fan}"),
+                    rpm,
+                });
+            }
+        }
+    }
+
+    (!zones.is_empty() || !fans.is_empty()).then_some(ThermalMetrics { zones, fans })
+}
+
+/// CPU frequency from cpufreq. Without firmware flags (`throttle` is `None`
+/// off a Raspberry Pi), throttling is inferred from active CPU/GPU cooling
+/// devices, capping from a lowered `scaling_max_freq` (e.g. a Jetson power
+/// model) and undervoltage from hwmon low-voltage alarms.
+fn collect_power(
+    sysfs: &Path,
+    throttle: Option<(ThrottleFlags, ThrottleFlags)>,
+) -> Option<PowerMetrics> {
+    let cpus = sorted_entries(&sysfs.join("devices/system/cpu"), "cpu");
+    let khz = |name: &str| -> Vec<u64> {
+        cpus.iter()
+            .filter_map(|cpu| read_trimmed(&cpu.join("cpufreq").join(name))?.parse().ok())
+            .collect()
+    };
+    let cur = khz("scaling_cur_freq");
+    let max = khz("cpuinfo_max_freq");
+    let cpu_freq_mhz =
+        (!cur.is_empty()).then(|| (cur.iter().sum::<u64>() / cur.len() as u64 / 1000) as u32);
+    let cpu_max_freq_mhz = max.iter().max().map(|khz| (khz / 1000) as u32);
+
+    if let Some((now, since_boot)) = throttle {
+        return Some(PowerMetrics {
+            cpu_freq_mhz,
+            cpu_max_freq_mhz,
+            throttle: now,
+            throttle_since_boot: Some(since_boot),
+        });
+    }
+
+    let scaling_max = khz("scaling_max_freq");
+    let freq_capped = match (scaling_max.iter().max(), max.iter().max()) {
+        (Some(scaling), Some(max)) => scaling < max,
+        _ => false,
+    };
+    let throttled = sorted_entries(&sysfs.join("class/thermal"), "cooling_device")
+        .iter()
+        .any(|device| {
+            let kind = read_trimmed(&device.join("type")).unwrap_or_default();
+            let state: u64 = read_trimmed(&device.join("cur_state"))
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(0);
+            (kind.contains("cpu") || kind.contains("gpu")) && state > 0
+        });
+    let undervoltage = sorted_entries(&sysfs.join("class/hwmon"), "hwmon")
+        .iter()
+        .flat_map(|hwmon| sorted_entries(hwmon, "in"))
+        .filter(|path| file_name(path).ends_with("_lcrit_alarm"))
+        .any(|alarm| read_trimmed(&alarm).as_deref() == Some("1"));
+
+    if cur.is_empty() && max.is_empty() && !undervoltage && !throttled {
+        return None;
+    }
+    Some(PowerMetrics {
+        cpu_freq_mhz,
+        cpu_max_freq_mhz,
+        throttle: ThrottleFlags {
+            undervoltage,
+            freq_capped,
+            throttled,
+            soft_temp_limit: false,
+        },
+        throttle_since_boot: None,
+    })
+}
+
+static HAS_VCGENCMD: OnceLock<bool> = OnceLock::new();
+
+/// Older Raspberry Pi kernels only expose the flags through `vcgencmd`.
+fn vcgencmd_throttled() -> Option<(ThrottleFlags, ThrottleFlags)> {
+    let has_vcgencmd = *HAS_VCGENCMD.get_or_init(|| {
+        Command::new("vcgencmd")
+            .arg("version")
+            .output()
+            .map(|o| o.status.success())
+            .unwrap_or(false)
+    });
+    if !has_vcgencmd {
+        return None;
+    }
+    let out = Command::new("vcgencmd")
+        .arg("get_throttled")
+        .output()
+        .ok()?;
+    if !out.status.success() {
+        return None;
+    }
+    parse_throttled(&String::from_utf8_lossy(&out.stdout))
+}
+
+/// The current and since-boot flags of a `get_throttled` bitmask, given as
+/// `throttled=0x50005` (vcgencmd) or `50005` (sysfs).
+fn parse_throttled(raw: &str) -> Option<(ThrottleFlags, ThrottleFlags)> {
+    let hex = raw.trim();
+    let hex = hex.strip_prefix("throttled=").unwrap_or(hex);
+    let hex = hex.strip_prefix("0x").unwrap_or(hex);
+    let bits = u32::from_str_radix(hex, 16).ok()?;
+    let flags = |shift: u32| ThrottleFlags {
+        undervoltage: bits >> shift & 1 != 0,
+        freq_capped: bits >> (shift + 1) & 1 != 0,
+        throttled: bits >> (shift + 2) & 1 != 0,
+        soft_temp_limit: bits >> (shift + 3) & 1 != 0,
+    };
+    Some((flags(0), flags(16)))
+}
+
+/// Entries of `dir` named `<prefix>...`, in name order.
+fn sorted_entries(dir: &Path, prefix: &str) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_name().to_string_lossy().starts_with(prefix))
+        .map(|e| e.path())
+        .collect();
+    paths.sort();
+    paths
+}
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    fs::read_to_string(path).ok().map(|s| s.trim().to_string())
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(root: &Path, path: &str, content: &str) {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn parses_pi_throttle_bits() {
+        // Undervoltage and throttling now, frequency capping earlier.
+        let (now, since_boot) = parse_throttled("throttled=0x60005\n").unwrap();
+        assert_eq!(now.names(), ["undervoltage", "throttled"]);
+        assert_eq!(since_boot.names(), ["freq_capped", "throttled"]);
+
+        let (now, since_boot) = parse_throttled("0").unwrap();
+        assert!(!now.any() && !since_boot.any());
+        assert!(parse_throttled("error=1").is_none());
+    }
+
+    #[test]
+    fn reads_zones_fans_and_frequencies() {
+        let dir = tempfile::tempdir().unwrap();
+        let sysfs = dir.path();
+        write(sysfs, "class/thermal/thermal_zone0/type", "cpu-thermal\n");
+        write(sysfs, "class/thermal/thermal_zone0/temp", "61250\n");
+        write(sysfs, "class/thermal/thermal_zone1/type", "GPU-therm\n");
+        write(sysfs, "class/thermal/thermal_zone1/temp", "48000\n");
+        write(sysfs, "class/thermal/cooling_device0/type", "pwm-fan\n");
+        write(sysfs, "class/thermal/cooling_device0/cur_state", "3\n");
+        write(sysfs, "class/hwmon/hwmon0/name", "pwmfan\n");
+        write(sysfs, "class/hwmon/hwmon0/fan1_input", "3100\n");
+        write(sysfs, "class/hwmon/hwmon1/name", "rpi_volt\n");
+        write(sysfs, "class/hwmon/hwmon1/in0_lcrit_alarm", "1\n");
+        for (cpu, cur) in [("cpu0", "1500000"), ("cpu1", "1300000")] {
+            let cpufreq = format!("devices/system/cpu/{cpu}/cpufreq");
+            write(sysfs, &format!("{cpufreq}/scaling_cur_freq"), cur);
+            write(sysfs, &format!("{cpufreq}/scaling_max_freq"), "1800000");
+            write(sysfs, &format!("{cpufreq}/cpuinfo_max_freq"), "1800000");
+        }
+
+        let thermal = collect_thermal(sysfs).unwrap();
+        assert_eq!(thermal.zones.len(), 2);
+        assert_eq!(thermal.zones[0].name, "cpu-thermal");
+        assert_eq!(thermal.max_temp_c(), Some(61.25));
+        assert_eq!(thermal.fans[0].name, "pwmfan/fan1");
+        assert_eq!(thermal.min_fan_rpm(), Some(3100));
+
+        let power = collect_power(sysfs, None).unwrap();
+        assert_eq!(power.cpu_freq_mhz, Some(1400));
+        assert_eq!(power.cpu_max_freq_mhz, Some(1800));
+        // A spinning fan is cooling, not throttling.
+        assert_eq!(power.throttle.names(), ["undervoltage"]);
+        assert!(power.throttle_since_boot.is_none());
+
+        write(
+            sysfs,
+            "class/thermal/cooling_device1/type",
+            "thermal-cpufreq-0\n",
+        );
+        write(sysfs, "class/thermal/cooling_device1/cur_state", "2\n");
+        write(
+            sysfs,
+            "devices/system/cpu/cpu0/cpufreq/scaling_max_freq",
+            "1200000",
+        );
+        write(
+            sysfs,
+            "devices/system/cpu/cpu1/cpufreq/scaling_max_freq",
+            "1200000",
+        );
+        let power = collect_power(sysfs, None).unwrap();
+        assert_eq!(
+            power.throttle.names(),
+            ["undervoltage", "freq_capped", "throttled"]
+        );
+    }
+
+    #[test]
+    fn nothing_to_report_without_sysfs_nodes() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(collect_thermal(dir.path()).is_none());
+        assert!(collect_power(dir.path(), None).is_none());
+    }
+}
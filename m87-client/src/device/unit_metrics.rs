@@ -0,0 +1,166 @@
+use std::{collections::BTreeMap, path::Path, process::Stdio, time::Duration};
+
+use anyhow::{Result, anyhow, bail};
+use m87_shared::{deploy_spec::MetricsHook, metrics::UnitMetrics};
+use serde_json::Value;
+
+use crate::device::deployment_manager::{LocalRunState, RevisionStore};
+use crate::util::command::{build_command, safe_run_command};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Series kept per unit, so a chatty exporter can't bloat every heartbeat.
+const MAX_SERIES: usize = 100;
+const MAX_NAME_LEN: usize = 200;
+
+/// Run a unit's `metrics` hook in its workdir and parse what it printed.
+pub async fn run_metrics_hook(
+    wd: &Path,
+    env: &BTreeMap<String, String>,
+    hook: &MetricsHook,
+) -> Result<BTreeMap<String, f64>> {
+    let mut cmd = build_command(&hook.observe)?;
+    cmd.current_dir(wd).envs(env).stdin(Stdio::null());
+    let out = safe_run_command(cmd, hook.observe_timeout.unwrap_or(DEFAULT_TIMEOUT)).await?;
+    if !out.status.success() {
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        bail!("exited with {}: {}", out.status, stderr.trim());
+    }
+    parse_metrics(&String::from_utf8_lossy(&out.stdout))
+}
+
+/// The last values of each unit with a `metrics` hook, for heartbeats and the
+/// metrics stream.
+pub fn latest_unit_metrics() -> Vec<UnitMetrics> {
+    RevisionStore::unit_workdirs(None)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|(unit, _)| unit.observe.as_ref().is_some_and(|o| o.metrics.is_some()))
+        .filter_map(|(unit, wd)| {
+            let st = LocalRunState::load(&wd).ok()?;
+            Some(UnitMetrics {
+                unit: unit.id,
+                collected_at: st.metrics_at_ms?,
+                values: st.metrics,
+            })
+        })
+        .collect()
+}
+
+/// A JSON object (`{"fps": 29.7, "queue": {"depth": 3}}`, nested keys joined
+/// with `.`) or Prometheus text (`queue_depth{queue="in"} 3`, the labels kept
+/// as part of the name). Non-numeric and non-finite values are skipped.
+pub fn parse_metrics(output: &str) -> Result<BTreeMap<String, f64>> {
+    let output = output.trim();
+    let mut values = BTreeMap::new();
+    if output.starts_with('{') {
+        let json: Value = serde_json::from_str(output).map_err(|e| anyhow!("invalid JSON: {e}"))?;
+        flatten_json("", &json, &mut values);
+    } else {
+        for line in output.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some((name, value)) = parse_prometheus_line(line) {
+                insert(&mut values, name.to_string(), value);
+            }
+        }
+    }
+    if values.is_empty() && !output.is_empty() {
+        bail!("no numeric values in output");
+    }
+    Ok(values)
+}
+
+/// `name{labels} value [timestamp]`
+fn parse_prometheus_line(line: &str) -> Option<(&str, f64)> {
+    let name_end = match (line.find('{'), line.rfind('}')) {
+        (Some(open), Some(close)) if open < close => close + 1,
+        _ => line.find(char::is_whitespace)?,
+    };
+    let (name, rest) = line.split_at(name_end);
+    let value = rest.split_whitespace().next()?.parse().ok()?;
+    Some((name, value))
+}
+
+fn flatten_json(prefix: &str, value: &Value, values: &mut BTreeMap<String, f64>) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                let name = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{prefix}.{key}")
+                };
+                flatten_json(&name, value, values);
+            }
+        }
+        Value::Number(n) => {
+            if let Some(v) = n.as_f64() {
+                insert(values, prefix.to_string(), v);
+            }
+        }
+        Value::Bool(b) => insert(values, prefix.to_string(), if *b { 1.0 } else { 0.0 }),
+        _ => {}
+    }
+}
+
+fn insert(values: &mut BTreeMap<String, f64>, name: String, value: f64) {
+    if value.is_finite()
+        && !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && (values.len() < MAX_SERIES || values.contains_key(&name))
+    {
+        values.insert(name, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_prometheus_text() {
+        let out = "# HELP queue_depth Items waiting\n\
+                   # TYPE queue_depth gauge\n\
+                   queue_depth 12\n\
+                   inference_fps{model=\"yolo v8\"} 29.7 1700000000000\n\
+                   latency_seconds_bucket{le=\"+Inf\"} 4\n\
+                   last_error NaN\n\
+                   garbage\n";
+        let values = parse_metrics(out).unwrap();
+        assert_eq!(values.len(), 3);
+        assert_eq!(values["queue_depth"], 12.0);
+        assert_eq!(values["inference_fps{model=\"yolo v8\"}"], 29.7);
+        assert_eq!(values["latency_seconds_bucket{le=\"+Inf\"}"], 4.0);
+    }
+
+    #[test]
+    fn parses_json_objects() {
+        let out =
+            r#"{"fps": 29.7, "queue": {"depth": 3, "name": "in"}, "ready": true, "temp": null}"#;
+        let values = parse_metrics(out).unwrap();
+        assert_eq!(
+            values.into_iter().collect::<Vec<_>>(),
+            [
+                ("fps".to_string(), 29.7),
+                ("queue.depth".to_string(), 3.0),
+                ("ready".to_string(), 1.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_output_without_values() {
+        assert!(parse_metrics("").unwrap().is_empty());
+        assert!(parse_metrics("starting up...").is_err());
+        assert!(parse_metrics("{\"fps\": ").is_err());
+    }
+
+    #[test]
+    fn caps_the_number_of_series() {
+        let out: String = (0..MAX_SERIES + 10)
+            .map(|i| format!("m{i} {i}\n"))
+            .collect();
+        assert_eq!(parse_metrics(&out).unwrap().len(), MAX_SERIES);
+    }
+}
@@ -1,13 +1,18 @@
+use std::collections::BTreeMap;
 use std::io::{self, Write};
 
 use anyhow::{Result, anyhow};
-use m87_shared::device::{AuditLog, DeviceStatus, PublicDevice};
+use chrono::{SecondsFormat, TimeZone, Utc};
+use m87_shared::device::{
+    AuditLog, DeviceStatus, PublicDevice, SessionRecording, UpdateDeviceLabelsBody,
+};
 use m87_shared::roles::Role;
 use m87_shared::users::User;
 use tracing::warn;
 
 use crate::util::device_cache;
 use crate::util::servers_parallel::fanout_servers;
+use crate::util::time::{now_ms, parse_time};
 use crate::{auth::AuthManager, config::Config, server};
 
 pub async fn list_devices() -> Result<Vec<PublicDevice>> {
@@ -153,32 +158,65 @@ pub async fn get_device_status(name: &str) -> Result<DeviceStatus> {
     Ok(status)
 }
 
-pub async fn get_audit_logs(
-    name: &str,
-    until: Option<String>,
-    since: Option<String>,
-    max: u32,
-) -> Result<Vec<AuditLog>> {
+/// Filters for [`get_audit_logs`]. `since`/`until` accept the same formats
+/// as other `--since` flags (`30m`, `24h`, `2026-05-25`, RFC 3339).
+#[derive(Debug, Clone, Default)]
+pub struct AuditLogQuery {
+    pub since: Option<String>,
+    pub until: Option<String>,
+    pub max: u32,
+    /// Email or name of the acting user.
+    pub user: Option<String>,
+    /// Relayed stream type (`terminal`, `exec`, `forward`, ...).
+    pub stream_type: Option<String>,
+}
+
+pub async fn get_audit_logs(name: &str, query: AuditLogQuery) -> Result<Vec<AuditLog>> {
     let resolved = resolve_device_cached(name).await?;
 
     let token = AuthManager::get_cli_token().await?;
     let config = Config::load()?;
     let trust = config.trust_invalid_server_cert;
 
+    let now = now_ms();
+    let since = query.since.map(|s| time_param(&s, now)).transpose()?;
+    let until = query.until.map(|s| time_param(&s, now)).transpose()?;
+
     let logs = server::get_device_audit_logs(
         &resolved.url,
         &token,
         trust,
         &resolved.id,
-        max,
-        until,
+        query.max,
         since,
+        until,
+        query.user,
+        query.stream_type,
     )
     .await?;
 
     Ok(logs)
 }
 
+/// `--since`/`--until` value as the RFC 3339 timestamp the server expects.
+fn time_param(input: &str, now: u64) -> Result<String> {
+    let ms = parse_time(input, now)?;
+    Utc.timestamp_millis_opt(ms as i64)
+        .single()
+        .map(|dt| dt.to_rfc3339_opts(SecondsFormat::Millis, true))
+        .ok_or_else(|| anyhow!("timestamp out of range: {input}"))
+}
+
+pub async fn get_session_recording(name: &str, session_id: &str) -> Result<SessionRecording> {
+    let resolved = resolve_device_cached(name).await?;
+
+    let token = AuthManager::get_cli_token().await?;
+    let config = Config::load()?;
+    let trust = config.trust_invalid_server_cert;
+
+    server::get_session_recording(&resolved.url, &token, trust, &resolved.id, session_id).await
+}
+
 pub async fn get_device_users(name: &str) -> Result<Vec<User>> {
     let resolved = resolve_device_cached(name).await?;
 
@@ -191,6 +229,34 @@ pub async fn get_device_users(name: &str) -> Result<Vec<User>> {
     Ok(users)
 }
 
+/// Set and remove labels on a device; returns the labels after the change.
+pub async fn update_labels(
+    name: &str,
+    set: BTreeMap<String, String>,
+    remove: Vec<String>,
+) -> Result<BTreeMap<String, String>> {
+    let resolved = resolve_device_cached(name).await?;
+
+    let token = AuthManager::get_cli_token().await?;
+    let config = Config::load()?;
+    let trust = config.trust_invalid_server_cert;
+
+    let body = UpdateDeviceLabelsBody { set, remove };
+    server::update_device_labels(&resolved.url, &token, trust, &resolved.id, &body).await
+}
+
+/// Invalidate the device's key and disconnect it. It has to register and be
+/// approved again to reconnect.
+pub async fn revoke(name: &str) -> Result<()> {
+    let resolved = resolve_device_cached(name).await?;
+
+    let token = AuthManager::get_cli_token().await?;
+    let config = Config::load()?;
+    let trust = config.trust_invalid_server_cert;
+
+    server::revoke_device(&resolved.url, &token, trust, &resolved.id).await
+}
+
 pub async fn add_access(name: &str, email_or_org_id: &str, role: Role) -> Result<()> {
     let resolved = resolve_device_cached(name).await?;
 
@@ -17,6 +17,10 @@ pub mod tui;
 // === CLI entrypoint ===
 pub mod cli;
 
+pub mod admin;
+pub mod alerts;
+pub mod api_keys;
+pub mod logs;
 pub mod org;
 
 // MCP (Model Context Protocol) server for AI agent integration
@@ -0,0 +1,36 @@
+use anyhow::Result;
+use m87_shared::device::{DeviceLogEntry, DeviceLogQuery};
+
+use crate::{
+    auth::AuthManager, config::Config, org::get_or_resolve_default_org_id, server,
+    util::servers_parallel::fanout_servers,
+};
+
+/// Logs shipped by the org's devices, oldest first.
+pub async fn query_org_logs(
+    org_id: Option<String>,
+    query: DeviceLogQuery,
+) -> Result<Vec<DeviceLogEntry>> {
+    let token = AuthManager::get_cli_token().await?;
+    let config = Config::load()?;
+    let trust = config.trust_invalid_server_cert;
+
+    let org_id = get_or_resolve_default_org_id(org_id).await?;
+
+    let results = fanout_servers(config.manager_server_urls, 4, false, |server_url| {
+        let token = token.clone();
+        let org_id = org_id.clone();
+        let query = query.clone();
+        async move { server::list_org_logs(&server_url, &token, trust, &org_id, &query).await }
+    })
+    .await?;
+
+    // Each server returns its newest lines; keep the newest overall.
+    let mut logs: Vec<DeviceLogEntry> = results.into_iter().map(|(_, line)| line).collect();
+    logs.sort_by_key(|line| line.at_ms);
+    if let Some(limit) = query.limit {
+        let excess = logs.len().saturating_sub(limit as usize);
+        logs.drain(..excess);
+    }
+    Ok(logs)
+}
@@ -8,15 +8,17 @@ use std::sync::atomic::{AtomicU64, Ordering};
 
 use crate::{auth, device, devices, org, tui};
 use crate::device::forward::start_forward;
+use crate::device::progress::{ProgressEvent, ProgressSink, TransferProgress};
 use crate::streams::stream_type::ForwardTarget;
 use crate::util::shutdown::SHUTDOWN;
 use dashmap::DashMap;
 use rmcp::{
-    ServerHandler,
+    Peer, RoleServer, ServerHandler,
     handler::server::tool::ToolRouter,
     handler::server::wrapper::Parameters,
     model::{
-        CallToolResult, Content, ErrorData, Implementation, ServerCapabilities, ServerInfo,
+        CallToolResult, Content, ErrorData, Implementation, Meta, ProgressNotificationParam,
+        ServerCapabilities, ServerInfo,
     },
     schemars::{self, JsonSchema},
     tool, tool_handler, tool_router,
@@ -70,6 +72,41 @@ fn strip_ansi(s: &str) -> String {
     out
 }
 
+/// Forward cp/sync progress as MCP progress notifications when the caller
+/// sent a progress token. Nothing else is printed: stdout is the transport.
+fn transfer_progress(peer: Peer<RoleServer>, meta: &Meta) -> TransferProgress {
+    let Some(progress_token) = meta.get_progress_token() else {
+        return TransferProgress::new(ProgressSink::Callback(Arc::new(|_| {})));
+    };
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while let Some(param) = rx.recv().await {
+            if peer.notify_progress(param).await.is_err() {
+                break;
+            }
+        }
+    });
+    TransferProgress::new(ProgressSink::Callback(Arc::new(move |event| {
+        let ProgressEvent::File {
+            path,
+            index,
+            files,
+            done_bytes,
+            total_bytes,
+            ..
+        } = event
+        else {
+            return;
+        };
+        let _ = tx.send(ProgressNotificationParam {
+            progress_token: progress_token.clone(),
+            progress: *done_bytes as f64,
+            total: Some(*total_bytes as f64),
+            message: Some(format!("[{index}/{files}] {path}")),
+        });
+    })))
+}
+
 // ===== Batch support =====
 
 #[derive(Deserialize, JsonSchema)]
@@ -158,6 +195,11 @@ struct DeviceAuditLogsReq {
     until: Option<String>,
     /// Maximum number of logs
     max: Option<u32>,
+    /// Only entries by this user (email or name)
+    user: Option<String>,
+    /// Only relayed-traffic entries of this stream type (terminal, exec, forward, ...)
+    #[serde(rename = "type")]
+    stream_type: Option<String>,
 }
 
 #[derive(Deserialize, JsonSchema)]
@@ -210,6 +252,12 @@ struct DeviceSyncReq {
     dry_run: Option<bool>,
     /// Exclude patterns
     exclude: Option<Vec<String>>,
+    /// Read .gitignore / .m87ignore files (default true)
+    ignore_files: Option<bool>,
+    /// Sync changes in both directions (deletions included)
+    bidirectional: Option<bool>,
+    /// Conflict policy for bidirectional sync (newer, keep-both, abort)
+    on_conflict: Option<String>,
 }
 
 #[derive(Deserialize, JsonSchema)]
@@ -469,14 +517,18 @@ impl M87McpServer {
     async fn device_audit_logs(&self, Parameters(req): Parameters<DeviceAuditLogsReq>) -> Result<CallToolResult, ErrorData> {
         let (devices, is_batch) = req.target.resolve()?;
         let max = req.max.unwrap_or(100);
-        let until = req.until;
-        let since = req.since;
+        let query = devices::AuditLogQuery {
+            since: req.since,
+            until: req.until,
+            max,
+            user: req.user,
+            stream_type: req.stream_type,
+        };
 
         let results = run_batch(devices, |device| {
-            let until = until.clone();
-            let since = since.clone();
+            let query = query.clone();
             async move {
-                match devices::get_audit_logs(&device, until, since, max).await {
+                match devices::get_audit_logs(&device, query).await {
                     Ok(logs) => match serde_json::to_value(&logs) {
                         Ok(v) => serde_json::json!({ "logs": v }),
                         Err(e) => serde_json::json!({ "error": format!("{e:?}") }),
@@ -532,19 +584,50 @@ impl M87McpServer {
     }
 
     #[tool(description = "Copy files between local and remote device")]
-    async fn device_cp(&self, Parameters(req): Parameters<DeviceCpReq>) -> Result<CallToolResult, ErrorData> {
-        device::fs::copy(&req.source, &req.dest).await
+    async fn device_cp(
+        &self,
+        Parameters(req): Parameters<DeviceCpReq>,
+        peer: Peer<RoleServer>,
+        meta: Meta,
+    ) -> Result<CallToolResult, ErrorData> {
+        let progress = transfer_progress(peer, &meta);
+        device::fs::copy(&req.source, &req.dest, &progress).await
             .map_err(internal_err)?;
         Ok(CallToolResult::success(vec![Content::text(serde_json::json!({"status": "copied"}).to_string())]))
     }
 
     #[tool(description = "Sync files between local and remote device")]
-    async fn device_sync(&self, Parameters(req): Parameters<DeviceSyncReq>) -> Result<CallToolResult, ErrorData> {
+    async fn device_sync(
+        &self,
+        Parameters(req): Parameters<DeviceSyncReq>,
+        peer: Peer<RoleServer>,
+        meta: Meta,
+    ) -> Result<CallToolResult, ErrorData> {
+        let progress = transfer_progress(peer, &meta);
         let delete = req.delete.unwrap_or(false);
         let dry_run = req.dry_run.unwrap_or(false);
-        let exclude = req.exclude.unwrap_or_default();
-        device::fs::sync(&req.source, &req.dest, delete, dry_run, &exclude).await
-            .map_err(internal_err)?;
+        let filter = device::fs::SyncFilter::new(
+            req.exclude.unwrap_or_default(),
+            req.ignore_files.unwrap_or(true),
+        );
+        if req.bidirectional.unwrap_or(false) {
+            let policy = match req.on_conflict.as_deref().unwrap_or("abort") {
+                "newer" => device::fs::ConflictPolicy::Newer,
+                "keep-both" => device::fs::ConflictPolicy::KeepBoth,
+                "abort" => device::fs::ConflictPolicy::Abort,
+                other => {
+                    return Err(ErrorData::invalid_request(
+                        format!("Invalid on_conflict '{other}' (newer, keep-both, abort)"),
+                        None,
+                    ));
+                }
+            };
+            device::fs::sync_bidirectional(&req.source, &req.dest, policy, dry_run, &filter, &progress).await
+                .map_err(internal_err)?;
+        } else {
+            device::fs::sync(&req.source, &req.dest, delete, dry_run, &filter, &progress).await
+                .map_err(internal_err)?;
+        }
         Ok(CallToolResult::success(vec![Content::text(serde_json::json!({"status": "synced"}).to_string())]))
     }
 
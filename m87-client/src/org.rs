@@ -3,7 +3,10 @@ use std::collections::HashSet;
 use anyhow::{Result, anyhow};
 use m87_shared::{
     device::PublicDevice,
-    org::{Invite, Organization},
+    org::{
+        CreateWebhookBody, CreatedWebhook, Invite, OrgSettings, Organization,
+        UpdateOrgSettingsBody, Webhook, WebhookDelivery, WebhookEvent,
+    },
     roles::Role,
     users::User,
 };
@@ -87,6 +90,37 @@ pub async fn update_organization(id: &str, new_id: &str) -> Result<()> {
     Ok(())
 }
 
+/// Read the org's settings, applying `update` first if it changes anything.
+pub async fn settings(id: Option<String>, update: UpdateOrgSettingsBody) -> Result<OrgSettings> {
+    let token = AuthManager::get_cli_token().await?;
+    let config = Config::load()?;
+    let trust = config.trust_invalid_server_cert;
+
+    let id = get_or_resolve_default_org_id(id).await?;
+    let update = &update;
+
+    let results = fanout_servers(config.manager_server_urls, 4, false, |server_url| {
+        let token = token.clone();
+        let id = id.clone();
+        async move {
+            let settings = match update.record_sessions {
+                Some(_) => {
+                    server::update_org_settings(&server_url, &token, trust, &id, update).await?
+                }
+                None => server::get_org_settings(&server_url, &token, trust, &id).await?,
+            };
+            Ok(vec![settings])
+        }
+    })
+    .await?;
+
+    results
+        .into_iter()
+        .next()
+        .map(|(_, settings)| settings)
+        .ok_or_else(|| anyhow!("Organization {id} not found"))
+}
+
 // list members
 pub async fn list_members(id: Option<String>) -> Result<Vec<User>> {
     let token = AuthManager::get_cli_token().await?;
@@ -278,6 +312,102 @@ pub async fn remove_device(org_id: Option<String>, device_name: &str) -> Result<
     Ok(())
 }
 
+pub async fn list_webhooks(org_id: Option<String>) -> Result<Vec<Webhook>> {
+    let token = AuthManager::get_cli_token().await?;
+    let config = Config::load()?;
+    let trust = config.trust_invalid_server_cert;
+
+    let org_id = get_or_resolve_default_org_id(org_id).await?;
+
+    let results = fanout_servers(config.manager_server_urls, 4, false, |server_url| {
+        let token = token.clone();
+        let org_id = org_id.clone();
+        async move { server::list_org_webhooks(&server_url, &token, trust, &org_id).await }
+    })
+    .await?;
+
+    Ok(results.into_iter().map(|(_, hook)| hook).collect())
+}
+
+pub async fn add_webhook(
+    org_id: Option<String>,
+    url: &str,
+    events: Vec<WebhookEvent>,
+) -> Result<CreatedWebhook> {
+    let token = AuthManager::get_cli_token().await?;
+    let config = Config::load()?;
+    let trust = config.trust_invalid_server_cert;
+
+    let org_id = get_or_resolve_default_org_id(org_id).await?;
+    let body = &CreateWebhookBody {
+        url: url.to_string(),
+        events,
+    };
+
+    let results = fanout_servers(config.manager_server_urls, 4, false, |server_url| {
+        let token = token.clone();
+        let org_id = org_id.clone();
+        async move {
+            let created =
+                server::create_org_webhook(&server_url, &token, trust, &org_id, body).await?;
+            Ok(vec![created])
+        }
+    })
+    .await?;
+
+    results
+        .into_iter()
+        .next()
+        .map(|(_, created)| created)
+        .ok_or_else(|| anyhow!("Could not create webhook for organization {org_id}"))
+}
+
+pub async fn remove_webhook(org_id: Option<String>, webhook_id: &str) -> Result<()> {
+    let token = AuthManager::get_cli_token().await?;
+    let config = Config::load()?;
+    let trust = config.trust_invalid_server_cert;
+
+    let org_id = get_or_resolve_default_org_id(org_id).await?;
+
+    let results = fanout_servers(config.manager_server_urls, 4, false, |server_url| {
+        let token = token.clone();
+        let org_id = org_id.clone();
+        async move {
+            server::delete_org_webhook(&server_url, &token, trust, &org_id, webhook_id).await?;
+            Ok(vec![()])
+        }
+    })
+    .await?;
+
+    if results.is_empty() {
+        return Err(anyhow!("Webhook {webhook_id} not found"));
+    }
+    Ok(())
+}
+
+pub async fn list_webhook_deliveries(
+    org_id: Option<String>,
+    webhook_id: &str,
+) -> Result<Vec<WebhookDelivery>> {
+    let token = AuthManager::get_cli_token().await?;
+    let config = Config::load()?;
+    let trust = config.trust_invalid_server_cert;
+
+    let org_id = get_or_resolve_default_org_id(org_id).await?;
+
+    let results = fanout_servers(config.manager_server_urls, 4, false, |server_url| {
+        let token = token.clone();
+        let org_id = org_id.clone();
+        async move {
+            server::list_org_webhook_deliveries(&server_url, &token, trust, &org_id, webhook_id)
+                .await
+        }
+    })
+    .await?;
+
+    Ok(results.into_iter().map(|(_, delivery)| delivery).collect())
+}
+
 pub async fn get_or_resolve_default_org_id(org_id: Option<String>) -> Result<String> {
     let mut config = Config::load()?;
 
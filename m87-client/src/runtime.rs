@@ -15,6 +15,8 @@ use tracing::{error, info, warn};
 use crate::config::Config;
 use crate::device::control_tunnel;
 use crate::device::deployment_manager::DeploymentManager;
+use crate::device::log_shipper;
+use crate::device::serial_capture;
 use crate::util::command::current_exe_path;
 use crate::util::shutdown::SHUTDOWN;
 use crate::util::system_info::get_system_info;
@@ -394,6 +396,18 @@ async fn login_and_run() -> Result<()> {
     set_tls_provider();
 
     let config = Config::load()?;
+    // Before registering, so boot output is kept while the network is down.
+    serial_capture::start_captures(&config.serial_captures);
+    if let Some(shipping) = &config.log_shipping {
+        let spool = dirs::data_dir()
+            .context("data_dir missing")?
+            .join("m87")
+            .join("log-spool");
+        if let Err(e) = log_shipper::start(shipping, spool) {
+            error!("Log shipping disabled: {e:#}");
+        }
+    }
+
     let system_info = get_system_info().await?;
     loop {
         let success = register_device(config.owner_reference.clone(), system_info.clone()).await;
@@ -1,17 +1,25 @@
+use std::collections::BTreeMap;
 use std::time::Duration;
 
 use anyhow::{Result, anyhow};
+use m87_shared::alerts::{Alert, AlertRule, CreateAlertRuleBody, SilenceAlertRuleBody};
+use m87_shared::auth::{ApiKeyInfo, CreateApiKeyBody, CreatedApiKey};
 use m87_shared::deploy_spec::{
     CreateDeployRevisionBody, DeployReport, DeploymentRevision, DeploymentStatusSnapshot, JobRun,
     Lifecycle, LifecycleUpdate, TriggerJobBody, UpdateDeployRevisionBody,
 };
-use m87_shared::device::{AddDeviceAccessBody, AuditLog, DeviceStatus, UpdateDeviceBody};
+use m87_shared::device::{
+    AddDeviceAccessBody, AuditLog, DeviceLogEntry, DeviceLogQuery, DeviceStatus, SessionRecording,
+    UpdateDeviceBody, UpdateDeviceLabelsBody,
+};
+use m87_shared::metrics::{UnitMetrics, UnitMetricsQuery};
 use m87_shared::org::{
-    AcceptRejectBody, AddDeviceBody, CreateOrganizationBody, Invite, InviteMemberBody,
-    Organization, UpdateOrganizationBody,
+    AcceptRejectBody, AddDeviceBody, CreateOrganizationBody, CreateWebhookBody, CreatedWebhook,
+    Invite, InviteMemberBody, OrgSettings, Organization, UpdateOrgSettingsBody,
+    UpdateOrganizationBody, Webhook, WebhookDelivery,
 };
 use m87_shared::roles::Role;
-use m87_shared::users::User;
+use m87_shared::users::{AdminUser, ListUsersQuery, User, UserStatus};
 use reqwest::Client;
 
 use tracing::error;
@@ -686,6 +694,7 @@ pub async fn rollback_device(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn get_device_audit_logs(
     api_url: &str,
     token: &str,
@@ -694,6 +703,8 @@ pub async fn get_device_audit_logs(
     limit: u32,
     since: Option<String>, // RFC3339, e.g. "2026-01-01T00:00:00Z"
     until: Option<String>,
+    user: Option<String>,
+    stream_type: Option<String>,
 ) -> Result<Vec<AuditLog>> {
     let url = format!("{}/device/{}/audit_logs", api_url, device_id);
     let client = get_client(trust_invalid_server_cert)?;
@@ -705,6 +716,12 @@ pub async fn get_device_audit_logs(
     if let Some(u) = until {
         q.push(("until", u.to_string()));
     }
+    if let Some(u) = user {
+        q.push(("user", u));
+    }
+    if let Some(t) = stream_type {
+        q.push(("type", t));
+    }
 
     let res = client.get(&url).bearer_auth(token).query(&q).send().await?;
 
@@ -714,6 +731,47 @@ pub async fn get_device_audit_logs(
     }
 }
 
+pub async fn get_unit_metrics(
+    api_url: &str,
+    token: &str,
+    trust_invalid_server_cert: bool,
+    device_id: &str,
+    unit: &str,
+    query: &UnitMetricsQuery,
+) -> Result<Vec<UnitMetrics>> {
+    let url = format!("{}/device/{}/units/{}/metrics", api_url, device_id, unit);
+    let client = get_client(trust_invalid_server_cert)?;
+    let res = client
+        .get(&url)
+        .bearer_auth(token)
+        .query(query)
+        .send()
+        .await?;
+
+    match res.error_for_status() {
+        Ok(r) => Ok(r.json().await?),
+        Err(e) => Err(anyhow!(e)),
+    }
+}
+
+pub async fn get_session_recording(
+    api_url: &str,
+    token: &str,
+    trust_invalid_server_cert: bool,
+    device_id: &str,
+    session_id: &str,
+) -> Result<SessionRecording> {
+    let url = format!("{}/device/{}/sessions/{}", api_url, device_id, session_id);
+    let client = get_client(trust_invalid_server_cert)?;
+
+    let res = client.get(&url).bearer_auth(token).send().await?;
+
+    match res.error_for_status() {
+        Ok(r) => Ok(r.json().await?),
+        Err(e) => Err(anyhow!(e)),
+    }
+}
+
 pub async fn get_device_users(
     api_url: &str,
     token: &str,
@@ -780,6 +838,46 @@ pub async fn add_device_access(
     }
 }
 
+pub async fn update_device_labels(
+    api_url: &str,
+    token: &str,
+    trust_invalid_server_cert: bool,
+    device_id: &str,
+    body: &UpdateDeviceLabelsBody,
+) -> Result<BTreeMap<String, String>> {
+    let url = format!("{}/device/{}/labels", api_url, device_id);
+    let client = get_client(trust_invalid_server_cert)?;
+
+    let res = client
+        .put(&url)
+        .bearer_auth(token)
+        .json(body)
+        .send()
+        .await?;
+
+    match res.error_for_status() {
+        Ok(r) => Ok(r.json().await?),
+        Err(e) => Err(anyhow!(e)),
+    }
+}
+
+pub async fn revoke_device(
+    api_url: &str,
+    token: &str,
+    trust_invalid_server_cert: bool,
+    device_id: &str,
+) -> Result<()> {
+    let url = format!("{}/device/{}/revoke", api_url, device_id);
+    let client = get_client(trust_invalid_server_cert)?;
+
+    let res = client.post(&url).bearer_auth(token).send().await?;
+
+    match res.error_for_status() {
+        Ok(_) => Ok(()),
+        Err(e) => Err(anyhow!(e)),
+    }
+}
+
 pub async fn update_device_access(
     api_url: &str,
     token: &str,
@@ -1145,3 +1243,372 @@ mod tests {
         assert_eq!(hits.load(Ordering::SeqCst), 1, "must not retry a server error");
     }
 }
+
+pub async fn get_org_settings(
+    server_url: &str,
+    token: &str,
+    trust: bool,
+    org_id: &str,
+) -> Result<OrgSettings> {
+    let url = format!("{}/organization/{}/settings", server_url, org_id);
+    let client = get_client(trust)?;
+
+    let res = client.get(&url).bearer_auth(token).send().await?;
+
+    match res.error_for_status() {
+        Ok(r) => Ok(r.json().await?),
+        Err(e) => Err(anyhow!(e)),
+    }
+}
+
+pub async fn update_org_settings(
+    server_url: &str,
+    token: &str,
+    trust: bool,
+    org_id: &str,
+    body: &UpdateOrgSettingsBody,
+) -> Result<OrgSettings> {
+    let url = format!("{}/organization/{}/settings", server_url, org_id);
+    let client = get_client(trust)?;
+
+    let res = client
+        .put(&url)
+        .bearer_auth(token)
+        .json(body)
+        .send()
+        .await?;
+
+    match res.error_for_status() {
+        Ok(r) => Ok(r.json().await?),
+        Err(e) => Err(anyhow!(e)),
+    }
+}
+
+pub async fn list_org_webhooks(
+    server_url: &str,
+    token: &str,
+    trust: bool,
+    org_id: &str,
+) -> Result<Vec<Webhook>> {
+    let url = format!("{}/organization/{}/webhooks", server_url, org_id);
+    let client = get_client(trust)?;
+
+    let res = client.get(&url).bearer_auth(token).send().await?;
+
+    match res.error_for_status() {
+        Ok(r) => Ok(r.json().await?),
+        Err(e) => Err(anyhow!(e)),
+    }
+}
+
+pub async fn create_org_webhook(
+    server_url: &str,
+    token: &str,
+    trust: bool,
+    org_id: &str,
+    body: &CreateWebhookBody,
+) -> Result<CreatedWebhook> {
+    let url = format!("{}/organization/{}/webhooks", server_url, org_id);
+    let client = get_client(trust)?;
+
+    let res = client
+        .post(&url)
+        .bearer_auth(token)
+        .json(body)
+        .send()
+        .await?;
+
+    match res.error_for_status() {
+        Ok(r) => Ok(r.json().await?),
+        Err(e) => Err(anyhow!(e)),
+    }
+}
+
+pub async fn delete_org_webhook(
+    server_url: &str,
+    token: &str,
+    trust: bool,
+    org_id: &str,
+    webhook_id: &str,
+) -> Result<()> {
+    let url = format!(
+        "{}/organization/{}/webhooks/{}",
+        server_url, org_id, webhook_id
+    );
+    let client = get_client(trust)?;
+
+    let res = client.delete(&url).bearer_auth(token).send().await?;
+
+    match res.error_for_status() {
+        Ok(_) => Ok(()),
+        Err(e) => Err(anyhow!(e)),
+    }
+}
+
+pub async fn list_org_webhook_deliveries(
+    server_url: &str,
+    token: &str,
+    trust: bool,
+    org_id: &str,
+    webhook_id: &str,
+) -> Result<Vec<WebhookDelivery>> {
+    let url = format!(
+        "{}/organization/{}/webhooks/{}/deliveries",
+        server_url, org_id, webhook_id
+    );
+    let client = get_client(trust)?;
+
+    let res = client.get(&url).bearer_auth(token).send().await?;
+
+    match res.error_for_status() {
+        Ok(r) => Ok(r.json().await?),
+        Err(e) => Err(anyhow!(e)),
+    }
+}
+
+pub async fn list_org_alerts(
+    server_url: &str,
+    token: &str,
+    trust: bool,
+    org_id: &str,
+    all: bool,
+) -> Result<Vec<Alert>> {
+    let url = format!("{}/organization/{}/alerts", server_url, org_id);
+    let client = get_client(trust)?;
+
+    let res = client
+        .get(&url)
+        .bearer_auth(token)
+        .query(&[("all", all)])
+        .send()
+        .await?;
+
+    match res.error_for_status() {
+        Ok(r) => Ok(r.json().await?),
+        Err(e) => Err(anyhow!(e)),
+    }
+}
+
+pub async fn list_org_logs(
+    server_url: &str,
+    token: &str,
+    trust: bool,
+    org_id: &str,
+    query: &DeviceLogQuery,
+) -> Result<Vec<DeviceLogEntry>> {
+    let url = format!("{}/organization/{}/logs", server_url, org_id);
+    let client = get_client(trust)?;
+
+    let res = client
+        .get(&url)
+        .bearer_auth(token)
+        .query(query)
+        .send()
+        .await?;
+
+    match res.error_for_status() {
+        Ok(r) => Ok(r.json().await?),
+        Err(e) => Err(anyhow!(e)),
+    }
+}
+
+pub async fn ack_org_alert(
+    server_url: &str,
+    token: &str,
+    trust: bool,
+    org_id: &str,
+    alert_id: &str,
+) -> Result<Alert> {
+    let url = format!(
+        "{}/organization/{}/alerts/{}/ack",
+        server_url, org_id, alert_id
+    );
+    let client = get_client(trust)?;
+
+    let res = client.post(&url).bearer_auth(token).send().await?;
+
+    match res.error_for_status() {
+        Ok(r) => Ok(r.json().await?),
+        Err(e) => Err(anyhow!(e)),
+    }
+}
+
+pub async fn list_org_alert_rules(
+    server_url: &str,
+    token: &str,
+    trust: bool,
+    org_id: &str,
+) -> Result<Vec<AlertRule>> {
+    let url = format!("{}/organization/{}/alert-rules", server_url, org_id);
+    let client = get_client(trust)?;
+
+    let res = client.get(&url).bearer_auth(token).send().await?;
+
+    match res.error_for_status() {
+        Ok(r) => Ok(r.json().await?),
+        Err(e) => Err(anyhow!(e)),
+    }
+}
+
+pub async fn create_org_alert_rule(
+    server_url: &str,
+    token: &str,
+    trust: bool,
+    org_id: &str,
+    body: &CreateAlertRuleBody,
+) -> Result<AlertRule> {
+    let url = format!("{}/organization/{}/alert-rules", server_url, org_id);
+    let client = get_client(trust)?;
+
+    let res = client
+        .post(&url)
+        .bearer_auth(token)
+        .json(body)
+        .send()
+        .await?;
+
+    match res.error_for_status() {
+        Ok(r) => Ok(r.json().await?),
+        Err(e) => Err(anyhow!(e)),
+    }
+}
+
+pub async fn delete_org_alert_rule(
+    server_url: &str,
+    token: &str,
+    trust: bool,
+    org_id: &str,
+    rule_id: &str,
+) -> Result<()> {
+    let url = format!(
+        "{}/organization/{}/alert-rules/{}",
+        server_url, org_id, rule_id
+    );
+    let client = get_client(trust)?;
+
+    let res = client.delete(&url).bearer_auth(token).send().await?;
+
+    match res.error_for_status() {
+        Ok(_) => Ok(()),
+        Err(e) => Err(anyhow!(e)),
+    }
+}
+
+pub async fn silence_org_alert_rule(
+    server_url: &str,
+    token: &str,
+    trust: bool,
+    org_id: &str,
+    rule_id: &str,
+    body: &SilenceAlertRuleBody,
+) -> Result<AlertRule> {
+    let url = format!(
+        "{}/organization/{}/alert-rules/{}/silence",
+        server_url, org_id, rule_id
+    );
+    let client = get_client(trust)?;
+
+    let res = client
+        .put(&url)
+        .bearer_auth(token)
+        .json(body)
+        .send()
+        .await?;
+
+    match res.error_for_status() {
+        Ok(r) => Ok(r.json().await?),
+        Err(e) => Err(anyhow!(e)),
+    }
+}
+
+pub async fn list_admin_users(
+    server_url: &str,
+    token: &str,
+    trust: bool,
+    status: Option<UserStatus>,
+) -> Result<Vec<AdminUser>> {
+    let url = format!("{}/admin/users", server_url);
+    let client = get_client(trust)?;
+
+    let res = client
+        .get(&url)
+        .bearer_auth(token)
+        .query(&ListUsersQuery { status })
+        .send()
+        .await?;
+
+    match res.error_for_status() {
+        Ok(r) => Ok(r.json().await?),
+        Err(e) => Err(anyhow!(e)),
+    }
+}
+
+/// `action` is one of `approve`, `reject`, `disable` or `enable`.
+pub async fn update_admin_user(
+    server_url: &str,
+    token: &str,
+    trust: bool,
+    user: &str,
+    action: &str,
+) -> Result<AdminUser> {
+    let url = format!("{}/admin/users/{}/{}", server_url, user, action);
+    let client = get_client(trust)?;
+
+    let res = client.post(&url).bearer_auth(token).send().await?;
+
+    match res.error_for_status() {
+        Ok(r) => Ok(r.json().await?),
+        Err(e) => Err(anyhow!(e)),
+    }
+}
+
+pub async fn list_api_keys(server_url: &str, token: &str, trust: bool) -> Result<Vec<ApiKeyInfo>> {
+    let url = format!("{}/api-keys", server_url);
+    let client = get_client(trust)?;
+
+    let res = client.get(&url).bearer_auth(token).send().await?;
+
+    match res.error_for_status() {
+        Ok(r) => Ok(r.json().await?),
+        Err(e) => Err(anyhow!(e)),
+    }
+}
+
+pub async fn create_api_key(
+    server_url: &str,
+    token: &str,
+    trust: bool,
+    body: &CreateApiKeyBody,
+) -> Result<CreatedApiKey> {
+    let url = format!("{}/api-keys", server_url);
+    let client = get_client(trust)?;
+
+    let res = client
+        .post(&url)
+        .bearer_auth(token)
+        .json(body)
+        .send()
+        .await?;
+
+    match res.error_for_status() {
+        Ok(r) => Ok(r.json().await?),
+        Err(e) => Err(anyhow!(e)),
+    }
+}
+
+pub async fn revoke_api_key(
+    server_url: &str,
+    token: &str,
+    trust: bool,
+    key_id: &str,
+) -> Result<()> {
+    let url = format!("{}/api-keys/{}", server_url, key_id);
+    let client = get_client(trust)?;
+
+    let res = client.delete(&url).bearer_auth(token).send().await?;
+
+    match res.error_for_status() {
+        Ok(_) => Ok(()),
+        Err(e) => Err(anyhow!(e)),
+    }
+}
@@ -0,0 +1,586 @@
+//! Optional zstd compression for device -> client stream output.
+//!
+//! Negotiation:
+//! 1. The client sets `compression` in the stream header (`StreamHeader`).
+//! 2. A device that supports it answers with `MAGIC` followed by frames.
+//!    Older devices ignore the field and answer with plain bytes, which
+//!    `DecompressedIo` detects and passes through unchanged.
+//!
+//! Only the device -> client direction is compressed; that is where exec
+//! output and log lines flow. Client -> device bytes are always plain.
+//!
+//! Frame: 1 byte kind (`FRAME_RAW` / `FRAME_ZSTD`), 4 byte BE length, payload.
+//! zstd frames are flushed blocks of one continuous zstd stream, so history
+//! is shared across writes. Writes that already look compressed are sent raw.
+
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::task::{Context, Poll, ready};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use zstd::stream::raw::{Decoder, Encoder, InBuffer, Operation, OutBuffer};
+
+/// Sent by the device before the first frame.
+pub const MAGIC: &[u8; 4] = b"\0m8z";
+
+const FRAME_RAW: u8 = 0;
+const FRAME_ZSTD: u8 = 1;
+const FRAME_HEADER_LEN: usize = 5;
+const MAX_FRAME: usize = 64 * 1024;
+const ZSTD_LEVEL: i32 = 3;
+
+// ---------------------------------------------------------------------------
+// Stats (`--stats`)
+// ---------------------------------------------------------------------------
+
+/// Process-wide byte counters for client streams, printed by `--stats`.
+pub struct StreamStats {
+    enabled: AtomicBool,
+    /// Bytes as seen by the command (exec output, log lines).
+    pub payload: AtomicU64,
+    /// Bytes sent and received on the QUIC stream.
+    pub wire: AtomicU64,
+    /// SFTP data of `cp` / `sync`. SFTP runs inside SSH, which compresses
+    /// with its own zlib rather than this module's zstd.
+    pub sftp_payload: AtomicU64,
+    /// SSH bytes carrying that SFTP data.
+    pub sftp_wire: AtomicU64,
+}
+
+pub static STREAM_STATS: StreamStats = StreamStats {
+    enabled: AtomicBool::new(false),
+    payload: AtomicU64::new(0),
+    wire: AtomicU64::new(0),
+    sftp_payload: AtomicU64::new(0),
+    sftp_wire: AtomicU64::new(0),
+};
+
+impl StreamStats {
+    pub fn enable(&self) {
+        self.enabled.store(true, Ordering::Relaxed);
+    }
+
+    /// Print the counters to stderr if `--stats` was given, one line for each
+    /// kind of stream that carried data.
+    pub fn report(&self) {
+        if !self.enabled.load(Ordering::Relaxed) {
+            return;
+        }
+        let (streams, sftp) = if compression_enabled() {
+            ("exec/log streams (zstd)", "cp/sync (SFTP over SSH zlib)")
+        } else {
+            (
+                "exec/log streams (uncompressed)",
+                "cp/sync (SFTP, uncompressed)",
+            )
+        };
+        for (label, payload, wire) in [
+            (streams, &self.payload, &self.wire),
+            (sftp, &self.sftp_payload, &self.sftp_wire),
+        ] {
+            let payload = payload.load(Ordering::Relaxed);
+            let wire = wire.load(Ordering::Relaxed);
+            if payload > 0 || wire > 0 {
+                eprintln!("{}", format_stats(label, payload, wire));
+            }
+        }
+    }
+}
+
+fn format_stats(label: &str, payload: u64, wire: u64) -> String {
+    let ratio = if wire > 0 {
+        payload as f64 / wire as f64
+    } else {
+        1.0
+    };
+    format!(
+        "stream stats: {label}: {} payload, {} on the wire ({:.1}x)",
+        crate::tui::fs::human_size(payload),
+        crate::tui::fs::human_size(wire),
+        ratio
+    )
+}
+
+static COMPRESSION_DISABLED: AtomicBool = AtomicBool::new(false);
+
+/// `--no-compress`: ask devices for plain output and don't negotiate SSH
+/// compression, for payloads that are already compressed or CPU-starved
+/// devices.
+pub fn disable_compression() {
+    COMPRESSION_DISABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn compression_enabled() -> bool {
+    !COMPRESSION_DISABLED.load(Ordering::Relaxed)
+}
+
+/// Counts every byte read or written through `inner` into `counter`.
+pub struct Counted<IO> {
+    inner: IO,
+    counter: &'static AtomicU64,
+}
+
+impl<IO> Counted<IO> {
+    pub fn new(inner: IO, counter: &'static AtomicU64) -> Self {
+        Self { inner, counter }
+    }
+}
+
+impl<IO: AsyncRead + Unpin> AsyncRead for Counted<IO> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        ready!(Pin::new(&mut self.inner).poll_read(cx, buf))?;
+        let n = buf.filled().len() - before;
+        self.counter.fetch_add(n as u64, Ordering::Relaxed);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<IO: AsyncWrite + Unpin> AsyncWrite for Counted<IO> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        data: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let n = ready!(Pin::new(&mut self.inner).poll_write(cx, data))?;
+        self.counter.fetch_add(n as u64, Ordering::Relaxed);
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Device side: compress writes
+// ---------------------------------------------------------------------------
+
+/// Device side of a compressed stream: reads pass through, writes are framed
+/// and compressed. Each write is flushed as a complete frame, so callers that
+/// never flush (e.g. the logs handler) still deliver output promptly.
+pub struct CompressedIo<IO> {
+    inner: IO,
+    encoder: Encoder<'static>,
+    pending: Vec<u8>,
+    pending_pos: usize,
+}
+
+impl<IO> CompressedIo<IO> {
+    pub fn new(inner: IO) -> io::Result<Self> {
+        Ok(Self {
+            inner,
+            encoder: Encoder::new(ZSTD_LEVEL)?,
+            pending: MAGIC.to_vec(),
+            pending_pos: 0,
+        })
+    }
+
+    fn encode_frame(&mut self, chunk: &[u8]) -> io::Result<()> {
+        let (kind, payload) = if looks_compressed(chunk) {
+            (FRAME_RAW, chunk.to_vec())
+        } else {
+            (FRAME_ZSTD, compress_block(&mut self.encoder, chunk)?)
+        };
+
+        if self.pending_pos == self.pending.len() {
+            self.pending.clear();
+            self.pending_pos = 0;
+        }
+        self.pending.push(kind);
+        self.pending
+            .extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        self.pending.extend_from_slice(&payload);
+        Ok(())
+    }
+}
+
+impl<IO: AsyncWrite + Unpin> CompressedIo<IO> {
+    /// Write out buffered frames.
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while self.pending_pos < self.pending.len() {
+            let n = ready!(
+                Pin::new(&mut self.inner).poll_write(cx, &self.pending[self.pending_pos..])
+            )?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.pending_pos += n;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<IO: AsyncRead + Unpin> AsyncRead for CompressedIo<IO> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl<IO: AsyncWrite + Unpin> AsyncWrite for CompressedIo<IO> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        data: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        // Backpressure: don't buffer more than one frame ahead.
+        ready!(this.poll_drain(cx))?;
+        if data.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        let chunk = &data[..data.len().min(MAX_FRAME)];
+        this.encode_frame(chunk)?;
+        // The chunk is accepted; if the stream is blocked, the rest goes out
+        // with the next write or flush.
+        if let Poll::Ready(Err(e)) = this.poll_drain(cx) {
+            return Poll::Ready(Err(e));
+        }
+        Poll::Ready(Ok(chunk.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+/// Feed `chunk` into the running zstd stream and flush it into a block the
+/// peer can decode right away.
+fn compress_block(encoder: &mut Encoder<'static>, chunk: &[u8]) -> io::Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(chunk.len() / 2 + 64);
+    let mut tmp = vec![0u8; 32 * 1024];
+    let mut input = InBuffer::around(chunk);
+
+    while input.pos() < chunk.len() {
+        let mut output = OutBuffer::around(&mut tmp[..]);
+        encoder.run(&mut input, &mut output)?;
+        let n = output.pos();
+        out.extend_from_slice(&tmp[..n]);
+    }
+    loop {
+        let mut output = OutBuffer::around(&mut tmp[..]);
+        let remaining = encoder.flush(&mut output)?;
+        let n = output.pos();
+        out.extend_from_slice(&tmp[..n]);
+        if remaining == 0 {
+            break;
+        }
+    }
+    Ok(out)
+}
+
+/// Heuristic for payloads zstd can't shrink: known compressed formats, or
+/// a sample with near-random byte entropy.
+fn looks_compressed(data: &[u8]) -> bool {
+    const SIGNATURES: &[&[u8]] = &[
+        b"\x1f\x8b",         // gzip
+        b"\x28\xb5\x2f\xfd", // zstd
+        b"\xfd7zXZ\0",       // xz
+        b"PK\x03\x04",       // zip, jar, docx
+        b"BZh",              // bzip2
+        b"7z\xbc\xaf",       // 7z
+        b"\x89PNG",          // png
+        b"\xff\xd8\xff",     // jpeg
+    ];
+    if SIGNATURES.iter().any(|sig| data.starts_with(sig)) {
+        return true;
+    }
+
+    let sample = &data[..data.len().min(4096)];
+    if sample.len() < 512 {
+        return false;
+    }
+    let mut counts = [0u32; 256];
+    for &b in sample {
+        counts[b as usize] += 1;
+    }
+    let len = sample.len() as f64;
+    let entropy: f64 = counts
+        .iter()
+        .filter(|&&c| c > 0)
+        .map(|&c| {
+            let p = c as f64 / len;
+            -p * p.log2()
+        })
+        .sum();
+    entropy > 7.5
+}
+
+// ---------------------------------------------------------------------------
+// Client side: decompress reads
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, PartialEq, Eq)]
+enum ReadMode {
+    /// Waiting for enough bytes to tell `MAGIC` from plain output.
+    Detect,
+    Plain,
+    Framed,
+}
+
+/// Client side of a compressed stream: writes pass through, reads are
+/// decompressed (or passed through if the device answered without `MAGIC`).
+pub struct DecompressedIo<IO> {
+    inner: IO,
+    decoder: Decoder<'static>,
+    mode: ReadMode,
+    /// Bytes read from `inner` that have not been decoded yet.
+    raw: Vec<u8>,
+    /// Decoded bytes not yet handed to the caller.
+    decoded: Vec<u8>,
+    decoded_pos: usize,
+    eof: bool,
+}
+
+impl<IO> DecompressedIo<IO> {
+    pub fn new(inner: IO) -> io::Result<Self> {
+        Ok(Self {
+            inner,
+            decoder: Decoder::new()?,
+            mode: ReadMode::Detect,
+            raw: Vec::new(),
+            decoded: Vec::new(),
+            decoded_pos: 0,
+            eof: false,
+        })
+    }
+
+    /// Move as much of `raw` as possible into `decoded`.
+    fn process(&mut self) -> io::Result<()> {
+        if self.mode == ReadMode::Detect {
+            let n = self.raw.len().min(MAGIC.len());
+            if self.raw[..n] != MAGIC[..n] {
+                self.mode = ReadMode::Plain;
+            } else if n == MAGIC.len() {
+                self.raw.drain(..n);
+                self.mode = ReadMode::Framed;
+            } else if self.eof {
+                self.mode = ReadMode::Plain;
+            } else {
+                return Ok(());
+            }
+        }
+
+        if self.decoded_pos == self.decoded.len() {
+            self.decoded.clear();
+            self.decoded_pos = 0;
+        }
+
+        match self.mode {
+            ReadMode::Detect => {}
+            ReadMode::Plain => self.decoded.append(&mut self.raw),
+            ReadMode::Framed => {
+                let mut consumed = 0;
+                while self.raw.len() - consumed >= FRAME_HEADER_LEN {
+                    let header = &self.raw[consumed..consumed + FRAME_HEADER_LEN];
+                    let kind = header[0];
+                    let len = u32::from_be_bytes([header[1], header[2], header[3], header[4]])
+                        as usize;
+                    let start = consumed + FRAME_HEADER_LEN;
+                    if self.raw.len() < start + len {
+                        break;
+                    }
+                    let payload = &self.raw[start..start + len];
+                    match kind {
+                        FRAME_RAW => self.decoded.extend_from_slice(payload),
+                        FRAME_ZSTD => {
+                            decompress_block(&mut self.decoder, payload, &mut self.decoded)?
+                        }
+                        other => {
+                            return Err(io::Error::new(
+                                io::ErrorKind::InvalidData,
+                                format!("unknown compression frame kind {other}"),
+                            ));
+                        }
+                    }
+                    consumed = start + len;
+                }
+                self.raw.drain(..consumed);
+                if self.eof && !self.raw.is_empty() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "compressed stream ended mid-frame",
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn decompress_block(decoder: &mut Decoder<'static>, payload: &[u8], out: &mut Vec<u8>) -> io::Result<()> {
+    let mut tmp = vec![0u8; 64 * 1024];
+    let mut input = InBuffer::around(payload);
+    loop {
+        let mut output = OutBuffer::around(&mut tmp[..]);
+        decoder.run(&mut input, &mut output)?;
+        let n = output.pos();
+        out.extend_from_slice(&tmp[..n]);
+        if input.pos() == payload.len() && n < tmp.len() {
+            return Ok(());
+        }
+    }
+}
+
+impl<IO: AsyncRead + Unpin> AsyncRead for DecompressedIo<IO> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if this.decoded_pos < this.decoded.len() {
+                let n = buf.remaining().min(this.decoded.len() - this.decoded_pos);
+                buf.put_slice(&this.decoded[this.decoded_pos..this.decoded_pos + n]);
+                this.decoded_pos += n;
+                return Poll::Ready(Ok(()));
+            }
+            if this.eof {
+                return Poll::Ready(Ok(()));
+            }
+
+            let mut tmp = [0u8; 16 * 1024];
+            let mut read_buf = ReadBuf::new(&mut tmp);
+            ready!(Pin::new(&mut this.inner).poll_read(cx, &mut read_buf))?;
+            let filled = read_buf.filled();
+            if filled.is_empty() {
+                this.eof = true;
+            } else {
+                this.raw.extend_from_slice(filled);
+            }
+            this.process()?;
+        }
+    }
+}
+
+impl<IO: AsyncWrite + Unpin> AsyncWrite for DecompressedIo<IO> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        data: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, data)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    async fn roundtrip(chunks: &[Vec<u8>]) -> (Vec<u8>, usize) {
+        let (device, client) = tokio::io::duplex(1024 * 1024);
+        let mut writer = CompressedIo::new(device).unwrap();
+        for chunk in chunks {
+            writer.write_all(chunk).await.unwrap();
+        }
+        writer.shutdown().await.unwrap();
+        drop(writer);
+
+        let wire = Counted::new(client, Box::leak(Box::new(AtomicU64::new(0))));
+        let counter = wire.counter;
+        let mut reader = DecompressedIo::new(wire).unwrap();
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out).await.unwrap();
+        (out, counter.load(Ordering::Relaxed) as usize)
+    }
+
+    #[tokio::test]
+    async fn test_roundtrip_compresses_text() {
+        let line = b"2026-10-18T12:00:00Z INFO service started on port 8080\n".to_vec();
+        let chunks: Vec<_> = (0..200).map(|_| line.clone()).collect();
+        let (out, wire) = roundtrip(&chunks).await;
+        assert_eq!(out, chunks.concat());
+        assert!(wire < out.len() / 2, "wire {wire} vs payload {}", out.len());
+    }
+
+    #[tokio::test]
+    async fn test_roundtrip_mixed_raw_and_zstd_frames() {
+        let gzip = [b"\x1f\x8b".to_vec(), vec![7u8; 2000]].concat();
+        let chunks = vec![b"hello\n".to_vec(), gzip, b"world\n".to_vec()];
+        let (out, _) = roundtrip(&chunks).await;
+        assert_eq!(out, chunks.concat());
+    }
+
+    #[tokio::test]
+    async fn test_plain_output_from_older_device_passes_through() {
+        let (mut device, client) = tokio::io::duplex(1024);
+        device.write_all(b"plain output\n").await.unwrap();
+        drop(device);
+
+        let mut reader = DecompressedIo::new(client).unwrap();
+        let mut out = String::new();
+        reader.read_to_string(&mut out).await.unwrap();
+        assert_eq!(out, "plain output\n");
+    }
+
+    #[tokio::test]
+    async fn test_short_plain_output_passes_through() {
+        let (mut device, client) = tokio::io::duplex(1024);
+        device.write_all(b"\0m").await.unwrap();
+        drop(device);
+
+        let mut reader = DecompressedIo::new(client).unwrap();
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out).await.unwrap();
+        assert_eq!(out, b"\0m");
+    }
+
+    #[test]
+    fn test_looks_compressed() {
+        assert!(looks_compressed(b"\x28\xb5\x2f\xfdrest"));
+        assert!(!looks_compressed(b"just some log text"));
+        assert!(!looks_compressed(&vec![b'a'; 4096]));
+
+        // xorshift noise is close to 8 bits/byte
+        let mut x = 0x2545_f491_4f6c_dd1du64;
+        let noise: Vec<u8> = (0..4096)
+            .map(|_| {
+                x ^= x << 13;
+                x ^= x >> 7;
+                x ^= x << 17;
+                x as u8
+            })
+            .collect();
+        assert!(looks_compressed(&noise));
+    }
+
+    #[test]
+    fn test_format_stats_names_the_stream_kind() {
+        assert_eq!(
+            format_stats("cp/sync (SFTP over SSH zlib)", 4096, 1024),
+            "stream stats: cp/sync (SFTP over SSH zlib): 4.0K payload, 1.0K on the wire (4.0x)"
+        );
+    }
+}
@@ -17,12 +17,11 @@ use serde::{Deserialize, Serialize};
 use std::io::{Read, Write};
 use std::process::Stdio;
 use std::sync::Arc;
-use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
 use tokio::process::Command;
 use tokio::sync::{Mutex, mpsc};
 use tokio::select;
 
-use crate::streams::quic::QuicIo;
 use crate::util::shell::{self, ShellMode};
 
 #[derive(Deserialize)]
@@ -41,7 +40,10 @@ struct ExecResult {
     exit_code: i32,
 }
 
-pub async fn handle_exec_io(io: QuicIo) {
+pub async fn handle_exec_io<IO>(io: IO)
+where
+    IO: AsyncRead + AsyncWrite + Send + 'static,
+{
     // Split into reader/writer
     let (reader, writer) = tokio::io::split(io);
     let mut reader = BufReader::new(reader);
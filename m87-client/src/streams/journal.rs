@@ -0,0 +1,203 @@
+use std::process::Stdio;
+
+use anyhow::{Result, anyhow};
+use m87_shared::deploy_spec::{JournalPriority, JournalSpec};
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::process::Command;
+use tracing::warn;
+
+use crate::streams::stream_type::{JournalEntry, JournalMessage};
+
+/// Fields of a `Journal` stream.
+pub struct JournalQuery {
+    pub filter: JournalSpec,
+    pub since_ms: Option<u64>,
+    pub until_ms: Option<u64>,
+    pub tail: usize,
+    pub follow: bool,
+}
+
+impl JournalQuery {
+    fn args(&self) -> Vec<String> {
+        let mut args: Vec<String> = ["--output=json", "--no-pager", "--quiet"]
+            .map(String::from)
+            .into();
+        args.extend(self.filter.filter_args());
+        if let Some(since) = self.since_ms {
+            args.push(format!("--since=@{}", since / 1000));
+        }
+        if let Some(until) = self.until_ms {
+            args.push(format!("--until=@{}", until.div_ceil(1000)));
+        }
+        if self.tail > 0 {
+            args.push(format!("--lines={}", self.tail));
+        }
+        if self.follow {
+            args.push("--follow".into());
+        }
+        args
+    }
+}
+
+/// Answer a `Journal` stream from `journalctl -o json`. With `follow`, new
+/// entries keep coming until the client goes away.
+pub async fn handle_journal_io<IO>(io: &mut IO, query: JournalQuery)
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+{
+    let end = match send_journal(io, &query).await {
+        Ok(true) => JournalMessage::Done,
+        Ok(false) => return,
+        Err(e) => JournalMessage::Error {
+            message: format!("{e:#}"),
+        },
+    };
+    if let Err(e) = write_message(io, &end).await {
+        warn!("journal stream ended: {e}");
+    }
+    let _ = io.shutdown().await;
+}
+
+/// `Ok(false)` when the client left before journalctl finished.
+async fn send_journal<IO>(io: &mut IO, query: &JournalQuery) -> Result<bool>
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut child = Command::new("journalctl")
+        .args(query.args())
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => anyhow!("journalctl not found on the device"),
+            _ => anyhow!("failed to run journalctl: {e}"),
+        })?;
+    let mut stdout = BufReader::new(child.stdout.take().expect("piped")).lines();
+    let mut stderr = child.stderr.take().expect("piped");
+    let stderr_task = tokio::spawn(async move {
+        let mut err = String::new();
+        let _ = stderr.read_to_string(&mut err).await;
+        err
+    });
+
+    let mut buf = [0u8; 64];
+    loop {
+        tokio::select! {
+            // Nothing is expected from the client; this notices it leaving.
+            n = io.read(&mut buf) => if n.unwrap_or(0) == 0 {
+                return Ok(false);
+            },
+            line = stdout.next_line() => match line? {
+                Some(line) => {
+                    let Some(entry) = serde_json::from_str(&line).ok().and_then(|v| parse_entry(&v))
+                    else {
+                        continue;
+                    };
+                    if write_message(io, &JournalMessage::Entry(entry)).await.is_err() {
+                        return Ok(false);
+                    }
+                }
+                None => break,
+            },
+        }
+    }
+
+    let status = child.wait().await?;
+    if !status.success() {
+        let err = stderr_task.await.unwrap_or_default();
+        return Err(anyhow!("journalctl failed ({status}): {}", err.trim()));
+    }
+    Ok(true)
+}
+
+async fn write_message<IO>(io: &mut IO, message: &JournalMessage) -> std::io::Result<()>
+where
+    IO: AsyncWrite + Unpin,
+{
+    let mut json = serde_json::to_vec(message)?;
+    json.push(b'\n');
+    io.write_all(&json).await?;
+    io.flush().await
+}
+
+/// One `journalctl -o json` object. Field values are strings, or byte arrays
+/// when they aren't valid UTF-8.
+fn parse_entry(fields: &Value) -> Option<JournalEntry> {
+    let field = |name: &str| match fields.get(name)? {
+        Value::String(s) => Some(s.clone()),
+        Value::Array(bytes) => {
+            let bytes: Vec<u8> = bytes
+                .iter()
+                .filter_map(|b| b.as_u64().map(|b| b as u8))
+                .collect();
+            Some(String::from_utf8_lossy(&bytes).into_owned())
+        }
+        _ => None,
+    };
+    let at_us: u64 = field("__REALTIME_TIMESTAMP")?.parse().ok()?;
+    Some(JournalEntry {
+        at_ms: at_us / 1000,
+        priority: field("PRIORITY")
+            .and_then(|p| p.parse().ok())
+            .and_then(JournalPriority::from_level),
+        identifier: field("SYSLOG_IDENTIFIER"),
+        unit: field("_SYSTEMD_UNIT"),
+        pid: field("_PID").and_then(|p| p.parse().ok()),
+        message: field("MESSAGE").unwrap_or_default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parses_journalctl_json() {
+        let entry = parse_entry(&json!({
+            "__REALTIME_TIMESTAMP": "1767225600123456",
+            "PRIORITY": "3",
+            "SYSLOG_IDENTIFIER": "dockerd",
+            "_SYSTEMD_UNIT": "docker.service",
+            "_PID": "812",
+            "MESSAGE": [115, 101, 103, 102, 97, 117, 108, 116],
+        }))
+        .unwrap();
+        assert_eq!(entry.at_ms, 1767225600123);
+        assert_eq!(entry.priority, Some(JournalPriority::Err));
+        assert_eq!(entry.unit.as_deref(), Some("docker.service"));
+        assert_eq!(entry.pid, Some(812));
+        assert_eq!(entry.message, "segfault");
+
+        assert!(parse_entry(&json!({ "MESSAGE": "no timestamp" })).is_none());
+    }
+
+    #[test]
+    fn builds_journalctl_args() {
+        let query = JournalQuery {
+            filter: JournalSpec {
+                units: vec!["docker.service".into()],
+                kernel: false,
+                priority: Some(JournalPriority::Warning),
+            },
+            since_ms: Some(1_500),
+            until_ms: Some(2_500),
+            tail: 50,
+            follow: true,
+        };
+        assert_eq!(
+            query.args()[3..],
+            [
+                "--unit=docker.service",
+                "--priority=4",
+                "--since=@1",
+                "--until=@3",
+                "--lines=50",
+                "--follow",
+            ]
+        );
+    }
+}
@@ -1,14 +1,20 @@
-use anyhow::{Result, anyhow};
+use anyhow::{Context, Result, anyhow};
+use regex::Regex;
 use std::sync::Arc;
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
 use tokio::sync::broadcast::error::RecvError;
 
+use crate::device::log_store::{self, LogQuery};
+use crate::streams::stream_type::PersistedLogMessage;
 use crate::util::format;
 use crate::{
-    device::deployment_manager::DeploymentManager, streams::quic::QuicIo, util::logging::get_log_rx,
+    device::deployment_manager::DeploymentManager, util::logging::get_log_rx,
 };
 
-pub async fn handle_logs_io(io: &mut QuicIo, unit_manager: Arc<DeploymentManager>) -> Result<()> {
+pub async fn handle_logs_io<IO>(io: &mut IO, unit_manager: Arc<DeploymentManager>) -> Result<()>
+where
+    IO: AsyncWrite + Unpin,
+{
     let _ = unit_manager.start_log_follow().await?;
     let mut app_rx = match get_log_rx() {
         Some(r) => r,
@@ -51,3 +57,69 @@ pub async fn handle_logs_io(io: &mut QuicIo, unit_manager: Arc<DeploymentManager
 
     Ok(())
 }
+
+/// Query fields of a `PersistedLogs` stream, before the regex is compiled.
+pub struct PersistedLogQuery {
+    pub since_ms: Option<u64>,
+    pub until_ms: Option<u64>,
+    pub grep: Option<String>,
+    pub tail: usize,
+}
+
+/// Answer a `PersistedLogs` query from the unit's on-disk store as NDJSON
+/// `PersistedLogMessage`s.
+pub async fn handle_persisted_logs_io<IO>(
+    io: &mut IO,
+    unit_manager: Arc<DeploymentManager>,
+    unit: &str,
+    query: PersistedLogQuery,
+) -> Result<()>
+where
+    IO: AsyncWrite + Unpin,
+{
+    let messages = match read_persisted(&unit_manager, unit, query).await {
+        Ok(lines) => lines
+            .into_iter()
+            .map(|(at_ms, line)| PersistedLogMessage::Line { at_ms, line })
+            .chain([PersistedLogMessage::Done])
+            .collect(),
+        Err(e) => vec![PersistedLogMessage::Error {
+            message: format!("{e:#}"),
+        }],
+    };
+
+    for message in &messages {
+        let mut json = serde_json::to_vec(message)?;
+        json.push(b'\n');
+        io.write_all(&json).await?;
+    }
+    io.shutdown().await?;
+    Ok(())
+}
+
+async fn read_persisted(
+    unit_manager: &DeploymentManager,
+    unit: &str,
+    query: PersistedLogQuery,
+) -> Result<Vec<(u64, String)>> {
+    let dir = unit_manager.persisted_logs_dir(unit);
+    if !dir.is_dir() {
+        return Err(anyhow!(
+            "no persisted logs for {unit}; set observe.logs.persist on the unit"
+        ));
+    }
+    let grep = query
+        .grep
+        .as_deref()
+        .map(Regex::new)
+        .transpose()
+        .context("invalid --grep pattern")?;
+    let query = LogQuery {
+        since_ms: query.since_ms,
+        until_ms: query.until_ms,
+        grep,
+        tail: query.tail,
+    };
+    let lines = tokio::task::spawn_blocking(move || log_store::read(&dir, &query)).await??;
+    Ok(lines)
+}
@@ -1,5 +1,8 @@
 // Shared modules (used by both m87 runtime and m87 command line)
+pub mod compress;
+pub mod p2p;
 pub mod quic;
+pub mod serial_control;
 pub mod stream_type;
 
 // Runtime-specific: These modules handle incoming streams on the device side
@@ -9,10 +12,14 @@ mod docker;
 #[cfg(feature = "runtime")]
 mod exec;
 #[cfg(feature = "runtime")]
+mod journal;
+#[cfg(feature = "runtime")]
 mod logs;
 #[cfg(feature = "runtime")]
 mod metrics;
 #[cfg(feature = "runtime")]
+mod processes;
+#[cfg(feature = "runtime")]
 pub mod router;
 #[cfg(feature = "runtime")]
 mod serial;
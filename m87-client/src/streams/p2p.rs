@@ -0,0 +1,681 @@
+//! Direct client↔device QUIC connections with relay fallback.
+//!
+//! Over an already relayed (and therefore authenticated) connection the
+//! client opens a `P2p` stream carrying its candidate addresses. The device
+//! answers with its own candidates, the SHA-256 fingerprint of a throwaway
+//! self-signed certificate and a single-use ticket, punches its NAT towards
+//! the client's candidates and then listens for one direct QUIC connection on
+//! that same socket. The client dials every device candidate from the socket
+//! it advertised, pins the certificate and presents the ticket. Any failure
+//! along the way leaves the caller on the relay.
+//!
+//! The server can't see what goes over a direct session, so it only carries
+//! port forwards and SFTP (see `router::allowed_on_direct`), never anything
+//! that would be recorded. The relayed connection and its `P2p` stream stay
+//! open as the session's lease: the device reports every finished direct
+//! stream on it for the server's audit, and the session is closed as soon as
+//! the lease or the control tunnel it was negotiated on goes away, so revoking
+//! the user or the device ends it too. Both sides have to opt in with
+//! `direct_connections`, and the server refuses the negotiation for devices
+//! whose orgs record sessions.
+
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result, anyhow, bail};
+use futures::FutureExt;
+use quinn::{ClientConfig, Endpoint, EndpointConfig, IdleTimeout, TokioRuntime};
+use quinn_proto::crypto::rustls::QuicClientConfig;
+use rustls::{
+    ClientConfig as RustlsClientConfig, DigitallySignedStruct, SignatureScheme,
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::WebPkiSupportedAlgorithms,
+    pki_types::{CertificateDer, ServerName, UnixTime},
+};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncReadExt;
+use tokio::net::UdpSocket;
+use tokio::time::timeout;
+use tracing::debug;
+
+use crate::streams::quic::{QuicIo, open_quic_stream};
+use crate::streams::stream_type::StreamType;
+use crate::util::network::{primary_local_ip, reflexive_address};
+
+pub const P2P_ALPN: &[u8] = b"m87-p2p";
+/// Name on the device's self-signed certificate (identity comes from the pin).
+const DEVICE_SERVER_NAME: &str = "m87-device";
+const GATHER_TIMEOUT: Duration = Duration::from_millis(800);
+const NEGOTIATE_TIMEOUT: Duration = Duration::from_secs(3);
+const DIAL_TIMEOUT: Duration = Duration::from_secs(3);
+const LEASE_DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_MSG_LEN: usize = 64 * 1024;
+
+/// Device reply to a `P2p` stream.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct P2pAnswer {
+    pub candidates: Vec<SocketAddr>,
+    /// Hex SHA-256 of the DER certificate the device's direct endpoint presents.
+    pub cert_sha256: String,
+    /// Single-use secret the client sends first on the direct connection.
+    pub ticket: String,
+}
+
+/// Which path a device connection ended up on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionPath {
+    Relay,
+    Direct(SocketAddr),
+}
+
+impl fmt::Display for ConnectionPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectionPath::Relay => write!(f, "relay"),
+            ConnectionPath::Direct(addr) => write!(f, "direct ({addr})"),
+        }
+    }
+}
+
+/// LAN address plus the STUN-mapped public address of `socket`.
+pub async fn gather_candidates(socket: &UdpSocket) -> Vec<SocketAddr> {
+    let Ok(port) = socket.local_addr().map(|a| a.port()) else {
+        return Vec::new();
+    };
+    let mut candidates = Vec::new();
+    if let Some(ip) = primary_local_ip() {
+        candidates.push(SocketAddr::new(ip, port));
+    }
+    if let Ok(Ok(addr)) = timeout(GATHER_TIMEOUT, reflexive_address(socket, GATHER_TIMEOUT)).await
+        && !candidates.contains(&addr)
+    {
+        candidates.push(addr);
+    }
+    candidates
+}
+
+pub fn cert_fingerprint(der: &[u8]) -> String {
+    Sha256::digest(der)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Negotiate over `relay` and dial the device directly. Also returns the
+/// negotiation stream, which has to stay open for the session (see
+/// [`hold_relay`]).
+pub async fn try_direct(
+    relay: &quinn::Connection,
+    token: &str,
+) -> Result<(Endpoint, quinn::Connection, SocketAddr, QuicIo)> {
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    let candidates = gather_candidates(&socket).await;
+    if candidates.is_empty() {
+        bail!("no local candidate addresses");
+    }
+
+    let (answer, lease) = timeout(NEGOTIATE_TIMEOUT, negotiate(relay, token, candidates))
+        .await
+        .context("direct connection negotiation timed out")??;
+    if answer.candidates.is_empty() {
+        bail!("device offered no candidate addresses");
+    }
+
+    let mut endpoint = Endpoint::new(
+        EndpointConfig::default(),
+        None,
+        socket.into_std()?,
+        Arc::new(TokioRuntime),
+    )?;
+    endpoint.set_default_client_config(pinned_client_config(&answer.cert_sha256)?);
+
+    let attempts = answer.candidates.iter().map(|&addr| {
+        let endpoint = endpoint.clone();
+        async move {
+            let conn = endpoint.connect(addr, DEVICE_SERVER_NAME)?.await?;
+            Ok::<_, anyhow::Error>((conn, addr))
+        }
+        .boxed()
+    });
+    let ((conn, addr), _) = timeout(DIAL_TIMEOUT, futures::future::select_ok(attempts))
+        .await
+        .context("no device candidate reachable")??;
+
+    // Same framing as the server token: u16 BE length + bytes on a uni stream.
+    let mut send = conn.open_uni().await?;
+    send.write_all(&(answer.ticket.len() as u16).to_be_bytes())
+        .await?;
+    send.write_all(answer.ticket.as_bytes()).await?;
+    send.finish()?;
+
+    Ok((endpoint, conn, addr, lease))
+}
+
+async fn negotiate(
+    relay: &quinn::Connection,
+    token: &str,
+    candidates: Vec<SocketAddr>,
+) -> Result<(P2pAnswer, QuicIo)> {
+    let stream_type = StreamType::P2p {
+        token: token.to_string(),
+        candidates,
+    };
+    let mut io = open_quic_stream(relay, stream_type).await?;
+    // Devices without direct-connection support drop the stream here.
+    let answer = read_json(&mut io.recv).await?;
+    Ok((answer, io))
+}
+
+/// Keep the relayed connection and its `P2p` stream open while `direct`
+/// lives. The server tracks the session through that connection and closes
+/// it when the user loses access; the device ends the direct session as soon
+/// as the `P2p` stream goes away, and reports the session's streams on it.
+pub fn hold_relay(
+    relay_endpoint: Endpoint,
+    relay: quinn::Connection,
+    mut lease: QuicIo,
+    direct: quinn::Connection,
+) {
+    tokio::spawn(async move {
+        tokio::select! {
+            _ = direct.closed() => {
+                // Give the device time to report the last streams.
+                let _ = timeout(LEASE_DRAIN_TIMEOUT, read_until_end(&mut lease.recv)).await;
+            }
+            _ = read_until_end(&mut lease.recv) => {
+                debug!("relayed session ended, closing direct connection");
+                direct.close(0u32.into(), b"relay-closed");
+            }
+        }
+        relay.close(0u32.into(), b"direct-closed");
+        drop(relay_endpoint);
+    });
+}
+
+/// Resolves when the peer finishes or resets the stream, or the connection ends.
+pub(crate) async fn read_until_end(recv: &mut quinn::RecvStream) {
+    let mut buf = [0u8; 1024];
+    while let Ok(Some(_)) = recv.read(&mut buf).await {}
+}
+
+pub async fn write_json<T: Serialize>(send: &mut quinn::SendStream, msg: &T) -> Result<()> {
+    let json = serde_json::to_vec(msg)?;
+    send.write_all(&(json.len() as u32).to_be_bytes()).await?;
+    send.write_all(&json).await?;
+    Ok(())
+}
+
+pub async fn read_json<T: DeserializeOwned>(recv: &mut quinn::RecvStream) -> Result<T> {
+    let len = recv.read_u32().await? as usize;
+    if len > MAX_MSG_LEN {
+        bail!("message too large ({len} bytes)");
+    }
+    let mut buf = vec![0u8; len];
+    recv.read_exact(&mut buf).await?;
+    Ok(serde_json::from_slice(&buf)?)
+}
+
+fn pinned_client_config(cert_sha256: &str) -> Result<ClientConfig> {
+    let verifier = PinnedCertVerifier {
+        cert_sha256: cert_sha256.to_ascii_lowercase(),
+        algorithms: rustls::crypto::ring::default_provider().signature_verification_algorithms,
+    };
+    let mut tls = RustlsClientConfig::builder()
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier))
+        .with_no_client_auth();
+    tls.alpn_protocols = vec![P2P_ALPN.to_vec()];
+
+    let crypto =
+        Arc::new(QuicClientConfig::try_from(tls).context("failed converting rustls→quic config")?);
+    let mut cfg = ClientConfig::new(crypto);
+    cfg.transport_config(Arc::new(direct_transport()));
+    Ok(cfg)
+}
+
+/// Transport settings for direct sessions, on both ends.
+pub fn direct_transport() -> quinn::TransportConfig {
+    let mut transport = quinn::TransportConfig::default();
+    transport.keep_alive_interval(Some(Duration::from_secs(5)));
+    transport.max_concurrent_bidi_streams(1024u32.into());
+    transport.max_idle_timeout(Some(
+        IdleTimeout::try_from(Duration::from_secs(180)).unwrap(),
+    ));
+    transport
+}
+
+/// Accepts exactly the certificate whose fingerprint the device sent over
+/// the authenticated relay; handshake signatures are still verified.
+#[derive(Debug)]
+struct PinnedCertVerifier {
+    cert_sha256: String,
+    algorithms: WebPkiSupportedAlgorithms,
+}
+
+impl ServerCertVerifier for PinnedCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if cert_fingerprint(end_entity) == self.cert_sha256 {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::General(
+                "device certificate does not match the negotiated fingerprint".into(),
+            ))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.algorithms.supported_schemes()
+    }
+}
+
+// Device side: answer a `P2p` stream and serve the direct session.
+#[cfg(feature = "runtime")]
+pub use device::handle_p2p_io;
+
+#[cfg(feature = "runtime")]
+mod device {
+    use super::*;
+
+    use std::future::Future;
+    use std::pin::Pin;
+
+    use m87_shared::p2p::DirectStreamUsage;
+    use quinn::ServerConfig;
+    use quinn_proto::crypto::rustls::QuicServerConfig;
+    use rand::RngCore;
+    use rustls::pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer};
+    use tokio::sync::{mpsc, watch};
+    use tracing::{debug, info, warn};
+
+    use crate::config::Config;
+    use crate::device::control_tunnel::serve_connection;
+    use crate::device::deployment_manager::DeploymentManager;
+    use crate::streams::quic::QuicIo;
+
+    /// How long the device waits for the client's direct connection.
+    const ACCEPT_TIMEOUT: Duration = Duration::from_secs(10);
+    const TICKET_TIMEOUT: Duration = Duration::from_secs(5);
+    /// How long usage reports of winding-down streams are still forwarded.
+    const USAGE_DRAIN_TIMEOUT: Duration = Duration::from_secs(2);
+
+    /// `tunnel_shutdown` is the control tunnel's; the direct session ends with it.
+    ///
+    /// The `P2p` stream stays open for the lifetime of the direct session: it
+    /// carries usage reports for the server's audit, and the session ends as
+    /// soon as the client's relayed connection (and with it the stream) goes away,
+    /// e.g. when the server revokes the user.
+    pub async fn handle_p2p_io(
+        client_candidates: Vec<SocketAddr>,
+        mut io: QuicIo,
+        unit_manager: Arc<DeploymentManager>,
+        tunnel_shutdown: watch::Receiver<bool>,
+    ) {
+        match start_direct_session(client_candidates, &mut io).await {
+            Ok((endpoint, ticket)) => {
+                serve_direct_session(endpoint, ticket, io, unit_manager, tunnel_shutdown).await
+            }
+            // Dropping the stream without an answer sends the client back to the relay.
+            Err(e) => debug!("direct session not offered: {e:#}"),
+        }
+    }
+
+    async fn start_direct_session(
+        client_candidates: Vec<SocketAddr>,
+        io: &mut QuicIo,
+    ) -> Result<(Endpoint, String)> {
+        if !Config::load()?.direct_connections {
+            bail!("direct connections are disabled in the device config");
+        }
+
+        let socket = UdpSocket::bind("0.0.0.0:0").await?;
+        let candidates = gather_candidates(&socket).await;
+
+        let cert = rcgen::generate_simple_self_signed(vec![DEVICE_SERVER_NAME.to_string()])?;
+        let cert_der = CertificateDer::from(cert.cert.der().to_vec());
+        let key = PrivateKeyDer::from(PrivatePkcs8KeyDer::from(cert.signing_key.serialize_der()));
+
+        let mut ticket = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut ticket);
+        let ticket: String = ticket.iter().map(|b| format!("{b:02x}")).collect();
+
+        let answer = P2pAnswer {
+            candidates,
+            cert_sha256: cert_fingerprint(&cert_der),
+            ticket: ticket.clone(),
+        };
+
+        // Open our NAT mapping towards the client before QUIC owns the socket.
+        for addr in &client_candidates {
+            for _ in 0..3 {
+                let _ = socket.send_to(b"m87-punch", addr).await;
+            }
+        }
+
+        let endpoint = Endpoint::new(
+            EndpointConfig::default(),
+            Some(direct_server_config(cert_der, key)?),
+            socket.into_std()?,
+            Arc::new(TokioRuntime),
+        )?;
+
+        write_json(&mut io.send, &answer).await?;
+        Ok((endpoint, ticket))
+    }
+
+    /// Boxed: direct sessions route streams through the router that
+    /// dispatched us here, which would otherwise make the future type recursive.
+    fn serve_direct_session(
+        endpoint: Endpoint,
+        ticket: String,
+        lease: QuicIo,
+        unit_manager: Arc<DeploymentManager>,
+        mut tunnel_shutdown: watch::Receiver<bool>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        Box::pin(async move {
+            let QuicIo {
+                recv: mut lease_recv,
+                send: mut lease_send,
+                ..
+            } = lease;
+
+            let conn = tokio::select! {
+                res = accept_direct(&endpoint, &ticket) => res,
+                _ = tunnel_closed(&mut tunnel_shutdown) => Err(anyhow!("control tunnel closed")),
+                _ = read_until_end(&mut lease_recv) => Err(anyhow!("client session ended")),
+            };
+            match conn {
+                Ok(conn) => {
+                    info!(peer = %conn.remote_address(), "direct session established");
+                    let (usage_tx, mut usage_rx) = mpsc::unbounded_channel::<DirectStreamUsage>();
+                    let (shutdown_tx, _) = watch::channel(false);
+                    let mut serve = Box::pin(serve_connection(
+                        &conn,
+                        unit_manager,
+                        shutdown_tx.clone(),
+                        Some(usage_tx),
+                    ));
+
+                    let reason: &'static [u8] = loop {
+                        tokio::select! {
+                            _ = &mut serve => break b"closed",
+                            _ = tunnel_closed(&mut tunnel_shutdown) => {
+                                info!("control tunnel closed, ending direct session");
+                                break b"tunnel-closed";
+                            }
+                            _ = read_until_end(&mut lease_recv) => {
+                                info!("client session ended, ending direct session");
+                                break b"session-ended";
+                            }
+                            Some(usage) = usage_rx.recv() => {
+                                if let Err(e) = write_json(&mut lease_send, &usage).await {
+                                    warn!("failed to report direct stream usage: {e:#}");
+                                    break b"session-ended";
+                                }
+                            }
+                        }
+                    };
+
+                    let _ = shutdown_tx.send(true);
+                    conn.close(0u32.into(), reason);
+                    drop(serve);
+
+                    // Streams report as they wind down; pass on what arrives in time.
+                    while let Ok(Some(usage)) = timeout(USAGE_DRAIN_TIMEOUT, usage_rx.recv()).await
+                    {
+                        if write_json(&mut lease_send, &usage).await.is_err() {
+                            break;
+                        }
+                    }
+                    debug!("direct session closed");
+                }
+                Err(e) => debug!("no direct connection: {e:#}"),
+            }
+            let _ = lease_send.finish();
+            endpoint.close(0u32.into(), b"done");
+        })
+    }
+
+    /// Resolves once the control tunnel signals shutdown or is gone.
+    async fn tunnel_closed(shutdown: &mut watch::Receiver<bool>) {
+        let _ = shutdown.wait_for(|closed| *closed).await;
+    }
+
+    /// First connection presenting the ticket, within `ACCEPT_TIMEOUT`.
+    async fn accept_direct(endpoint: &Endpoint, ticket: &str) -> Result<quinn::Connection> {
+        let deadline = tokio::time::Instant::now() + ACCEPT_TIMEOUT;
+        loop {
+            let incoming = tokio::time::timeout_at(deadline, endpoint.accept())
+                .await
+                .context("client did not connect in time")?
+                .ok_or_else(|| anyhow!("endpoint closed"))?;
+            let conn = match incoming.await {
+                Ok(conn) => conn,
+                Err(e) => {
+                    warn!("direct handshake failed: {e:?}");
+                    continue;
+                }
+            };
+            match timeout(TICKET_TIMEOUT, read_ticket(&conn)).await {
+                Ok(Ok(presented)) if presented == ticket => return Ok(conn),
+                _ => {
+                    warn!(peer = %conn.remote_address(), "direct connection without valid ticket");
+                    conn.close(0x101u32.into(), b"invalid-ticket");
+                }
+            }
+        }
+    }
+
+    async fn read_ticket(conn: &quinn::Connection) -> Result<String> {
+        let mut recv = conn.accept_uni().await?;
+        let len = recv.read_u16().await? as usize;
+        if len > 256 {
+            bail!("ticket too long");
+        }
+        let mut buf = vec![0u8; len];
+        recv.read_exact(&mut buf).await?;
+        Ok(String::from_utf8(buf)?)
+    }
+
+    fn direct_server_config(
+        cert: CertificateDer<'static>,
+        key: PrivateKeyDer<'static>,
+    ) -> Result<ServerConfig> {
+        let mut tls = rustls::ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert], key)?;
+        tls.alpn_protocols = vec![P2P_ALPN.to_vec()];
+
+        let crypto = QuicServerConfig::try_from(tls).context("quic rustls server config")?;
+        let mut cfg = ServerConfig::with_crypto(Arc::new(crypto));
+        cfg.transport = Arc::new(direct_transport());
+        Ok(cfg)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::util::tls::set_tls_provider;
+
+        #[test]
+        fn test_direct_sessions_only_carry_forwards_and_sftp() {
+            use crate::streams::router::allowed_on_direct;
+
+            let token = "t".to_string();
+            assert!(allowed_on_direct(&StreamType::Ssh {
+                token: token.clone()
+            }));
+            assert!(!allowed_on_direct(&StreamType::Terminal {
+                token: token.clone(),
+                term: None,
+            }));
+            assert!(!allowed_on_direct(&StreamType::Exec {
+                token: token.clone()
+            }));
+            assert!(!allowed_on_direct(&StreamType::P2p {
+                token,
+                candidates: vec![],
+            }));
+        }
+
+        #[tokio::test]
+        async fn test_tunnel_closed_resolves_on_shutdown_or_drop() {
+            let (tx, mut rx) = watch::channel(false);
+            tx.send(true).unwrap();
+            timeout(Duration::from_secs(1), tunnel_closed(&mut rx))
+                .await
+                .unwrap();
+
+            let (tx, mut rx) = watch::channel(false);
+            drop(tx);
+            timeout(Duration::from_secs(1), tunnel_closed(&mut rx))
+                .await
+                .unwrap();
+        }
+
+        async fn device_endpoint() -> (Endpoint, String) {
+            let cert =
+                rcgen::generate_simple_self_signed(vec![DEVICE_SERVER_NAME.to_string()]).unwrap();
+            let cert_der = CertificateDer::from(cert.cert.der().to_vec());
+            let key =
+                PrivateKeyDer::from(PrivatePkcs8KeyDer::from(cert.signing_key.serialize_der()));
+            let fingerprint = cert_fingerprint(&cert_der);
+            let endpoint = Endpoint::server(
+                direct_server_config(cert_der, key).unwrap(),
+                "127.0.0.1:0".parse().unwrap(),
+            )
+            .unwrap();
+            (endpoint, fingerprint)
+        }
+
+        async fn dial(device: &Endpoint, fingerprint: &str) -> Result<quinn::Connection> {
+            let mut client = Endpoint::client("127.0.0.1:0".parse().unwrap())?;
+            client.set_default_client_config(pinned_client_config(fingerprint)?);
+            let addr = device.local_addr()?;
+            Ok(client.connect(addr, DEVICE_SERVER_NAME)?.await?)
+        }
+
+        async fn send_ticket(conn: &quinn::Connection, ticket: &str) {
+            let mut send = conn.open_uni().await.unwrap();
+            send.write_all(&(ticket.len() as u16).to_be_bytes())
+                .await
+                .unwrap();
+            send.write_all(ticket.as_bytes()).await.unwrap();
+            send.finish().unwrap();
+        }
+
+        #[tokio::test]
+        async fn test_direct_connection_with_pinned_cert_and_ticket() {
+            set_tls_provider();
+            let (device, fingerprint) = device_endpoint().await;
+            let accept = tokio::spawn({
+                let device = device.clone();
+                async move { accept_direct(&device, "t1").await }
+            });
+
+            let conn = dial(&device, &fingerprint).await.unwrap();
+            send_ticket(&conn, "t1").await;
+
+            let accepted = accept.await.unwrap().unwrap();
+            assert!(accepted.remote_address().ip().is_loopback());
+        }
+
+        #[tokio::test]
+        async fn test_wrong_fingerprint_is_rejected() {
+            set_tls_provider();
+            let (device, _) = device_endpoint().await;
+            tokio::spawn({
+                let device = device.clone();
+                async move { accept_direct(&device, "t1").await }
+            });
+
+            assert!(dial(&device, &"00".repeat(32)).await.is_err());
+        }
+
+        #[tokio::test]
+        async fn test_wrong_ticket_is_rejected() {
+            set_tls_provider();
+            let (device, fingerprint) = device_endpoint().await;
+            let accept = tokio::spawn({
+                let device = device.clone();
+                async move { timeout(Duration::from_secs(2), accept_direct(&device, "t1")).await }
+            });
+
+            let conn = dial(&device, &fingerprint).await.unwrap();
+            send_ticket(&conn, "wrong").await;
+            let reason = conn.closed().await;
+            assert!(matches!(
+                reason,
+                quinn::ConnectionError::ApplicationClosed(_)
+            ));
+            // Still waiting for a connection with the right ticket.
+            assert!(accept.await.unwrap().is_err());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connection_path_display() {
+        assert_eq!(ConnectionPath::Relay.to_string(), "relay");
+        let addr: SocketAddr = "192.168.1.5:40000".parse().unwrap();
+        assert_eq!(
+            ConnectionPath::Direct(addr).to_string(),
+            "direct (192.168.1.5:40000)"
+        );
+    }
+
+    #[test]
+    fn test_p2p_stream_type_roundtrip() {
+        let st = StreamType::P2p {
+            token: "t".to_string(),
+            candidates: vec!["10.0.0.2:5000".parse().unwrap()],
+        };
+        let json = serde_json::to_string(&st).unwrap();
+        match serde_json::from_str::<StreamType>(&json).unwrap() {
+            StreamType::P2p { candidates, .. } => {
+                assert_eq!(candidates, vec!["10.0.0.2:5000".parse().unwrap()])
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_cert_fingerprint_is_lowercase_hex_sha256() {
+        let fp = cert_fingerprint(b"abc");
+        assert_eq!(
+            fp,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
@@ -0,0 +1,66 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tracing::warn;
+
+use crate::device::processes::{self, ProcessSampler};
+use crate::streams::quic::QuicIo;
+use crate::streams::stream_type::SignalResult;
+
+const INTERVAL: Duration = Duration::from_secs(2);
+/// The first snapshot's CPU usage is measured over this long.
+const FIRST_SAMPLE: Duration = Duration::from_millis(500);
+
+/// Send a process snapshot every couple of seconds until the client leaves.
+pub async fn handle_processes_io(io: &mut QuicIo) {
+    let sampler = Arc::new(Mutex::new(ProcessSampler::default()));
+    sampler.lock().unwrap().refresh();
+    let mut next = tokio::time::Instant::now() + FIRST_SAMPLE;
+    let mut buf = [0u8; 64];
+    loop {
+        tokio::select! {
+            // Nothing is expected from the client; this notices it leaving.
+            n = io.read(&mut buf) => if n.unwrap_or(0) == 0 {
+                break;
+            },
+            _ = tokio::time::sleep_until(next) => {
+                next += INTERVAL;
+                // Reading /proc for every process blocks.
+                let sampler = sampler.clone();
+                let Ok(list) = tokio::task::spawn_blocking(move || {
+                    let mut sampler = sampler.lock().unwrap();
+                    sampler.refresh();
+                    sampler.snapshot()
+                })
+                .await
+                else {
+                    break;
+                };
+                let Ok(mut json) = serde_json::to_vec(&list) else {
+                    break;
+                };
+                json.push(b'\n');
+                if let Err(e) = io.write_all(&json).await {
+                    warn!("process stream ended: {e}");
+                    break;
+                }
+            }
+        }
+    }
+    let _ = io.shutdown().await;
+}
+
+pub async fn handle_signal_io(pid: u32, signal: String, io: &mut QuicIo) {
+    let result = match processes::send_signal(pid, &signal) {
+        Ok(()) => SignalResult::Sent,
+        Err(e) => SignalResult::Error {
+            message: format!("{e:#}"),
+        },
+    };
+    if let Ok(mut json) = serde_json::to_vec(&result) {
+        json.push(b'\n');
+        let _ = io.write_all(&json).await;
+    }
+    let _ = io.shutdown().await;
+}
@@ -2,13 +2,17 @@ use anyhow::{Context, Result};
 use quinn::{ClientConfig, Endpoint, IdleTimeout};
 use quinn_proto::crypto::rustls::QuicClientConfig;
 use rustls::{ClientConfig as RustlsClientConfig, RootCertStore};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Duration;
 use std::{net::SocketAddr, sync::Arc};
 use std::{pin::Pin, task::Poll};
 use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, ReadBuf};
 use tracing::{debug, error, warn};
 
-use crate::streams::stream_type::StreamType;
+use crate::config::Config;
+use crate::streams::compress::{Counted, DecompressedIo, STREAM_STATS, compression_enabled};
+use crate::streams::p2p::{self, ConnectionPath};
+use crate::streams::stream_type::{Compression, StreamHeader, StreamType};
 use crate::util::tls::NoVerify; // reuse the same NoVerify struct
 
 async fn resolve_host(host: &str, port: u16) -> Result<SocketAddr> {
@@ -131,6 +135,26 @@ pub async fn get_quic_connection(
 pub struct QuicIo {
     pub recv: quinn::RecvStream,
     pub send: quinn::SendStream,
+    /// Counts what goes through the `AsyncRead` / `AsyncWrite` impls, e.g.
+    /// for the audit of direct sessions.
+    pub traffic: Option<Arc<StreamTraffic>>,
+}
+
+impl QuicIo {
+    pub fn new(recv: quinn::RecvStream, send: quinn::SendStream) -> Self {
+        Self {
+            recv,
+            send,
+            traffic: None,
+        }
+    }
+}
+
+/// Bytes read from and written to a [`QuicIo`].
+#[derive(Debug, Default)]
+pub struct StreamTraffic {
+    pub read: AtomicU64,
+    pub written: AtomicU64,
 }
 
 impl AsyncRead for QuicIo {
@@ -139,7 +163,13 @@ impl AsyncRead for QuicIo {
         cx: &mut std::task::Context<'_>,
         buf: &mut ReadBuf<'_>,
     ) -> Poll<std::io::Result<()>> {
-        Pin::new(&mut self.recv).poll_read(cx, buf)
+        let before = buf.filled().len();
+        let res = Pin::new(&mut self.recv).poll_read(cx, buf);
+        if let Some(traffic) = &self.traffic {
+            let n = buf.filled().len() - before;
+            traffic.read.fetch_add(n as u64, Ordering::Relaxed);
+        }
+        res
     }
 }
 
@@ -149,9 +179,13 @@ impl AsyncWrite for QuicIo {
         cx: &mut std::task::Context<'_>,
         data: &[u8],
     ) -> Poll<std::io::Result<usize>> {
-        Pin::new(&mut self.send)
+        let res = Pin::new(&mut self.send)
             .poll_write(cx, data)
-            .map_err(|e| std::io::Error::from(e))
+            .map_err(|e| std::io::Error::from(e));
+        if let (Some(traffic), Poll::Ready(Ok(n))) = (&self.traffic, &res) {
+            traffic.written.fetch_add(*n as u64, Ordering::Relaxed);
+        }
+        res
     }
 
     fn poll_flush(
@@ -181,6 +215,34 @@ pub async fn open_quic_io(
     Ok((conn, io))
 }
 
+/// Stream that asks the device for compressed output: counts wire bytes,
+/// decompresses, then counts payload bytes (for `--stats`).
+pub type CompressedQuicIo = Counted<DecompressedIo<Counted<QuicIo>>>;
+
+/// Like `open_quic_io`, but requests zstd compression of the device's output
+/// unless `--no-compress` was given. Output from devices without compression
+/// support passes through unchanged.
+pub async fn open_compressed_quic_io(
+    host: &str,
+    token: &str,
+    device_short_id: &str,
+    stream_type: StreamType,
+    trust_invalid: bool,
+) -> Result<(quinn::Connection, CompressedQuicIo)> {
+    let (_endpoint, conn) = connect_quic_only(host, token, device_short_id, trust_invalid).await?;
+    let header = StreamHeader {
+        stream_type,
+        compression: if compression_enabled() {
+            Compression::Zstd
+        } else {
+            Compression::None
+        },
+    };
+    let io = open_quic_stream_with_header(&conn, &header).await?;
+    let io = DecompressedIo::new(Counted::new(io, &STREAM_STATS.wire))?;
+    Ok((conn, Counted::new(io, &STREAM_STATS.payload)))
+}
+
 pub async fn connect_quic_only(
     host: &str,
     token: &str,
@@ -191,11 +253,44 @@ pub async fn connect_quic_only(
     get_quic_connection(&full_host, token, trust_invalid).await
 }
 
+/// Like `connect_quic_only`, but upgrades to a direct peer-to-peer connection
+/// when one can be established (see `streams::p2p`), falling back to the relay.
+pub async fn connect_preferring_direct(
+    host: &str,
+    token: &str,
+    device_short_id: &str,
+    trust_invalid: bool,
+) -> Result<(Endpoint, quinn::Connection, ConnectionPath)> {
+    let (endpoint, relay) = connect_quic_only(host, token, device_short_id, trust_invalid).await?;
+    if !Config::load().is_ok_and(|c| c.direct_connections) {
+        return Ok((endpoint, relay, ConnectionPath::Relay));
+    }
+
+    match p2p::try_direct(&relay, token).await {
+        Ok((direct_endpoint, conn, addr, lease)) => {
+            debug!(%addr, "using direct connection");
+            p2p::hold_relay(endpoint, relay, lease, conn.clone());
+            Ok((direct_endpoint, conn, ConnectionPath::Direct(addr)))
+        }
+        Err(e) => {
+            debug!("direct connection unavailable, using relay: {e:#}");
+            Ok((endpoint, relay, ConnectionPath::Relay))
+        }
+    }
+}
+
 pub async fn open_quic_stream(conn: &quinn::Connection, stream_type: StreamType) -> Result<QuicIo> {
+    open_quic_stream_with_header(conn, &StreamHeader::new(stream_type)).await
+}
+
+pub async fn open_quic_stream_with_header(
+    conn: &quinn::Connection,
+    header: &StreamHeader,
+) -> Result<QuicIo> {
     debug!("Opening QUIC stream");
     let (mut send, recv) = conn.open_bi().await?;
 
-    let json = serde_json::to_vec(&stream_type)?;
+    let json = serde_json::to_vec(header)?;
     let len = (json.len() as u32).to_be_bytes();
 
     send.write_all(&len).await?;
@@ -204,5 +299,5 @@ pub async fn open_quic_stream(conn: &quinn::Connection, stream_type: StreamType)
 
     debug!("Stream opened");
 
-    Ok(QuicIo { recv, send })
+    Ok(QuicIo::new(recv, send))
 }
@@ -1,36 +1,148 @@
 use bytes::Bytes;
+use m87_shared::deploy_spec::JournalSpec;
+use m87_shared::p2p::DirectStreamUsage;
 use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use tokio::sync::{mpsc, watch};
 use tracing::{debug, warn};
 
 // use crate::streams::auth::validate_token;
 use crate::device::deployment_manager::DeploymentManager;
-use crate::streams::quic::QuicIo;
-use crate::streams::serial::handle_serial_io;
-use crate::streams::stream_type::StreamType;
+use crate::streams::compress::CompressedIo;
+use crate::streams::journal::{JournalQuery, handle_journal_io};
+use crate::streams::logs::{PersistedLogQuery, handle_persisted_logs_io};
+use crate::streams::p2p::handle_p2p_io;
+use crate::streams::processes::{handle_processes_io, handle_signal_io};
+use crate::streams::quic::{QuicIo, StreamTraffic};
+use crate::streams::serial::{handle_serial_io, handle_serial_list_io, handle_serial_log_io};
+use crate::streams::stream_type::{Compression, StreamHeader, StreamType};
 use crate::streams::udp_manager::UdpChannelManager;
 use crate::streams::{
     docker::handle_docker_io, exec::handle_exec_io, forward::handle_port_forward_io,
     logs::handle_logs_io, metrics::handle_system_metrics_io, ssh::handle_ssh_io,
     terminal::handle_terminal_io,
 };
+use crate::util::time::now_ms;
+
+/// The device-side connection a stream arrived on.
+#[derive(Clone)]
+pub enum StreamOrigin {
+    /// The control tunnel, relayed (and audited) by the server. `shutdown`
+    /// fires when the tunnel goes down.
+    ControlTunnel { shutdown: watch::Receiver<bool> },
+    /// A direct session (see `streams::p2p`). Each finished stream is
+    /// reported on `usage` for the server's audit.
+    Direct {
+        usage: mpsc::UnboundedSender<DirectStreamUsage>,
+    },
+}
+
+/// Stream types a direct session carries: port forwards and SFTP. Everything
+/// else stays on the relay, which audits and records it.
+pub fn allowed_on_direct(stream_type: &StreamType) -> bool {
+    matches!(
+        stream_type,
+        StreamType::Forward { .. } | StreamType::Ssh { .. }
+    )
+}
 
 pub async fn handle_incoming_stream(
     mut io: QuicIo,
     manager: UdpChannelManager,
     datagram_tx: tokio::sync::mpsc::Sender<(u32, Bytes)>,
     unit_manager: Arc<DeploymentManager>,
+    origin: StreamOrigin,
 ) -> anyhow::Result<()> {
     debug!("router: parsing stream type header");
-    let stream_type = match StreamType::from_incoming_stream(&mut io.recv).await {
-        Ok(st) => st,
+    let StreamHeader {
+        stream_type,
+        compression,
+    } = match StreamHeader::from_incoming_stream(&mut io.recv).await {
+        Ok(header) => header,
         Err(e) => {
             warn!("router: failed to parse stream type: {e:?}");
             return Err(e);
         }
     };
 
-    debug!("router: stream type = {:?}", stream_type.variant_name());
+    debug!(
+        "router: stream type = {:?}, compression = {:?}",
+        stream_type.variant_name(),
+        compression
+    );
+
+    match origin {
+        StreamOrigin::ControlTunnel { shutdown } => {
+            dispatch(
+                stream_type,
+                compression,
+                io,
+                manager,
+                datagram_tx,
+                unit_manager,
+                shutdown,
+            )
+            .await
+        }
+        StreamOrigin::Direct { usage } => {
+            dispatch_direct(stream_type, io, manager, datagram_tx, usage).await
+        }
+    }
+}
+
+/// Streams of a direct session, each reported on `usage` once it ends.
+async fn dispatch_direct(
+    stream_type: StreamType,
+    mut io: QuicIo,
+    manager: UdpChannelManager,
+    datagram_tx: tokio::sync::mpsc::Sender<(u32, Bytes)>,
+    usage: mpsc::UnboundedSender<DirectStreamUsage>,
+) -> anyhow::Result<()> {
+    if !allowed_on_direct(&stream_type) {
+        warn!(
+            "router: refusing {} stream on a direct session",
+            stream_type.variant_name()
+        );
+        return Ok(());
+    }
+
+    let started_at_ms = now_ms();
+    let mut header = serde_json::to_value(&stream_type)?;
+    if let Some(obj) = header.as_object_mut() {
+        obj.remove("token");
+    }
+    let traffic = Arc::new(StreamTraffic::default());
+    io.traffic = Some(traffic.clone());
+    match stream_type {
+        StreamType::Forward { target, .. } => {
+            debug!("router: dispatching to direct port forward handler");
+            handle_port_forward_io(target, io, manager, datagram_tx).await;
+        }
+        StreamType::Ssh { .. } => {
+            debug!("router: dispatching to direct sftp handler");
+            handle_ssh_io(io, true).await;
+        }
+        _ => unreachable!("checked by allowed_on_direct"),
+    }
+    let _ = usage.send(DirectStreamUsage {
+        header,
+        started_at_ms,
+        bytes_up: traffic.read.load(Ordering::Relaxed),
+        bytes_down: traffic.written.load(Ordering::Relaxed),
+    });
+    Ok(())
+}
 
+/// Streams of the control tunnel.
+async fn dispatch(
+    stream_type: StreamType,
+    compression: Compression,
+    mut io: QuicIo,
+    manager: UdpChannelManager,
+    datagram_tx: tokio::sync::mpsc::Sender<(u32, Bytes)>,
+    unit_manager: Arc<DeploymentManager>,
+    tunnel_shutdown: watch::Receiver<bool>,
+) -> anyhow::Result<()> {
     // let token = stream_type.get_token();
     // if let Err(e) = validate_token(token).await {
     //     warn!("router: token validation failed: {e:?}");
@@ -44,24 +156,110 @@ pub async fn handle_incoming_stream(
         }
         StreamType::Exec { .. } => {
             debug!("router: dispatching to exec handler");
-            handle_exec_io(io).await;
+            match compression {
+                Compression::Zstd => handle_exec_io(CompressedIo::new(io)?).await,
+                Compression::None => handle_exec_io(io).await,
+            }
         }
         StreamType::Logs { .. } => {
             debug!("router: dispatching to logs handler");
-            let _ = handle_logs_io(&mut io, unit_manager).await;
+            let _ = match compression {
+                Compression::Zstd => {
+                    handle_logs_io(&mut CompressedIo::new(io)?, unit_manager).await
+                }
+                Compression::None => handle_logs_io(&mut io, unit_manager).await,
+            };
+        }
+        StreamType::PersistedLogs {
+            unit,
+            since_ms,
+            until_ms,
+            grep,
+            tail,
+            ..
+        } => {
+            debug!("router: dispatching to persisted logs handler");
+            let query = PersistedLogQuery {
+                since_ms,
+                until_ms,
+                grep,
+                tail,
+            };
+            let _ = match compression {
+                Compression::Zstd => {
+                    let mut io = CompressedIo::new(io)?;
+                    handle_persisted_logs_io(&mut io, unit_manager, &unit, query).await
+                }
+                Compression::None => {
+                    handle_persisted_logs_io(&mut io, unit_manager, &unit, query).await
+                }
+            };
+        }
+        StreamType::Journal {
+            units,
+            kernel,
+            priority,
+            since_ms,
+            until_ms,
+            tail,
+            follow,
+            ..
+        } => {
+            debug!("router: dispatching to journal handler");
+            let query = JournalQuery {
+                filter: JournalSpec {
+                    units,
+                    kernel,
+                    priority,
+                },
+                since_ms,
+                until_ms,
+                tail,
+                follow,
+            };
+            match compression {
+                Compression::Zstd => handle_journal_io(&mut CompressedIo::new(io)?, query).await,
+                Compression::None => handle_journal_io(&mut io, query).await,
+            }
         }
         StreamType::Forward { target, .. } => {
             debug!("router: dispatching to port forward handler");
             handle_port_forward_io(target, io, manager, datagram_tx).await;
         }
-        StreamType::Serial { name, baud, .. } => {
+        StreamType::Serial {
+            name,
+            baud,
+            control,
+            ..
+        } => {
             debug!("router: dispatching to serial handler");
-            handle_serial_io(name, baud, &mut io).await;
+            handle_serial_io(name, baud, control, &mut io).await;
+        }
+        StreamType::SerialLog {
+            name,
+            since_secs,
+            follow,
+            ..
+        } => {
+            debug!("router: dispatching to serial log handler");
+            handle_serial_log_io(name, since_secs, follow, &mut io).await;
+        }
+        StreamType::SerialList { .. } => {
+            debug!("router: dispatching to serial list handler");
+            handle_serial_list_io(&mut io).await;
         }
         StreamType::Metrics { .. } => {
             debug!("router: dispatching to metrics handler");
             handle_system_metrics_io(&mut io).await;
         }
+        StreamType::Processes { .. } => {
+            debug!("router: dispatching to processes handler");
+            handle_processes_io(&mut io).await;
+        }
+        StreamType::Signal { pid, signal, .. } => {
+            debug!("router: dispatching to signal handler");
+            handle_signal_io(pid, signal, &mut io).await;
+        }
         StreamType::Docker { .. } => {
             debug!("router: dispatching to docker handler");
             handle_docker_io(&mut io).await;
@@ -69,10 +267,14 @@ pub async fn handle_incoming_stream(
         StreamType::Ssh { .. } => {
             debug!("router: dispatching to ssh handler");
             tokio::spawn(async move {
-                handle_ssh_io(io).await;
+                handle_ssh_io(io, false).await;
             });
             return Ok(());
         }
+        StreamType::P2p { candidates, .. } => {
+            debug!("router: dispatching to direct connection handler");
+            handle_p2p_io(candidates, io, unit_manager, tunnel_shutdown).await;
+        }
     }
     debug!("router: handler finished");
     Ok(())
@@ -1,32 +1,205 @@
-use tokio::{io, io::AsyncWriteExt};
-use tokio_serial::SerialPortBuilderExt;
-use tracing::{error, info};
+use std::sync::Arc;
 
+use tokio::{
+    io,
+    io::{AsyncReadExt, AsyncWriteExt},
+    sync::broadcast,
+};
+use tracing::{debug, error, info, warn};
+
+use crate::device::serial_capture::{self, SharedPort};
 use crate::streams::quic::QuicIo;
+use crate::streams::serial_control::{
+    self, CapturedChunk, Frame, FrameReader, SerialControl, SerialEvent,
+};
+use crate::util::time::now_ms;
 
-pub async fn handle_serial_io(name: String, baud: Option<u32>, io: &mut QuicIo) {
+/// Serial streams attach to a shared port, so several viewers (and the
+/// capture, if the port has one) see the same output.
+pub async fn handle_serial_io(name: String, baud: Option<u32>, control: bool, io: &mut QuicIo) {
     let baud = baud.unwrap_or(115200);
 
-    let serial_path = format!("/dev/{}", name); // e.g. ttyUSB0
-
-    let builder = tokio_serial::new(serial_path.clone(), baud)
-        .data_bits(tokio_serial::DataBits::Eight)
-        .parity(tokio_serial::Parity::None)
-        .stop_bits(tokio_serial::StopBits::One)
-        .flow_control(tokio_serial::FlowControl::None);
-
-    let mut serial = match builder.open_native_async() {
-        Ok(s) => s,
+    let port = match serial_capture::attach(&name, baud) {
+        Ok(port) => port,
         Err(e) => {
-            let _ = io
-                .write_all(format!("Failed to open {serial_path}: {e}\n").as_bytes())
-                .await;
+            let message = format!("{e:#}");
+            let _ = if control {
+                serial_control::write_control(io, &SerialEvent::Error { message }).await
+            } else {
+                io.write_all(format!("{message}\n").as_bytes()).await
+            };
             return;
         }
     };
 
-    match io::copy_bidirectional(io, &mut serial).await {
-        Ok((a, b)) => info!("serial closed cleanly (client→dev={a}, dev→client={b})"),
+    let result = if control {
+        handle_controlled(io, &port).await
+    } else {
+        handle_plain(io, &port).await
+    };
+    match result {
+        Ok(()) => info!("serial closed cleanly"),
         Err(e) => error!("serial forwarding error: {e}"),
     }
 }
+
+/// Raw bytes both ways.
+async fn handle_plain(io: &mut QuicIo, port: &SharedPort) -> io::Result<()> {
+    let mut live = port.subscribe();
+    let mut buf = [0u8; 4096];
+    loop {
+        tokio::select! {
+            n = io.read(&mut buf) => match n? {
+                0 => return Ok(()),
+                n => port.write(buf[..n].to_vec()).await,
+            },
+            chunk = next_live(&mut live) => match chunk {
+                Some(chunk) => io.write_all(&chunk.data).await?,
+                None => return Ok(()),
+            },
+            _ = port.closed() => return Ok(()),
+        }
+    }
+}
+
+/// Framed stream: serial bytes plus line control and modem line changes.
+async fn handle_controlled(io: &mut QuicIo, port: &SharedPort) -> io::Result<()> {
+    let (rx, mut tx) = io::split(io);
+    let mut live = port.subscribe();
+    let mut modem = port.modem();
+    // Report the lines as they are now, not only once they change.
+    modem.mark_changed();
+    let settings = port.settings();
+    serial_control::write_control(&mut tx, &SerialEvent::Opened { settings }).await?;
+
+    let mut frames = FrameReader::new(rx);
+    loop {
+        tokio::select! {
+            frame = frames.next() => match frame? {
+                Some(Frame::Data(data)) => port.write(data).await,
+                Some(Frame::Control(json)) => {
+                    let applied = match Frame::parse::<SerialControl>(&json) {
+                        Ok(ctl) => port.control(ctl).await,
+                        Err(e) => Err(e.to_string()),
+                    };
+                    if let Err(message) = applied {
+                        debug!("serial: control rejected: {message}");
+                        serial_control::write_control(&mut tx, &SerialEvent::Error { message })
+                            .await?;
+                    }
+                }
+                None => return Ok(()),
+            },
+
+            chunk = next_live(&mut live) => match chunk {
+                Some(chunk) => serial_control::write_data(&mut tx, &chunk.data).await?,
+                None => return Ok(()),
+            },
+
+            changed = modem.changed() => {
+                if changed.is_err() {
+                    return Ok(());
+                }
+                let status = *modem.borrow_and_update();
+                if let Some(status) = status {
+                    serial_control::write_control(&mut tx, &SerialEvent::Modem { status }).await?;
+                }
+            }
+
+            _ = port.closed() => return Ok(()),
+        }
+    }
+}
+
+/// Recorded output of a captured port, then live output if `follow` is set.
+/// Each data frame carries one encoded [`CapturedChunk`].
+pub async fn handle_serial_log_io(
+    name: String,
+    since_secs: Option<u64>,
+    follow: bool,
+    io: &mut QuicIo,
+) {
+    let Some(port) = serial_capture::captured(&name) else {
+        let message =
+            format!("{name} is not captured; add it to serial_captures in the m87 config");
+        let _ = serial_control::write_control(io, &SerialEvent::Error { message }).await;
+        return;
+    };
+
+    if let Err(e) = send_serial_log(io, &port, since_secs, follow).await {
+        warn!("serial log stream ended: {e}");
+    }
+}
+
+async fn send_serial_log(
+    io: &mut QuicIo,
+    port: &SharedPort,
+    since_secs: Option<u64>,
+    follow: bool,
+) -> io::Result<()> {
+    let settings = port.settings();
+    serial_control::write_control(io, &SerialEvent::Opened { settings }).await?;
+
+    // Subscribe before reading the history so nothing falls in between;
+    // chunks already sent from disk are skipped by seq.
+    let mut live = port.subscribe();
+    let mut last_seq = None;
+    if let Some(since_secs) = since_secs {
+        let since_ms = now_ms().saturating_sub(since_secs.saturating_mul(1000));
+        let history = port.history(since_ms).await.map_err(io::Error::other)?;
+        for chunk in history {
+            last_seq = Some(chunk.seq);
+            serial_control::write_frame(io, &Frame::Data(chunk.encode())).await?;
+        }
+    }
+    if !follow {
+        return io.shutdown().await;
+    }
+
+    let mut buf = [0u8; 64];
+    loop {
+        tokio::select! {
+            // Nothing is expected from the client; this notices it leaving.
+            n = io.read(&mut buf) => if n? == 0 {
+                return Ok(());
+            },
+            chunk = next_live(&mut live) => match chunk {
+                Some(chunk) if last_seq.is_some_and(|seq| chunk.seq <= seq) => {}
+                Some(chunk) => {
+                    serial_control::write_frame(io, &Frame::Data(chunk.encode())).await?;
+                }
+                None => return Ok(()),
+            },
+        }
+    }
+}
+
+/// The device's serial ports as a JSON array of `SerialPortEntry`.
+pub async fn handle_serial_list_io(io: &mut QuicIo) {
+    let ports = tokio::task::spawn_blocking(serial_capture::list_ports)
+        .await
+        .unwrap_or_default();
+    let result = async {
+        io.write_all(&serde_json::to_vec(&ports)?).await?;
+        io.shutdown().await
+    }
+    .await;
+    if let Err(e) = result {
+        warn!("serial list stream ended: {e}");
+    }
+}
+
+/// Next live chunk; a viewer that fell behind skips what it missed.
+async fn next_live(
+    live: &mut broadcast::Receiver<Arc<CapturedChunk>>,
+) -> Option<Arc<CapturedChunk>> {
+    loop {
+        match live.recv().await {
+            Ok(chunk) => return Some(chunk),
+            Err(broadcast::error::RecvError::Lagged(n)) => {
+                debug!("serial: viewer fell behind, skipped {n} chunks");
+            }
+            Err(broadcast::error::RecvError::Closed) => return None,
+        }
+    }
+}
@@ -0,0 +1,327 @@
+//! In-band control for `Serial` streams.
+//!
+//! When the stream header has `control: true`, both directions carry frames
+//! instead of raw bytes: a one-byte tag, a big-endian `u32` payload length
+//! and the payload. Data frames carry serial bytes; control frames carry
+//! JSON, a [`SerialControl`] from the client or a [`SerialEvent`] from the
+//! device. The device answers the header with [`SerialEvent::Opened`] or
+//! [`SerialEvent::Error`] before sending anything else, so a client can tell
+//! devices without control support apart.
+//!
+//! `SerialLog` streams reuse the framing to read a captured port: data frames
+//! carry an encoded [`CapturedChunk`] each. `SerialList` streams are not
+//! framed: the device answers with a JSON array of [`SerialPortEntry`].
+
+use serde::{Deserialize, Serialize};
+use tokio::io::{self, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+const TAG_DATA: u8 = 0;
+const TAG_CONTROL: u8 = 1;
+const MAX_FRAME_LEN: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Parity {
+    None,
+    Odd,
+    Even,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FlowControl {
+    None,
+    Software,
+    Hardware,
+}
+
+/// Line settings of an open port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SerialSettings {
+    pub baud: u32,
+    pub data_bits: u8,
+    pub parity: Parity,
+    pub stop_bits: u8,
+    pub flow_control: FlowControl,
+}
+
+impl SerialSettings {
+    /// 8N1 without flow control, what plain serial streams use.
+    pub fn with_baud(baud: u32) -> Self {
+        Self {
+            baud,
+            data_bits: 8,
+            parity: Parity::None,
+            stop_bits: 1,
+            flow_control: FlowControl::None,
+        }
+    }
+}
+
+/// Sent by the client to change the port while the stream is open.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum SerialControl {
+    Baud {
+        baud: u32,
+    },
+    DataBits {
+        bits: u8,
+    },
+    Parity {
+        parity: Parity,
+    },
+    StopBits {
+        bits: u8,
+    },
+    FlowControl {
+        flow_control: FlowControl,
+    },
+    Dtr {
+        on: bool,
+    },
+    Rts {
+        on: bool,
+    },
+    Break {
+        on: bool,
+    },
+    /// Discard buffered input (`rx`) and/or output (`tx`).
+    Purge {
+        rx: bool,
+        tx: bool,
+    },
+}
+
+/// Modem status lines as read from the port.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModemStatus {
+    pub cts: bool,
+    pub dsr: bool,
+    pub ri: bool,
+    pub cd: bool,
+}
+
+/// Sent by the device.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum SerialEvent {
+    Opened {
+        settings: SerialSettings,
+    },
+    /// Modem lines changed.
+    Modem {
+        status: ModemStatus,
+    },
+    /// The port could not be opened, or a control was rejected.
+    Error {
+        message: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Frame {
+    Data(Vec<u8>),
+    /// JSON encoded [`SerialControl`] or [`SerialEvent`].
+    Control(Vec<u8>),
+}
+
+impl Frame {
+    pub fn control<T: Serialize>(msg: &T) -> io::Result<Self> {
+        Ok(Frame::Control(serde_json::to_vec(msg)?))
+    }
+
+    pub fn parse<T: for<'de> Deserialize<'de>>(payload: &[u8]) -> io::Result<T> {
+        serde_json::from_slice(payload).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+/// A serial port found on the device.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SerialPortEntry {
+    /// Name under `/dev`, e.g. `ttyUSB0`.
+    pub name: String,
+    /// `usb`, `pci`, `bluetooth` or `other`.
+    pub kind: String,
+    #[serde(default)]
+    pub vid: Option<u16>,
+    #[serde(default)]
+    pub pid: Option<u16>,
+    #[serde(default)]
+    pub serial_number: Option<String>,
+    #[serde(default)]
+    pub manufacturer: Option<String>,
+    #[serde(default)]
+    pub product: Option<String>,
+    /// Stable names under `/dev/serial` (`by-id/...`, `by-path/...`) that
+    /// can be used in place of `name`.
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    /// Recorded by the runtime (see `serial_captures`).
+    #[serde(default)]
+    pub captured: bool,
+}
+
+/// Serial output as recorded by the device, stamped with the time it was
+/// read from the port. `seq` increases per port and survives restarts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapturedChunk {
+    pub seq: u64,
+    pub at_ms: u64,
+    pub data: Vec<u8>,
+}
+
+impl CapturedChunk {
+    const HEADER_LEN: usize = 16;
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(Self::HEADER_LEN + self.data.len());
+        buf.extend_from_slice(&self.seq.to_be_bytes());
+        buf.extend_from_slice(&self.at_ms.to_be_bytes());
+        buf.extend_from_slice(&self.data);
+        buf
+    }
+
+    pub fn decode(buf: &[u8]) -> io::Result<Self> {
+        if buf.len() < Self::HEADER_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "captured chunk too short",
+            ));
+        }
+        let (seq, rest) = buf.split_at(8);
+        let (at_ms, data) = rest.split_at(8);
+        Ok(Self {
+            seq: u64::from_be_bytes(seq.try_into().unwrap()),
+            at_ms: u64::from_be_bytes(at_ms.try_into().unwrap()),
+            data: data.to_vec(),
+        })
+    }
+}
+
+pub async fn write_frame<W: AsyncWrite + Unpin>(w: &mut W, frame: &Frame) -> io::Result<()> {
+    let (tag, payload) = match frame {
+        Frame::Data(data) => (TAG_DATA, data),
+        Frame::Control(json) => (TAG_CONTROL, json),
+    };
+    let mut buf = Vec::with_capacity(5 + payload.len());
+    buf.push(tag);
+    buf.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    buf.extend_from_slice(payload);
+    w.write_all(&buf).await?;
+    w.flush().await
+}
+
+pub async fn write_data<W: AsyncWrite + Unpin>(w: &mut W, data: &[u8]) -> io::Result<()> {
+    write_frame(w, &Frame::Data(data.to_vec())).await
+}
+
+pub async fn write_control<W: AsyncWrite + Unpin, T: Serialize>(
+    w: &mut W,
+    msg: &T,
+) -> io::Result<()> {
+    write_frame(w, &Frame::control(msg)?).await
+}
+
+/// Reads frames from a stream. [`FrameReader::next`] is cancel safe, so it
+/// can be used in `tokio::select!` next to reads from the port.
+pub struct FrameReader<R> {
+    inner: R,
+    buf: Vec<u8>,
+}
+
+impl<R: AsyncRead + Unpin> FrameReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            buf: Vec::new(),
+        }
+    }
+
+    /// Next frame, or `None` once the stream ended.
+    pub async fn next(&mut self) -> io::Result<Option<Frame>> {
+        let mut chunk = [0u8; 8192];
+        loop {
+            if let Some(frame) = self.parse()? {
+                return Ok(Some(frame));
+            }
+            let n = self.inner.read(&mut chunk).await?;
+            if n == 0 {
+                return Ok(None);
+            }
+            self.buf.extend_from_slice(&chunk[..n]);
+        }
+    }
+
+    fn parse(&mut self) -> io::Result<Option<Frame>> {
+        if self.buf.len() < 5 {
+            return Ok(None);
+        }
+        let tag = self.buf[0];
+        let len = u32::from_be_bytes([self.buf[1], self.buf[2], self.buf[3], self.buf[4]]) as usize;
+        if tag > TAG_CONTROL || len > MAX_FRAME_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid serial frame",
+            ));
+        }
+        if self.buf.len() < 5 + len {
+            return Ok(None);
+        }
+        let payload = self.buf[5..5 + len].to_vec();
+        self.buf.drain(..5 + len);
+        Ok(Some(match tag {
+            TAG_DATA => Frame::Data(payload),
+            _ => Frame::Control(payload),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn frames_roundtrip_in_pieces() {
+        let mut wire = Vec::new();
+        write_data(&mut wire, b"hello").await.unwrap();
+        write_control(&mut wire, &SerialControl::Dtr { on: false })
+            .await
+            .unwrap();
+
+        // Deliver the bytes one at a time to exercise partial frames.
+        let (mut tx, rx) = tokio::io::duplex(1);
+        tokio::spawn(async move { tx.write_all(&wire).await });
+
+        let mut reader = FrameReader::new(rx);
+        assert_eq!(
+            reader.next().await.unwrap(),
+            Some(Frame::Data(b"hello".to_vec()))
+        );
+        let Some(Frame::Control(json)) = reader.next().await.unwrap() else {
+            panic!("expected control frame");
+        };
+        assert_eq!(
+            Frame::parse::<SerialControl>(&json).unwrap(),
+            SerialControl::Dtr { on: false }
+        );
+        assert_eq!(reader.next().await.unwrap(), None);
+    }
+
+    #[test]
+    fn captured_chunk_roundtrip() {
+        let chunk = CapturedChunk {
+            seq: 42,
+            at_ms: 1_760_000_000_000,
+            data: b"boot: ok\r\n".to_vec(),
+        };
+        assert_eq!(CapturedChunk::decode(&chunk.encode()).unwrap(), chunk);
+        assert!(CapturedChunk::decode(&[0u8; 15]).is_err());
+    }
+
+    #[tokio::test]
+    async fn raw_bytes_are_rejected() {
+        let mut reader = FrameReader::new(&b"ESP-ROM:esp32\r\n"[..]);
+        assert!(reader.next().await.is_err());
+    }
+}
@@ -7,9 +7,13 @@ use crate::{
     util::ssh::{M87SshHandler, make_server_config},
 };
 
-pub async fn handle_ssh_io(io: QuicIo) {
+/// Serve an SSH session on `io`; `sftp_only` refuses shells and exec.
+pub async fn handle_ssh_io(io: QuicIo, sftp_only: bool) {
     let config = make_server_config();
-    let handler = M87SshHandler::new(PathBuf::from("/"));
+    let mut handler = M87SshHandler::new(PathBuf::from("/"));
+    if sftp_only {
+        handler = handler.sftp_only();
+    }
 
     match server::run_stream(config, io, handler).await {
         Ok(running) => {
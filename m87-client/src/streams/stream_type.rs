@@ -1,3 +1,4 @@
+use m87_shared::deploy_spec::JournalPriority;
 use serde::{Deserialize, Serialize};
 use std::{fmt, num::ParseIntError};
 
@@ -292,6 +293,37 @@ pub enum StreamType {
     Logs {
         token: String,
     },
+    /// Query a unit's persisted logs (`observe.logs.persist`); the device
+    /// answers with NDJSON [`PersistedLogMessage`]s.
+    PersistedLogs {
+        token: String,
+        unit: String,
+        since_ms: Option<u64>,
+        until_ms: Option<u64>,
+        /// Regex evaluated on the device.
+        grep: Option<String>,
+        /// Only the last `tail` matching lines; 0 keeps all.
+        #[serde(default)]
+        tail: usize,
+    },
+    /// Read the device's systemd journal; the device answers with NDJSON
+    /// [`JournalMessage`]s, and keeps sending new entries with `follow`.
+    Journal {
+        token: String,
+        #[serde(default)]
+        units: Vec<String>,
+        /// Kernel messages.
+        #[serde(default)]
+        kernel: bool,
+        priority: Option<JournalPriority>,
+        since_ms: Option<u64>,
+        until_ms: Option<u64>,
+        /// Only the last `tail` entries; 0 keeps all.
+        #[serde(default)]
+        tail: usize,
+        #[serde(default)]
+        follow: bool,
+    },
     Forward {
         token: String,
         target: ForwardTarget,
@@ -300,16 +332,50 @@ pub enum StreamType {
         token: String,
         name: String,
         baud: Option<u32>,
+        /// Frame the stream to carry line control (see `streams::serial_control`).
+        #[serde(default)]
+        control: bool,
+    },
+    /// Read a port captured by the runtime: history since `since_secs` ago,
+    /// then live output if `follow` is set.
+    SerialLog {
+        token: String,
+        name: String,
+        since_secs: Option<u64>,
+        #[serde(default)]
+        follow: bool,
+    },
+    /// List the device's serial ports.
+    SerialList {
+        token: String,
     },
     Metrics {
         token: String,
     },
+    /// Process snapshots (`m87_shared::metrics::ProcessList`) as NDJSON,
+    /// every couple of seconds until the client closes the stream.
+    Processes {
+        token: String,
+    },
+    /// Send a signal to a process; the device answers with one
+    /// [`SignalResult`].
+    Signal {
+        token: String,
+        pid: u32,
+        /// Name like `TERM`, with or without `SIG`.
+        signal: String,
+    },
     Docker {
         token: String,
     },
     Ssh {
         token: String,
     },
+    /// Negotiate a direct client↔device connection (see `streams::p2p`).
+    P2p {
+        token: String,
+        candidates: Vec<std::net::SocketAddr>,
+    },
 }
 
 impl StreamType {
@@ -318,11 +384,18 @@ impl StreamType {
             StreamType::Terminal { .. } => "Terminal",
             StreamType::Exec { .. } => "Exec",
             StreamType::Logs { .. } => "Logs",
+            StreamType::PersistedLogs { .. } => "PersistedLogs",
+            StreamType::Journal { .. } => "Journal",
             StreamType::Forward { .. } => "Forward",
             StreamType::Serial { .. } => "Serial",
+            StreamType::SerialLog { .. } => "SerialLog",
+            StreamType::SerialList { .. } => "SerialList",
             StreamType::Metrics { .. } => "Metrics",
+            StreamType::Processes { .. } => "Processes",
+            StreamType::Signal { .. } => "Signal",
             StreamType::Docker { .. } => "Docker",
             StreamType::Ssh { .. } => "Ssh",
+            StreamType::P2p { .. } => "P2p",
         }
     }
 
@@ -331,15 +404,104 @@ impl StreamType {
             StreamType::Terminal { token, .. } => token,
             StreamType::Exec { token, .. } => token,
             StreamType::Logs { token, .. } => token,
+            StreamType::PersistedLogs { token, .. } => token,
+            StreamType::Journal { token, .. } => token,
             StreamType::Forward { token, .. } => token,
             StreamType::Serial { token, .. } => token,
+            StreamType::SerialLog { token, .. } => token,
+            StreamType::SerialList { token } => token,
             StreamType::Metrics { token } => token,
+            StreamType::Processes { token } => token,
+            StreamType::Signal { token, .. } => token,
             StreamType::Docker { token } => token,
             StreamType::Ssh { token } => token,
+            StreamType::P2p { token, .. } => token,
+        }
+    }
+}
+
+/// One line of a `PersistedLogs` response. The device ends every answer
+/// with `Done` or `Error`, so a stream closed without either comes from a
+/// device that doesn't know the query.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PersistedLogMessage {
+    Line { at_ms: u64, line: String },
+    Done,
+    Error { message: String },
+}
+
+/// One line of a `Journal` response; ends with `Done` or `Error` like
+/// [`PersistedLogMessage`].
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum JournalMessage {
+    Entry(JournalEntry),
+    Done,
+    Error { message: String },
+}
+
+/// The commonly useful fields of a journal entry.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct JournalEntry {
+    pub at_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<JournalPriority>,
+    /// `SYSLOG_IDENTIFIER`, e.g. `kernel` or `NetworkManager`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identifier: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pid: Option<u32>,
+    pub message: String,
+}
+
+/// Answer to a `Signal` stream.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SignalResult {
+    Sent,
+    Error { message: String },
+}
+
+/// Compression the client asks for on a stream (see `streams::compress`).
+/// Unknown values from newer clients fall back to `None`.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    Zstd,
+    #[default]
+    #[serde(other)]
+    None,
+}
+
+impl Compression {
+    pub fn is_none(&self) -> bool {
+        *self == Compression::None
+    }
+}
+
+/// First message on every stream: the stream type plus per-stream options.
+/// Options are flattened next to the `type` tag, so devices that only know
+/// `StreamType` still parse the header and ignore them.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StreamHeader {
+    #[serde(flatten)]
+    pub stream_type: StreamType,
+    #[serde(default, skip_serializing_if = "Compression::is_none")]
+    pub compression: Compression,
+}
+
+impl StreamHeader {
+    pub fn new(stream_type: StreamType) -> Self {
+        Self {
+            stream_type,
+            compression: Compression::None,
         }
     }
 
-    pub async fn from_incoming_stream(recv: &mut quinn::RecvStream) -> anyhow::Result<StreamType> {
+    pub async fn from_incoming_stream(recv: &mut quinn::RecvStream) -> anyhow::Result<StreamHeader> {
         // length header
         let mut len_buf = [0u8; 4];
         recv.read_exact(&mut len_buf).await?;
@@ -349,8 +511,7 @@ impl StreamType {
         let mut buf = vec![0u8; len];
         recv.read_exact(&mut buf).await?;
 
-        // deserialize directly into enum
-        let msg: StreamType = serde_json::from_slice(&buf)?;
+        let msg: StreamHeader = serde_json::from_slice(&buf)?;
         Ok(msg)
     }
 }
@@ -562,7 +723,8 @@ mod tests {
             StreamType::Serial {
                 token: token.clone(),
                 name: "ttyUSB0".to_string(),
-                baud: None
+                baud: None,
+                control: false
             }
             .variant_name(),
             "Serial"
@@ -627,6 +789,40 @@ mod tests {
         assert_eq!(StreamType::Ssh { token }.get_token(), "my-unique-token");
     }
 
+    // --- StreamHeader tests ---
+
+    #[test]
+    fn test_stream_header_compression_roundtrip() {
+        let header = StreamHeader {
+            stream_type: StreamType::Exec {
+                token: "t".to_string(),
+            },
+            compression: Compression::Zstd,
+        };
+        let json = serde_json::to_value(&header).unwrap();
+        assert_eq!(json["type"], "Exec");
+        assert_eq!(json["compression"], "zstd");
+
+        let parsed: StreamHeader = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(parsed.compression, Compression::Zstd);
+        // devices that predate StreamHeader parse the same bytes as StreamType
+        let legacy: StreamType = serde_json::from_value(json).unwrap();
+        assert_eq!(legacy.variant_name(), "Exec");
+    }
+
+    #[test]
+    fn test_stream_header_without_compression() {
+        let header = StreamHeader::new(StreamType::Logs {
+            token: "t".to_string(),
+        });
+        let json = serde_json::to_string(&header).unwrap();
+        assert!(!json.contains("compression"));
+
+        let parsed: StreamHeader =
+            serde_json::from_str(r#"{"type":"Logs","token":"t","compression":"lz4"}"#).unwrap();
+        assert_eq!(parsed.compression, Compression::None);
+    }
+
     // --- ForwardParseError Display tests ---
 
     #[test]
@@ -0,0 +1,217 @@
+use crate::tui::helper::{
+    Align, ColSpec, RenderOpts, Table, dim, format_relative_time, green, red, terminal_width,
+    yellow,
+};
+use m87_shared::alerts::{Alert, AlertRule, AlertState};
+
+pub fn print_alerts(alerts: &[Alert]) {
+    if alerts.is_empty() {
+        println!("{}", dim("No alerts"));
+        return;
+    }
+
+    let term_w = terminal_width().unwrap_or(96);
+    let opts = RenderOpts::default();
+
+    let t = Table::new(
+        term_w.saturating_sub(2),
+        1,
+        vec![
+            ColSpec {
+                title: "ID",
+                min: 24,
+                max: Some(24),
+                weight: 0,
+                align: Align::Left,
+                wrap: false,
+            },
+            ColSpec {
+                title: "STATE",
+                min: 8,
+                max: Some(10),
+                weight: 0,
+                align: Align::Left,
+                wrap: false,
+            },
+            ColSpec {
+                title: "RULE",
+                min: 10,
+                max: None,
+                weight: 2,
+                align: Align::Left,
+                wrap: false,
+            },
+            ColSpec {
+                title: "DEVICE",
+                min: 10,
+                max: None,
+                weight: 2,
+                align: Align::Left,
+                wrap: false,
+            },
+            ColSpec {
+                title: "VALUE",
+                min: 6,
+                max: Some(24),
+                weight: 1,
+                align: Align::Left,
+                wrap: false,
+            },
+            ColSpec {
+                title: "FIRED",
+                min: 10,
+                max: Some(14),
+                weight: 0,
+                align: Align::Left,
+                wrap: false,
+            },
+            ColSpec {
+                title: "ACK",
+                min: 6,
+                max: None,
+                weight: 1,
+                align: Align::Left,
+                wrap: false,
+            },
+        ],
+    );
+
+    let mut out = String::new();
+    out.push_str("  ");
+    t.header(&mut out, &opts);
+
+    for a in alerts {
+        let state = match (a.state, a.silenced) {
+            (AlertState::Resolved, _) => green(&a.state.to_string()),
+            (AlertState::Firing, true) => yellow("silenced"),
+            (AlertState::Firing, false) => red(&a.state.to_string()),
+        };
+        let device = if a.subject.is_empty() {
+            a.device_name.clone()
+        } else {
+            format!("{}/{}", a.device_name, a.subject)
+        };
+        let value = a.value.clone().unwrap_or_else(|| dim("-"));
+        let fired = format_relative_time(&a.fired_at);
+        let acked = a.acked_by.clone().unwrap_or_else(|| dim("-"));
+
+        out.push_str("  ");
+        t.row(
+            &mut out,
+            &[&a.id, &state, &a.rule_name, &device, &value, &fired, &acked],
+            &opts,
+        );
+    }
+
+    print!("{out}");
+}
+
+pub fn print_alert_rules(rules: &[AlertRule]) {
+    if rules.is_empty() {
+        println!("{}", dim("No alert rules found"));
+        return;
+    }
+
+    let term_w = terminal_width().unwrap_or(96);
+    let opts = RenderOpts::default();
+
+    let t = Table::new(
+        term_w.saturating_sub(2),
+        1,
+        vec![
+            ColSpec {
+                title: "ID",
+                min: 24,
+                max: Some(24),
+                weight: 0,
+                align: Align::Left,
+                wrap: false,
+            },
+            ColSpec {
+                title: "NAME",
+                min: 10,
+                max: None,
+                weight: 2,
+                align: Align::Left,
+                wrap: false,
+            },
+            ColSpec {
+                title: "CONDITION",
+                min: 12,
+                max: None,
+                weight: 2,
+                align: Align::Left,
+                wrap: false,
+            },
+            ColSpec {
+                title: "FOR",
+                min: 4,
+                max: Some(6),
+                weight: 0,
+                align: Align::Right,
+                wrap: false,
+            },
+            ColSpec {
+                title: "LABELS",
+                min: 8,
+                max: None,
+                weight: 2,
+                align: Align::Left,
+                wrap: true,
+            },
+            ColSpec {
+                title: "SILENCED UNTIL",
+                min: 14,
+                max: Some(20),
+                weight: 0,
+                align: Align::Left,
+                wrap: false,
+            },
+        ],
+    );
+
+    let mut out = String::new();
+    out.push_str("  ");
+    t.header(&mut out, &opts);
+
+    for r in rules {
+        let labels = if r.labels.is_empty() {
+            dim("all devices")
+        } else {
+            r.labels
+                .iter()
+                .map(|(k, v)| format!("{k}={v}"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let silenced = match &r.silenced_until {
+            Some(until) => yellow(&until.replace('T', " ").chars().take(16).collect::<String>()),
+            None => dim("-"),
+        };
+
+        out.push_str("  ");
+        t.row(
+            &mut out,
+            &[
+                &r.id,
+                &r.name,
+                &r.condition.to_string(),
+                &format_secs(r.for_secs),
+                &labels,
+                &silenced,
+            ],
+            &opts,
+        );
+    }
+
+    print!("{out}");
+}
+
+fn format_secs(secs: u64) -> String {
+    match secs {
+        0 => "-".to_string(),
+        s if s % 3600 == 0 => format!("{}h", s / 3600),
+        s if s % 60 == 0 => format!("{}m", s / 60),
+        s => format!("{s}s"),
+    }
+}
@@ -0,0 +1,95 @@
+use crate::tui::helper::{Align, ColSpec, RenderOpts, Table, dim, red, role_badge, terminal_width};
+use m87_shared::auth::ApiKeyInfo;
+
+pub fn print_api_keys(keys: &[(String, ApiKeyInfo)]) {
+    if keys.is_empty() {
+        println!("{}", dim("No API keys found"));
+        return;
+    }
+
+    let term_w = terminal_width().unwrap_or(96);
+    let opts = RenderOpts::default();
+
+    let t = Table::new(
+        term_w.saturating_sub(2),
+        1,
+        vec![
+            ColSpec {
+                title: "KEY ID",
+                min: 12,
+                max: Some(12),
+                weight: 0,
+                align: Align::Left,
+                wrap: false,
+            },
+            ColSpec {
+                title: "NAME",
+                min: 8,
+                max: Some(24),
+                weight: 2,
+                align: Align::Left,
+                wrap: false,
+            },
+            ColSpec {
+                title: "SCOPE",
+                min: 12,
+                max: None,
+                weight: 3,
+                align: Align::Left,
+                wrap: false,
+            },
+            ColSpec {
+                title: "ROLE",
+                min: 8,
+                max: Some(10),
+                weight: 0,
+                align: Align::Left,
+                wrap: false,
+            },
+            ColSpec {
+                title: "EXPIRES",
+                min: 10,
+                max: Some(20),
+                weight: 0,
+                align: Align::Left,
+                wrap: false,
+            },
+            ColSpec {
+                title: "SERVER",
+                min: 10,
+                max: None,
+                weight: 2,
+                align: Align::Left,
+                wrap: false,
+            },
+        ],
+    );
+
+    let mut out = String::new();
+    out.push_str("  ");
+    t.header(&mut out, &opts);
+
+    let now = chrono::Utc::now();
+    for (server_url, k) in keys {
+        let role = role_badge(&k.role);
+        let expires = match &k.expires_at {
+            Some(at) if chrono::DateTime::parse_from_rfc3339(at).is_ok_and(|at| at < now) => {
+                red("expired")
+            }
+            Some(at) => at.replace('T', " ").chars().take(16).collect(),
+            None => dim("never"),
+        };
+        let server = dim(server_url
+            .trim_start_matches("https://")
+            .trim_start_matches("http://"));
+
+        out.push_str("  ");
+        t.row(
+            &mut out,
+            &[&k.key_id, &k.name, &k.scope, &role, &expires, &server],
+            &opts,
+        );
+    }
+
+    print!("{out}");
+}
@@ -174,13 +174,17 @@ pub fn print_devices_table(devices: &[PublicDevice], auth_requests: &[DeviceAuth
         for dev in devices {
             let os = dev.system_info.operating_system.as_str();
             let ip = dev.system_info.public_ip_address.as_deref().unwrap_or("-");
+            let status = match dev.revoked_at {
+                Some(_) => dim("revoked"),
+                None => status_badge(dev.online),
+            };
 
             t_devices.row(
                 &mut out,
                 &[
                     &dev.short_id,
                     &dev.name,
-                    &status_badge(dev.online),
+                    &status,
                     &role_badge(&dev.role),
                     &dev.system_info.architecture,
                     os,
@@ -348,6 +352,75 @@ pub fn print_device_status(name: &str, status: &DeviceStatus) {
     }
 }
 
+/// Output format of `m87 <device> audit`.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default)]
+pub enum AuditFormat {
+    #[default]
+    Table,
+    /// One JSON object per line
+    Ndjson,
+    Csv,
+}
+
+/// Render audit entries as NDJSON (one JSON object per line).
+pub fn print_audit_logs_ndjson(logs: &[AuditLog]) {
+    for log in logs {
+        match serde_json::to_string(log) {
+            Ok(line) => println!("{line}"),
+            Err(e) => eprintln!("(json serialize failed: {e})"),
+        }
+    }
+}
+
+const AUDIT_CSV_HEADER: &str = "timestamp,user_name,user_email,device_id,action,details,\
+stream_type,target,streams,started_at,ended_at,duration_ms,bytes_up,bytes_down,close_reason";
+
+/// Render audit entries as CSV; stream columns are empty for other entries.
+pub fn print_audit_logs_csv(logs: &[AuditLog]) {
+    println!("{AUDIT_CSV_HEADER}");
+    for log in logs {
+        println!("{}", audit_csv_row(log));
+    }
+}
+
+fn audit_csv_row(log: &AuditLog) -> String {
+    let mut fields: Vec<String> = vec![
+        log.timestamp.clone(),
+        log.user_name.clone(),
+        log.user_email.clone(),
+        log.device_id.clone().unwrap_or_default(),
+        log.action.clone(),
+        log.details.clone(),
+    ];
+    match &log.stream {
+        Some(s) => fields.extend([
+            s.stream_type.clone(),
+            s.target.clone().unwrap_or_default(),
+            s.streams.to_string(),
+            s.started_at.clone(),
+            s.ended_at.clone(),
+            s.duration_ms.to_string(),
+            s.bytes_up.to_string(),
+            s.bytes_down.to_string(),
+            s.close_reason.clone(),
+        ]),
+        None => fields.extend(std::iter::repeat_n(String::new(), 9)),
+    }
+    fields
+        .iter()
+        .map(|f| csv_field(f))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
 pub fn print_deployment_reports(reports: &[AuditLog], show_details: bool) {
     if reports.is_empty() {
         println!("{}", dim("No deployment reports found"));
@@ -452,3 +525,45 @@ pub fn print_deployment_reports(reports: &[AuditLog], show_details: bool) {
 
     print!("{out}");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use m87_shared::device::StreamAuditEvent;
+
+    #[test]
+    fn audit_csv_quotes_and_stream_columns() {
+        let plain = AuditLog {
+            user_name: "Ada".into(),
+            user_email: "ada@example.com".into(),
+            timestamp: "2026-01-01T00:00:00Z".into(),
+            action: "Connected to device".into(),
+            details: "a \"quoted\", detail".into(),
+            device_id: None,
+            stream: None,
+        };
+        assert_eq!(
+            audit_csv_row(&plain),
+            "2026-01-01T00:00:00Z,Ada,ada@example.com,,Connected to device,\
+             \"a \"\"quoted\"\", detail\",,,,,,,,,"
+        );
+
+        let relayed = AuditLog {
+            stream: Some(StreamAuditEvent {
+                stream_type: "forward".into(),
+                target: Some("tcp:127.0.0.1:80".into()),
+                streams: 2,
+                duration_ms: 1500,
+                bytes_up: 10,
+                bytes_down: 20,
+                close_reason: "client_closed".into(),
+                ..Default::default()
+            }),
+            details: String::new(),
+            ..plain
+        };
+        let row = audit_csv_row(&relayed);
+        assert_eq!(row.split(',').count(), AUDIT_CSV_HEADER.split(',').count());
+        assert!(row.ends_with(",forward,tcp:127.0.0.1:80,2,,,1500,10,20,client_closed"));
+    }
+}
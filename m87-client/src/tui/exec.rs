@@ -1,4 +1,5 @@
-use crate::streams::quic::open_quic_io;
+use crate::streams::compress::STREAM_STATS;
+use crate::streams::quic::{CompressedQuicIo, open_compressed_quic_io};
 use crate::streams::stream_type::StreamType;
 use crate::{auth::AuthManager, config::Config, devices, util::shutdown::SHUTDOWN};
 use anyhow::{Context, Result};
@@ -35,6 +36,24 @@ pub async fn run_exec_capture(
     command: Vec<String>,
     timeout_secs: u64,
 ) -> Result<ExecCapture> {
+    let io = connect_exec(device).await?;
+    let cmd_str = command.join(" ");
+    let (output, exit_code) = run_output_only_capture(io, cmd_str, timeout_secs).await?;
+    Ok(ExecCapture { output, exit_code })
+}
+
+/// Run a command on a remote device, streaming its output to stdout
+/// (non-interactive, no TTY).
+///
+/// Unlike `run_exec`, a non-zero exit code is returned instead of terminating
+/// the process, so callers can keep running (e.g. `sync --watch --on-change`).
+pub async fn run_exec_streaming(device: &str, command: String) -> Result<i32> {
+    let io = connect_exec(device).await?;
+    run_noninteractive(io, command, None, &mut tokio::io::stdout()).await
+}
+
+/// Open an exec stream to `device`.
+async fn connect_exec(device: &str) -> Result<CompressedQuicIo> {
     rustls::crypto::CryptoProvider::install_default(rustls::crypto::ring::default_provider()).ok();
 
     let config = Config::load()?;
@@ -44,7 +63,7 @@ pub async fn run_exec_capture(
     let stream_type = StreamType::Exec {
         token: token.to_string(),
     };
-    let (_, io) = open_quic_io(
+    let (_, io) = open_compressed_quic_io(
         &resolved.host,
         &token,
         &resolved.short_id,
@@ -53,10 +72,7 @@ pub async fn run_exec_capture(
     )
     .await
     .context("Failed to connect to exec stream")?;
-
-    let cmd_str = command.join(" ");
-    let (output, exit_code) = run_output_only_capture(io, cmd_str, timeout_secs).await?;
-    Ok(ExecCapture { output, exit_code })
+    Ok(io)
 }
 
 /// Run a command on a remote device.
@@ -65,26 +81,7 @@ pub async fn run_exec_capture(
 /// - `stdin` (`-i`): Keep stdin open, forward input to remote (for prompts like Y/n)
 /// - `tty` (`-t`): Allocate pseudo-TTY with raw mode (for TUI apps like vim, htop)
 pub async fn run_exec(device: &str, command: Vec<String>, stdin: bool, tty: bool) -> Result<()> {
-    rustls::crypto::CryptoProvider::install_default(rustls::crypto::ring::default_provider()).ok();
-
-    let config = Config::load()?;
-
-    let resolved = devices::resolve_device_cached(device).await?;
-
-    let token = AuthManager::get_cli_token().await?;
-
-    let stream_type = StreamType::Exec {
-        token: token.to_string(),
-    };
-    let (_, io) = open_quic_io(
-        &resolved.host,
-        &token,
-        &resolved.short_id,
-        stream_type,
-        config.trust_invalid_server_cert,
-    )
-    .await
-    .context("Failed to connect to RAW metrics stream")?;
+    let io = connect_exec(device).await?;
     tracing::info!("Connected to device");
 
     // Join command into single string (shell will interpret operators like && |)
@@ -98,6 +95,12 @@ pub async fn run_exec(device: &str, command: Vec<String>, stdin: bool, tty: bool
     }
 }
 
+/// Exit with the remote command's exit code, printing `--stats` first.
+fn exit_with(code: i32) -> ! {
+    STREAM_STATS.report();
+    std::process::exit(code);
+}
+
 /// Try to parse exit code from a line (server sends JSON before close)
 fn try_parse_exit_code(line: &str) -> Option<i32> {
     serde_json::from_str::<ExecResult>(line.trim())
@@ -110,6 +113,25 @@ fn try_parse_exit_code(line: &str) -> Option<i32> {
 async fn run_output_only_capture<IO>(io: IO, cmd_str: String, timeout_secs: u64) -> Result<(String, i32)>
 where
     IO: AsyncReadExt + AsyncWriteExt + Unpin + Send + 'static,
+{
+    let mut output = Vec::new();
+    let timeout = std::time::Duration::from_secs(timeout_secs);
+    let exit_code = run_noninteractive(io, cmd_str, Some(timeout), &mut output).await?;
+    Ok((String::from_utf8_lossy(&output).into_owned(), exit_code))
+}
+
+/// No stdin, no tty: send command config and copy the output to `out` line by
+/// line until the connection closes. Returns the exit code; non-zero exit is
+/// not an error, but cancellation, `timeout` and read errors are.
+async fn run_noninteractive<IO, W>(
+    io: IO,
+    cmd_str: String,
+    timeout: Option<std::time::Duration>,
+    out: &mut W,
+) -> Result<i32>
+where
+    IO: AsyncReadExt + AsyncWriteExt + Unpin + Send + 'static,
+    W: AsyncWriteExt + Unpin,
 {
     let (reader, mut writer) = tokio::io::split(io);
     let mut reader = BufReader::new(reader);
@@ -124,13 +146,17 @@ where
         .await?;
     writer.flush().await?;
 
-    let mut output = String::new();
     let mut exit_code = 0;
 
     // Stream output until connection closes or timeout
     let mut line = String::new();
-    let timeout = tokio::time::sleep(std::time::Duration::from_secs(timeout_secs));
-    tokio::pin!(timeout);
+    let deadline = async {
+        match timeout {
+            Some(timeout) => tokio::time::sleep(timeout).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::pin!(deadline);
 
     loop {
         line.clear();
@@ -139,10 +165,10 @@ where
             _ = SHUTDOWN.cancelled() => {
                 return Err(anyhow::anyhow!("Command cancelled"));
             }
-            _ = &mut timeout => {
+            _ = &mut deadline => {
                 return Err(anyhow::anyhow!(
                     "Command execution timed out after {} seconds",
-                    timeout_secs
+                    timeout.unwrap_or_default().as_secs()
                 ));
             }
             result = reader.read_line(&mut line) => {
@@ -152,7 +178,8 @@ where
                         if let Some(code) = try_parse_exit_code(&line) {
                             exit_code = code;
                         } else {
-                            output.push_str(&line);
+                            out.write_all(line.as_bytes()).await?;
+                            out.flush().await?;
                         }
                     }
                     Err(e) => {
@@ -163,7 +190,7 @@ where
         }
     }
 
-    Ok((output, exit_code))
+    Ok(exit_code)
 }
 
 /// No stdin, no tty: just send command config and stream output
@@ -193,7 +220,7 @@ where
         line.clear();
         tokio::select! {
             _ = SHUTDOWN.cancelled() => {
-                std::process::exit(130);
+                exit_with(130);
             }
             result = reader.read_line(&mut line) => {
                 match result {
@@ -213,7 +240,7 @@ where
     }
 
     if exit_code != 0 {
-        std::process::exit(exit_code);
+        exit_with(exit_code);
     }
     Ok(())
 }
@@ -283,7 +310,7 @@ where
         line.clear();
         tokio::select! {
             _ = SHUTDOWN.cancelled() => {
-                std::process::exit(130);
+                exit_with(130);
             }
             result = reader.read_line(&mut line) => {
                 match result {
@@ -305,7 +332,7 @@ where
     stdin_task.abort();
 
     if exit_code != 0 {
-        std::process::exit(exit_code);
+        exit_with(exit_code);
     }
     Ok(())
 }
@@ -340,7 +367,7 @@ where
         tokio::select! {
             _ = SHUTDOWN.cancelled() => {
                 drop(raw_mode);
-                std::process::exit(130);
+                exit_with(130);
             }
             result = reader.read(&mut buf) => {
                 match result {
@@ -378,7 +405,7 @@ where
     drop(raw_mode);
 
     if exit_code != 0 {
-        std::process::exit(exit_code);
+        exit_with(exit_code);
     }
     Ok(())
 }
@@ -508,7 +535,7 @@ where
     drop(raw_mode);
 
     if final_code != 0 {
-        std::process::exit(final_code);
+        exit_with(final_code);
     }
     Ok(())
 }
@@ -1,8 +1,12 @@
-use crate::streams::quic::open_quic_io;
-use crate::streams::stream_type::StreamType;
+use crate::streams::quic::open_compressed_quic_io;
+use crate::streams::stream_type::{JournalEntry, JournalMessage, PersistedLogMessage, StreamType};
+use crate::tui::helper::{dim, red, yellow};
+use crate::util::time::format_ms;
 use crate::{auth::AuthManager, config::Config, devices, util::shutdown::SHUTDOWN};
-use anyhow::{Context, Result};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use anyhow::{Context, Result, bail};
+use m87_shared::deploy_spec::{JournalPriority, JournalSpec};
+use m87_shared::device::DeviceLogEntry;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
 use tokio::sync::mpsc;
 
 /// Stream live logs from a device using RAW upgraded connection.
@@ -18,7 +22,7 @@ pub async fn run_logs(device: &str) -> Result<()> {
     let stream_type = StreamType::Logs {
         token: token.to_string(),
     };
-    let (_, mut io) = open_quic_io(
+    let (_, mut io) = open_compressed_quic_io(
         &resolved.host,
         &token,
         &resolved.short_id,
@@ -79,3 +83,155 @@ pub async fn run_logs(device: &str) -> Result<()> {
     println!("\nLogs stream closed.");
     Ok(())
 }
+
+/// Query a unit's persisted logs (`observe.logs.persist`). The time window
+/// and `grep` are evaluated on the device.
+pub async fn print_persisted_logs(
+    device: &str,
+    unit: &str,
+    since_ms: Option<u64>,
+    until_ms: Option<u64>,
+    grep: Option<String>,
+    tail: usize,
+    json: bool,
+) -> Result<()> {
+    if let Some(pattern) = &grep {
+        regex::Regex::new(pattern).context("invalid --grep pattern")?;
+    }
+    let config = Config::load()?;
+    let resolved = devices::resolve_device_cached(device).await?;
+    let token = AuthManager::get_cli_token().await?;
+
+    let stream_type = StreamType::PersistedLogs {
+        token: token.to_string(),
+        unit: unit.to_string(),
+        since_ms,
+        until_ms,
+        grep,
+        tail,
+    };
+    let (_, io) = open_compressed_quic_io(
+        &resolved.host,
+        &token,
+        &resolved.short_id,
+        stream_type,
+        config.trust_invalid_server_cert,
+    )
+    .await
+    .context("Failed to connect to persisted logs stream")?;
+
+    let mut lines = BufReader::new(io).lines();
+    while let Some(raw) = lines.next_line().await? {
+        match serde_json::from_str::<PersistedLogMessage>(&raw)? {
+            PersistedLogMessage::Line { at_ms, line } => {
+                if json {
+                    println!("{raw}");
+                } else {
+                    println!("{} {line}", dim(&format_ms(at_ms)));
+                }
+            }
+            PersistedLogMessage::Done => return Ok(()),
+            PersistedLogMessage::Error { message } => bail!("{message}"),
+        }
+    }
+    bail!("{device} does not support persisted logs; update m87 on the device")
+}
+
+/// Read the device's systemd journal; with `follow`, keep printing new
+/// entries until Ctrl+C.
+pub async fn print_journal(
+    device: &str,
+    filter: JournalSpec,
+    since_ms: Option<u64>,
+    until_ms: Option<u64>,
+    tail: usize,
+    follow: bool,
+    json: bool,
+) -> Result<()> {
+    let config = Config::load()?;
+    let resolved = devices::resolve_device_cached(device).await?;
+    let token = AuthManager::get_cli_token().await?;
+
+    let stream_type = StreamType::Journal {
+        token: token.to_string(),
+        units: filter.units,
+        kernel: filter.kernel,
+        priority: filter.priority,
+        since_ms,
+        until_ms,
+        tail,
+        follow,
+    };
+    let (_, io) = open_compressed_quic_io(
+        &resolved.host,
+        &token,
+        &resolved.short_id,
+        stream_type,
+        config.trust_invalid_server_cert,
+    )
+    .await
+    .context("Failed to connect to journal stream")?;
+
+    let mut lines = BufReader::new(io).lines();
+    loop {
+        let raw = tokio::select! {
+            raw = lines.next_line() => raw?,
+            _ = SHUTDOWN.cancelled() => return Ok(()),
+        };
+        let Some(raw) = raw else { break };
+        match serde_json::from_str::<JournalMessage>(&raw)? {
+            JournalMessage::Entry(entry) if json => {
+                println!("{}", serde_json::to_string(&entry)?);
+            }
+            JournalMessage::Entry(entry) => println!("{}", format_journal_entry(&entry)),
+            JournalMessage::Done => return Ok(()),
+            JournalMessage::Error { message } => bail!("{message}"),
+        }
+    }
+    bail!("{device} does not support journal access; update m87 on the device")
+}
+
+/// `time identifier[pid]: message`, errors in red and warnings in yellow.
+fn format_journal_entry(entry: &JournalEntry) -> String {
+    let source = match (&entry.identifier, &entry.unit) {
+        (Some(id), _) | (None, Some(id)) => id.as_str(),
+        (None, None) => "-",
+    };
+    let source = match entry.pid {
+        Some(pid) => format!("{source}[{pid}]"),
+        None => source.to_string(),
+    };
+    let message = match entry.priority {
+        Some(p) if p <= JournalPriority::Err => red(&entry.message),
+        Some(JournalPriority::Warning) => yellow(&entry.message),
+        _ => entry.message.clone(),
+    };
+    format!(
+        "{} {}: {message}",
+        dim(&format_ms(entry.at_ms)),
+        dim(&source)
+    )
+}
+
+/// Logs shipped to the server, across devices.
+pub fn print_shipped_logs(logs: &[DeviceLogEntry], json: bool) {
+    for entry in logs {
+        if json {
+            if let Ok(line) = serde_json::to_string(entry) {
+                println!("{line}");
+            }
+            continue;
+        }
+        let device = if entry.device_name.is_empty() {
+            &entry.device_id
+        } else {
+            &entry.device_name
+        };
+        println!(
+            "{} {} {}",
+            dim(&format_ms(entry.at_ms)),
+            dim(&format!("{device} [{}]", entry.source)),
+            entry.line
+        );
+    }
+}
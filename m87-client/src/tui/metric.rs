@@ -1,17 +1,26 @@
 use crate::{
     auth::AuthManager,
     config::Config,
-    devices,
+    devices, server,
     streams::{quic::open_quic_io, stream_type::StreamType},
+    tui::fs::human_size,
+    util::time::now_ms,
 };
 use anyhow::{Result, anyhow};
-use m87_shared::metrics::SystemMetrics;
+use m87_shared::metrics::{ContainerInfo, SystemMetrics, UnitMetrics, UnitMetricsQuery};
+use std::collections::{BTreeMap, VecDeque};
 
 use ratatui::Terminal;
 use tokio::io::{AsyncBufReadExt, BufReader};
 
-pub async fn run_metrics(device: &str) -> Result<()> {
-    let result = run_metrics_inner(device).await;
+/// How far back `--unit` charts start, from the server's history.
+const UNIT_HISTORY_MS: u64 = 3600 * 1000;
+const UNIT_HISTORY_LEN: usize = 720;
+
+/// Live system metrics, or with `containers` the device's Docker containers,
+/// or with `unit` the values of that unit's `metrics` hook.
+pub async fn run_metrics(device: &str, containers: bool, unit: Option<&str>) -> Result<()> {
+    let result = run_metrics_inner(device, containers, unit).await;
 
     // ensure alternate screen is closed
     println!("{}", termion::screen::ToMainScreen);
@@ -23,12 +32,37 @@ pub async fn run_metrics(device: &str) -> Result<()> {
     result
 }
 
-async fn run_metrics_inner(device: &str) -> Result<()> {
+async fn run_metrics_inner(device: &str, containers: bool, unit: Option<&str>) -> Result<()> {
     let config = Config::load()?;
     // let host = config.get_runtime_server_hostname();
     let resolved = devices::resolve_device_cached(device).await?;
     let token = AuthManager::get_cli_token().await?;
 
+    // The history is optional: the live stream still works without it.
+    let unit_history = match unit {
+        Some(unit) => {
+            let query = UnitMetricsQuery {
+                since: Some(now_ms().saturating_sub(UNIT_HISTORY_MS)),
+                limit: Some(UNIT_HISTORY_LEN as u32),
+            };
+            server::get_unit_metrics(
+                &resolved.url,
+                &token,
+                config.trust_invalid_server_cert,
+                &resolved.id,
+                unit,
+                &query,
+            )
+            .await
+            .unwrap_or_else(|e| {
+                tracing::warn!("failed to load metrics history of '{unit}': {e:#}");
+                Vec::new()
+            })
+        }
+        None => Vec::new(),
+    };
+    let unit = unit.map(str::to_string);
+
     let stream_type = StreamType::Metrics {
         token: token.clone(),
     };
@@ -68,7 +102,14 @@ async fn run_metrics_inner(device: &str) -> Result<()> {
 
     // spawn UI loop
     let ui_task = tokio::spawn(async move {
-        if let Err(e) = ui_loop(rx).await {
+        let result = if let Some(unit) = unit {
+            unit_ui_loop(rx, unit, unit_history).await
+        } else if containers {
+            containers_ui_loop(rx).await
+        } else {
+            ui_loop(rx).await
+        };
+        if let Err(e) = result {
             tracing::error!("UI loop exited: {}", e);
         }
     });
@@ -100,6 +141,7 @@ pub async fn ui_loop(
         backend::TermionBackend,
         layout::{Alignment, Constraint, Direction, Layout, Rect},
         style::{Color, Style},
+        text::{Line, Span},
         widgets::{Block, Borders, Gauge, Paragraph, Row, Sparkline, Table},
     };
     use std::cmp::min;
@@ -204,14 +246,16 @@ pub async fn ui_loop(
                 return;
             }
             let m = latest.as_ref().unwrap();
+            // A second header line for thermal / power, where reported.
+            let header_height = if thermal_summary(m).is_some() { 4 } else { 3 };
 
             // -------- root layout --------
             let root_chunks = Layout::default()
                 .direction(Direction::Vertical)
                 .constraints([
-                    Constraint::Length(3),      // header
-                    Constraint::Percentage(45), // CPU + NET
-                    Constraint::Percentage(52), // MEM + DISK + GPU
+                    Constraint::Length(header_height), // header
+                    Constraint::Percentage(45),        // CPU + NET
+                    Constraint::Percentage(52),        // MEM + DISK + GPU
                 ])
                 .split(size);
 
@@ -222,12 +266,22 @@ pub async fn ui_loop(
             // -------- header --------
             let uptime_h = m.uptime_secs / 3600;
             let uptime_m = (m.uptime_secs % 3600) / 60;
-            let header_text = format!(
+            let mut header_lines = vec![Line::from(format!(
                 "{} | {} | {} | uptime {:02}h{:02}m | CPU {:4.1}%",
                 m.hostname, m.os, m.arch, uptime_h, uptime_m, m.cpu.usage_percent
-            );
+            ))];
+            if let Some(thermal) = thermal_summary(m) {
+                let mut spans = vec![Span::raw(thermal)];
+                if let Some(alarms) = power_alarms(m) {
+                    spans.push(Span::styled(
+                        format!(" | {alarms}"),
+                        Style::default().fg(Color::Red),
+                    ));
+                }
+                header_lines.push(Line::from(spans));
+            }
 
-            let header_paragraph = Paragraph::new(header_text)
+            let header_paragraph = Paragraph::new(header_lines)
                 .alignment(Alignment::Center)
                 .block(Block::default().borders(Borders::ALL).title("System"));
 
@@ -568,3 +622,434 @@ pub async fn ui_loop(
         })?;
     }
 }
+
+/// Live table of the device's containers, grouped by the service that
+/// started them.
+pub async fn containers_ui_loop(
+    mut rx: tokio::sync::mpsc::Receiver<SystemMetrics>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use ratatui::{
+        backend::TermionBackend,
+        layout::Constraint,
+        style::{Color, Style},
+        widgets::{Block, Borders, Paragraph, Row, Table},
+    };
+    use termion::{
+        async_stdin, event::Key, input::TermRead, raw::IntoRawMode, screen::IntoAlternateScreen,
+    };
+
+    let stdout = std::io::stdout();
+    let raw = stdout.into_raw_mode()?;
+    let screen = raw.into_alternate_screen()?;
+    let mut terminal = Terminal::new(TermionBackend::new(screen))?;
+    let mut keys = async_stdin().keys();
+
+    let mut latest: Option<SystemMetrics> = None;
+
+    loop {
+        if let Some(Ok(Key::Ctrl('c') | Key::Char('q') | Key::Esc)) = keys.next() {
+            return Ok(());
+        }
+
+        if let Ok(Some(m)) =
+            tokio::time::timeout(std::time::Duration::from_millis(20), rx.recv()).await
+        {
+            latest = Some(m);
+        }
+
+        terminal.draw(|f| {
+            let Some(m) = &latest else {
+                return;
+            };
+            let Some(containers) = &m.containers else {
+                let text = Paragraph::new(
+                    "No container data: Docker is not reachable on the device, \
+                     or m87 on the device is too old to report containers",
+                )
+                .block(Block::default().borders(Borders::ALL).title("Containers"))
+                .style(Style::default().fg(Color::Gray));
+                f.render_widget(text, f.area());
+                return;
+            };
+
+            let header = Row::new(vec![
+                "SERVICE", "NAME", "STATE", "HEALTH", "CPU%", "MEM", "RESTARTS", "IMAGE", "DIGEST",
+            ])
+            .style(Style::default().fg(Color::LightBlue));
+            let rows: Vec<Row> = sorted_containers(&containers.containers)
+                .into_iter()
+                .map(|c| {
+                    let style = match (c.state.as_str(), c.health.as_deref()) {
+                        (_, Some("unhealthy")) => Style::default().fg(Color::Red),
+                        ("running", _) => Style::default(),
+                        _ => Style::default().fg(Color::Yellow),
+                    };
+                    Row::new(vec![
+                        c.service.clone().unwrap_or_default(),
+                        c.name.clone(),
+                        c.state.clone(),
+                        c.health.clone().unwrap_or_default(),
+                        format!("{:.1}", c.cpu_percent),
+                        memory_usage(c),
+                        c.restart_count.to_string(),
+                        c.image.clone(),
+                        short_digest(c.image_digest.as_deref()),
+                    ])
+                    .style(style)
+                })
+                .collect();
+
+            let table = Table::new(
+                rows,
+                [
+                    Constraint::Length(16),
+                    Constraint::Length(24),
+                    Constraint::Length(10),
+                    Constraint::Length(9),
+                    Constraint::Length(6),
+                    Constraint::Length(17),
+                    Constraint::Length(8),
+                    Constraint::Min(20),
+                    Constraint::Length(19),
+                ],
+            )
+            .header(header)
+            .block(Block::default().borders(Borders::ALL).title(format!(
+                "Containers on {} ({} running / {} total, {} unhealthy)",
+                m.hostname, containers.running, containers.total, containers.unhealthy
+            )));
+            f.render_widget(table, f.area());
+        })?;
+    }
+}
+
+/// A sparkline per value of a unit's `metrics` hook, starting from the
+/// server's history.
+pub async fn unit_ui_loop(
+    mut rx: tokio::sync::mpsc::Receiver<SystemMetrics>,
+    unit: String,
+    history: Vec<UnitMetrics>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use ratatui::{
+        backend::TermionBackend,
+        layout::{Constraint, Direction, Layout},
+        style::{Color, Style},
+        widgets::{Block, Borders, Paragraph, Sparkline},
+    };
+    use termion::{
+        async_stdin, event::Key, input::TermRead, raw::IntoRawMode, screen::IntoAlternateScreen,
+    };
+
+    let stdout = std::io::stdout();
+    let raw = stdout.into_raw_mode()?;
+    let screen = raw.into_alternate_screen()?;
+    let mut terminal = Terminal::new(TermionBackend::new(screen))?;
+    let mut keys = async_stdin().keys();
+
+    let mut series: BTreeMap<String, VecDeque<f64>> = BTreeMap::new();
+    let mut last_collected_at = 0;
+    for sample in &history {
+        push_unit_sample(&mut series, &mut last_collected_at, sample);
+    }
+    let mut hostname = String::new();
+
+    loop {
+        if let Some(Ok(Key::Ctrl('c') | Key::Char('q') | Key::Esc)) = keys.next() {
+            return Ok(());
+        }
+
+        if let Ok(Some(m)) =
+            tokio::time::timeout(std::time::Duration::from_millis(20), rx.recv()).await
+        {
+            if let Some(sample) = m.units.iter().find(|u| u.unit == unit) {
+                push_unit_sample(&mut series, &mut last_collected_at, sample);
+            }
+            hostname = m.hostname;
+        }
+
+        terminal.draw(|f| {
+            let title = format!("Metrics of {unit} on {hostname}");
+            if series.is_empty() {
+                let text = Paragraph::new(
+                    "No values yet: the unit has no `metrics` observe hook, \
+                     its hook hasn't run successfully, or m87 on the device is too old",
+                )
+                .block(Block::default().borders(Borders::ALL).title(title))
+                .style(Style::default().fg(Color::Gray));
+                f.render_widget(text, f.area());
+                return;
+            }
+
+            let fits = (f.area().height / 3).max(1) as usize;
+            let rows = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints(vec![Constraint::Length(3); fits.min(series.len())])
+                .split(f.area());
+            for ((name, values), area) in series.iter().zip(rows.iter()) {
+                let latest = values.back().copied().unwrap_or_default();
+                let spark = Sparkline::default()
+                    .block(
+                        Block::default()
+                            .borders(Borders::ALL)
+                            .title(format!("{name}: {latest}")),
+                    )
+                    .data(spark_points(values, area.width.saturating_sub(2)))
+                    .style(Style::default().fg(Color::Cyan));
+                f.render_widget(spark, *area);
+            }
+        })?;
+    }
+}
+
+/// Append the values of `sample` unless it was already seen; heartbeats and
+/// the stream repeat a sample until the hook runs again.
+fn push_unit_sample(
+    series: &mut BTreeMap<String, VecDeque<f64>>,
+    last_collected_at: &mut u64,
+    sample: &UnitMetrics,
+) {
+    if sample.collected_at <= *last_collected_at {
+        return;
+    }
+    *last_collected_at = sample.collected_at;
+    for (name, value) in &sample.values {
+        let values = series.entry(name.clone()).or_default();
+        values.push_back(*value);
+        if values.len() > UNIT_HISTORY_LEN {
+            values.pop_front();
+        }
+    }
+}
+
+/// The newest values that fit in `width`, oldest first, scaled between their
+/// minimum and maximum so small changes of large values stay visible.
+fn spark_points(values: &VecDeque<f64>, width: u16) -> Vec<u64> {
+    let shown: Vec<f64> = values
+        .iter()
+        .skip(values.len().saturating_sub(width.max(1) as usize))
+        .copied()
+        .collect();
+    let min = shown.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = shown.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    shown
+        .iter()
+        .map(|v| {
+            if max > min {
+                1 + ((v - min) / (max - min) * 99.0) as u64
+            } else {
+                1
+            }
+        })
+        .collect()
+}
+
+/// By service, with containers no service started last, then by name.
+fn sorted_containers(containers: &[ContainerInfo]) -> Vec<&ContainerInfo> {
+    let mut sorted: Vec<_> = containers.iter().collect();
+    sorted.sort_by(|a, b| {
+        (a.service.is_none(), &a.service, &a.name).cmp(&(b.service.is_none(), &b.service, &b.name))
+    });
+    sorted
+}
+
+/// `412.0 MB / 1.0 GB`, or just the usage without a memory limit.
+fn memory_usage(c: &ContainerInfo) -> String {
+    if c.memory_bytes == 0 && c.state != "running" {
+        return String::new();
+    }
+    match c.memory_limit_bytes {
+        0 => human_size(c.memory_bytes),
+        limit => format!("{} / {}", human_size(c.memory_bytes), human_size(limit)),
+    }
+}
+
+/// `sha256:4f3c9a1b2c7d`, like `docker images --digests` abbreviated.
+fn short_digest(digest: Option<&str>) -> String {
+    match digest {
+        Some(digest) => digest.chars().take("sha256:".len() + 12).collect(),
+        None => String::new(),
+    }
+}
+
+/// `61.2°C cpu-thermal | 1400/1800 MHz | fan 3100 rpm`, or `None` when the
+/// device reports neither section.
+fn thermal_summary(m: &SystemMetrics) -> Option<String> {
+    if m.thermal.is_none() && m.power.is_none() {
+        return None;
+    }
+    let mut parts = Vec::new();
+    if let Some(thermal) = &m.thermal {
+        let hottest = thermal
+            .zones
+            .iter()
+            .max_by(|a, b| a.temp_c.total_cmp(&b.temp_c));
+        if let Some(zone) = hottest {
+            parts.push(format!("{:.1}°C {}", zone.temp_c, zone.name));
+        }
+    }
+    if let Some(power) = &m.power {
+        match (power.cpu_freq_mhz, power.cpu_max_freq_mhz) {
+            (Some(cur), Some(max)) => parts.push(format!("{cur}/{max} MHz")),
+            (Some(cur), None) => parts.push(format!("{cur} MHz")),
+            _ => {}
+        }
+    }
+    if let Some(thermal) = &m.thermal {
+        for fan in &thermal.fans {
+            parts.push(format!("{} {} rpm", fan.name, fan.rpm));
+        }
+    }
+    Some(parts.join(" | "))
+}
+
+/// Active throttle flags, then the ones that only occurred since boot.
+fn power_alarms(m: &SystemMetrics) -> Option<String> {
+    let power = m.power.as_ref()?;
+    let now = power.throttle.names();
+    let mut alarms: Vec<String> = now.iter().map(|n| n.to_uppercase()).collect();
+    if let Some(since_boot) = &power.throttle_since_boot {
+        let earlier: Vec<_> = since_boot
+            .names()
+            .into_iter()
+            .filter(|n| !now.contains(n))
+            .collect();
+        if !earlier.is_empty() {
+            alarms.push(format!("since boot: {}", earlier.join(", ")));
+        }
+    }
+    (!alarms.is_empty()).then(|| alarms.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use m87_shared::metrics::{
+        CpuMetrics, DiskMetrics, FanMetrics, MemoryMetrics, NetworkMetrics, PowerMetrics,
+        ThermalMetrics, ThermalZoneMetrics, ThrottleFlags,
+    };
+
+    fn metrics() -> SystemMetrics {
+        SystemMetrics {
+            hostname: "pi".into(),
+            os: "Debian".into(),
+            arch: "aarch64".into(),
+            uptime_secs: 0,
+            cpu: CpuMetrics {
+                usage_percent: 0.0,
+                cores: 4,
+                load_avg: (0.0, 0.0, 0.0),
+                per_core: Vec::new(),
+            },
+            memory: MemoryMetrics {
+                total_mb: 0,
+                used_mb: 0,
+                usage_percent: 0.0,
+            },
+            disk: DiskMetrics {
+                total_gb: 0,
+                used_gb: 0,
+                usage_percent: 0.0,
+            },
+            network: NetworkMetrics {
+                rx_mbps: 0.0,
+                tx_mbps: 0.0,
+                interfaces: Vec::new(),
+            },
+            gpu: Vec::new(),
+            thermal: None,
+            power: None,
+            containers: None,
+            units: Vec::new(),
+            timestamp: 0,
+        }
+    }
+
+    #[test]
+    fn summarizes_thermal_and_power() {
+        let mut m = metrics();
+        assert_eq!(thermal_summary(&m), None);
+
+        m.thermal = Some(ThermalMetrics {
+            zones: vec![
+                ThermalZoneMetrics {
+                    name: "cpu-thermal".into(),
+                    temp_c: 71.25,
+                },
+                ThermalZoneMetrics {
+                    name: "gpu-thermal".into(),
+                    temp_c: 52.0,
+                },
+            ],
+            fans: vec![FanMetrics {
+                name: "pwmfan/fan1".into(),
+                rpm: 3100,
+            }],
+        });
+        m.power = Some(PowerMetrics {
+            cpu_freq_mhz: Some(1400),
+            cpu_max_freq_mhz: Some(1800),
+            throttle: ThrottleFlags {
+                throttled: true,
+                ..Default::default()
+            },
+            throttle_since_boot: Some(ThrottleFlags {
+                undervoltage: true,
+                throttled: true,
+                ..Default::default()
+            }),
+        });
+        assert_eq!(
+            thermal_summary(&m).unwrap(),
+            "71.2°C cpu-thermal | 1400/1800 MHz | pwmfan/fan1 3100 rpm"
+        );
+        assert_eq!(
+            power_alarms(&m).unwrap(),
+            "THROTTLED since boot: undervoltage"
+        );
+    }
+
+    #[test]
+    fn sorts_containers_by_service() {
+        let container = |name: &str, service: Option<&str>| ContainerInfo {
+            name: name.into(),
+            service: service.map(String::from),
+            ..Default::default()
+        };
+        let containers = vec![
+            container("watchtower", None),
+            container("web-db-1", Some("web")),
+            container("cam-1", Some("camera")),
+            container("web-app-1", Some("web")),
+        ];
+        let names: Vec<_> = sorted_containers(&containers)
+            .iter()
+            .map(|c| c.name.as_str())
+            .collect();
+        assert_eq!(names, ["cam-1", "web-app-1", "web-db-1", "watchtower"]);
+        assert_eq!(
+            short_digest(Some("sha256:4f3c9a1b2c7d8e0f1122")),
+            "sha256:4f3c9a1b2c7d"
+        );
+    }
+
+    #[test]
+    fn charts_each_unit_sample_once() {
+        let sample = |collected_at: u64, fps: f64| UnitMetrics {
+            unit: "detector".into(),
+            values: BTreeMap::from([("fps".to_string(), fps)]),
+            collected_at,
+        };
+        let mut series = BTreeMap::new();
+        let mut last = 0;
+        for s in [sample(1000, 30.0), sample(1000, 30.0), sample(2000, 20.0)] {
+            push_unit_sample(&mut series, &mut last, &s);
+        }
+        // A stale sample, e.g. history overlapping the live stream.
+        push_unit_sample(&mut series, &mut last, &sample(1500, 25.0));
+        assert_eq!(series["fps"], [30.0, 20.0]);
+
+        let values = VecDeque::from([5.0, 10.0, 15.0, 10.0]);
+        assert_eq!(spark_points(&values, 3), [1, 100, 1]);
+        assert_eq!(spark_points(&VecDeque::from([7.0, 7.0]), 10), [1, 1]);
+    }
+}
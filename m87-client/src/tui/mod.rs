@@ -3,10 +3,15 @@ pub mod log;
 pub mod metric;
 pub mod shell;
 
+pub mod alerts;
+pub mod api_key;
 pub mod deploy;
 pub mod device;
 pub mod events;
 pub mod fs;
 pub mod helper;
 pub mod org;
+pub mod process;
+pub mod replay;
+pub mod serial;
 pub mod user;
@@ -1,5 +1,8 @@
-use crate::tui::helper::{Align, ColSpec, RenderOpts, Table, dim, role_badge, terminal_width};
-use m87_shared::org::Organization; // adjust if needed
+use crate::tui::helper::{
+    Align, ColSpec, RenderOpts, Table, dim, format_relative_time, green, red, role_badge,
+    terminal_width, yellow,
+};
+use m87_shared::org::{Organization, Webhook, WebhookDelivery, WebhookDeliveryStatus};
 
 pub fn print_device_organizations(orgs: &[Organization]) {
     if orgs.is_empty() {
@@ -47,3 +50,150 @@ pub fn print_device_organizations(orgs: &[Organization]) {
 
     print!("{out}");
 }
+
+pub fn print_webhooks(hooks: &[Webhook]) {
+    if hooks.is_empty() {
+        println!("{}", dim("No webhooks found"));
+        return;
+    }
+
+    let term_w = terminal_width().unwrap_or(96);
+    let opts = RenderOpts::default();
+
+    let t = Table::new(
+        term_w.saturating_sub(2),
+        1,
+        vec![
+            ColSpec {
+                title: "ID",
+                min: 24,
+                max: Some(24),
+                weight: 0,
+                align: Align::Left,
+                wrap: false,
+            },
+            ColSpec {
+                title: "URL",
+                min: 20,
+                max: None,
+                weight: 3,
+                align: Align::Left,
+                wrap: false,
+            },
+            ColSpec {
+                title: "EVENTS",
+                min: 12,
+                max: None,
+                weight: 2,
+                align: Align::Left,
+                wrap: true,
+            },
+        ],
+    );
+
+    let mut out = String::new();
+    out.push_str("  ");
+    t.header(&mut out, &opts);
+
+    for h in hooks {
+        let events = if h.events.is_empty() {
+            dim("all")
+        } else {
+            h.events
+                .iter()
+                .map(|e| e.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        out.push_str("  ");
+        t.row(&mut out, &[&h.id, &h.url, &events], &opts);
+    }
+
+    print!("{out}");
+}
+
+pub fn print_webhook_deliveries(deliveries: &[WebhookDelivery]) {
+    if deliveries.is_empty() {
+        println!("{}", dim("No deliveries yet"));
+        return;
+    }
+
+    let term_w = terminal_width().unwrap_or(96);
+    let opts = RenderOpts::default();
+
+    let t = Table::new(
+        term_w.saturating_sub(2),
+        1,
+        vec![
+            ColSpec {
+                title: "EVENT",
+                min: 14,
+                max: Some(20),
+                weight: 1,
+                align: Align::Left,
+                wrap: false,
+            },
+            ColSpec {
+                title: "STATUS",
+                min: 9,
+                max: Some(9),
+                weight: 0,
+                align: Align::Left,
+                wrap: false,
+            },
+            ColSpec {
+                title: "TRIES",
+                min: 5,
+                max: Some(5),
+                weight: 0,
+                align: Align::Right,
+                wrap: false,
+            },
+            ColSpec {
+                title: "RESULT",
+                min: 12,
+                max: None,
+                weight: 3,
+                align: Align::Left,
+                wrap: false,
+            },
+            ColSpec {
+                title: "CREATED",
+                min: 10,
+                max: Some(14),
+                weight: 0,
+                align: Align::Left,
+                wrap: false,
+            },
+        ],
+    );
+
+    let mut out = String::new();
+    out.push_str("  ");
+    t.header(&mut out, &opts);
+
+    for d in deliveries {
+        let status = match d.status {
+            WebhookDeliveryStatus::Delivered => green(&d.status.to_string()),
+            WebhookDeliveryStatus::Pending => yellow(&d.status.to_string()),
+            WebhookDeliveryStatus::Failed => red(&d.status.to_string()),
+        };
+        let result = match (&d.last_error, d.last_status_code) {
+            (Some(err), _) => err.clone(),
+            (None, Some(code)) => format!("HTTP {code}"),
+            (None, None) => dim("-"),
+        };
+        let attempts = d.attempts.to_string();
+        let created = format_relative_time(&d.created_at);
+
+        out.push_str("  ");
+        t.row(
+            &mut out,
+            &[d.event.as_str(), &status, &attempts, &result, &created],
+            &opts,
+        );
+    }
+
+    print!("{out}");
+}
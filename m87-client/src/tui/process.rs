@@ -0,0 +1,348 @@
+use std::cmp::Ordering;
+
+use anyhow::{Result, anyhow};
+use m87_shared::metrics::{ProcessInfo, ProcessList};
+use ratatui::Terminal;
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+use crate::device::ps::signal_on;
+use crate::streams::quic::open_quic_io;
+use crate::streams::stream_type::StreamType;
+use crate::tui::fs::human_size;
+use crate::tui::helper::{Align, ColSpec, RenderOpts, Table, dim, terminal_width};
+use crate::{auth::AuthManager, config::Config, devices};
+
+/// Order of `m87 <device> ps` and `top`.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ProcessSort {
+    #[default]
+    Cpu,
+    Mem,
+    Pid,
+}
+
+fn sort_processes(processes: &mut [ProcessInfo], sort: ProcessSort) {
+    processes.sort_by(|a, b| match sort {
+        ProcessSort::Cpu => b
+            .cpu_percent
+            .partial_cmp(&a.cpu_percent)
+            .unwrap_or(Ordering::Equal),
+        ProcessSort::Mem => b.rss_bytes.cmp(&a.rss_bytes),
+        ProcessSort::Pid => a.pid.cmp(&b.pid),
+    });
+}
+
+/// The command line, or `[name]` for kernel threads like `ps` shows them.
+fn command(p: &ProcessInfo) -> String {
+    if p.cmd.is_empty() {
+        format!("[{}]", p.name)
+    } else {
+        p.cmd.clone()
+    }
+}
+
+fn mem_percent(p: &ProcessInfo, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        p.rss_bytes as f64 * 100.0 / total as f64
+    }
+}
+
+pub fn print_processes(mut list: ProcessList, sort: ProcessSort, limit: usize, json: bool) {
+    sort_processes(&mut list.processes, sort);
+    if limit > 0 {
+        list.processes.truncate(limit);
+    }
+    if json {
+        for p in &list.processes {
+            match serde_json::to_string(p) {
+                Ok(line) => println!("{line}"),
+                Err(e) => eprintln!("(json serialize failed: {e})"),
+            }
+        }
+        return;
+    }
+
+    let term_w = terminal_width().unwrap_or(120);
+    let opts = RenderOpts::default();
+    let col = |title, min, max, weight, align| ColSpec {
+        title,
+        min,
+        max,
+        weight,
+        align,
+        wrap: false,
+    };
+    let t = Table::new(
+        term_w.saturating_sub(2),
+        1,
+        vec![
+            col("PID", 7, Some(7), 0, Align::Right),
+            col("USER", 6, Some(12), 0, Align::Left),
+            col("CPU%", 5, Some(6), 0, Align::Right),
+            col("MEM%", 5, Some(5), 0, Align::Right),
+            col("RSS", 6, Some(7), 0, Align::Right),
+            col("S", 1, Some(8), 0, Align::Left),
+            col("CONTAINER", 12, Some(12), 0, Align::Left),
+            col("COMMAND", 20, None, 1, Align::Left),
+        ],
+    );
+
+    let mut out = String::new();
+    out.push_str("  ");
+    t.header(&mut out, &opts);
+    for p in &list.processes {
+        let user = p.user.clone().unwrap_or_else(|| dim("-"));
+        let container = p.container.clone().unwrap_or_else(|| dim("-"));
+        out.push_str("  ");
+        t.row(
+            &mut out,
+            &[
+                &p.pid.to_string(),
+                &user,
+                &format!("{:.1}", p.cpu_percent),
+                &format!("{:.1}", mem_percent(p, list.total_memory_bytes)),
+                &human_size(p.rss_bytes),
+                &p.status,
+                &container,
+                &command(p),
+            ],
+            &opts,
+        );
+    }
+    print!("{out}");
+}
+
+/// Live process view; select a process and press `t` to terminate or `K`
+/// to kill it.
+pub async fn run_top(device: &str) -> Result<()> {
+    let result = run_top_inner(device).await;
+
+    // ensure alternate screen is closed
+    println!("{}", termion::screen::ToMainScreen);
+
+    result
+}
+
+async fn run_top_inner(device: &str) -> Result<()> {
+    let config = Config::load()?;
+    let resolved = devices::resolve_device_cached(device).await?;
+    let token = AuthManager::get_cli_token().await?;
+
+    let stream_type = StreamType::Processes {
+        token: token.clone(),
+    };
+    let (conn, io) = open_quic_io(
+        &resolved.host,
+        &token,
+        &resolved.short_id,
+        stream_type,
+        config.trust_invalid_server_cert,
+    )
+    .await?;
+
+    let (tx, rx) = tokio::sync::mpsc::channel(8);
+    let reader_task = tokio::spawn(async move {
+        let mut lines = BufReader::new(io).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            match serde_json::from_str::<ProcessList>(&line) {
+                Ok(list) => {
+                    if tx.send(list).await.is_err() {
+                        break;
+                    }
+                }
+                Err(e) => tracing::warn!("Bad process JSON: {}", e),
+            }
+        }
+    });
+
+    let ui_task = tokio::spawn(ui_loop(rx, conn.clone(), token));
+
+    tokio::select! {
+        reason = conn.closed() => Err(anyhow!("QUIC connection closed: {:?}", reason)),
+        _ = reader_task => Err(anyhow!("Process stream ended; is m87 on the device up to date?")),
+        res = ui_task => res?,
+    }
+}
+
+async fn ui_loop(
+    mut rx: tokio::sync::mpsc::Receiver<ProcessList>,
+    conn: quinn::Connection,
+    token: String,
+) -> Result<()> {
+    use ratatui::{
+        backend::TermionBackend,
+        layout::{Constraint, Direction, Layout},
+        style::{Color, Modifier, Style},
+        widgets::{Block, Borders, Paragraph, Row, Table, TableState},
+    };
+    use termion::{
+        async_stdin, event::Key, input::TermRead, raw::IntoRawMode, screen::IntoAlternateScreen,
+    };
+
+    let stdout = std::io::stdout();
+    let raw = stdout.into_raw_mode()?;
+    let screen = raw.into_alternate_screen()?;
+    let mut terminal = Terminal::new(TermionBackend::new(screen))?;
+    let mut keys = async_stdin().keys();
+
+    let (status_tx, mut status_rx) = tokio::sync::mpsc::channel::<String>(8);
+    let mut latest: Option<ProcessList> = None;
+    let mut sort = ProcessSort::Cpu;
+    // Follow the selected process, not its row, across refreshes.
+    let mut selected_pid: Option<u32> = None;
+    let mut status = String::from("↑/↓ select  c/m/p sort  t TERM  K KILL  q quit");
+
+    loop {
+        let mut offset: isize = 0;
+        let mut signal = None;
+        if let Some(Ok(key)) = keys.next() {
+            match key {
+                Key::Ctrl('c') | Key::Char('q') | Key::Esc => return Ok(()),
+                Key::Up => offset = -1,
+                Key::Down => offset = 1,
+                Key::PageUp => offset = -20,
+                Key::PageDown => offset = 20,
+                Key::Char('c') => sort = ProcessSort::Cpu,
+                Key::Char('m') => sort = ProcessSort::Mem,
+                Key::Char('p') => sort = ProcessSort::Pid,
+                Key::Char('t') => signal = Some("TERM"),
+                Key::Char('K') => signal = Some("KILL"),
+                _ => {}
+            }
+        }
+
+        if let Ok(Some(list)) =
+            tokio::time::timeout(std::time::Duration::from_millis(20), rx.recv()).await
+        {
+            latest = Some(list);
+        }
+        if let Ok(message) = status_rx.try_recv() {
+            status = message;
+        }
+
+        let Some(list) = latest.as_mut() else {
+            terminal.draw(|f| {
+                f.render_widget(Paragraph::new("Waiting for the device..."), f.area());
+            })?;
+            continue;
+        };
+        sort_processes(&mut list.processes, sort);
+
+        let count = list.processes.len();
+        let mut index = selected_pid
+            .and_then(|pid| list.processes.iter().position(|p| p.pid == pid))
+            .unwrap_or(0);
+        index = (index as isize + offset).clamp(0, count.saturating_sub(1) as isize) as usize;
+        selected_pid = list.processes.get(index).map(|p| p.pid);
+
+        if let (Some(signal), Some(pid)) = (signal, selected_pid) {
+            status = format!("Sending SIG{signal} to {pid}...");
+            let (conn, token, status_tx) = (conn.clone(), token.clone(), status_tx.clone());
+            tokio::spawn(async move {
+                let message = match signal_on(&conn, &token, pid, signal).await {
+                    Ok(()) => format!("Sent SIG{signal} to {pid}"),
+                    Err(e) => format!("SIG{signal} to {pid} failed: {e}"),
+                };
+                let _ = status_tx.send(message).await;
+            });
+        }
+
+        terminal.draw(|f| {
+            let chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Min(3), Constraint::Length(1)])
+                .split(f.area());
+
+            let sorted_by = |s: ProcessSort, title: &'static str| {
+                let style = if s == sort {
+                    Style::default().add_modifier(Modifier::UNDERLINED)
+                } else {
+                    Style::default()
+                };
+                ratatui::text::Span::styled(title, style)
+            };
+            let header = Row::new(vec![
+                sorted_by(ProcessSort::Pid, "PID"),
+                "USER".into(),
+                sorted_by(ProcessSort::Cpu, "CPU%"),
+                sorted_by(ProcessSort::Mem, "MEM%"),
+                "RSS".into(),
+                "S".into(),
+                "CONTAINER".into(),
+                "COMMAND".into(),
+            ])
+            .style(Style::default().fg(Color::LightBlue));
+
+            let rows: Vec<Row> = list
+                .processes
+                .iter()
+                .map(|p| {
+                    Row::new(vec![
+                        p.pid.to_string(),
+                        p.user.clone().unwrap_or_default(),
+                        format!("{:.1}", p.cpu_percent),
+                        format!("{:.1}", mem_percent(p, list.total_memory_bytes)),
+                        human_size(p.rss_bytes),
+                        p.status.clone(),
+                        p.container.clone().unwrap_or_default(),
+                        command(p),
+                    ])
+                })
+                .collect();
+
+            let table = Table::new(
+                rows,
+                [
+                    Constraint::Length(7),
+                    Constraint::Length(10),
+                    Constraint::Length(6),
+                    Constraint::Length(5),
+                    Constraint::Length(7),
+                    Constraint::Length(8),
+                    Constraint::Length(12),
+                    Constraint::Min(20),
+                ],
+            )
+            .header(header)
+            .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(format!("Processes ({count})")),
+            );
+
+            let mut state = TableState::default().with_selected(Some(index));
+            f.render_stateful_widget(table, chunks[0], &mut state);
+            f.render_widget(Paragraph::new(status.as_str()), chunks[1]);
+        })?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(pid: u32, cpu_percent: f32, rss_bytes: u64) -> ProcessInfo {
+        ProcessInfo {
+            pid,
+            cpu_percent,
+            rss_bytes,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn sorts_busiest_first() {
+        let mut ps = vec![
+            process(1, 0.5, 300),
+            process(2, 80.0, 100),
+            process(3, 3.0, 200),
+        ];
+        sort_processes(&mut ps, ProcessSort::Cpu);
+        assert_eq!(ps.iter().map(|p| p.pid).collect::<Vec<_>>(), [2, 3, 1]);
+        sort_processes(&mut ps, ProcessSort::Mem);
+        assert_eq!(ps.iter().map(|p| p.pid).collect::<Vec<_>>(), [1, 3, 2]);
+    }
+}
@@ -0,0 +1,59 @@
+use std::io::Write;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use m87_shared::device::SessionRecording;
+
+use crate::tui::helper::dim;
+use crate::util::shutdown::SHUTDOWN;
+
+/// Long pauses (user away from the keyboard) are shortened to this on replay.
+const MAX_IDLE: Duration = Duration::from_secs(2);
+
+/// Play back the output of an asciicast v2 recording with its original timing.
+pub async fn replay_session(recording: &SessionRecording) -> Result<()> {
+    eprintln!("{}", dim(&describe(recording)));
+
+    let mut lines = recording.cast.lines();
+    lines.next().context("empty session recording")?;
+
+    let mut stdout = std::io::stdout();
+    let mut last = 0.0f64;
+    for line in lines {
+        let Ok((at, code, data)) = serde_json::from_str::<(f64, String, String)>(line) else {
+            continue;
+        };
+        if code != "o" {
+            continue;
+        }
+
+        let wait = Duration::from_secs_f64((at - last).max(0.0)).min(MAX_IDLE);
+        last = at;
+        tokio::select! {
+            _ = tokio::time::sleep(wait) => {}
+            _ = SHUTDOWN.cancelled() => break,
+        }
+
+        stdout.write_all(data.as_bytes())?;
+        stdout.flush()?;
+    }
+
+    // Leave the terminal in a sane state whatever the session ended with.
+    stdout.write_all(b"\x1b[0m\r\n")?;
+    stdout.flush()?;
+    if recording.truncated {
+        eprintln!("{}", dim("(recording was truncated at the size limit)"));
+    }
+    Ok(())
+}
+
+fn describe(recording: &SessionRecording) -> String {
+    let what = match &recording.command {
+        Some(command) => format!("{} `{}`", recording.kind, command),
+        None => recording.kind.clone(),
+    };
+    format!(
+        "Session {}: {} by {} ({} – {})",
+        recording.session_id, what, recording.user_email, recording.started_at, recording.ended_at
+    )
+}
@@ -0,0 +1,73 @@
+use crate::streams::serial_control::SerialPortEntry;
+use crate::tui::helper::{Align, ColSpec, RenderOpts, Table, dim, terminal_width};
+
+pub fn print_serial_ports(ports: &[SerialPortEntry]) {
+    if ports.is_empty() {
+        println!("{}", dim("No serial ports found"));
+        return;
+    }
+
+    let term_w = terminal_width().unwrap_or(120);
+    let opts = RenderOpts::default();
+
+    let col = |title, min, max, weight, wrap| ColSpec {
+        title,
+        min,
+        max,
+        weight,
+        align: Align::Left,
+        wrap,
+    };
+    let t = Table::new(
+        term_w.saturating_sub(2),
+        1,
+        vec![
+            col("PORT", 8, Some(14), 0, false),
+            col("TYPE", 5, Some(9), 0, false),
+            col("USB ID", 9, Some(9), 0, false),
+            col("SERIAL", 8, Some(20), 1, false),
+            col("PRODUCT", 10, Some(32), 2, false),
+            col("ALIASES", 16, None, 4, true),
+        ],
+    );
+
+    let mut out = String::new();
+    out.push_str("  ");
+    t.header(&mut out, &opts);
+
+    for port in ports {
+        let name = if port.captured {
+            format!("{} {}", port.name, dim("●"))
+        } else {
+            port.name.clone()
+        };
+        let usb_id = match (port.vid, port.pid) {
+            (Some(vid), Some(pid)) => format!("{vid:04x}:{pid:04x}"),
+            _ => dim("-"),
+        };
+        let serial = port.serial_number.clone().unwrap_or_else(|| dim("-"));
+        let product = match (&port.manufacturer, &port.product) {
+            (Some(m), Some(p)) if !p.starts_with(m.as_str()) => format!("{m} {p}"),
+            (_, Some(p)) => p.clone(),
+            (Some(m), None) => m.clone(),
+            (None, None) => dim("-"),
+        };
+        let aliases = if port.aliases.is_empty() {
+            dim("-")
+        } else {
+            port.aliases.join(" ")
+        };
+
+        out.push_str("  ");
+        t.row(
+            &mut out,
+            &[&name, &port.kind, &usb_id, &serial, &product, &aliases],
+            &opts,
+        );
+    }
+
+    print!("{out}");
+    if ports.iter().any(|p| p.captured) {
+        println!("\n{} = captured by the runtime", dim("●"));
+    }
+}
@@ -1,6 +1,9 @@
-use crate::tui::helper::{Align, ColSpec, RenderOpts, Table, dim, role_badge, terminal_width};
+use crate::tui::helper::{
+    Align, ColSpec, RenderOpts, Table, dim, format_relative_time, green, red, role_badge,
+    terminal_width, yellow,
+};
 
-use m87_shared::users::User; // adjust import if Role lives elsewhere
+use m87_shared::users::{AdminUser, User, UserStatus}; // adjust import if Role lives elsewhere
 
 pub fn print_users(users: &[User]) {
     if users.is_empty() {
@@ -57,3 +60,84 @@ pub fn print_users(users: &[User]) {
 
     print!("{out}");
 }
+
+pub fn print_admin_users(users: &[AdminUser]) {
+    if users.is_empty() {
+        println!("{}", dim("No users found"));
+        return;
+    }
+
+    let term_w = terminal_width().unwrap_or(96);
+    let opts = RenderOpts::default();
+
+    let t = Table::new(
+        term_w.saturating_sub(2),
+        1,
+        vec![
+            ColSpec {
+                title: "EMAIL",
+                min: 22,
+                max: Some(48),
+                weight: 4,
+                align: Align::Left,
+                wrap: false,
+            },
+            ColSpec {
+                title: "NAME",
+                min: 8,
+                max: Some(24),
+                weight: 2,
+                align: Align::Left,
+                wrap: false,
+            },
+            ColSpec {
+                title: "STATUS",
+                min: 8,
+                max: Some(10),
+                weight: 0,
+                align: Align::Left,
+                wrap: false,
+            },
+            ColSpec {
+                title: "LAST LOGIN",
+                min: 10,
+                max: Some(14),
+                weight: 0,
+                align: Align::Left,
+                wrap: false,
+            },
+            ColSpec {
+                title: "ID",
+                min: 24,
+                max: Some(24),
+                weight: 0,
+                align: Align::Left,
+                wrap: false,
+            },
+        ],
+    );
+
+    let mut out = String::new();
+    out.push_str("  ");
+    t.header(&mut out, &opts);
+
+    for u in users {
+        let name = u.name.clone().unwrap_or_else(|| dim("-"));
+        let status = match u.status {
+            UserStatus::Active => green(&u.status.to_string()),
+            UserStatus::Pending => yellow(&u.status.to_string()),
+            UserStatus::Disabled => red(&u.status.to_string()),
+        };
+        let last_login = u
+            .last_login
+            .as_deref()
+            .map(format_relative_time)
+            .unwrap_or_else(|| dim("never"));
+        let id = dim(&u.id);
+
+        out.push_str("  ");
+        t.row(&mut out, &[&u.email, &name, &status, &last_login, &id], &opts);
+    }
+
+    print!("{out}");
+}
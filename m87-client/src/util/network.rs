@@ -60,6 +60,48 @@ async fn query_stun_server(server: &str) -> Result<IpAddr> {
         .await
         .context("Failed to bind UDP socket")?;
 
+    let mapped = stun_binding(&socket, server_addr, STUN_TIMEOUT).await?;
+    Ok(mapped.ip())
+}
+
+/// Public (server-reflexive) address of `socket` as seen by the first STUN
+/// server that answers within `timeout`. Used to advertise direct-connection
+/// candidates, so the query must go out of the socket that will carry QUIC.
+pub async fn reflexive_address(socket: &UdpSocket, timeout: Duration) -> Result<SocketAddr> {
+    let mut last_error = None;
+    for server in STUN_SERVERS {
+        let server_addr = match tokio::net::lookup_host(server).await {
+            Ok(mut addrs) => match addrs.find(|a| a.is_ipv4()) {
+                Some(addr) => addr,
+                None => continue,
+            },
+            Err(e) => {
+                last_error = Some(e.into());
+                continue;
+            }
+        };
+        match stun_binding(socket, server_addr, timeout).await {
+            Ok(addr) => return Ok(addr),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| anyhow!("No STUN servers available")))
+}
+
+/// IP of the interface that routes to the internet (no packets are sent).
+pub fn primary_local_ip() -> Option<IpAddr> {
+    let socket = std::net::UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.connect("8.8.8.8:80").ok()?;
+    let ip = socket.local_addr().ok()?.ip();
+    (!ip.is_unspecified() && !ip.is_loopback()).then_some(ip)
+}
+
+/// Send one STUN binding request from `socket` and return the mapped address.
+async fn stun_binding(
+    socket: &UdpSocket,
+    server_addr: SocketAddr,
+    timeout: Duration,
+) -> Result<SocketAddr> {
     // Create STUN binding request
     let mut message = stun::message::Message::new();
     message.build(&[
@@ -75,9 +117,16 @@ async fn query_stun_server(server: &str) -> Result<IpAddr> {
 
     // Receive STUN response with timeout
     let mut buf = vec![0u8; 1500];
-    let len = tokio::time::timeout(STUN_TIMEOUT, socket.recv(&mut buf))
-        .await
-        .context("STUN request timed out")??;
+    let len = tokio::time::timeout(timeout, async {
+        loop {
+            let (len, from) = socket.recv_from(&mut buf).await?;
+            if from == server_addr {
+                return Ok::<_, std::io::Error>(len);
+            }
+        }
+    })
+    .await
+    .context("STUN request timed out")??;
 
     // Parse STUN response
     let mut response = stun::message::Message::new();
@@ -88,7 +137,7 @@ async fn query_stun_server(server: &str) -> Result<IpAddr> {
     let mut xor_addr = stun::xoraddr::XorMappedAddress::default();
     xor_addr.get_from(&response)?;
 
-    Ok(xor_addr.ip)
+    Ok(SocketAddr::new(xor_addr.ip, xor_addr.port))
 }
 
 #[cfg(test)]
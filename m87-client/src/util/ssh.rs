@@ -62,6 +62,8 @@ pub struct M87SshHandler {
     default_shell: String,
     /// Environment variables requested by the client (per channel)
     env_vars: HashMap<ChannelId, HashMap<String, String>>,
+    /// Refuse shells and exec; only the SFTP subsystem is served.
+    sftp_only: bool,
 }
 
 impl M87SshHandler {
@@ -75,9 +77,16 @@ impl M87SshHandler {
             pty_sizes: HashMap::new(),
             default_shell: shell::detect_shell(),
             env_vars: HashMap::new(),
+            sftp_only: false,
         }
     }
 
+    /// Only serve SFTP, e.g. on direct sessions the relay can't record.
+    pub fn sftp_only(mut self) -> Self {
+        self.sftp_only = true;
+        self
+    }
+
     /// Spawns a PTY shell and returns the reader (for output).
     /// The writer is stored internally for use by the data handler.
     fn spawn_pty_shell_for_channel(&mut self, channel: ChannelId) -> Result<PtyReader> {
@@ -479,6 +488,11 @@ impl server::Handler for M87SshHandler {
         channel: ChannelId,
         session: &mut Session,
     ) -> Result<(), Self::Error> {
+        if self.sftp_only {
+            session.channel_failure(channel)?;
+            return Ok(());
+        }
+
         // Spawn PTY + shell
         let reader = self.spawn_pty_shell_for_channel(channel)?;
         session.channel_success(channel)?;
@@ -614,6 +628,11 @@ impl server::Handler for M87SshHandler {
         data: &[u8],
         session: &mut Session,
     ) -> Result<(), Self::Error> {
+        if self.sftp_only {
+            session.channel_failure(channel)?;
+            return Ok(());
+        }
+
         let cmd = String::from_utf8_lossy(data).to_string();
 
         session.channel_success(channel)?;
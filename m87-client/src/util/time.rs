@@ -48,6 +48,12 @@ pub fn parse_time(input: &str, now_ms_value: u64) -> Result<u64> {
     })
 }
 
+/// Parse a duration argument such as `--for 10m` into seconds.
+pub fn parse_duration_secs(input: &str) -> Result<u64> {
+    parse_relative_duration_secs(input.trim())
+        .ok_or_else(|| anyhow!("could not parse '{input}' as a duration (e.g. 30s, 10m, 1h, 7d)"))
+}
+
 /// Parse a duration suffix: `30s`, `5m`, `2h`, `24h`, `7d`, `2w`.
 /// A bare integer with no suffix is treated as seconds.
 /// Returns the duration in seconds, or `None` if the input is not a
@@ -162,6 +168,13 @@ mod tests {
         assert_eq!(parse_time("90", NOW).unwrap(), NOW - 90 * 1000);
     }
 
+    #[test]
+    fn durations_without_now() {
+        assert_eq!(parse_duration_secs("10m").unwrap(), 600);
+        assert_eq!(parse_duration_secs(" 1h ").unwrap(), 3600);
+        assert!(parse_duration_secs("soon").is_err());
+    }
+
     #[test]
     fn relative_is_case_insensitive() {
         assert_eq!(parse_time("1H", NOW).unwrap(), NOW - 60 * 60 * 1000);
@@ -0,0 +1,332 @@
+//! Server-side evaluation of org alert rules.
+//!
+//! Every `EVAL_INTERVAL` each rule is checked against the org devices its
+//! label selector matches. A device (or unit) for which the condition holds
+//! gets a pending alert, which fires once the condition has held for the
+//! rule's `for_secs` and resolves when it stops holding. State transitions
+//! are conditional updates, so replicas evaluating the same rule notify once.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::TryStreamExt;
+use m87_shared::alerts::{AlertCondition, MetricField};
+use m87_shared::org::WebhookEvent;
+use mongodb::bson::{DateTime, Document, doc, oid::ObjectId};
+use serde_json::json;
+use tracing::warn;
+
+use crate::db::Mongo;
+use crate::models::alert::{AlertDoc, AlertRuleDoc};
+use crate::models::deploy_spec::DeployRevisionDoc;
+use crate::models::device::{DeviceDoc, DeviceVitals};
+use crate::models::org;
+use crate::relay::relay_state::RelayState;
+use crate::response::ServerResult;
+use crate::webhooks;
+
+const EVAL_INTERVAL: Duration = Duration::from_secs(30);
+/// Devices reconnect after a server restart; don't call them offline before
+/// they had the chance.
+const STARTUP_GRACE: Duration = Duration::from_secs(90);
+/// Vitals older than this are ignored by metric rules.
+const MAX_VITALS_AGE: Duration = Duration::from_secs(10 * 60);
+
+/// Evaluate all alert rules periodically. Runs for the lifetime of the server.
+pub fn spawn_evaluator(db: Arc<Mongo>, relay: Arc<RelayState>) {
+    tokio::spawn(async move {
+        tokio::time::sleep(STARTUP_GRACE).await;
+        let mut interval = tokio::time::interval(EVAL_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            let rules = match AlertRuleDoc::all(&db).await {
+                Ok(rules) => rules,
+                Err(e) => {
+                    warn!("failed to load alert rules: {e:?}");
+                    continue;
+                }
+            };
+            for rule in rules {
+                if let Err(e) = evaluate_rule(&db, &relay, &rule).await {
+                    warn!(rule = ?rule.id, "failed to evaluate alert rule: {e:?}");
+                }
+            }
+        }
+    });
+}
+
+/// A device, or one of its units, for which a rule's condition holds.
+struct Violation {
+    subject: String,
+    value: Option<String>,
+}
+
+async fn evaluate_rule(
+    db: &Arc<Mongo>,
+    relay: &RelayState,
+    rule: &AlertRuleDoc,
+) -> ServerResult<()> {
+    let Some(rule_id) = rule.id else {
+        return Ok(());
+    };
+    let silenced = rule.is_silenced(DateTime::now());
+
+    let mut open: HashMap<(ObjectId, String), AlertDoc> = AlertDoc::open_for_rule(db, rule_id)
+        .await?
+        .into_iter()
+        .map(|alert| ((alert.device_id, alert.subject.clone()), alert))
+        .collect();
+
+    let devices: Vec<DeviceDoc> = db
+        .devices()
+        .find(device_selector(rule))
+        .await?
+        .try_collect()
+        .await?;
+
+    let online = match rule.condition {
+        AlertCondition::Offline => {
+            let ids: Vec<String> = devices.iter().map(|d| d.short_id.clone()).collect();
+            relay.online_devices(&ids).await
+        }
+        _ => HashSet::new(),
+    };
+
+    for device in devices {
+        let Some(device_id) = device.id else {
+            continue;
+        };
+        for violation in violations(db, &online, &rule.condition, &device).await? {
+            open.remove(&(device_id, violation.subject.clone()));
+            AlertDoc::observe(
+                db,
+                rule,
+                device_id,
+                &device.name,
+                &violation.subject,
+                violation.value,
+            )
+            .await?;
+            if let Some(alert) = AlertDoc::promote(db, rule, device_id, &violation.subject).await?
+                && !silenced
+            {
+                notify(db, rule, &alert, WebhookEvent::AlertFiring);
+            }
+        }
+    }
+
+    // Whatever is still open no longer holds, or its device left the selector.
+    for alert in open.into_values() {
+        if let Some(alert) = alert.resolve(db).await?
+            && !silenced
+        {
+            notify(db, rule, &alert, WebhookEvent::AlertResolved);
+        }
+    }
+    Ok(())
+}
+
+fn device_selector(rule: &AlertRuleDoc) -> Document {
+    let scope = org::org_scope(&rule.org_id);
+    let mut filter = doc! {
+        "$or": [
+            { "owner_scope": &scope },
+            { "allowed_scopes": &scope },
+        ],
+    };
+    for (key, value) in &rule.labels {
+        filter.insert(format!("labels.{key}"), value);
+    }
+    filter
+}
+
+/// `online` holds the short ids of the rule's devices that are connected.
+async fn violations(
+    db: &Arc<Mongo>,
+    online: &HashSet<String>,
+    condition: &AlertCondition,
+    device: &DeviceDoc,
+) -> ServerResult<Vec<Violation>> {
+    let whole_device = |value: Option<String>| {
+        vec![Violation {
+            subject: String::new(),
+            value,
+        }]
+    };
+
+    match condition {
+        AlertCondition::Offline => {
+            if online.contains(&device.short_id) {
+                return Ok(Vec::new());
+            }
+            let last_seen = device
+                .last_heartbeat_at
+                .and_then(|t| t.try_to_rfc3339_string().ok());
+            Ok(whole_device(last_seen))
+        }
+        AlertCondition::VersionMismatch => {
+            if version_mismatch(&device.version, &device.target_version) {
+                Ok(whole_device(Some(device.version.clone())))
+            } else {
+                Ok(Vec::new())
+            }
+        }
+        AlertCondition::Unhealthy {
+            unit,
+            consecutive_checks,
+        } => {
+            let Some(device_id) = device.id else {
+                return Ok(Vec::new());
+            };
+            let Some(revision_id) = DeployRevisionDoc::get_active_device_deployment(db, device_id)
+                .await?
+                .and_then(|rev| rev.revision.id)
+            else {
+                return Ok(Vec::new());
+            };
+            let mut filter = doc! {
+                "device_id": device_id,
+                "revision_id": revision_id,
+                "consecutive_unhealthy": { "$gte": *consecutive_checks as i64 },
+            };
+            if let Some(unit) = unit {
+                filter.insert("run_id", unit);
+            }
+            let states: Vec<_> = db
+                .current_run_states()
+                .find(filter)
+                .await?
+                .try_collect()
+                .await?;
+            Ok(states
+                .into_iter()
+                .map(|state| Violation {
+                    subject: state.run_id,
+                    value: Some(state.consecutive_unhealthy.to_string()),
+                })
+                .collect())
+        }
+        AlertCondition::Metric {
+            metric,
+            op,
+            threshold,
+        } => {
+            let Some(vitals) = &device.vitals else {
+                return Ok(Vec::new());
+            };
+            let fresh = vitals
+                .reported_at
+                .to_system_time()
+                .elapsed()
+                .is_ok_and(|age| age <= MAX_VITALS_AGE);
+            // Devices without e.g. a fan never match a fan rule.
+            let Some(value) = metric_value(vitals, *metric) else {
+                return Ok(Vec::new());
+            };
+            if fresh && op.holds(value, *threshold) {
+                Ok(whole_device(Some(format!("{value:.1}"))))
+            } else {
+                Ok(Vec::new())
+            }
+        }
+    }
+}
+
+/// Only pinned target versions count; `latest` follows whatever is current.
+fn version_mismatch(version: &str, target_version: &str) -> bool {
+    let target = target_version.trim().trim_start_matches('v');
+    let version = version.trim().trim_start_matches('v');
+    !target.is_empty() && target != "latest" && !version.is_empty() && version != target
+}
+
+fn metric_value(vitals: &DeviceVitals, metric: MetricField) -> Option<f64> {
+    let flag = |set: Option<bool>| set.map(|set| if set { 1.0 } else { 0.0 });
+    match metric {
+        MetricField::CpuUsagePercent => Some(vitals.cpu_usage_percent),
+        MetricField::MemoryUsagePercent => Some(vitals.memory_usage_percent),
+        MetricField::DiskUsagePercent => Some(vitals.disk_usage_percent),
+        MetricField::ThermalMaxTempC => vitals.max_temp_c,
+        MetricField::ThermalMinFanRpm => vitals.min_fan_rpm,
+        MetricField::CpuFreqMhz => vitals.cpu_freq_mhz,
+        MetricField::PowerUndervoltage => flag(vitals.undervoltage),
+        MetricField::PowerThrottled => flag(vitals.throttled),
+        MetricField::ContainersUnhealthy => vitals.unhealthy_containers,
+    }
+}
+
+fn notify(db: &Arc<Mongo>, rule: &AlertRuleDoc, alert: &AlertDoc, event: WebhookEvent) {
+    let data = json!({
+        "alert": alert.to_alert(false),
+        "rule": rule.to_alert_rule(),
+    });
+    webhooks::notify_org_device(
+        db,
+        &rule.org_id,
+        alert.device_id,
+        &alert.device_name,
+        event,
+        data,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_pinned_targets_mismatch() {
+        assert!(!version_mismatch("1.4.0", "latest"));
+        assert!(!version_mismatch("1.4.0", ""));
+        assert!(!version_mismatch("", "1.4.0"));
+        assert!(!version_mismatch("v1.4.0", "1.4.0"));
+        assert!(version_mismatch("1.3.2", "1.4.0"));
+    }
+
+    #[test]
+    fn selector_matches_org_and_labels() {
+        let rule = AlertRuleDoc {
+            id: None,
+            org_id: "acme".into(),
+            name: "disk".into(),
+            labels: [("site".to_string(), "berlin".to_string())].into(),
+            condition: "disk.usage_percent>90".parse().unwrap(),
+            for_secs: 0,
+            silenced_until: None,
+            created_at: DateTime::now(),
+        };
+        let filter = device_selector(&rule);
+        assert_eq!(filter.get_str("labels.site").unwrap(), "berlin");
+        assert_eq!(filter.get_array("$or").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn missing_readings_have_no_value() {
+        let vitals = DeviceVitals {
+            cpu_usage_percent: 12.0,
+            memory_usage_percent: 40.0,
+            disk_usage_percent: 70.0,
+            max_temp_c: Some(83.5),
+            min_fan_rpm: None,
+            cpu_freq_mhz: None,
+            undervoltage: Some(true),
+            throttled: Some(false),
+            unhealthy_containers: None,
+            reported_at: DateTime::now(),
+        };
+        assert_eq!(
+            metric_value(&vitals, MetricField::ThermalMaxTempC),
+            Some(83.5)
+        );
+        assert_eq!(
+            metric_value(&vitals, MetricField::PowerUndervoltage),
+            Some(1.0)
+        );
+        assert_eq!(
+            metric_value(&vitals, MetricField::PowerThrottled),
+            Some(0.0)
+        );
+        assert_eq!(metric_value(&vitals, MetricField::ThermalMinFanRpm), None);
+    }
+}
@@ -0,0 +1,185 @@
+use axum::Router;
+use axum::extract::{Path, Query, State};
+use axum::routing::{get, post};
+use m87_shared::users::{AdminUser, ListUsersQuery, UserStatus};
+
+use crate::auth::claims::Claims;
+use crate::models::audit_logs::AuditLogDoc;
+use crate::models::user::UserDoc;
+use crate::response::{ServerAppResult, ServerError, ServerResponse, ServerResult};
+use crate::util::app_state::AppState;
+
+/// Mounted under `/admin`. Only server admins (`admin_emails` or the
+/// `admin_key`) may call these.
+pub fn create_route() -> Router<AppState> {
+    Router::new()
+        .route("/users", get(list_users))
+        .route("/users/{user}/approve", post(approve_user))
+        .route("/users/{user}/reject", post(reject_user))
+        .route("/users/{user}/disable", post(disable_user))
+        .route("/users/{user}/enable", post(enable_user))
+}
+
+// --------------------
+// GET /admin/users
+// --------------------
+
+async fn list_users(
+    claims: Claims,
+    State(state): State<AppState>,
+    Query(query): Query<ListUsersQuery>,
+) -> ServerAppResult<Vec<AdminUser>> {
+    if !claims.is_admin {
+        return Err(ServerError::unauthorized(""));
+    }
+
+    let users = UserDoc::list(&state.db, query.status).await?;
+
+    Ok(ServerResponse::builder()
+        .body(users.iter().map(UserDoc::to_admin_user).collect())
+        .status_code(axum::http::StatusCode::OK)
+        .build())
+}
+
+// --------------------
+// POST /admin/users/{user}/approve
+// --------------------
+
+async fn approve_user(
+    claims: Claims,
+    State(state): State<AppState>,
+    Path(user): Path<String>,
+) -> ServerAppResult<AdminUser> {
+    set_access(
+        &claims,
+        &state,
+        &user,
+        &[UserStatus::Pending],
+        true,
+        false,
+        "Approved user",
+    )
+    .await
+}
+
+// --------------------
+// POST /admin/users/{user}/reject
+// --------------------
+
+async fn reject_user(
+    claims: Claims,
+    State(state): State<AppState>,
+    Path(user): Path<String>,
+) -> ServerAppResult<AdminUser> {
+    set_access(
+        &claims,
+        &state,
+        &user,
+        &[UserStatus::Pending],
+        false,
+        true,
+        "Rejected user",
+    )
+    .await
+}
+
+// --------------------
+// POST /admin/users/{user}/disable
+// --------------------
+
+async fn disable_user(
+    claims: Claims,
+    State(state): State<AppState>,
+    Path(user): Path<String>,
+) -> ServerAppResult<AdminUser> {
+    set_access(
+        &claims,
+        &state,
+        &user,
+        &[UserStatus::Pending, UserStatus::Active],
+        true,
+        true,
+        "Disabled user",
+    )
+    .await
+}
+
+// --------------------
+// POST /admin/users/{user}/enable
+// --------------------
+
+async fn enable_user(
+    claims: Claims,
+    State(state): State<AppState>,
+    Path(user): Path<String>,
+) -> ServerAppResult<AdminUser> {
+    set_access(
+        &claims,
+        &state,
+        &user,
+        &[UserStatus::Disabled],
+        true,
+        false,
+        "Enabled user",
+    )
+    .await
+}
+
+/// Set the access flags of a user currently in one of the `from` states.
+async fn set_access(
+    claims: &Claims,
+    state: &AppState,
+    user: &str,
+    from: &[UserStatus],
+    approved: bool,
+    disabled: bool,
+    action: &str,
+) -> ServerAppResult<AdminUser> {
+    if !claims.is_admin {
+        return Err(ServerError::unauthorized(""));
+    }
+
+    let doc = find_user(state, user).await?;
+    let status = doc.status();
+    if !from.contains(&status) {
+        return Err(ServerError::bad_request(&format!("User is {status}")));
+    }
+    let Some(id) = doc.id else {
+        return Err(ServerError::not_found("User not found"));
+    };
+    let doc = UserDoc::set_access(&state.db, id, approved, disabled)
+        .await?
+        .ok_or_else(|| ServerError::not_found("User not found"))?;
+
+    // Sessions on other replicas are closed by their periodic sweep.
+    let closed = if disabled {
+        state.relay.close_user_sessions(id).await
+    } else {
+        0
+    };
+
+    let user = doc.to_admin_user();
+    let _ = AuditLogDoc::add(
+        &state.db,
+        claims,
+        &state.config,
+        action,
+        &format!(
+            "user={} email={} closed_sessions={}",
+            user.id, user.email, closed
+        ),
+        None,
+    )
+    .await;
+
+    Ok(ServerResponse::builder()
+        .body(user)
+        .status_code(axum::http::StatusCode::OK)
+        .build())
+}
+
+async fn find_user(state: &AppState, user: &str) -> ServerResult<UserDoc> {
+    UserDoc::find_by_id_or_email(&state.db, user)
+        .await?
+        .ok_or_else(|| ServerError::not_found("User not found"))
+}
@@ -0,0 +1,255 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use axum::extract::{Path, Query, State};
+use axum::routing::{delete, get, post, put};
+use axum::{Json, Router};
+use m87_shared::alerts::{
+    Alert, AlertRule, CreateAlertRuleBody, ListAlertsQuery, SilenceAlertRuleBody,
+};
+use m87_shared::roles::Role;
+use mongodb::bson::{DateTime, oid::ObjectId};
+
+use crate::auth::claims::Claims;
+use crate::models::alert::{AlertDoc, AlertRuleDoc, MAX_ALERT_DURATION};
+use crate::models::audit_logs::AuditLogDoc;
+use crate::models::org;
+use crate::response::{ServerAppResult, ServerError, ServerResponse};
+use crate::util::app_state::AppState;
+
+/// Mounted under `/organization` next to the org routes.
+pub fn create_route() -> Router<AppState> {
+    Router::new()
+        .route("/{id}/alerts", get(list_alerts))
+        .route("/{id}/alerts/{alert_id}/ack", post(ack_alert))
+        .route(
+            "/{id}/alert-rules",
+            get(list_alert_rules).post(create_alert_rule),
+        )
+        .route("/{id}/alert-rules/{rule_id}", delete(delete_alert_rule))
+        .route(
+            "/{id}/alert-rules/{rule_id}/silence",
+            put(silence_alert_rule),
+        )
+}
+
+// --------------------
+// GET /organizations/{id}/alerts
+// --------------------
+
+async fn list_alerts(
+    claims: Claims,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<ListAlertsQuery>,
+) -> ServerAppResult<Vec<Alert>> {
+    let scope = org::org_scope(&id);
+    if !claims.has_scope_and_role(&scope, Role::Viewer) {
+        return Err(ServerError::forbidden("Not authorized for organization"));
+    }
+
+    let now = DateTime::now();
+    let silenced: HashMap<ObjectId, bool> = AlertRuleDoc::list_for_org(&state.db, &id)
+        .await?
+        .into_iter()
+        .filter_map(|rule| rule.id.map(|rule_id| (rule_id, rule.is_silenced(now))))
+        .collect();
+    let alerts = AlertDoc::list_for_org(&state.db, &id, query.all).await?;
+
+    Ok(ServerResponse::builder()
+        .body(
+            alerts
+                .iter()
+                .map(|a| a.to_alert(silenced.get(&a.rule_id).copied().unwrap_or(false)))
+                .collect(),
+        )
+        .status_code(axum::http::StatusCode::OK)
+        .build())
+}
+
+// --------------------
+// POST /organizations/{id}/alerts/{alert_id}/ack
+// --------------------
+
+async fn ack_alert(
+    claims: Claims,
+    State(state): State<AppState>,
+    Path((id, alert_id)): Path<(String, String)>,
+) -> ServerAppResult<Alert> {
+    let scope = org::org_scope(&id);
+    if !claims.has_scope_and_role(&scope, Role::Editor) {
+        return Err(ServerError::forbidden("Not authorized for organization"));
+    }
+
+    let oid =
+        ObjectId::parse_str(&alert_id).map_err(|_| ServerError::bad_request("Invalid alert id"))?;
+    let alert = AlertDoc::ack(&state.db, &id, oid, &claims.user_email)
+        .await?
+        .ok_or_else(|| ServerError::not_found("Alert not found"))?;
+
+    let _ = AuditLogDoc::add(
+        &state.db,
+        &claims,
+        &state.config,
+        "Acknowledged alert",
+        &format!("id={} alert={} rule={}", id, alert_id, alert.rule_name),
+        Some(alert.device_id),
+    )
+    .await;
+
+    Ok(ServerResponse::builder()
+        .body(alert.to_alert(false))
+        .status_code(axum::http::StatusCode::OK)
+        .build())
+}
+
+// --------------------
+// GET /organizations/{id}/alert-rules
+// --------------------
+
+async fn list_alert_rules(
+    claims: Claims,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> ServerAppResult<Vec<AlertRule>> {
+    let scope = org::org_scope(&id);
+    if !claims.has_scope_and_role(&scope, Role::Viewer) {
+        return Err(ServerError::forbidden("Not authorized for organization"));
+    }
+
+    let rules = AlertRuleDoc::list_for_org(&state.db, &id).await?;
+
+    Ok(ServerResponse::builder()
+        .body(rules.iter().map(AlertRuleDoc::to_alert_rule).collect())
+        .status_code(axum::http::StatusCode::OK)
+        .build())
+}
+
+// --------------------
+// POST /organizations/{id}/alert-rules
+// --------------------
+
+async fn create_alert_rule(
+    claims: Claims,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(payload): Json<CreateAlertRuleBody>,
+) -> ServerAppResult<AlertRule> {
+    let scope = org::org_scope(&id);
+    if !claims.has_scope_and_role(&scope, Role::Admin) {
+        return Err(ServerError::forbidden("Not authorized for organization"));
+    }
+
+    let rule = AlertRuleDoc::create(&state.db, &id, payload)
+        .await?
+        .to_alert_rule();
+
+    let _ = AuditLogDoc::add(
+        &state.db,
+        &claims,
+        &state.config,
+        "Created alert rule",
+        &format!(
+            "id={} rule={} name={} condition={}",
+            id, rule.id, rule.name, rule.condition
+        ),
+        None,
+    )
+    .await;
+
+    Ok(ServerResponse::builder()
+        .body(rule)
+        .status_code(axum::http::StatusCode::CREATED)
+        .build())
+}
+
+// --------------------
+// DELETE /organizations/{id}/alert-rules/{rule_id}
+// --------------------
+
+async fn delete_alert_rule(
+    claims: Claims,
+    State(state): State<AppState>,
+    Path((id, rule_id)): Path<(String, String)>,
+) -> ServerAppResult<()> {
+    let scope = org::org_scope(&id);
+    if !claims.has_scope_and_role(&scope, Role::Admin) {
+        return Err(ServerError::forbidden("Not authorized for organization"));
+    }
+
+    let oid =
+        ObjectId::parse_str(&rule_id).map_err(|_| ServerError::bad_request("Invalid rule id"))?;
+    if !AlertRuleDoc::delete(&state.db, &id, oid).await? {
+        return Err(ServerError::not_found("Alert rule not found"));
+    }
+
+    let _ = AuditLogDoc::add(
+        &state.db,
+        &claims,
+        &state.config,
+        "Deleted alert rule",
+        &format!("id={} rule={}", id, rule_id),
+        None,
+    )
+    .await;
+
+    Ok(ServerResponse::builder()
+        .status_code(axum::http::StatusCode::NO_CONTENT)
+        .build())
+}
+
+// --------------------
+// PUT /organizations/{id}/alert-rules/{rule_id}/silence
+// --------------------
+
+async fn silence_alert_rule(
+    claims: Claims,
+    State(state): State<AppState>,
+    Path((id, rule_id)): Path<(String, String)>,
+    Json(payload): Json<SilenceAlertRuleBody>,
+) -> ServerAppResult<AlertRule> {
+    let scope = org::org_scope(&id);
+    if !claims.has_scope_and_role(&scope, Role::Editor) {
+        return Err(ServerError::forbidden("Not authorized for organization"));
+    }
+
+    let oid =
+        ObjectId::parse_str(&rule_id).map_err(|_| ServerError::bad_request("Invalid rule id"))?;
+    let until = match payload.duration_secs {
+        Some(secs) if secs > MAX_ALERT_DURATION.as_secs() => {
+            return Err(ServerError::bad_request("Silences are limited to 30 days"));
+        }
+        Some(secs) => Some(DateTime::from_system_time(
+            DateTime::now().to_system_time() + Duration::from_secs(secs),
+        )),
+        None => None,
+    };
+    let rule = AlertRuleDoc::set_silence(&state.db, &id, oid, until)
+        .await?
+        .ok_or_else(|| ServerError::not_found("Alert rule not found"))?
+        .to_alert_rule();
+
+    let _ = AuditLogDoc::add(
+        &state.db,
+        &claims,
+        &state.config,
+        if until.is_some() {
+            "Silenced alert rule"
+        } else {
+            "Unsilenced alert rule"
+        },
+        &format!(
+            "id={} rule={} until={}",
+            id,
+            rule_id,
+            rule.silenced_until.as_deref().unwrap_or("-")
+        ),
+        None,
+    )
+    .await;
+
+    Ok(ServerResponse::builder()
+        .body(rule)
+        .status_code(axum::http::StatusCode::OK)
+        .build())
+}
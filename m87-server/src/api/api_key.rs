@@ -0,0 +1,168 @@
+use axum::extract::{Path, State};
+use axum::routing::{delete, get};
+use axum::{Json, Router};
+use m87_shared::auth::{ApiKeyInfo, CreateApiKeyBody, CreatedApiKey};
+use mongodb::bson::{doc, oid::ObjectId};
+
+use crate::auth::claims::Claims;
+use crate::models::api_key::{ApiKeyDoc, CreateApiKey};
+use crate::models::audit_logs::AuditLogDoc;
+use crate::models::device::DeviceDoc;
+use crate::models::org;
+use crate::models::roles::RoleDoc;
+use crate::models::user::UserDoc;
+use crate::response::{ServerAppResult, ServerError, ServerResponse, ServerResult};
+use crate::util::app_state::AppState;
+
+pub fn create_route() -> Router<AppState> {
+    Router::new()
+        .route("/", get(list_api_keys).post(create_api_key))
+        .route("/{key_id}", delete(revoke_api_key))
+}
+
+/// Keys are managed by the user they belong to, never through another key.
+fn key_owner(claims: &Claims) -> ServerResult<ObjectId> {
+    match (claims.user_id, &claims.api_key_id) {
+        (Some(user_id), None) => Ok(user_id),
+        _ => Err(ServerError::forbidden(
+            "API keys can only be managed by signed-in users",
+        )),
+    }
+}
+
+// --------------------
+// GET /api-keys
+// --------------------
+
+async fn list_api_keys(
+    claims: Claims,
+    State(state): State<AppState>,
+) -> ServerAppResult<Vec<ApiKeyInfo>> {
+    let owner_id = key_owner(&claims)?;
+
+    let keys = ApiKeyDoc::list_for_owner(&state.db, owner_id).await?;
+    let roles =
+        RoleDoc::list_for_references(&state.db, keys.iter().map(|k| k.key_id.clone()).collect())
+            .await?;
+
+    Ok(ServerResponse::builder()
+        .body(keys.iter().map(|k| k.to_api_key_info(&roles)).collect())
+        .status_code(axum::http::StatusCode::OK)
+        .build())
+}
+
+// --------------------
+// POST /api-keys
+// --------------------
+
+async fn create_api_key(
+    claims: Claims,
+    State(state): State<AppState>,
+    Json(payload): Json<CreateApiKeyBody>,
+) -> ServerAppResult<CreatedApiKey> {
+    let owner_id = key_owner(&claims)?;
+
+    let name = payload.name.trim();
+    if name.is_empty() {
+        return Err(ServerError::bad_request("API key name must not be empty"));
+    }
+
+    // A key never gets more than its creator holds on the scope.
+    let scope = match (&payload.org_id, &payload.device_id) {
+        (Some(_), Some(_)) => {
+            return Err(ServerError::bad_request(
+                "An API key is scoped to an org or a device, not both",
+            ));
+        }
+        (Some(org_id), None) => {
+            let scope = org::org_scope(org_id);
+            if !claims.has_scope_and_role(&scope, payload.role.clone()) {
+                return Err(ServerError::forbidden("Not authorized for organization"));
+            }
+            scope
+        }
+        (None, Some(device_id)) => {
+            let oid = ObjectId::parse_str(device_id)
+                .map_err(|_| ServerError::bad_request("Invalid device id"))?;
+            claims
+                .find_one_with_scope_and_role::<DeviceDoc>(
+                    &state.db.devices(),
+                    doc! { "_id": oid },
+                    payload.role.clone(),
+                )
+                .await?
+                .ok_or_else(|| ServerError::not_found("Device not found"))?;
+            DeviceDoc::scope_for_device(&oid)
+        }
+        (None, None) => UserDoc::create_reference_id(&claims.user_email),
+    };
+
+    let ttl_secs = payload
+        .ttl_secs
+        .map(|secs| i64::try_from(secs).map_err(|_| ServerError::bad_request("Invalid expiry")))
+        .transpose()?;
+    let (key_doc, api_key) = ApiKeyDoc::create(
+        &state.db,
+        CreateApiKey {
+            name: name.to_string(),
+            ttl_secs,
+            scopes: vec![(scope.clone(), payload.role.clone())],
+            owner_id: Some(owner_id),
+            owner_email: Some(claims.user_email.clone()),
+        },
+    )
+    .await?;
+    let roles = RoleDoc::list_for_reference(&state.db, &key_doc.key_id).await?;
+    let key = key_doc.to_api_key_info(&roles);
+
+    let _ = AuditLogDoc::add(
+        &state.db,
+        &claims,
+        &state.config,
+        "Created API key",
+        &format!(
+            "key={} name={} scope={} role={}",
+            key.key_id,
+            key.name,
+            key.scope,
+            key.role.to_string()
+        ),
+        None,
+    )
+    .await;
+
+    Ok(ServerResponse::builder()
+        .body(CreatedApiKey { key, api_key })
+        .status_code(axum::http::StatusCode::CREATED)
+        .build())
+}
+
+// --------------------
+// DELETE /api-keys/{key_id}
+// --------------------
+
+async fn revoke_api_key(
+    claims: Claims,
+    State(state): State<AppState>,
+    Path(key_id): Path<String>,
+) -> ServerAppResult<()> {
+    let owner_id = key_owner(&claims)?;
+
+    if !ApiKeyDoc::revoke(&state.db, owner_id, &key_id).await? {
+        return Err(ServerError::not_found("API key not found"));
+    }
+
+    let _ = AuditLogDoc::add(
+        &state.db,
+        &claims,
+        &state.config,
+        "Revoked API key",
+        &format!("key={}", key_id),
+        None,
+    )
+    .await;
+
+    Ok(ServerResponse::builder()
+        .status_code(axum::http::StatusCode::NO_CONTENT)
+        .build())
+}
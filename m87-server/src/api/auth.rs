@@ -4,6 +4,7 @@ use axum::{
     routing::{get, post},
 };
 use mongodb::bson::doc;
+use mongodb::bson::oid::ObjectId;
 use tokio::join;
 
 use crate::auth::claims::Claims;
@@ -108,24 +109,37 @@ async fn check_auth_request(
                 format!("device:{}", request.device_id.clone()),
                 Role::Editor,
             )],
+            owner_id: None,
+            owner_email: None,
         },
     )
     .await?;
 
-    // request approved -> create device + API key, then delete request
-    let _ = DeviceDoc::create_from(
+    // request approved -> create device + API key, then delete request.
+    // A known device (e.g. revoked) keeps its history and gets the new key.
+    let device_oid = ObjectId::parse_str(&request.device_id)?;
+    let reinstated = DeviceDoc::reinstate(
         &state.db,
-        CreateDeviceBody {
-            id: Some(request.device_id.clone()),
-            name: request.device_info.hostname.clone(),
-            owner_scope: request.owner_scope.clone(),
-            allowed_scopes: vec![],
-            target_version: Some("latest".to_string()),
-            api_key_id: api_key_doc.id.clone().unwrap(),
-            system_info: request.device_info.clone(),
-        },
+        device_oid,
+        api_key_doc.id.unwrap(),
+        &request.device_info,
     )
     .await?;
+    if !reinstated {
+        DeviceDoc::create_from(
+            &state.db,
+            CreateDeviceBody {
+                id: Some(request.device_id.clone()),
+                name: request.device_info.hostname.clone(),
+                owner_scope: request.owner_scope.clone(),
+                allowed_scopes: vec![],
+                target_version: Some("latest".to_string()),
+                api_key_id: api_key_doc.id.unwrap(),
+                system_info: request.device_info.clone(),
+            },
+        )
+        .await?;
+    }
 
     Ok(ServerResponse::builder()
         .body(DeviceAuthRequestCheckResponse {
@@ -143,13 +157,36 @@ async fn handle_auth_request(
 ) -> ServerAppResult<()> {
     let requests_col = state.db.device_auth_requests();
 
-    let _ = claims
+    let request = claims
         .find_one_with_access(&requests_col, doc! { "request_id": &payload.request_id })
         .await?
         .ok_or_else(|| ServerError::not_found("Auth request not found"))?;
 
     match payload.accept {
         true => {
+            // Taking over a registered device needs admin rights on it, not
+            // just on the scope the request claims.
+            if let Ok(device_oid) = ObjectId::parse_str(&request.device_id)
+                && state
+                    .db
+                    .devices()
+                    .find_one(doc! { "_id": device_oid })
+                    .await?
+                    .is_some()
+                && claims
+                    .find_one_with_scope_and_role::<DeviceDoc>(
+                        &state.db.devices(),
+                        doc! { "_id": device_oid },
+                        Role::Admin,
+                    )
+                    .await?
+                    .is_none()
+            {
+                return Err(ServerError::forbidden(
+                    "Device is already registered; approving requires admin access to it",
+                ));
+            }
+
             // Update request to mark as approved
             claims
                 .update_one_with_access(
@@ -1,8 +1,13 @@
+use std::collections::BTreeMap;
+
 use axum::extract::{Path, Query, State};
-use axum::routing::{delete, get, post};
+use axum::routing::{delete, get, post, put};
 use axum::{Json, Router};
 use m87_shared::deploy_spec::{FailureAggQuery, FailureAggResponse};
-use m87_shared::device::{AddDeviceAccessBody, AuditLog, DeviceStatus};
+use m87_shared::device::{
+    AddDeviceAccessBody, AuditLog, DeviceStatus, SessionRecording, UpdateDeviceLabelsBody,
+};
+use m87_shared::metrics::{UnitMetrics, UnitMetricsQuery};
 use m87_shared::roles::Role;
 use m87_shared::users::User;
 use mongodb::bson::doc;
@@ -10,10 +15,12 @@ use mongodb::bson::oid::ObjectId;
 
 use crate::api::deploy_spec::create_route as deploy_spec_route;
 use crate::auth::claims::Claims;
-use crate::models::audit_logs::AuditLogDoc;
+use crate::models::audit_logs::{AuditLogDoc, AuditLogFilter};
 use crate::models::deploy_spec::{DeployReportDoc, DeployRevisionDoc};
-use crate::models::device::{DeviceDoc, PublicDevice, UpdateDeviceBody};
+use crate::models::device::{DeviceDoc, PublicDevice, UpdateDeviceBody, labels_update_doc};
 use crate::models::org;
+use crate::models::session_recording::SessionRecordingDoc;
+use crate::models::unit_metrics::UnitMetricsDoc;
 use crate::response::{ResponsePagination, ServerAppResult, ServerError, ServerResponse};
 use crate::util::app_state::AppState;
 use crate::util::pagination::RequestPagination;
@@ -31,6 +38,10 @@ pub fn create_route() -> Router<AppState> {
         .route("/{id}/failure_agg", get(get_device_failure_agg))
         .route("/statuses", get(get_all_device_statuses))
         .route("/{id}/audit_logs", get(get_audit_logs_by_device_id))
+        .route("/{id}/sessions/{session_id}", get(get_session_recording))
+        .route("/{id}/units/{unit}/metrics", get(get_unit_metrics))
+        .route("/{id}/labels", put(update_device_labels))
+        .route("/{id}/revoke", post(revoke_device))
         .route("/{id}/users", get(get_device_users))
         .route("/{id}/access", post(add_device_access))
         .route(
@@ -58,9 +69,10 @@ async fn get_devices(
     }
 
     let mut devices = DeviceDoc::to_public_devices(device_map);
-    // for each check if state.relay.has_tunnel
+    let ids: Vec<String> = devices.iter().map(|d| d.short_id.clone()).collect();
+    let online = state.relay.online_devices(&ids).await;
     for device in &mut devices {
-        if state.relay.has_tunnel(&device.short_id).await {
+        if online.contains(&device.short_id) {
             device.online = true;
         }
     }
@@ -100,6 +112,23 @@ async fn get_device_by_id(
         .build())
 }
 
+async fn get_unit_metrics(
+    claims: Claims,
+    State(state): State<AppState>,
+    Path((id, unit)): Path<(String, String)>,
+    Query(query): Query<UnitMetricsQuery>,
+) -> ServerAppResult<Vec<UnitMetrics>> {
+    let device_id =
+        ObjectId::parse_str(&id).map_err(|_| ServerError::bad_request("Invalid ObjectId"))?;
+    claims
+        .find_one_with_access(&state.db.devices(), doc! { "_id": device_id })
+        .await?
+        .ok_or_else(|| ServerError::not_found("Device not found"))?;
+
+    let samples = UnitMetricsDoc::query(&state.db, device_id, &unit, &query).await?;
+    Ok(ServerResponse::builder().body(samples).ok().build())
+}
+
 async fn update_device_by_id(
     claims: Claims,
     State(state): State<AppState>,
@@ -154,6 +183,79 @@ async fn update_device_by_id(
         .build())
 }
 
+async fn update_device_labels(
+    claims: Claims,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(payload): Json<UpdateDeviceLabelsBody>,
+) -> ServerAppResult<BTreeMap<String, String>> {
+    let device_id =
+        ObjectId::parse_str(&id).map_err(|_| ServerError::bad_request("Invalid ObjectId"))?;
+    let update_doc = labels_update_doc(&payload)?;
+
+    claims
+        .update_one_with_access(&state.db.devices(), doc! { "_id": device_id }, update_doc)
+        .await?;
+
+    let device = claims
+        .find_one_with_access(&state.db.devices(), doc! { "_id": device_id })
+        .await?
+        .ok_or_else(|| ServerError::not_found("Device not found after update"))?;
+
+    let _ = AuditLogDoc::add(
+        &state.db,
+        &claims,
+        &state.config,
+        "Updated device labels",
+        &format!("set={:?} remove={:?}", payload.set, payload.remove),
+        Some(device_id),
+    )
+    .await;
+
+    Ok(ServerResponse::builder()
+        .body(device.labels)
+        .status_code(axum::http::StatusCode::OK)
+        .build())
+}
+
+async fn revoke_device(
+    claims: Claims,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> ServerAppResult<()> {
+    let device_oid =
+        ObjectId::parse_str(&id).map_err(|_| ServerError::bad_request("Invalid ObjectId"))?;
+    let device = claims
+        .find_one_with_scope_and_role::<DeviceDoc>(
+            &state.db.devices(),
+            doc! { "_id": device_oid },
+            Role::Admin,
+        )
+        .await?
+        .ok_or_else(|| ServerError::not_found("Device not found"))?;
+
+    device.revoke(&state.db).await?;
+    // Tunnels held by other replicas are closed by their session sweep.
+    let closed = state
+        .relay
+        .close_tunnel(&device.short_id, b"device-revoked")
+        .await;
+
+    let _ = AuditLogDoc::add(
+        &state.db,
+        &claims,
+        &state.config,
+        "Revoked device credentials",
+        &format!("tunnel_closed={}", closed),
+        Some(device_oid),
+    )
+    .await;
+
+    Ok(ServerResponse::builder()
+        .status_code(axum::http::StatusCode::NO_CONTENT)
+        .build())
+}
+
 async fn delete_device(
     claims: Claims,
     State(state): State<AppState>,
@@ -194,6 +296,7 @@ async fn get_audit_logs_by_device_id(
     State(state): State<AppState>,
     Path(id): Path<String>,
     pagination: RequestPagination,
+    Query(filter): Query<AuditLogFilter>,
 ) -> ServerAppResult<Vec<AuditLog>> {
     let device_oid = ObjectId::parse_str(&id)?;
     let device_opt = claims
@@ -205,7 +308,8 @@ async fn get_audit_logs_by_device_id(
         .await?;
     let _ = device_opt.ok_or_else(|| ServerError::not_found("Device not found"))?;
 
-    let audit_logs = AuditLogDoc::list_for_device(&state.db, device_oid, &pagination).await?;
+    let audit_logs =
+        AuditLogDoc::list_for_device(&state.db, device_oid, &pagination, &filter).await?;
     let audit_logs: Vec<AuditLog> = audit_logs.iter().map(|log| log.to_audit_log()).collect();
 
     Ok(ServerResponse::builder()
@@ -214,6 +318,41 @@ async fn get_audit_logs_by_device_id(
         .build())
 }
 
+async fn get_session_recording(
+    claims: Claims,
+    State(state): State<AppState>,
+    Path((id, session_id)): Path<(String, String)>,
+) -> ServerAppResult<SessionRecording> {
+    let device_oid = ObjectId::parse_str(&id)?;
+    let device_opt = claims
+        .find_one_with_scope_and_role(
+            &state.db.devices(),
+            doc! { "_id": &device_oid },
+            Role::Admin,
+        )
+        .await?;
+    let _ = device_opt.ok_or_else(|| ServerError::not_found("Device not found"))?;
+
+    let recording = SessionRecordingDoc::find_for_device(&state.db, device_oid, &session_id)
+        .await?
+        .ok_or_else(|| ServerError::not_found("Session recording not found"))?;
+
+    let _ = AuditLogDoc::add(
+        &state.db,
+        &claims,
+        &state.config,
+        "Viewed session recording",
+        &format!("session={}", session_id),
+        Some(device_oid),
+    )
+    .await;
+
+    Ok(ServerResponse::builder()
+        .body(recording.to_session_recording())
+        .status_code(axum::http::StatusCode::OK)
+        .build())
+}
+
 async fn get_device_status(
     claims: Claims,
     State(state): State<AppState>,
@@ -0,0 +1,64 @@
+use std::collections::HashMap;
+
+use axum::Router;
+use axum::extract::{Path, Query, State};
+use axum::routing::get;
+use futures::TryStreamExt;
+use m87_shared::device::{DeviceLogEntry, DeviceLogQuery};
+use m87_shared::roles::Role;
+use mongodb::bson::doc;
+
+use crate::auth::claims::Claims;
+use crate::models::device_log::DeviceLogDoc;
+use crate::models::org;
+use crate::response::{ServerAppResult, ServerError, ServerResponse};
+use crate::util::app_state::AppState;
+
+/// Mounted under `/organization` next to the org routes.
+pub fn create_route() -> Router<AppState> {
+    Router::new().route("/{id}/logs", get(query_logs))
+}
+
+// --------------------
+// GET /organization/{id}/logs
+// --------------------
+
+/// Shipped logs of the org's devices.
+async fn query_logs(
+    claims: Claims,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<DeviceLogQuery>,
+) -> ServerAppResult<Vec<DeviceLogEntry>> {
+    let scope = org::org_scope(&id);
+    if !claims.has_scope_and_role(&scope, Role::Viewer) {
+        return Err(ServerError::forbidden("Not authorized for organization"));
+    }
+
+    let mut filter = doc! { "allowed_scopes": &scope };
+    if let Some(device) = &query.device {
+        filter.insert("short_id", device);
+    }
+    let devices: HashMap<_, _> = state
+        .db
+        .devices()
+        .find(filter)
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?
+        .into_iter()
+        .filter_map(|d| Some((d.id?, (d.short_id, d.name))))
+        .collect();
+    if devices.is_empty() {
+        return Ok(ServerResponse::builder()
+            .body(Vec::new())
+            .status_code(axum::http::StatusCode::OK)
+            .build());
+    }
+
+    let logs = DeviceLogDoc::query(&state.db, &devices, &query).await?;
+    Ok(ServerResponse::builder()
+        .body(logs)
+        .status_code(axum::http::StatusCode::OK)
+        .build())
+}
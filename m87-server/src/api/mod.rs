@@ -1,8 +1,12 @@
+mod admin;
+mod alert;
+mod api_key;
 pub mod auth;
 mod certificate;
 mod client_connection;
 pub mod deploy_spec;
 pub mod device;
+mod device_log;
 mod org;
 mod quic;
 pub mod serve;
@@ -6,16 +6,20 @@ use mongodb::bson::{doc, oid::ObjectId};
 
 use m87_shared::device::PublicDevice;
 use m87_shared::org::{
-    AddDeviceBody, CreateOrganizationBody, InviteMemberBody, Organization, UpdateOrganizationBody,
+    AddDeviceBody, CreateOrganizationBody, CreateWebhookBody, CreatedWebhook, InviteMemberBody,
+    OrgSettings, Organization, UpdateOrgSettingsBody, UpdateOrganizationBody, Webhook,
+    WebhookDelivery,
 };
 use m87_shared::roles::Role;
 use m87_shared::users::User;
 
 use crate::auth::claims::Claims;
+use crate::models::alert::AlertRuleDoc;
 use crate::models::audit_logs::AuditLogDoc;
-use crate::models::org;
+use crate::models::org::{self, OrgSettingsDoc};
 use crate::models::roles::{CreateRoleBinding, RoleDoc};
 use crate::models::user::UserDoc;
+use crate::models::webhook::{WebhookDeliveryDoc, WebhookDoc};
 use crate::response::{ServerAppResult, ServerError, ServerResponse};
 use crate::util::app_state::AppState;
 
@@ -33,6 +37,16 @@ pub fn create_route() -> Router<AppState> {
         .route("/{id}/members/{member}", delete(remove_organization_member))
         .route("/{id}/devices", get(list_org_devices).post(add_org_device))
         .route("/{id}/devices/{device_id}", delete(remove_org_device))
+        .route(
+            "/{id}/settings",
+            get(get_org_settings).put(update_org_settings),
+        )
+        .route("/{id}/webhooks", get(list_webhooks).post(create_webhook))
+        .route("/{id}/webhooks/{webhook_id}", delete(delete_webhook))
+        .route(
+            "/{id}/webhooks/{webhook_id}/deliveries",
+            get(list_webhook_deliveries),
+        )
 }
 
 async fn list_organizations(claims: Claims) -> ServerAppResult<Vec<Organization>> {
@@ -153,6 +167,11 @@ async fn delete_organization(
         .delete_many(doc! { "reference_id": org::org_ref(&id) })
         .await?;
 
+    // 3) Drop org settings, webhooks and alert rules
+    OrgSettingsDoc::delete(&state.db, &id).await?;
+    WebhookDoc::delete_for_org(&state.db, &id).await?;
+    AlertRuleDoc::delete_for_org(&state.db, &id).await?;
+
     let _ = AuditLogDoc::add(
         &state.db,
         &claims,
@@ -221,6 +240,10 @@ async fn update_organization(
         )
         .await?;
 
+    OrgSettingsDoc::rename(&state.db, &id, new_id).await?;
+    WebhookDoc::rename_org(&state.db, &id, new_id).await?;
+    AlertRuleDoc::rename_org(&state.db, &id, new_id).await?;
+
     let _ = AuditLogDoc::add(
         &state.db,
         &claims,
@@ -342,8 +365,10 @@ async fn list_org_devices(
     let role = claims.get_role_for_scope(&scope).unwrap_or(Role::Viewer);
     let mut out: Vec<PublicDevice> = org::get_org_devices(&state.db, &id, &role).await?;
 
+    let ids: Vec<String> = out.iter().map(|d| d.short_id.clone()).collect();
+    let online = state.relay.online_devices(&ids).await;
     for d in &mut out {
-        if state.relay.has_tunnel(&d.short_id).await {
+        if online.contains(&d.short_id) {
             d.online = true;
         }
     }
@@ -435,3 +460,188 @@ async fn remove_org_device(
         .status_code(axum::http::StatusCode::NO_CONTENT)
         .build())
 }
+
+// --------------------
+// GET /organizations/{id}/settings
+// --------------------
+
+async fn get_org_settings(
+    claims: Claims,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> ServerAppResult<OrgSettings> {
+    let scope = org::org_scope(&id);
+    if !claims.has_scope_and_role(&scope, Role::Viewer) {
+        return Err(ServerError::forbidden("Not authorized for organization"));
+    }
+
+    let settings = OrgSettingsDoc::get(&state.db, &id).await?;
+
+    Ok(ServerResponse::builder()
+        .body(settings.to_org_settings())
+        .status_code(axum::http::StatusCode::OK)
+        .build())
+}
+
+// --------------------
+// PUT /organizations/{id}/settings
+// --------------------
+
+async fn update_org_settings(
+    claims: Claims,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(payload): Json<UpdateOrgSettingsBody>,
+) -> ServerAppResult<OrgSettings> {
+    let scope = org::org_scope(&id);
+    if !claims.has_scope_and_role(&scope, Role::Admin) {
+        return Err(ServerError::forbidden("Not authorized for organization"));
+    }
+
+    if let Some(record_sessions) = payload.record_sessions {
+        OrgSettingsDoc::set_record_sessions(&state.db, &id, record_sessions).await?;
+        let _ = AuditLogDoc::add(
+            &state.db,
+            &claims,
+            &state.config,
+            "Updated organization settings",
+            &format!("id={} record_sessions={}", id, record_sessions),
+            None,
+        )
+        .await;
+    }
+
+    let settings = OrgSettingsDoc::get(&state.db, &id).await?;
+
+    Ok(ServerResponse::builder()
+        .body(settings.to_org_settings())
+        .status_code(axum::http::StatusCode::OK)
+        .build())
+}
+
+// --------------------
+// GET /organizations/{id}/webhooks
+// --------------------
+
+async fn list_webhooks(
+    claims: Claims,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> ServerAppResult<Vec<Webhook>> {
+    let scope = org::org_scope(&id);
+    if !claims.has_scope_and_role(&scope, Role::Viewer) {
+        return Err(ServerError::forbidden("Not authorized for organization"));
+    }
+
+    let hooks = WebhookDoc::list_for_org(&state.db, &id).await?;
+
+    Ok(ServerResponse::builder()
+        .body(hooks.iter().map(WebhookDoc::to_webhook).collect())
+        .status_code(axum::http::StatusCode::OK)
+        .build())
+}
+
+// --------------------
+// POST /organizations/{id}/webhooks
+// --------------------
+
+async fn create_webhook(
+    claims: Claims,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(payload): Json<CreateWebhookBody>,
+) -> ServerAppResult<CreatedWebhook> {
+    let scope = org::org_scope(&id);
+    if !claims.has_scope_and_role(&scope, Role::Admin) {
+        return Err(ServerError::forbidden("Not authorized for organization"));
+    }
+
+    let hook = WebhookDoc::create(&state.db, &state.config, &id, payload).await?;
+    let webhook = hook.to_webhook();
+
+    let _ = AuditLogDoc::add(
+        &state.db,
+        &claims,
+        &state.config,
+        "Created organization webhook",
+        &format!("id={} webhook={} url={}", id, webhook.id, webhook.url),
+        None,
+    )
+    .await;
+
+    Ok(ServerResponse::builder()
+        .body(CreatedWebhook {
+            webhook,
+            secret: hook.secret,
+        })
+        .status_code(axum::http::StatusCode::CREATED)
+        .build())
+}
+
+// --------------------
+// DELETE /organizations/{id}/webhooks/{webhook_id}
+// --------------------
+
+async fn delete_webhook(
+    claims: Claims,
+    State(state): State<AppState>,
+    Path((id, webhook_id)): Path<(String, String)>,
+) -> ServerAppResult<()> {
+    let scope = org::org_scope(&id);
+    if !claims.has_scope_and_role(&scope, Role::Admin) {
+        return Err(ServerError::forbidden("Not authorized for organization"));
+    }
+
+    let oid = ObjectId::parse_str(&webhook_id)
+        .map_err(|_| ServerError::bad_request("Invalid webhook id"))?;
+    if !WebhookDoc::delete(&state.db, &id, oid).await? {
+        return Err(ServerError::not_found("Webhook not found"));
+    }
+
+    let _ = AuditLogDoc::add(
+        &state.db,
+        &claims,
+        &state.config,
+        "Deleted organization webhook",
+        &format!("id={} webhook={}", id, webhook_id),
+        None,
+    )
+    .await;
+
+    Ok(ServerResponse::builder()
+        .status_code(axum::http::StatusCode::NO_CONTENT)
+        .build())
+}
+
+// --------------------
+// GET /organizations/{id}/webhooks/{webhook_id}/deliveries
+// --------------------
+
+async fn list_webhook_deliveries(
+    claims: Claims,
+    State(state): State<AppState>,
+    Path((id, webhook_id)): Path<(String, String)>,
+) -> ServerAppResult<Vec<WebhookDelivery>> {
+    let scope = org::org_scope(&id);
+    if !claims.has_scope_and_role(&scope, Role::Viewer) {
+        return Err(ServerError::forbidden("Not authorized for organization"));
+    }
+
+    let oid = ObjectId::parse_str(&webhook_id)
+        .map_err(|_| ServerError::bad_request("Invalid webhook id"))?;
+    WebhookDoc::find(&state.db, &id, oid)
+        .await?
+        .ok_or_else(|| ServerError::not_found("Webhook not found"))?;
+
+    let deliveries = WebhookDeliveryDoc::list_for_webhook(&state.db, oid, 100).await?;
+
+    Ok(ServerResponse::builder()
+        .body(
+            deliveries
+                .iter()
+                .map(WebhookDeliveryDoc::to_webhook_delivery)
+                .collect(),
+        )
+        .status_code(axum::http::StatusCode::OK)
+        .build())
+}
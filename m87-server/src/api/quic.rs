@@ -1,11 +1,12 @@
-use futures::future::{AbortHandle, Abortable};
 use governor::{Quota, RateLimiter};
 use m87_shared::heartbeat::HeartbeatRequest;
+use m87_shared::org::WebhookEvent;
 use m87_shared::roles::Role;
-use mongodb::bson::doc;
+use mongodb::bson::{DateTime, doc};
 use quinn::{ConnectionError, Endpoint};
 use serde::Serialize;
 use serde::de::DeserializeOwned;
+use serde_json::json;
 use std::net::SocketAddr;
 use std::num::NonZeroU32;
 use std::sync::Arc;
@@ -17,11 +18,17 @@ use tracing::{debug, error, info, warn};
 
 use crate::api::client_connection::ClientConn;
 use crate::auth::claims::Claims;
+use crate::metrics;
 use crate::models::audit_logs::AuditLogDoc;
 use crate::models::device::DeviceDoc;
+use crate::relay::peer::{unix_now, verify_relay_token};
+use crate::relay::recording;
+use crate::relay::relay_state::TunnelRoute;
+use crate::relay::stream_audit::{self, ConnectionAudit, ForwardAudit};
 use crate::response::ServerError;
 use crate::response::ServerResult;
 use crate::util::app_state::AppState;
+use crate::webhooks;
 
 const AUTH_TIMEOUT: Duration = Duration::from_secs(5);
 const MAX_TOKEN_LEN: usize = 4096;
@@ -147,6 +154,13 @@ async fn handle_quic_connection(conn: quinn::Connection, state: AppState) -> Ser
         conn.close(0x100u32.into(), b"missing-token");
         return Err(ServerError::missing_token("missing api key or token"));
     };
+
+    // Node-to-node forward from another replica (authenticated by relay token)
+    if let Some(device_id) = extract_device_id_from_relay_sni(&sni, public) {
+        info!(%sni, "relay connection from peer node");
+        return handle_relay_forward(conn, &device_id, &token, state).await;
+    }
+
     let claims = Claims::from_bearer_or_key(&token, &state.db, &state.config).await?;
 
     if let Some(device_id) = extract_device_id_from_control_sni(&sni, public) {
@@ -167,7 +181,7 @@ async fn handle_quic_connection(conn: quinn::Connection, state: AppState) -> Ser
             .await;
         // .await?
         // .ok_or_else(|| ServerError::not_found("Device not found"))?;
-        match res {
+        let device = match res {
             Ok(Some(device)) => {
                 let _ = AuditLogDoc::add(
                     &state.db,
@@ -178,6 +192,7 @@ async fn handle_quic_connection(conn: quinn::Connection, state: AppState) -> Ser
                     device.id.clone(),
                 )
                 .await;
+                device
             }
             Ok(None) => {
                 let _ = AuditLogDoc::add(
@@ -209,9 +224,21 @@ async fn handle_quic_connection(conn: quinn::Connection, state: AppState) -> Ser
 
         if state.relay.has_tunnel(&device_id).await {
             debug!(%device_id, "forwarding to device");
-            let _ =
-                handle_forward_supervised(ClientConn::Raw(conn), device_id.clone(), state.clone())
-                    .await;
+            let audit = ForwardAudit::for_device(&state, &claims, &device).await;
+            let conn_id = conn.stable_id();
+            if let Some(user_id) = claims.user_id {
+                state.relay.add_client_session(user_id, conn.clone()).await;
+            }
+            let _ = handle_forward_supervised(
+                ClientConn::Raw(conn),
+                device_id.clone(),
+                state.clone(),
+                audit,
+            )
+            .await;
+            if let Some(user_id) = claims.user_id {
+                state.relay.remove_client_session(user_id, conn_id).await;
+            }
         } else {
             warn!(%device_id, "no tunnel registered for device");
             // print all tunnel ids
@@ -244,6 +271,19 @@ fn extract_device_id_from_control_sni(sni: &str, public_domain: &str) -> Option<
     None
 }
 
+fn extract_device_id_from_relay_sni(sni: &str, public_domain: &str) -> Option<String> {
+    // Expected pattern (peer replicas only):
+    //   "relay-<deviceid>.<public_domain>"
+    let short_id = sni
+        .strip_prefix("relay-")?
+        .strip_suffix(public_domain)?
+        .trim_end_matches('.');
+    if short_id.is_empty() {
+        return None;
+    }
+    Some(short_id.to_string())
+}
+
 fn extract_device_id_from_sni(sni: &str, public_domain: &str) -> Option<String> {
     // Expected patterns:
     //   "<deviceid>.<public_domain>"
@@ -268,7 +308,7 @@ async fn handle_control_tunnel(
 ) -> ServerResult<()> {
     let device_id = device_short_id.to_string();
 
-    claims
+    let device = claims
         .find_one_with_scope_and_role::<DeviceDoc>(
             &state.db.devices(),
             doc! { "short_id": &device_id },
@@ -276,9 +316,20 @@ async fn handle_control_tunnel(
         )
         .await?
         .ok_or_else(|| ServerError::not_found("Device not found"))?;
+    if device.revoked_at.is_some() {
+        conn.close(0u32.into(), b"device-revoked");
+        return Err(ServerError::unauthorized("device revoked"));
+    }
 
     // NOW publish as active tunnel
-    state.relay.replace_tunnel(&device_id, conn.clone()).await;
+    if state.relay.replace_tunnel(&device_id, conn.clone()).await {
+        webhooks::notify_device(
+            &state.db,
+            &device,
+            WebhookEvent::DeviceOnline,
+            json!({ "remote_address": conn.remote_address().to_string() }),
+        );
+    }
 
     let (shutdown_tx, shutdown_rx) = watch::channel(false);
     // Connection owner: only place that closes conn / awaits conn.closed()
@@ -305,10 +356,19 @@ async fn handle_control_tunnel(
         }
     }
 
-    state
+    if state
         .relay
         .remove_if_match(&device_id, conn.stable_id())
-        .await;
+        .await
+    {
+        let reason = conn.close_reason().map(|r| r.to_string());
+        webhooks::notify_device(
+            &state.db,
+            &device,
+            WebhookEvent::DeviceOffline,
+            json!({ "reason": reason }),
+        );
+    }
 
     Ok(())
 }
@@ -363,7 +423,14 @@ async fn run_heartbeat_loop(
                     break;
                 };
 
-                let body = device.handle_heartbeat(claims.clone(), &state.db, req, &state.config).await?;
+                let body = if req.is_log_batch_only() {
+                    device.handle_log_batch(&state.db, req, &state.config).await
+                } else {
+                    let started = Instant::now();
+                    let body = device.handle_heartbeat(claims.clone(), &state.db, req, &state.config).await?;
+                    metrics::record_heartbeat(started.elapsed());
+                    body
+                };
 
                 info!("sending heartbeat response");
                 match write_msg(&mut send, &body).await {
@@ -382,20 +449,83 @@ async fn run_heartbeat_loop(
     Ok(())
 }
 
+/// Serve a forward for a peer replica whose client landed there while the
+/// device's tunnel is held here. Runs a single session: if the device drops,
+/// the peer re-resolves the owner itself, as the device may reconnect to any node.
+async fn handle_relay_forward(
+    conn: quinn::Connection,
+    device_id: &str,
+    token: &str,
+    state: AppState,
+) -> ServerResult<()> {
+    let Some(registry) = state.relay.registry() else {
+        conn.close(0x101u32.into(), b"relay-disabled");
+        return Err(ServerError::unauthorized("cross-node relay is not enabled"));
+    };
+    let Some(peer_node) = verify_relay_token(registry.relay_secret(), token, device_id, unix_now())
+    else {
+        conn.close(0x101u32.into(), b"invalid-relay-token");
+        return Err(ServerError::invalid_token("invalid relay token"));
+    };
+
+    let Some(device_conn) = state.relay.get_tunnel(device_id).await else {
+        warn!(%device_id, %peer_node, "relay requested but no local tunnel");
+        conn.close(0u32.into(), b"No tunnel");
+        return Ok(());
+    };
+
+    // The node the client is connected to records sessions, not this one.
+    debug!(%device_id, %peer_node, "serving relay forward");
+    let _ = handle_forward_once(&ClientConn::Raw(conn), &device_conn, device_id, None).await;
+    Ok(())
+}
+
 enum ForwardEnd {
     ClientClosed,
     DeviceClosed,
 }
 
+impl ForwardEnd {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ForwardEnd::ClientClosed => "client_closed",
+            ForwardEnd::DeviceClosed => "device_closed",
+        }
+    }
+}
+
+/// Connection to forward device streams over: the device's own tunnel, or a
+/// node-to-node connection to the replica that holds it.
+struct DeviceLink {
+    conn: quinn::Connection,
+    via_peer: bool,
+}
+
 async fn wait_for_device_conn(
     state: &AppState,
     device_id: &str,
     timeout: Duration,
-) -> Option<quinn::Connection> {
+) -> Option<DeviceLink> {
     let start = Instant::now();
     loop {
-        if let Some(conn) = state.relay.get_tunnel(device_id).await {
-            return Some(conn);
+        match state.relay.route(device_id).await {
+            Some(TunnelRoute::Local(conn)) => {
+                return Some(DeviceLink {
+                    conn,
+                    via_peer: false,
+                });
+            }
+            Some(TunnelRoute::Remote(owner)) => {
+                if let Some(registry) = state.relay.registry()
+                    && let Some(conn) = registry.connect_to_owner(device_id, &owner).await
+                {
+                    return Some(DeviceLink {
+                        conn,
+                        via_peer: true,
+                    });
+                }
+            }
+            None => {}
         }
         if start.elapsed() >= timeout {
             return None;
@@ -408,13 +538,13 @@ pub async fn handle_forward_supervised(
     client_conn: ClientConn,
     device_id: String,
     state: AppState,
+    audit: Arc<ForwardAudit>,
 ) -> io::Result<()> {
     const RECONNECT_TIMEOUT: Duration = Duration::from_secs(45);
 
     loop {
         // wait (with timeout) for a device tunnel
-        let Some(device_conn) = wait_for_device_conn(&state, &device_id, RECONNECT_TIMEOUT).await
-        else {
+        let Some(device) = wait_for_device_conn(&state, &device_id, RECONNECT_TIMEOUT).await else {
             warn!(%device_id, "device did not reconnect within timeout, closing forward");
             return Err(io::Error::new(
                 io::ErrorKind::NotConnected,
@@ -422,10 +552,13 @@ pub async fn handle_forward_supervised(
             ));
         };
 
-        debug!(%device_id, "starting forward session");
-        match handle_forward_once(&client_conn, &device_conn, &device_id).await {
+        debug!(%device_id, via_peer = device.via_peer, "starting forward session");
+        match handle_forward_once(&client_conn, &device.conn, &device_id, Some(&audit)).await {
             ForwardEnd::ClientClosed => {
                 debug!(%device_id, "client closed, ending supervised forward");
+                if device.via_peer {
+                    device.conn.close(0u32.into(), b"client-closed");
+                }
                 return Ok(());
             }
             ForwardEnd::DeviceClosed => {
@@ -437,13 +570,16 @@ pub async fn handle_forward_supervised(
 }
 
 const MAX_PARALLEL_STREAMS: usize = 128;
+const STREAM_DRAIN_TIMEOUT: Duration = Duration::from_secs(10);
 
 async fn handle_forward_once(
     client_conn: &ClientConn,
     device_conn: &quinn::Connection,
     device_id: &str,
+    audit: Option<&Arc<ForwardAudit>>,
 ) -> ForwardEnd {
     let active_streams = Arc::new(tokio::sync::Semaphore::new(MAX_PARALLEL_STREAMS));
+    let connection_audit = Arc::new(ConnectionAudit::default());
     spawn_udp_bridge(
         client_conn.clone(),
         device_conn.clone(),
@@ -458,17 +594,17 @@ async fn handle_forward_once(
 
     debug!("handle_forward_once: starting");
 
-    loop {
+    let end = loop {
         tokio::select! {
 
             _ = &mut client_closed_fut => {
                 debug!("handle_forward_once: client closed");
-                return ForwardEnd::ClientClosed;
+                break ForwardEnd::ClientClosed;
             }
 
             _ = &mut device_closed_fut => {
                 warn!("handle_forward_once: device connection closed");
-                return ForwardEnd::DeviceClosed;
+                break ForwardEnd::DeviceClosed;
             }
 
             client_bi = client_conn.accept_bi() => {
@@ -485,19 +621,22 @@ async fn handle_forward_once(
                     Ok(s) => s,
                     Err(e) => {
                         warn!("client accept_bi failed: {e:?}");
-                        return ForwardEnd::ClientClosed;
+                        break ForwardEnd::ClientClosed;
                     }
                 };
 
                 let dev_conn = device_conn.clone();
                 let device_id = device_id.to_string();
+                let audit = audit.cloned();
+                let connection_audit = connection_audit.clone();
 
                 tokio::spawn(async move {
                     let _permit = permit;
+                    let started_at = DateTime::now();
 
                     debug!("forward: opening device stream");
 
-                    let (mut dev_send, mut dev_recv) = match dev_conn.open_bi().await {
+                    let (mut dev_send, dev_recv) = match dev_conn.open_bi().await {
                         Ok(s) => s,
                         Err(e) => {
                             warn!("device open_bi failed: {e:?}");
@@ -506,35 +645,87 @@ async fn handle_forward_once(
                         }
                     };
 
-                    let (abort_uplink, reg_up) = AbortHandle::new_pair();
-                    let (abort_down, reg_dn) = AbortHandle::new_pair();
-
-                    let uplink = tokio::spawn(Abortable::new(async move {
-                        let r = tokio::io::copy(&mut client_recv, &mut dev_send).await;
-                        let _ = dev_send.finish();
-                        r
-                    }, reg_up));
+                    // Peer relay: the node the client is connected to audits.
+                    let Some(audit) = audit else {
+                        stream_audit::bridge(client_send, client_recv, dev_send, dev_recv, None)
+                            .await;
+                        debug!(%device_id, "stream bridge complete");
+                        return;
+                    };
 
-                    let downlink = tokio::spawn(Abortable::new(async move {
-                        let r = tokio::io::copy(&mut dev_recv, &mut client_send).await;
-                        let _ = client_send.shutdown().await;
-                        r
-                    }, reg_dn));
+                    let header = match stream_audit::forward_header(
+                        &mut client_recv,
+                        &mut dev_send,
+                        audit.record_sessions,
+                    )
+                    .await
+                    {
+                        Ok(h) => h,
+                        Err(e) => {
+                            warn!(%device_id, "failed to forward stream header: {e:?}");
+                            return;
+                        }
+                    };
 
-                    tokio::select! {
-                        _ = uplink => {
-                            abort_down.abort();
+                    let is_p2p = header.info.as_ref().is_some_and(|i| i.stream_type == "p2p");
+                    let traffic = match header.session {
+                        // Streams of the direct session are reported back on this one.
+                        None if is_p2p => {
+                            let connection_audit = connection_audit.clone();
+                            stream_audit::bridge_direct(
+                                client_send,
+                                client_recv,
+                                dev_send,
+                                dev_recv,
+                                move |info, started_at, traffic| {
+                                    metrics::record_stream(Some(&info.stream_type), traffic);
+                                    connection_audit.record(info, started_at, traffic);
+                                },
+                            )
+                            .await
                         }
-                        _ = downlink => {
-                            abort_uplink.abort();
+                        Some(kind) => {
+                            recording::bridge_recorded(
+                                &audit, kind, client_send, client_recv, dev_send, dev_recv,
+                            )
+                            .await
                         }
+                        None => {
+                            stream_audit::bridge(client_send, client_recv, dev_send, dev_recv, None)
+                                .await
+                        }
+                    };
+                    metrics::record_stream(
+                        header.info.as_ref().map(|i| i.stream_type.as_str()),
+                        traffic,
+                    );
+                    if let Some(info) = header.info {
+                        connection_audit.record(info, started_at, traffic);
                     }
 
                     debug!(%device_id, "stream bridge complete");
                 });
             }
         }
+    };
+
+    if let Some(audit) = audit.cloned() {
+        let close_reason = end.as_str();
+        tokio::spawn(async move {
+            // Let in-flight streams finish (the connection is gone, so they
+            // end promptly) before writing their totals.
+            let _ = tokio::time::timeout(
+                STREAM_DRAIN_TIMEOUT,
+                active_streams.acquire_many(MAX_PARALLEL_STREAMS as u32),
+            )
+            .await;
+            audit
+                .write_events(connection_audit.take(), close_reason)
+                .await;
+        });
     }
+
+    end
 }
 
 const MAX_UDP_PAYLOAD: usize = 64 * 1024;
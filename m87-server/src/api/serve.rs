@@ -20,14 +20,15 @@ use tracing::{info, warn};
 
 use crate::{
     api::{
-        auth,
+        admin, alert, api_key, auth,
         certificate::{create_tls_config, update_cert},
-        device, org,
+        device, device_log, org,
         quic::run_quic_endpoint,
         web_transport::run_webtransport,
     },
     config::AppConfig,
     db::Mongo,
+    metrics,
     relay::relay_state::RelayState,
     response::ServerResult,
     util::app_state::AppState,
@@ -68,14 +69,25 @@ pub async fn serve(
     // Admin route: writes certs to disk + signals reload
     let admin = Router::new()
         .route("/update-cert", post(update_cert))
-        .layer(Extension(reload_tx.clone()));
+        .layer(Extension(reload_tx.clone()))
+        .merge(admin::create_route());
 
     let app = Router::new()
         .nest("/auth", auth::create_route())
         .nest("/device", device::create_route())
-        .nest("/organization", org::create_route())
+        .nest("/api-keys", api_key::create_route())
+        .nest(
+            "/organization",
+            org::create_route()
+                .merge(alert::create_route())
+                .merge(device_log::create_route()),
+        )
         .nest("/admin", admin)
         .route("/status", get(get_status))
+        .merge(match cfg.metrics_port {
+            Some(_) => Router::new(),
+            None => metrics::create_route(&state),
+        })
         .layer(cors)
         .layer(SetSensitiveHeadersLayer::new(std::iter::once(
             header::AUTHORIZATION,
@@ -125,6 +137,9 @@ pub async fn serve(
     // ===== QUIC SERVER =====
     let quic_task = tokio::spawn(run_quic_endpoint(state.clone(), reload_rx.clone()));
     let wt_task = tokio::spawn(run_webtransport(state.clone(), reload_rx.clone()));
+    if let Some(port) = cfg.metrics_port {
+        tokio::spawn(metrics::serve_separate(state.clone(), port));
+    }
     let _ = tokio::join!(https_task, quic_task, wt_task);
 
     Ok(())
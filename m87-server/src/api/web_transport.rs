@@ -20,6 +20,7 @@ use crate::{
     },
     auth::claims::Claims,
     models::{audit_logs::AuditLogDoc, device::DeviceDoc},
+    relay::stream_audit::ForwardAudit,
     response::{ServerError, ServerResult},
     util::app_state::AppState,
 };
@@ -203,7 +204,7 @@ async fn handle_webtransport_forward(
         )
         .await;
 
-    match res {
+    let device = match res {
         Ok(Some(device)) => {
             let _ = AuditLogDoc::add(
                 &state.db,
@@ -214,6 +215,7 @@ async fn handle_webtransport_forward(
                 device.id.clone(),
             )
             .await;
+            device
         }
         Ok(None) => {
             let _ = AuditLogDoc::add(
@@ -246,13 +248,32 @@ async fn handle_webtransport_forward(
     if !state.relay.has_tunnel(&device_id).await {
         return Err(ServerError::not_found("device tunnel not connected"));
     };
+    let audit = ForwardAudit::for_device(&state, &claims, &device).await;
     let web = WebConn::new(Arc::new(session), inner_conn.clone());
+    let user_id = claims.user_id;
+    if let Some(user_id) = user_id {
+        state
+            .relay
+            .add_client_session(user_id, inner_conn.clone())
+            .await;
+    }
     tokio::spawn(async move {
-        if let Err(e) =
-            handle_forward_supervised(ClientConn::Web(web), device_id.clone(), state.clone()).await
+        if let Err(e) = handle_forward_supervised(
+            ClientConn::Web(web),
+            device_id.clone(),
+            state.clone(),
+            audit,
+        )
+        .await
         {
             warn!(%device_id, "WT forward error: {:?}", e);
         }
+        if let Some(user_id) = user_id {
+            state
+                .relay
+                .remove_client_session(user_id, inner_conn.stable_id())
+                .await;
+        }
     });
 
     let _ = send.write_all(b"OK").await;
@@ -6,7 +6,7 @@ use futures::TryStreamExt;
 use headers::{Authorization, authorization::Bearer};
 use mongodb::{
     Collection,
-    bson::{Document, oid::ObjectId},
+    bson::{Document, doc, oid::ObjectId},
     options::FindOptions,
 };
 
@@ -14,6 +14,7 @@ use crate::{
     auth::access_control::{AccessControlled, ADMIN_WILDCARD_SCOPE},
     config::AppConfig,
     db::Mongo,
+    metrics,
     models::{
         api_key::ApiKeyDoc,
         roles::{Role, RoleDoc},
@@ -30,6 +31,8 @@ pub struct Claims {
     pub user_name: String,
     pub user_email: String,
     pub user_id: Option<ObjectId>,
+    /// Set when authenticated with an API key rather than as the user.
+    pub api_key_id: Option<String>,
 }
 
 impl FromRequestParts<AppState> for Claims {
@@ -42,7 +45,11 @@ impl FromRequestParts<AppState> for Claims {
         let TypedHeader(Authorization(bearer)) =
             TypedHeader::<Authorization<Bearer>>::from_request_parts(parts, state)
                 .await
-                .map_err(|_| ServerError::missing_token("missing API key"))?;
+                .map_err(|_| {
+                    let err = ServerError::missing_token("missing API key");
+                    metrics::record_auth_failure(&err);
+                    err
+                })?;
 
         let token = bearer.token();
         Claims::from_bearer_or_key(token, &state.db, &state.config).await
@@ -74,6 +81,18 @@ impl Claims {
         token: &str,
         db: &Arc<Mongo>,
         config: &Arc<AppConfig>,
+    ) -> ServerResult<Self> {
+        let claims = Self::authenticate(token, db, config).await;
+        if let Err(e) = &claims {
+            metrics::record_auth_failure(e);
+        }
+        claims
+    }
+
+    async fn authenticate(
+        token: &str,
+        db: &Arc<Mongo>,
+        config: &Arc<AppConfig>,
     ) -> ServerResult<Self> {
         let is_jwt = token.matches('.').count() == 2;
 
@@ -81,31 +100,21 @@ impl Claims {
             // Handle JWT
             let user = UserDoc::get_or_create(&token, db, config).await?;
 
+            if user.disabled {
+                return Err(ServerError::unauthorized("user disabled"));
+            }
             if !user.approved {
                 return Err(ServerError::unauthorized("user not approved"));
             }
 
-            let reference_id = user.get_reference_id();
-            let mut roles = RoleDoc::list_for_reference(db, &reference_id).await?;
-
-            roles.push(RoleDoc {
-                id: None,
-                reference_id: reference_id.clone(),
-                scope: reference_id.clone(),
-                role: Role::Owner,
-                created_at: None,
-            });
-
-            let is_admin = match &user.email {
-                Some(email) => config.admin_emails.contains(email),
-                None => false,
-            };
+            let (roles, is_admin) = Self::user_roles(&user, db, config).await?;
             Ok(Self {
                 roles,
                 is_admin,
                 user_name: user.name.clone().unwrap_or("unknown".to_string()),
                 user_email: user.email.clone().unwrap_or("unknown".to_string()),
                 user_id: user.id.clone(),
+                api_key_id: None,
             })
         } else {
             // check if token is config.admin_key
@@ -118,6 +127,7 @@ impl Claims {
                             user_name: "admin".to_string(),
                             user_email: "".to_string(),
                             user_id: None,
+                            api_key_id: None,
                         });
                     }
                 }
@@ -126,17 +136,57 @@ impl Claims {
 
             // Handle API key
             let key_doc = ApiKeyDoc::find_and_validate_key(db, token).await?;
-            let roles = RoleDoc::list_for_reference(db, &key_doc.key_id).await?;
+            let mut roles = RoleDoc::list_for_reference(db, &key_doc.key_id).await?;
+            // Personal and service keys stop working with their creator, and
+            // never hold more than the creator currently does on their scope.
+            if let Some(owner_id) = key_doc.owner_id {
+                let owner = db
+                    .users()
+                    .find_one(doc! { "_id": owner_id })
+                    .await?
+                    .filter(|u| u.approved && !u.disabled)
+                    .ok_or_else(|| ServerError::unauthorized("API key owner disabled"))?;
+                let (owner_roles, owner_is_admin) = Self::user_roles(&owner, db, config).await?;
+                if !owner_is_admin {
+                    roles = cap_to_owner_roles(db, roles, &owner_roles).await?;
+                }
+            }
             Ok(Self {
                 roles,
                 is_admin: false,
                 user_name: format!("API Key {}", key_doc.name),
-                user_email: "".to_string(),
-                user_id: None,
+                user_email: key_doc.owner_email.clone().unwrap_or_default(),
+                user_id: key_doc.owner_id,
+                api_key_id: Some(key_doc.key_id.clone()),
             })
         }
     }
 
+    /// Role bindings of a user, including the implicit ownership of their
+    /// own scope, and whether they are a server admin.
+    async fn user_roles(
+        user: &UserDoc,
+        db: &Arc<Mongo>,
+        config: &Arc<AppConfig>,
+    ) -> ServerResult<(Vec<RoleDoc>, bool)> {
+        let reference_id = user.get_reference_id();
+        let mut roles = RoleDoc::list_for_reference(db, &reference_id).await?;
+
+        roles.push(RoleDoc {
+            id: None,
+            reference_id: reference_id.clone(),
+            scope: reference_id.clone(),
+            role: Role::Owner,
+            created_at: None,
+        });
+
+        let is_admin = match &user.email {
+            Some(email) => config.admin_emails.contains(email),
+            None => false,
+        };
+        Ok((roles, is_admin))
+    }
+
     pub async fn find_one_with_access<T>(
         &self,
         coll: &Collection<T>,
@@ -345,3 +395,79 @@ impl Claims {
             .ok_or_else(|| ServerError::forbidden("Not found or access denied"))
     }
 }
+
+/// Lower each key binding to what the owner holds on the same scope right now
+/// and drop bindings the owner lost, so demoting or removing a user also
+/// demotes their keys. On a device scope the owner's roles on the orgs and
+/// users the device belongs to count as well.
+async fn cap_to_owner_roles(
+    db: &Arc<Mongo>,
+    key_roles: Vec<RoleDoc>,
+    owner_roles: &[RoleDoc],
+) -> ServerResult<Vec<RoleDoc>> {
+    let mut capped = Vec::with_capacity(key_roles.len());
+    for mut binding in key_roles {
+        let mut scopes = vec![binding.scope.clone()];
+        if let Some(device_id) = binding
+            .scope
+            .strip_prefix("device:")
+            .and_then(|id| ObjectId::parse_str(id).ok())
+            && let Some(device) = db.devices().find_one(doc! { "_id": device_id }).await?
+        {
+            scopes.push(device.owner_scope);
+            scopes.extend(device.allowed_scopes);
+        }
+        let Some(ceiling) = owner_role_on(owner_roles, &scopes) else {
+            continue;
+        };
+        if !Role::allows(&ceiling, &binding.role) {
+            binding.role = ceiling;
+        }
+        capped.push(binding);
+    }
+    Ok(capped)
+}
+
+/// Highest role among `roles` on any of `scopes`.
+fn owner_role_on(roles: &[RoleDoc], scopes: &[String]) -> Option<Role> {
+    roles
+        .iter()
+        .filter(|r| scopes.contains(&r.scope))
+        .map(|r| r.role.clone())
+        .max_by_key(Role::rank)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn binding(scope: &str, role: Role) -> RoleDoc {
+        RoleDoc {
+            id: None,
+            reference_id: "user:a@example.com".into(),
+            scope: scope.into(),
+            role,
+            created_at: None,
+        }
+    }
+
+    #[test]
+    fn owner_role_takes_highest_matching_scope() {
+        let roles = vec![
+            binding("org:a", Role::Viewer),
+            binding("org:b", Role::Admin),
+            binding("device:x", Role::Editor),
+        ];
+        let scopes = |s: &[&str]| s.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            owner_role_on(&roles, &scopes(&["org:a"])),
+            Some(Role::Viewer)
+        );
+        assert_eq!(
+            owner_role_on(&roles, &scopes(&["device:x", "org:a", "org:b"])),
+            Some(Role::Admin)
+        );
+        assert_eq!(owner_role_on(&roles, &scopes(&["org:c"])), None);
+    }
+}
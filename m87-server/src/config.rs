@@ -22,6 +22,22 @@ fn default_audit_retention_days() -> u32 {
     7
 }
 
+fn default_log_retention_days() -> u32 {
+    7
+}
+
+fn default_log_quota_mb_per_day() -> u32 {
+    64
+}
+
+fn default_unit_metrics_retention_days() -> u32 {
+    7
+}
+
+fn default_device_key_rotation_days() -> u32 {
+    90
+}
+
 fn default_allow_cros_org_device_sharing() -> bool {
     false
 }
@@ -45,8 +61,35 @@ pub struct AppConfig {
     pub report_retention_days: u32,
     #[serde(default = "default_audit_retention_days")]
     pub audit_retention_days: u32,
+    /// How long shipped device logs are kept.
+    #[serde(default = "default_log_retention_days")]
+    pub log_retention_days: u32,
+    /// Shipped log bytes a device may store per UTC day. `0` disables the quota.
+    #[serde(default = "default_log_quota_mb_per_day")]
+    pub log_quota_mb_per_day: u32,
+    /// How long values of units' `metrics` hooks are kept.
+    #[serde(default = "default_unit_metrics_retention_days")]
+    pub unit_metrics_retention_days: u32,
     #[serde(default = "default_allow_cros_org_device_sharing")]
     pub allow_cros_org_device_sharing: bool,
+    /// Age after which device API keys are rotated over the control tunnel.
+    /// `0` disables rotation.
+    #[serde(default = "default_device_key_rotation_days")]
+    pub device_key_rotation_days: u32,
+    /// Bearer token for `/metrics`. The endpoint is off without one.
+    pub metrics_token: Option<String>,
+    /// Serve `/metrics` on this plain-HTTP port instead of the API port.
+    pub metrics_port: Option<u16>,
+    /// Identifies this replica in the shared tunnel registry.
+    pub node_id: String,
+    /// `host:port` under which other replicas reach this node's QUIC endpoint.
+    /// Cross-node tunnel routing is enabled when this and `relay_secret` are set.
+    pub node_address: Option<String>,
+    /// Shared secret replicas use to authenticate node-to-node forwards.
+    pub relay_secret: Option<String>,
+    /// Hosts webhooks may target even though they are (or resolve to)
+    /// loopback, private or link-local addresses.
+    pub webhook_allowed_hosts: Vec<String>,
 }
 
 impl AppConfig {
@@ -102,12 +145,51 @@ impl AppConfig {
             .unwrap_or_else(|_| "7".to_string())
             .parse()
             .unwrap();
+        let log_retention_days = std::env::var("LOG_RETENTION_DAYS")
+            .unwrap_or_else(|_| "7".to_string())
+            .parse()
+            .unwrap();
+        let log_quota_mb_per_day = std::env::var("LOG_QUOTA_MB_PER_DAY")
+            .unwrap_or_else(|_| "64".to_string())
+            .parse()
+            .unwrap();
+        let unit_metrics_retention_days = std::env::var("UNIT_METRICS_RETENTION_DAYS")
+            .unwrap_or_else(|_| "7".to_string())
+            .parse()
+            .unwrap();
 
         let allow_cros_org_device_sharing = std::env::var("ALLOW_CROSS_ORG_DEVICE_SHARING")
             .unwrap_or_else(|_| "false".to_string())
             .parse()
             .unwrap();
 
+        let device_key_rotation_days = std::env::var("DEVICE_KEY_ROTATION_DAYS")
+            .unwrap_or_else(|_| "90".to_string())
+            .parse()
+            .unwrap();
+
+        let metrics_token = std::env::var("METRICS_TOKEN")
+            .ok()
+            .filter(|s| !s.is_empty());
+        let metrics_port = std::env::var("METRICS_PORT")
+            .ok()
+            .filter(|s| !s.is_empty())
+            .map(|s| s.parse().unwrap());
+
+        let node_id = std::env::var("NODE_ID")
+            .ok()
+            .filter(|s| !s.is_empty())
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        let node_address = std::env::var("NODE_ADDRESS").ok().filter(|s| !s.is_empty());
+        let relay_secret = std::env::var("RELAY_SECRET").ok().filter(|s| !s.is_empty());
+
+        let webhook_allowed_hosts = std::env::var("WEBHOOK_ALLOWED_HOSTS")
+            .unwrap_or_else(|_| "".to_string())
+            .split(',')
+            .map(|host| host.trim().to_ascii_lowercase())
+            .filter(|host| !host.is_empty())
+            .collect();
+
         Ok(Self {
             mongo_uri,
             mongo_db,
@@ -123,7 +205,17 @@ impl AppConfig {
             admin_key,
             report_retention_days,
             audit_retention_days,
+            log_retention_days,
+            log_quota_mb_per_day,
+            unit_metrics_retention_days,
             allow_cros_org_device_sharing,
+            device_key_rotation_days,
+            metrics_token,
+            metrics_port,
+            node_id,
+            node_address,
+            relay_secret,
+            webhook_allowed_hosts,
         })
     }
 }
@@ -1,18 +1,26 @@
 use std::time::Duration;
 
 use crate::{
+    metrics,
     models::{
+        alert::{AlertDoc, AlertRuleDoc},
         api_key::ApiKeyDoc,
         audit_logs::AuditLogDoc,
         deploy_spec::{CurrentRunStateDoc, DeployReportDoc, DeployRevisionDoc, JobRunDoc},
         device::DeviceDoc,
         device_auth_request::DeviceAuthRequestDoc,
+        device_log::{DeviceLogDoc, DeviceLogUsageDoc},
+        org::OrgSettingsDoc,
         roles::RoleDoc,
+        session_recording::SessionRecordingDoc,
+        tunnel_lease::TunnelLeaseDoc,
+        unit_metrics::UnitMetricsDoc,
         user::UserDoc,
+        webhook::{WebhookDeliveryDoc, WebhookDoc},
     },
     response::ServerResult,
 };
-use mongodb::{Client, Collection, IndexModel, options::ClientOptions};
+use mongodb::{Client, Collection, IndexModel, event::EventHandler, options::ClientOptions};
 use mongodb::{bson::doc, options::IndexOptions};
 
 #[derive(Clone)]
@@ -29,6 +37,7 @@ impl Mongo {
         // ingestion path (several sequential DB ops per report). Raise it so a
         // burst of reports can't starve every other request of a connection.
         opts.max_pool_size = Some(50);
+        opts.command_event_handler = Some(EventHandler::callback(metrics::record_mongo_command));
         let client = Client::with_options(opts)?;
         Ok(Self {
             client,
@@ -80,6 +89,46 @@ impl Mongo {
         self.col("audit_logs")
     }
 
+    pub fn tunnel_leases(&self) -> Collection<TunnelLeaseDoc> {
+        self.col("tunnel_leases")
+    }
+
+    pub fn org_settings(&self) -> Collection<OrgSettingsDoc> {
+        self.col("org_settings")
+    }
+
+    pub fn session_recordings(&self) -> Collection<SessionRecordingDoc> {
+        self.col("session_recordings")
+    }
+
+    pub fn webhooks(&self) -> Collection<WebhookDoc> {
+        self.col("webhooks")
+    }
+
+    pub fn webhook_deliveries(&self) -> Collection<WebhookDeliveryDoc> {
+        self.col("webhook_deliveries")
+    }
+
+    pub fn alert_rules(&self) -> Collection<AlertRuleDoc> {
+        self.col("alert_rules")
+    }
+
+    pub fn alerts(&self) -> Collection<AlertDoc> {
+        self.col("alerts")
+    }
+
+    pub fn device_logs(&self) -> Collection<DeviceLogDoc> {
+        self.col("device_logs")
+    }
+
+    pub fn device_log_usage(&self) -> Collection<DeviceLogUsageDoc> {
+        self.col("device_log_usage")
+    }
+
+    pub fn unit_metrics(&self) -> Collection<UnitMetricsDoc> {
+        self.col("unit_metrics")
+    }
+
     pub async fn ensure_indexes(&self) -> ServerResult<()> {
         // Add indexes as needed later (expires_at TTL, etc.)
         self.roles()
@@ -151,6 +200,23 @@ impl Mongo {
         self.api_keys()
             .create_index(IndexModel::builder().keys(doc! { "key_id": 1 }).build())
             .await?;
+        self.api_keys()
+            .create_index(IndexModel::builder().keys(doc! { "owner_id": 1 }).build())
+            .await?;
+
+        // Expired tunnel leases (crashed or redeployed nodes) are cleaned up by Mongo
+        self.tunnel_leases()
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "lease_expires_at": 1 })
+                    .options(
+                        IndexOptions::builder()
+                            .expire_after(Some(Duration::from_secs(0)))
+                            .build(),
+                    )
+                    .build(),
+            )
+            .await?;
 
         // add index to users sub
         self.users()
@@ -330,6 +396,161 @@ impl Mongo {
             .create_index(IndexModel::builder().keys(doc! { "device_id": 1 }).build())
             .await?;
 
+        // `DeviceLogDoc::query` filters `device_id` and sorts `at` desc.
+        self.device_logs()
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "device_id": 1, "at": -1 })
+                    .build(),
+            )
+            .await?;
+        self.device_logs()
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "expires_at": 1 })
+                    .options(
+                        IndexOptions::builder()
+                            .name(Some("ttl_device_logs_expires_at".to_string()))
+                            .expire_after(Some(Duration::from_secs(0)))
+                            .build(),
+                    )
+                    .build(),
+            )
+            .await?;
+        self.device_log_usage()
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "expires_at": 1 })
+                    .options(
+                        IndexOptions::builder()
+                            .name(Some("ttl_device_log_usage_expires_at".to_string()))
+                            .expire_after(Some(Duration::from_secs(0)))
+                            .build(),
+                    )
+                    .build(),
+            )
+            .await?;
+
+        // One sample per hook run; heartbeats repeat the latest one.
+        self.unit_metrics()
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "device_id": 1, "unit": 1, "collected_at": -1 })
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+            )
+            .await?;
+        self.unit_metrics()
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "expires_at": 1 })
+                    .options(
+                        IndexOptions::builder()
+                            .name(Some("ttl_unit_metrics_expires_at".to_string()))
+                            .expire_after(Some(Duration::from_secs(0)))
+                            .build(),
+                    )
+                    .build(),
+            )
+            .await?;
+
+        // `SessionRecordingDoc::find_for_device`
+        self.session_recordings()
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "device_id": 1, "session_id": 1 })
+                    .build(),
+            )
+            .await?;
+        self.session_recordings()
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "expires_at": 1 })
+                    .options(
+                        IndexOptions::builder()
+                            .name(Some("ttl_session_recordings_expires_at".to_string()))
+                            .expire_after(Some(Duration::from_secs(0)))
+                            .partial_filter_expression(doc! { "expires_at": { "$exists": true } })
+                            .build(),
+                    )
+                    .build(),
+            )
+            .await?;
+
+        self.webhooks()
+            .create_index(IndexModel::builder().keys(doc! { "org_id": 1 }).build())
+            .await?;
+        // `WebhookDeliveryDoc::claim`
+        self.webhook_deliveries()
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "status": 1, "next_attempt_at": 1 })
+                    .build(),
+            )
+            .await?;
+        self.webhook_deliveries()
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "webhook_id": 1, "created_at": -1 })
+                    .build(),
+            )
+            .await?;
+        self.webhook_deliveries()
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "expires_at": 1 })
+                    .options(
+                        IndexOptions::builder()
+                            .name(Some("ttl_webhook_deliveries_expires_at".to_string()))
+                            .expire_after(Some(Duration::from_secs(0)))
+                            .partial_filter_expression(doc! { "expires_at": { "$exists": true } })
+                            .build(),
+                    )
+                    .build(),
+            )
+            .await?;
+
+        self.alert_rules()
+            .create_index(IndexModel::builder().keys(doc! { "org_id": 1 }).build())
+            .await?;
+        // One open alert per rule, device and subject; `AlertDoc::observe`
+        // relies on this when several replicas evaluate at once.
+        self.alerts()
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "rule_id": 1, "device_id": 1, "subject": 1 })
+                    .options(
+                        IndexOptions::builder()
+                            .name(Some("uniq_alerts_open".to_string()))
+                            .unique(true)
+                            .partial_filter_expression(doc! { "open": true })
+                            .build(),
+                    )
+                    .build(),
+            )
+            .await?;
+        self.alerts()
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "org_id": 1, "state": 1, "fired_at": -1 })
+                    .build(),
+            )
+            .await?;
+        self.alerts()
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "expires_at": 1 })
+                    .options(
+                        IndexOptions::builder()
+                            .name(Some("ttl_alerts_expires_at".to_string()))
+                            .expire_after(Some(Duration::from_secs(0)))
+                            .partial_filter_expression(doc! { "expires_at": { "$exists": true } })
+                            .build(),
+                    )
+                    .build(),
+            )
+            .await?;
+
         self.job_runs()
             .create_index(
                 IndexModel::builder()
@@ -1,17 +1,23 @@
+mod alerts;
 mod api;
 mod auth;
 mod config;
 mod db;
+mod metrics;
 mod models;
 mod relay;
 mod response;
 mod util;
+mod webhooks;
 
 use std::sync::Arc;
 use tracing::info;
 use util::logging::init_tracing;
 
-use crate::{relay::relay_state::RelayState, response::ServerResult};
+use crate::{
+    relay::{registry::TunnelRegistry, relay_state::RelayState},
+    response::ServerResult,
+};
 
 #[tokio::main]
 async fn main() -> ServerResult<()> {
@@ -31,8 +37,19 @@ async fn main() -> ServerResult<()> {
     let db = Arc::new(db::Mongo::connect(&mongo_uri, &db_name).await?);
     db.ensure_indexes().await?;
     let config = Arc::new(config);
-    // Shared relay state
-    let relay_state = Arc::new(RelayState::new());
+    // Shared relay state; with a tunnel registry when running several replicas
+    let relay_state = match TunnelRegistry::from_config(db.clone(), &config) {
+        Some(registry) => {
+            info!(node_id = %config.node_id, "cross-node tunnel routing enabled");
+            RelayState::with_registry(registry)
+        }
+        None => RelayState::new(),
+    };
+    relay_state.spawn_lease_renewal();
+    relay_state.spawn_session_sweep(db.clone());
+    let relay_state = Arc::new(relay_state);
+    webhooks::spawn_delivery_worker(db.clone(), config.webhook_allowed_hosts.clone());
+    alerts::spawn_evaluator(db.clone(), relay_state.clone());
 
     info!("server started");
     if let Err(e) = api::serve::serve(db, relay_state, config).await {
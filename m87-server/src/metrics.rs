@@ -0,0 +1,366 @@
+//! Prometheus metrics for operating the server itself.
+//!
+//! Counters and histograms live in a process-wide registry and are bumped
+//! where things happen; tunnel and session gauges are read from
+//! `RelayState` on every scrape. Served on `/metrics` when `METRICS_TOKEN`
+//! is set, optionally on a separate plain-HTTP port (`METRICS_PORT`).
+
+use std::net::SocketAddr;
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use axum::Router;
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use m87_shared::deploy_spec::DeployReportKind;
+use mongodb::event::command::CommandEvent;
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts,
+    Registry, TextEncoder,
+};
+use subtle::ConstantTimeEq;
+use tracing::{info, warn};
+
+use crate::relay::stream_audit::Traffic;
+use crate::response::{AuthError, ServerError};
+use crate::util::app_state::AppState;
+
+/// Stream types as sent by the client; anything else is counted as `other`
+/// so a client can't grow the label set.
+const STREAM_TYPES: &[&str] = &[
+    "terminal",
+    "exec",
+    "logs",
+    "persistedlogs",
+    "journal",
+    "forward",
+    "serial",
+    "seriallog",
+    "seriallist",
+    "metrics",
+    "processes",
+    "signal",
+    "docker",
+    "ssh",
+    "p2p",
+];
+
+pub struct Metrics {
+    registry: Registry,
+    tunnels: IntGauge,
+    client_sessions: IntGauge,
+    relayed_streams: IntCounterVec,
+    relayed_bytes: IntCounterVec,
+    heartbeats: IntCounter,
+    heartbeat_duration: Histogram,
+    deploy_reports: IntCounterVec,
+    shipped_log_lines: IntCounterVec,
+    mongo_command_duration: HistogramVec,
+    mongo_command_failures: IntCounterVec,
+    auth_failures: IntCounterVec,
+}
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+impl Metrics {
+    fn new() -> Self {
+        let registry =
+            Registry::new_custom(Some("m87".to_string()), None).expect("valid metrics namespace");
+
+        let tunnels = IntGauge::new(
+            "tunnels_connected",
+            "Device control tunnels held by this node",
+        )
+        .unwrap();
+        let client_sessions = IntGauge::new(
+            "client_sessions",
+            "Authenticated client connections forwarding through this node",
+        )
+        .unwrap();
+        let relayed_streams = IntCounterVec::new(
+            Opts::new("relayed_streams_total", "Relayed client streams by type"),
+            &["type"],
+        )
+        .unwrap();
+        let relayed_bytes = IntCounterVec::new(
+            Opts::new(
+                "relayed_bytes_total",
+                "Bytes relayed between clients and devices",
+            ),
+            &["type", "direction"],
+        )
+        .unwrap();
+        let heartbeats = IntCounter::new("heartbeats_total", "Device heartbeats handled").unwrap();
+        let heartbeat_duration = Histogram::with_opts(HistogramOpts::new(
+            "heartbeat_duration_seconds",
+            "Time to handle a device heartbeat",
+        ))
+        .unwrap();
+        let deploy_reports = IntCounterVec::new(
+            Opts::new(
+                "deploy_reports_total",
+                "Deploy reports ingested from heartbeats",
+            ),
+            &["kind", "result"],
+        )
+        .unwrap();
+        let shipped_log_lines = IntCounterVec::new(
+            Opts::new(
+                "shipped_log_lines_total",
+                "Device log lines received from heartbeats",
+            ),
+            &["result"],
+        )
+        .unwrap();
+        let mongo_command_duration = HistogramVec::new(
+            HistogramOpts::new("mongo_command_duration_seconds", "MongoDB command latency"),
+            &["command"],
+        )
+        .unwrap();
+        let mongo_command_failures = IntCounterVec::new(
+            Opts::new("mongo_command_failures_total", "Failed MongoDB commands"),
+            &["command"],
+        )
+        .unwrap();
+        let auth_failures = IntCounterVec::new(
+            Opts::new("auth_failures_total", "Rejected authentications by reason"),
+            &["reason"],
+        )
+        .unwrap();
+
+        for collector in [
+            Box::new(tunnels.clone()) as Box<dyn prometheus::core::Collector>,
+            Box::new(client_sessions.clone()),
+            Box::new(relayed_streams.clone()),
+            Box::new(relayed_bytes.clone()),
+            Box::new(heartbeats.clone()),
+            Box::new(heartbeat_duration.clone()),
+            Box::new(deploy_reports.clone()),
+            Box::new(shipped_log_lines.clone()),
+            Box::new(mongo_command_duration.clone()),
+            Box::new(mongo_command_failures.clone()),
+            Box::new(auth_failures.clone()),
+        ] {
+            registry
+                .register(collector)
+                .expect("metric registered once");
+        }
+
+        Self {
+            registry,
+            tunnels,
+            client_sessions,
+            relayed_streams,
+            relayed_bytes,
+            heartbeats,
+            heartbeat_duration,
+            deploy_reports,
+            shipped_log_lines,
+            mongo_command_duration,
+            mongo_command_failures,
+            auth_failures,
+        }
+    }
+}
+
+/// A finished relayed stream. `stream_type` is `None` if its header
+/// couldn't be read.
+pub fn record_stream(stream_type: Option<&str>, traffic: Traffic) {
+    let stream_type = stream_type
+        .and_then(|t| STREAM_TYPES.iter().find(|known| **known == t))
+        .copied()
+        .unwrap_or("other");
+    let m = &*METRICS;
+    m.relayed_streams.with_label_values(&[stream_type]).inc();
+    m.relayed_bytes
+        .with_label_values(&[stream_type, "up"])
+        .inc_by(traffic.bytes_up);
+    m.relayed_bytes
+        .with_label_values(&[stream_type, "down"])
+        .inc_by(traffic.bytes_down);
+}
+
+pub fn record_heartbeat(elapsed: Duration) {
+    METRICS.heartbeats.inc();
+    METRICS.heartbeat_duration.observe(elapsed.as_secs_f64());
+}
+
+/// `result` is `stored`, `over_quota`, `too_large` or `invalid` (counted per batch).
+pub fn record_shipped_logs(result: &str, lines: u64) {
+    METRICS
+        .shipped_log_lines
+        .with_label_values(&[result])
+        .inc_by(lines);
+}
+
+pub fn record_deploy_report(kind: &DeployReportKind, ok: bool) {
+    let kind = match kind {
+        DeployReportKind::DeploymentRevisionReport(_) => "revision",
+        DeployReportKind::RunReport(_) => "run",
+        DeployReportKind::StepReport(_) => "step",
+        DeployReportKind::RollbackReport(_) => "rollback",
+        DeployReportKind::RunState(_) => "run_state",
+        DeployReportKind::JobRunReport(_) => "job_run",
+    };
+    let result = if ok { "ok" } else { "error" };
+    METRICS
+        .deploy_reports
+        .with_label_values(&[kind, result])
+        .inc();
+}
+
+/// Feed MongoDB command monitoring events into the latency histogram.
+pub fn record_mongo_command(event: CommandEvent) {
+    match event {
+        CommandEvent::Succeeded(e) => METRICS
+            .mongo_command_duration
+            .with_label_values(&[e.command_name.as_str()])
+            .observe(e.duration.as_secs_f64()),
+        CommandEvent::Failed(e) => {
+            METRICS
+                .mongo_command_duration
+                .with_label_values(&[e.command_name.as_str()])
+                .observe(e.duration.as_secs_f64());
+            METRICS
+                .mongo_command_failures
+                .with_label_values(&[e.command_name.as_str()])
+                .inc();
+        }
+        _ => {}
+    }
+}
+
+/// Count a rejected authentication. Errors that aren't about the
+/// credentials (e.g. a database outage) are not counted.
+pub fn record_auth_failure(err: &ServerError) {
+    if let Some(reason) = auth_failure_reason(err) {
+        METRICS.auth_failures.with_label_values(&[&reason]).inc();
+    }
+}
+
+/// Token errors carry decoder details, so only their kind is used; the
+/// other messages are fixed strings like "API key expired".
+fn auth_failure_reason(err: &ServerError) -> Option<String> {
+    let ServerError::AuthError(err) = err else {
+        return None;
+    };
+    let reason = match err {
+        AuthError::MissingToken(_) => "missing_token".to_string(),
+        AuthError::InvalidToken(_) => "invalid_token".to_string(),
+        AuthError::ExpiredToken(_) => "expired_token".to_string(),
+        AuthError::Unauthorized(msg) | AuthError::Forbidden(msg) => msg
+            .trim()
+            .to_ascii_lowercase()
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>()
+            .join("_"),
+    };
+    Some(if reason.is_empty() {
+        "unauthorized".to_string()
+    } else {
+        reason
+    })
+}
+
+/// Routes for `/metrics`; empty unless a metrics token is configured.
+pub fn create_route(state: &AppState) -> Router<AppState> {
+    if state.config.metrics_token.is_none() {
+        return Router::new();
+    }
+    Router::new().route("/metrics", get(get_metrics))
+}
+
+/// Serve `/metrics` on its own port, without TLS.
+pub async fn serve_separate(state: AppState, port: u16) {
+    let addr = SocketAddr::from(([0, 0, 0, 0], port));
+    let app = create_route(&state).with_state(state);
+    let listener = match tokio::net::TcpListener::bind(addr).await {
+        Ok(l) => l,
+        Err(e) => {
+            warn!("failed to bind metrics port {port}: {e}");
+            return;
+        }
+    };
+    info!("Metrics listening on {}", addr);
+    if let Err(e) = axum::serve(listener, app).await {
+        warn!("metrics server exited: {e}");
+    }
+}
+
+// --------------------
+// GET /metrics
+// --------------------
+
+async fn get_metrics(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let Some(expected) = state.config.metrics_token.as_deref() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let token = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    // Constant-time: the endpoint may be reachable from outside.
+    let authorized =
+        token.is_some_and(|token| bool::from(token.as_bytes().ct_eq(expected.as_bytes())));
+    if !authorized {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    let m = &*METRICS;
+    m.tunnels.set(state.relay.local_tunnel_count().await as i64);
+    m.client_sessions
+        .set(state.relay.client_session_count().await as i64);
+
+    let encoder = TextEncoder::new();
+    let mut body = Vec::new();
+    if let Err(e) = encoder.encode(&m.registry.gather(), &mut body) {
+        warn!("failed to encode metrics: {e}");
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+    (
+        [(header::CONTENT_TYPE, encoder.format_type().to_string())],
+        body,
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn auth_failure_reasons_are_bounded() {
+        let reason = |e: ServerError| auth_failure_reason(&e);
+        assert_eq!(
+            reason(ServerError::unauthorized("API key expired")).as_deref(),
+            Some("api_key_expired")
+        );
+        assert_eq!(
+            reason(ServerError::invalid_token(
+                "Token verification failed: bad sig"
+            ))
+            .as_deref(),
+            Some("invalid_token")
+        );
+        assert_eq!(
+            reason(ServerError::internal_error("DB lookup failed")),
+            None
+        );
+    }
+
+    #[test]
+    fn unknown_stream_types_are_folded() {
+        record_stream(
+            Some("made-up"),
+            Traffic {
+                bytes_up: 3,
+                bytes_down: 4,
+            },
+        );
+        let other = METRICS.relayed_bytes.with_label_values(&["other", "down"]);
+        assert!(other.get() >= 4);
+    }
+}
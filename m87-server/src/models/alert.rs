@@ -0,0 +1,419 @@
+use std::collections::BTreeMap;
+use std::{sync::Arc, time::Duration};
+
+use futures::TryStreamExt;
+use m87_shared::alerts::{Alert, AlertCondition, AlertRule, AlertState, CreateAlertRuleBody};
+use mongodb::bson::{DateTime, doc, oid::ObjectId};
+use mongodb::error::{ErrorKind, WriteFailure};
+use mongodb::options::{FindOneAndUpdateOptions, FindOptions, ReturnDocument};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    db::Mongo,
+    response::{ServerError, ServerResult},
+};
+
+/// How long resolved alerts stay in the alert history.
+const RESOLVED_RETENTION: Duration = Duration::from_hours(24 * 7);
+/// Upper bound for `for_secs` and silences.
+pub const MAX_ALERT_DURATION: Duration = Duration::from_hours(24 * 30);
+const MAX_RULE_NAME_LEN: usize = 128;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertRuleDoc {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub org_id: String,
+    pub name: String,
+    /// Device labels that must all match; empty selects every org device.
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    pub condition: AlertCondition,
+    #[serde(default)]
+    pub for_secs: u64,
+    /// Alerts keep firing and resolving while silenced, but send no webhooks.
+    #[serde(default)]
+    pub silenced_until: Option<DateTime>,
+    pub created_at: DateTime,
+}
+
+impl AlertRuleDoc {
+    pub async fn create(
+        db: &Arc<Mongo>,
+        org_id: &str,
+        body: CreateAlertRuleBody,
+    ) -> ServerResult<Self> {
+        let name = body.name.trim().to_string();
+        if name.is_empty() || name.len() > MAX_RULE_NAME_LEN {
+            return Err(ServerError::bad_request(&format!(
+                "Rule name must be 1 to {} characters",
+                MAX_RULE_NAME_LEN
+            )));
+        }
+        if body.for_secs > MAX_ALERT_DURATION.as_secs() {
+            return Err(ServerError::bad_request(
+                "Rule duration is limited to 30 days",
+            ));
+        }
+        if let AlertCondition::Metric { threshold, .. } = &body.condition
+            && !threshold.is_finite()
+        {
+            return Err(ServerError::bad_request(
+                "Metric threshold must be a number",
+            ));
+        }
+        if body
+            .labels
+            .keys()
+            .any(|k| k.is_empty() || k.contains(['.', '$']))
+        {
+            return Err(ServerError::bad_request("Invalid label key in selector"));
+        }
+
+        let doc = Self {
+            id: Some(ObjectId::new()),
+            org_id: org_id.to_string(),
+            name,
+            labels: body.labels,
+            condition: body.condition,
+            for_secs: body.for_secs,
+            silenced_until: None,
+            created_at: DateTime::now(),
+        };
+        db.alert_rules()
+            .insert_one(&doc)
+            .await
+            .map_err(|_| ServerError::internal_error("Failed to create alert rule"))?;
+        Ok(doc)
+    }
+
+    pub async fn list_for_org(db: &Arc<Mongo>, org_id: &str) -> ServerResult<Vec<Self>> {
+        let rules = db
+            .alert_rules()
+            .find(doc! { "org_id": org_id })
+            .sort(doc! { "created_at": 1 })
+            .await?
+            .try_collect()
+            .await?;
+        Ok(rules)
+    }
+
+    pub async fn all(db: &Arc<Mongo>) -> ServerResult<Vec<Self>> {
+        let rules = db.alert_rules().find(doc! {}).await?.try_collect().await?;
+        Ok(rules)
+    }
+
+    pub async fn delete(db: &Arc<Mongo>, org_id: &str, id: ObjectId) -> ServerResult<bool> {
+        let res = db
+            .alert_rules()
+            .delete_one(doc! { "_id": id, "org_id": org_id })
+            .await?;
+        if res.deleted_count == 0 {
+            return Ok(false);
+        }
+        db.alerts().delete_many(doc! { "rule_id": id }).await?;
+        Ok(true)
+    }
+
+    /// Silence the rule until `until`, or lift the silence with `None`.
+    pub async fn set_silence(
+        db: &Arc<Mongo>,
+        org_id: &str,
+        id: ObjectId,
+        until: Option<DateTime>,
+    ) -> ServerResult<Option<Self>> {
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+        let rule = db
+            .alert_rules()
+            .find_one_and_update(
+                doc! { "_id": id, "org_id": org_id },
+                doc! { "$set": { "silenced_until": until } },
+            )
+            .with_options(options)
+            .await?;
+        Ok(rule)
+    }
+
+    pub fn is_silenced(&self, now: DateTime) -> bool {
+        self.silenced_until.is_some_and(|until| until > now)
+    }
+
+    pub async fn delete_for_org(db: &Arc<Mongo>, org_id: &str) -> ServerResult<()> {
+        db.alert_rules()
+            .delete_many(doc! { "org_id": org_id })
+            .await?;
+        db.alerts().delete_many(doc! { "org_id": org_id }).await?;
+        Ok(())
+    }
+
+    pub async fn rename_org(db: &Arc<Mongo>, old_id: &str, new_id: &str) -> ServerResult<()> {
+        db.alert_rules()
+            .update_many(
+                doc! { "org_id": old_id },
+                doc! { "$set": { "org_id": new_id } },
+            )
+            .await?;
+        db.alerts()
+            .update_many(
+                doc! { "org_id": old_id },
+                doc! { "$set": { "org_id": new_id } },
+            )
+            .await?;
+        Ok(())
+    }
+
+    pub fn to_alert_rule(&self) -> AlertRule {
+        AlertRule {
+            id: self.id.map(|id| id.to_hex()).unwrap_or_default(),
+            org_id: self.org_id.clone(),
+            name: self.name.clone(),
+            labels: self.labels.clone(),
+            condition: self.condition.clone(),
+            for_secs: self.for_secs,
+            silenced_until: self
+                .silenced_until
+                .filter(|until| *until > DateTime::now())
+                .and_then(|until| until.try_to_rfc3339_string().ok()),
+            created_at: self.created_at.try_to_rfc3339_string().unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertDocState {
+    /// Condition holds, but not yet for the rule's `for_secs`.
+    Pending,
+    Firing,
+    Resolved,
+}
+
+/// One rule matching one device (and unit, for `unhealthy` rules). At most
+/// one open (pending or firing) alert exists per rule, device and subject.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertDoc {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub rule_id: ObjectId,
+    pub rule_name: String,
+    pub org_id: String,
+    pub device_id: ObjectId,
+    pub device_name: String,
+    #[serde(default)]
+    pub subject: String,
+    pub condition: String,
+    pub state: AlertDocState,
+    pub open: bool,
+    /// When the condition started to hold.
+    pub active_since: DateTime,
+    #[serde(default)]
+    pub fired_at: Option<DateTime>,
+    #[serde(default)]
+    pub resolved_at: Option<DateTime>,
+    #[serde(default)]
+    pub value: Option<String>,
+    #[serde(default)]
+    pub acked_by: Option<String>,
+    #[serde(default)]
+    pub acked_at: Option<DateTime>,
+    pub updated_at: DateTime,
+    #[serde(default)]
+    pub expires_at: Option<DateTime>,
+}
+
+impl AlertDoc {
+    /// Open alerts of a rule, for the evaluator.
+    pub async fn open_for_rule(db: &Arc<Mongo>, rule_id: ObjectId) -> ServerResult<Vec<Self>> {
+        let alerts = db
+            .alerts()
+            .find(doc! { "rule_id": rule_id, "open": true })
+            .await?
+            .try_collect()
+            .await?;
+        Ok(alerts)
+    }
+
+    /// Record that the condition holds, opening a pending alert if there is
+    /// none yet. Concurrent evaluators are kept to one open alert by the
+    /// unique partial index on open alerts.
+    pub async fn observe(
+        db: &Arc<Mongo>,
+        rule: &AlertRuleDoc,
+        device_id: ObjectId,
+        device_name: &str,
+        subject: &str,
+        value: Option<String>,
+    ) -> ServerResult<()> {
+        let now = DateTime::now();
+        let filter = doc! {
+            "rule_id": rule.id,
+            "device_id": device_id,
+            "subject": subject,
+            "open": true,
+        };
+        let update = doc! {
+            "$set": {
+                "value": value,
+                "device_name": device_name,
+                "rule_name": &rule.name,
+                "updated_at": now,
+            },
+            "$setOnInsert": {
+                "_id": ObjectId::new(),
+                "org_id": &rule.org_id,
+                "condition": rule.condition.to_string(),
+                "state": "pending",
+                "active_since": now,
+            },
+        };
+        let res = db.alerts().update_one(filter, update).upsert(true).await;
+        match res {
+            Ok(_) => Ok(()),
+            Err(e) => match e.kind.as_ref() {
+                // Another replica opened it first.
+                ErrorKind::Write(WriteFailure::WriteError(we)) if we.code == 11000 => Ok(()),
+                _ => Err(e.into()),
+            },
+        }
+    }
+
+    /// Move the pending alert to firing once it has been pending for the
+    /// rule's `for_secs`. Returns the alert if this call fired it.
+    pub async fn promote(
+        db: &Arc<Mongo>,
+        rule: &AlertRuleDoc,
+        device_id: ObjectId,
+        subject: &str,
+    ) -> ServerResult<Option<Self>> {
+        let now = DateTime::now();
+        let since =
+            DateTime::from_system_time(now.to_system_time() - Duration::from_secs(rule.for_secs));
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+        let fired = db
+            .alerts()
+            .find_one_and_update(
+                doc! {
+                    "rule_id": rule.id,
+                    "device_id": device_id,
+                    "subject": subject,
+                    "state": "pending",
+                    "active_since": { "$lte": since },
+                },
+                doc! { "$set": { "state": "firing", "fired_at": now, "updated_at": now } },
+            )
+            .with_options(options)
+            .await?;
+        Ok(fired)
+    }
+
+    /// The condition no longer holds: drop a pending alert, resolve a firing
+    /// one. Returns the alert if this call resolved it from firing.
+    pub async fn resolve(&self, db: &Arc<Mongo>) -> ServerResult<Option<Self>> {
+        if self.state == AlertDocState::Pending {
+            db.alerts()
+                .delete_one(doc! { "_id": self.id, "state": "pending" })
+                .await?;
+            return Ok(None);
+        }
+
+        let now = DateTime::now();
+        let expires_at = DateTime::from_system_time(now.to_system_time() + RESOLVED_RETENTION);
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+        let resolved = db
+            .alerts()
+            .find_one_and_update(
+                doc! { "_id": self.id, "state": "firing" },
+                doc! { "$set": {
+                    "state": "resolved",
+                    "open": false,
+                    "resolved_at": now,
+                    "updated_at": now,
+                    "expires_at": expires_at,
+                } },
+            )
+            .with_options(options)
+            .await?;
+        Ok(resolved)
+    }
+
+    /// Firing alerts of an org, plus resolved ones when `include_resolved`.
+    /// Pending alerts are an evaluator detail and never listed.
+    pub async fn list_for_org(
+        db: &Arc<Mongo>,
+        org_id: &str,
+        include_resolved: bool,
+    ) -> ServerResult<Vec<Self>> {
+        let states = if include_resolved {
+            vec!["firing", "resolved"]
+        } else {
+            vec!["firing"]
+        };
+        let options = FindOptions::builder()
+            .sort(doc! { "fired_at": -1 })
+            .limit(Some(500))
+            .build();
+        let alerts = db
+            .alerts()
+            .find(doc! { "org_id": org_id, "state": { "$in": states } })
+            .with_options(options)
+            .await?
+            .try_collect()
+            .await?;
+        Ok(alerts)
+    }
+
+    pub async fn ack(
+        db: &Arc<Mongo>,
+        org_id: &str,
+        id: ObjectId,
+        by: &str,
+    ) -> ServerResult<Option<Self>> {
+        let now = DateTime::now();
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+        let alert = db
+            .alerts()
+            .find_one_and_update(
+                doc! {
+                    "_id": id,
+                    "org_id": org_id,
+                    "state": { "$in": ["firing", "resolved"] },
+                },
+                doc! { "$set": { "acked_by": by, "acked_at": now, "updated_at": now } },
+            )
+            .with_options(options)
+            .await?;
+        Ok(alert)
+    }
+
+    pub fn to_alert(&self, silenced: bool) -> Alert {
+        let fmt = |t: &DateTime| t.try_to_rfc3339_string().unwrap_or_default();
+        Alert {
+            id: self.id.to_hex(),
+            rule_id: self.rule_id.to_hex(),
+            rule_name: self.rule_name.clone(),
+            org_id: self.org_id.clone(),
+            device_id: self.device_id.to_hex(),
+            device_name: self.device_name.clone(),
+            subject: self.subject.clone(),
+            condition: self.condition.clone(),
+            state: match self.state {
+                AlertDocState::Resolved => AlertState::Resolved,
+                _ => AlertState::Firing,
+            },
+            value: self.value.clone(),
+            fired_at: fmt(self.fired_at.as_ref().unwrap_or(&self.active_since)),
+            resolved_at: self.resolved_at.as_ref().map(fmt),
+            acked_by: self.acked_by.clone(),
+            acked_at: self.acked_at.as_ref().map(fmt),
+            silenced,
+        }
+    }
+}
@@ -4,6 +4,8 @@ use crate::{
     response::{ServerError, ServerResult},
 };
 use argon2::password_hash::{Error as PasswordHashError, SaltString};
+use futures::TryStreamExt;
+use m87_shared::auth::ApiKeyInfo;
 use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
 use mongodb::bson::{doc, oid::ObjectId, DateTime};
 use rand::{distributions::Alphanumeric, Rng};
@@ -24,6 +26,11 @@ pub struct ApiKeyDoc {
     pub created_at: Option<DateTime>,
     #[serde(default)]
     pub expires_at: Option<DateTime>,
+    /// User who created the key; `None` for device keys.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner_id: Option<ObjectId>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner_email: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -31,6 +38,8 @@ pub struct CreateApiKey {
     pub name: String,
     pub ttl_secs: Option<i64>,
     pub scopes: Vec<(String, Role)>,
+    pub owner_id: Option<ObjectId>,
+    pub owner_email: Option<String>,
 }
 
 impl ApiKeyDoc {
@@ -53,6 +62,8 @@ impl ApiKeyDoc {
             name: req.name,
             created_at: Some(now),
             expires_at,
+            owner_id: req.owner_id,
+            owner_email: req.owner_email,
         };
 
         // 4. Store in Mongo
@@ -92,6 +103,61 @@ impl ApiKeyDoc {
         Ok(())
     }
 
+    pub async fn find_by_id(db: &Arc<Mongo>, id: ObjectId) -> ServerResult<Option<Self>> {
+        Ok(db.api_keys().find_one(doc! { "_id": id }).await?)
+    }
+
+    /// Delete a key and its role bindings. No-op if it's already gone.
+    pub async fn delete_with_roles(db: &Arc<Mongo>, id: ObjectId) -> ServerResult<()> {
+        let Some(key) = db
+            .api_keys()
+            .find_one_and_delete(doc! { "_id": id })
+            .await?
+        else {
+            return Ok(());
+        };
+        RoleDoc::delete_for_reference(db, &key.key_id).await
+    }
+
+    /// Keys a user created, newest first.
+    pub async fn list_for_owner(db: &Arc<Mongo>, owner_id: ObjectId) -> ServerResult<Vec<Self>> {
+        let keys: Vec<Self> = db
+            .api_keys()
+            .find(doc! { "owner_id": owner_id })
+            .sort(doc! { "created_at": -1 })
+            .await?
+            .try_collect()
+            .await?;
+        Ok(keys)
+    }
+
+    /// Delete a user's key together with its role bindings. Returns false if
+    /// the user has no such key.
+    pub async fn revoke(db: &Arc<Mongo>, owner_id: ObjectId, key_id: &str) -> ServerResult<bool> {
+        let res = db
+            .api_keys()
+            .delete_one(doc! { "key_id": key_id, "owner_id": owner_id })
+            .await?;
+        if res.deleted_count == 0 {
+            return Ok(false);
+        }
+        RoleDoc::delete_for_reference(db, key_id).await?;
+        Ok(true)
+    }
+
+    /// `roles` are the key's bindings; user-created keys have exactly one.
+    pub fn to_api_key_info(&self, roles: &[RoleDoc]) -> ApiKeyInfo {
+        let binding = roles.iter().find(|r| r.reference_id == self.key_id);
+        ApiKeyInfo {
+            key_id: self.key_id.clone(),
+            name: self.name.clone(),
+            scope: binding.map(|r| r.scope.clone()).unwrap_or_default(),
+            role: binding.map(|r| r.role.clone()).unwrap_or_default(),
+            created_at: self.created_at.and_then(|t| t.try_to_rfc3339_string().ok()),
+            expires_at: self.expires_at.and_then(|t| t.try_to_rfc3339_string().ok()),
+        }
+    }
+
     pub async fn find_and_validate_key(db: &Arc<Mongo>, api_key: &str) -> ServerResult<ApiKeyDoc> {
         let (key_id, secret) =
             split_api_key(api_key).ok_or_else(|| ServerError::unauthorized("Malformed API key"))?;
@@ -187,4 +253,35 @@ mod tests {
             "wrong key must not verify"
         );
     }
+
+    #[test]
+    fn key_info_uses_own_binding() {
+        let key = ApiKeyDoc {
+            id: None,
+            key_id: "m87_abcdefgh".into(),
+            key_hash: String::new(),
+            name: "ci".into(),
+            created_at: Some(DateTime::from_millis(0)),
+            expires_at: None,
+            owner_id: Some(ObjectId::new()),
+            owner_email: Some("ada@example.com".into()),
+        };
+        let binding = |reference_id: &str, scope: &str, role| RoleDoc {
+            id: None,
+            reference_id: reference_id.into(),
+            scope: scope.into(),
+            role,
+            created_at: None,
+        };
+        let roles = vec![
+            binding("m87_other123", "org:other", Role::Admin),
+            binding("m87_abcdefgh", "org:acme", Role::Editor),
+        ];
+
+        let info = key.to_api_key_info(&roles);
+        assert_eq!(info.scope, "org:acme");
+        assert_eq!(info.role, Role::Editor);
+        assert_eq!(info.created_at.as_deref(), Some("1970-01-01T00:00:00Z"));
+        assert!(info.expires_at.is_none());
+    }
 }
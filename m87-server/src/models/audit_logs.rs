@@ -1,7 +1,7 @@
 use std::{sync::Arc, time::Duration};
 
 use futures::TryStreamExt;
-use m87_shared::device::AuditLog;
+use m87_shared::device::{AuditLog, StreamAuditEvent};
 use mongodb::{
     bson::{DateTime, doc, oid::ObjectId},
     options::FindOptions,
@@ -29,6 +29,63 @@ pub struct AuditLogDoc {
     pub device_id: Option<ObjectId>,
     #[serde(default)]
     pub expires_at: Option<DateTime>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream: Option<StreamAuditFields>,
+}
+
+/// Typed part of a relayed-traffic entry (see `relay::stream_audit`).
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct StreamAuditFields {
+    pub stream_type: String,
+    pub target: Option<String>,
+    pub streams: i64,
+    pub started_at: DateTime,
+    pub ended_at: DateTime,
+    pub bytes_up: i64,
+    pub bytes_down: i64,
+    pub close_reason: String,
+}
+
+impl StreamAuditFields {
+    pub fn to_stream_audit_event(&self) -> StreamAuditEvent {
+        StreamAuditEvent {
+            stream_type: self.stream_type.clone(),
+            target: self.target.clone(),
+            streams: self.streams as u32,
+            started_at: self.started_at.try_to_rfc3339_string().unwrap_or_default(),
+            ended_at: self.ended_at.try_to_rfc3339_string().unwrap_or_default(),
+            duration_ms: (self.ended_at.timestamp_millis() - self.started_at.timestamp_millis())
+                .max(0) as u64,
+            bytes_up: self.bytes_up as u64,
+            bytes_down: self.bytes_down as u64,
+            close_reason: self.close_reason.clone(),
+        }
+    }
+
+    /// One-line summary used as the entry's free-text `details`.
+    fn summary(&self) -> String {
+        let mut out = String::new();
+        if let Some(target) = &self.target {
+            out.push_str(target);
+            out.push(' ');
+        }
+        out.push_str(&format!(
+            "streams={} bytes_up={} bytes_down={} close={}",
+            self.streams, self.bytes_up, self.bytes_down, self.close_reason
+        ));
+        out
+    }
+}
+
+/// Extra filters for `list_for_device` besides the time window.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct AuditLogFilter {
+    /// Matches the user's email or name.
+    #[serde(default)]
+    pub user: Option<String>,
+    /// Only relayed-traffic entries of this stream type.
+    #[serde(default, rename = "type")]
+    pub stream_type: Option<String>,
 }
 
 impl AuditLogDoc {
@@ -55,6 +112,7 @@ impl AuditLogDoc {
             details: details.to_string(),
             device_id,
             expires_at,
+            stream: None,
         };
         db.audit_logs()
             .insert_one(&doc)
@@ -63,12 +121,54 @@ impl AuditLogDoc {
         Ok(())
     }
 
+    /// Record relayed traffic once the client connection it belongs to ended.
+    pub async fn add_stream_event(
+        db: &Arc<Mongo>,
+        claims: &Claims,
+        config: &Arc<AppConfig>,
+        device_id: Option<ObjectId>,
+        stream: StreamAuditFields,
+    ) -> ServerResult<()> {
+        let expires_at = Some(DateTime::from_system_time(
+            DateTime::now().to_system_time()
+                + Duration::from_hours((config.audit_retention_days * 24) as u64),
+        ));
+
+        let doc = Self {
+            id: None,
+            timestamp: DateTime::now(),
+            user_id: claims.user_id,
+            user_name: claims.user_name.clone(),
+            user_mail: claims.user_email.clone(),
+            action: format!("Relayed {} session", stream.stream_type),
+            details: stream.summary(),
+            device_id,
+            expires_at,
+            stream: Some(stream),
+        };
+        db.audit_logs()
+            .insert_one(&doc)
+            .await
+            .map_err(|_| ServerError::internal_error("Failed to insert audit log"))?;
+        Ok(())
+    }
+
     pub async fn list_for_device(
         db: &Arc<Mongo>,
         device_id: ObjectId,
         pagination: &RequestPagination,
+        extra: &AuditLogFilter,
     ) -> ServerResult<Vec<AuditLogDoc>> {
         let mut filter = doc! { "device_id": device_id };
+        if let Some(user) = &extra.user {
+            filter.insert(
+                "$or",
+                vec![doc! { "user_mail": user }, doc! { "user_name": user }],
+            );
+        }
+        if let Some(stream_type) = &extra.stream_type {
+            filter.insert("stream.stream_type", stream_type.to_ascii_lowercase());
+        }
         if pagination.since.is_some() || pagination.until.is_some() {
             let mut ts = mongodb::bson::Document::new();
             if let Some(since) = pagination.since {
@@ -102,6 +202,7 @@ impl AuditLogDoc {
             action: self.action.clone(),
             details: self.details.clone(),
             device_id: self.device_id.clone().map(|id| id.to_string()),
+            stream: self.stream.as_ref().map(|s| s.to_stream_audit_event()),
         }
     }
 }
@@ -12,19 +12,22 @@ use m87_shared::{
         StepState, StepStatus, UnitKind, UpdateDeployRevisionBody,
     },
     device::ObserveStatus,
+    org::WebhookEvent,
 };
 use mongodb::{
     bson::{Bson, DateTime as BsonDateTime, Document, doc, oid::ObjectId, to_bson},
     error::{ErrorKind, WriteFailure},
-    options::{FindOptions, UpdateModifications, UpdateOptions},
+    options::{FindOneAndUpdateOptions, FindOptions, ReturnDocument, UpdateModifications},
 };
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 
 use crate::{
     auth::access_control::AccessControlled,
     db::Mongo,
     response::{ServerError, ServerResult},
     util::pagination::RequestPagination,
+    webhooks,
 };
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -619,13 +622,52 @@ impl DeployReportDoc {
         let res = db.deploy_reports().insert_one(&doc).await.map_err(|e| {
             ServerError::internal_error(&format!("Failed to create deploy report: {:?}", e))
         })?;
-        if let Err(e) = CurrentRunStateDoc::upsert_from_deploy_report(db, &doc).await {
-            tracing::error!("Failed to upsert current run state: {:?}", e);
+        match CurrentRunStateDoc::upsert_from_deploy_report(db, &doc).await {
+            Ok(true) => doc.notify_failure(db, WebhookEvent::ObserveUnhealthy),
+            Ok(false) => {}
+            Err(e) => tracing::error!("Failed to upsert current run state: {:?}", e),
+        }
+        match &doc.kind {
+            DeployReportKind::StepReport(r) if !r.success => {
+                doc.notify_failure(db, WebhookEvent::StepFailed)
+            }
+            DeployReportKind::JobRunReport(r) if r.status == JobRunStatus::Failed => {
+                doc.notify_failure(db, WebhookEvent::JobFailed)
+            }
+            _ => {}
         }
         doc.id = res.inserted_id.as_object_id();
         Ok(doc)
     }
 
+    /// Tell the device's org webhooks about a failure carried by this report.
+    fn notify_failure(&self, db: &Arc<Mongo>, event: WebhookEvent) {
+        let mut data = json!({ "revision_id": &self.revision_id });
+        match &self.kind {
+            DeployReportKind::RunState(r) => {
+                data["run_id"] = json!(r.run_id);
+                data["alive"] = json!(r.alive);
+                data["log_tail"] = json!(r.log_tail);
+            }
+            DeployReportKind::StepReport(r) => {
+                data["run_id"] = json!(r.run_id);
+                data["step"] = json!(r.name);
+                data["attempts"] = json!(r.attempts);
+                data["exit_code"] = json!(r.exit_code);
+                data["is_undo"] = json!(r.is_undo);
+                data["error"] = json!(r.error);
+                data["log_tail"] = json!(r.log_tail);
+            }
+            DeployReportKind::JobRunReport(r) => {
+                data["run_id"] = json!(r.run_id);
+                data["job_def_id"] = json!(r.job_def_id);
+                data["error"] = json!(r.error);
+            }
+            _ => {}
+        }
+        webhooks::notify_device_id(db, self.device_id, event, data);
+    }
+
     pub fn to_pub_report(&self) -> DeployReport {
         DeployReport {
             device_id: self.device_id.to_string(),
@@ -1225,22 +1267,32 @@ pub struct CurrentRunStateDoc {
     #[serde(default)]
     pub unhealthy_checks: u64,
 
+    /// Unhealthy checks in a row since the last healthy one
+    #[serde(default)]
+    pub consecutive_unhealthy: u64,
+
     /// Total number of crash events observed so far
     #[serde(default)]
     pub crashes: u64,
 
+    /// Last values of the unit's `metrics` hook
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metrics: BTreeMap<String, f64>,
+
     /// When this state was last updated (server time)
     pub updated_at: BsonDateTime,
 }
 
 impl CurrentRunStateDoc {
+    /// Returns true if the run just turned unhealthy (it was healthy, or
+    /// unknown, before this report).
     pub async fn upsert_from_deploy_report(
         db: &Arc<Mongo>,
         report: &DeployReportDoc,
-    ) -> ServerResult<()> {
+    ) -> ServerResult<bool> {
         let run_state = match &report.kind {
             DeployReportKind::RunState(rs) => rs,
-            _ => return Ok(()),
+            _ => return Ok(false),
         };
 
         let now = BsonDateTime::now();
@@ -1261,6 +1313,17 @@ impl CurrentRunStateDoc {
         }
         if let Some(healthy) = run_state.healthy {
             set_doc.insert("healthy", healthy);
+            if healthy {
+                set_doc.insert("consecutive_unhealthy", 0i64);
+            }
+        }
+        if !run_state.metrics.is_empty() {
+            let metrics: Document = run_state
+                .metrics
+                .iter()
+                .map(|(name, value)| (name.clone(), Bson::Double(*value)))
+                .collect();
+            set_doc.insert("metrics", metrics);
         }
 
         // Build $inc dynamically (or keep zeros out)
@@ -1268,6 +1331,7 @@ impl CurrentRunStateDoc {
         if let Some(healthy) = run_state.healthy {
             if !healthy {
                 inc_doc.insert("unhealthy_checks", 1i64);
+                inc_doc.insert("consecutive_unhealthy", 1i64);
             }
         }
         if let Some(alive) = run_state.alive {
@@ -1300,20 +1364,26 @@ impl CurrentRunStateDoc {
             ],
         };
 
+        let options = FindOneAndUpdateOptions::builder()
+            .upsert(true)
+            .return_document(ReturnDocument::Before)
+            .build();
         let res = db
             .current_run_states()
-            .update_one(filter, update)
-            .with_options(UpdateOptions::builder().upsert(true).build())
+            .find_one_and_update(filter, update)
+            .with_options(options)
             .await;
 
         match res {
-            Ok(_) => Ok(()),
+            Ok(before) => {
+                Ok(run_state.healthy == Some(false) && before.is_none_or(|prev| prev.healthy))
+            }
 
             Err(e) => match e.kind.as_ref() {
                 // This is the case you want to ignore
                 ErrorKind::Write(WriteFailure::WriteError(we)) if we.code == 11000 => {
                     // Existing doc + older report → ignored
-                    Ok(())
+                    Ok(false)
                 }
 
                 // Everything else is real failure
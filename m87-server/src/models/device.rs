@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::Display;
 use std::sync::Arc;
 use std::time::{Duration, SystemTime};
@@ -6,7 +6,9 @@ use std::time::{Duration, SystemTime};
 use m87_shared::deploy_spec::{
     DeployReportKind, DeploymentRevision, LifecycleUpdate, build_instruction_hash,
 };
-use m87_shared::device::DeviceStatus;
+use m87_shared::device::{DeviceStatus, UpdateDeviceLabelsBody};
+use m87_shared::heartbeat::{LogBatch, LogBatchAck};
+use m87_shared::metrics::SystemMetrics;
 use m87_shared::roles::Role;
 use m87_shared::users::User;
 use mongodb::bson::{DateTime, Document, doc, oid::ObjectId, to_bson};
@@ -20,12 +22,16 @@ pub use m87_shared::heartbeat::{HeartbeatRequest, HeartbeatResponse};
 use tokio_stream::StreamExt;
 
 use crate::config::AppConfig;
+use crate::metrics;
+use crate::models::api_key::{ApiKeyDoc, CreateApiKey};
 use crate::models::audit_logs::AuditLogDoc;
 use crate::models::deploy_spec::{
     CreateDeployReportBody, DeployReportDoc, DeployRevisionDoc, JobRunDoc,
 };
+use crate::models::device_log::DeviceLogDoc;
 use crate::models::org;
 use crate::models::roles::{CreateRoleBinding, RoleDoc};
+use crate::models::unit_metrics::UnitMetricsDoc;
 use crate::models::user::UserDoc;
 use crate::{
     auth::{access_control::AccessControlled, claims::Claims},
@@ -95,6 +101,58 @@ impl UpdateDeviceBody {
     }
 }
 
+const MAX_LABEL_KEY_LEN: usize = 63;
+const MAX_LABEL_VALUE_LEN: usize = 256;
+
+/// Label keys become document paths (`labels.<key>`), so keep them to a
+/// conservative charset without `.` or `$`.
+fn validate_label_key(key: &str) -> ServerResult<()> {
+    let valid = !key.is_empty()
+        && key.len() <= MAX_LABEL_KEY_LEN
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '/'));
+    if !valid {
+        return Err(ServerError::bad_request(&format!(
+            "Invalid label key '{}': use up to {} of a-z, A-Z, 0-9, '_', '-', '/'",
+            key, MAX_LABEL_KEY_LEN
+        )));
+    }
+    Ok(())
+}
+
+pub fn labels_update_doc(body: &UpdateDeviceLabelsBody) -> ServerResult<Document> {
+    let mut set_fields = doc! {};
+    for (key, value) in &body.set {
+        validate_label_key(key)?;
+        if value.len() > MAX_LABEL_VALUE_LEN {
+            return Err(ServerError::bad_request(&format!(
+                "Label value for '{}' is longer than {} bytes",
+                key, MAX_LABEL_VALUE_LEN
+            )));
+        }
+        set_fields.insert(format!("labels.{key}"), value);
+    }
+    let mut unset_fields = doc! {};
+    for key in &body.remove {
+        validate_label_key(key)?;
+        if body.set.contains_key(key) {
+            return Err(ServerError::bad_request(&format!(
+                "Label '{}' is both set and removed",
+                key
+            )));
+        }
+        unset_fields.insert(format!("labels.{key}"), "");
+    }
+
+    set_fields.insert("updated_at", DateTime::now());
+    let mut update = doc! { "$set": set_fields };
+    if !unset_fields.is_empty() {
+        update.insert("$unset", unset_fields);
+    }
+    Ok(update)
+}
+
 #[derive(Deserialize, Serialize, Default)]
 pub struct CreateDeviceBody {
     pub id: Option<String>,
@@ -130,6 +188,61 @@ pub struct DeviceDoc {
     pub last_deployment_hash: String,
     #[serde(default)]
     pub pending_lifecycle_updates: Vec<LifecycleUpdate>,
+    /// Free-form `key=value` tags, used by alert rule selectors.
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    #[serde(default)]
+    pub last_heartbeat_at: Option<DateTime>,
+    /// Headline numbers from the latest heartbeat that carried metrics.
+    #[serde(default)]
+    pub vitals: Option<DeviceVitals>,
+    /// Key handed out during a rotation, until the device confirms it.
+    #[serde(default)]
+    pub pending_api_key_id: Option<ObjectId>,
+    /// Set by `revoke`; the device can't connect until re-approved.
+    #[serde(default)]
+    pub revoked_at: Option<DateTime>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct DeviceVitals {
+    pub cpu_usage_percent: f64,
+    pub memory_usage_percent: f64,
+    pub disk_usage_percent: f64,
+    /// Thermal and power readings, on devices that report them.
+    #[serde(default)]
+    pub max_temp_c: Option<f64>,
+    #[serde(default)]
+    pub min_fan_rpm: Option<f64>,
+    #[serde(default)]
+    pub cpu_freq_mhz: Option<f64>,
+    #[serde(default)]
+    pub undervoltage: Option<bool>,
+    #[serde(default)]
+    pub throttled: Option<bool>,
+    /// Unset on devices without Docker.
+    #[serde(default)]
+    pub unhealthy_containers: Option<f64>,
+    pub reported_at: DateTime,
+}
+
+impl DeviceVitals {
+    pub fn from_metrics(metrics: &SystemMetrics) -> Self {
+        let thermal = metrics.thermal.as_ref();
+        let power = metrics.power.as_ref();
+        Self {
+            cpu_usage_percent: metrics.cpu.usage_percent as f64,
+            memory_usage_percent: metrics.memory.usage_percent as f64,
+            disk_usage_percent: metrics.disk.usage_percent as f64,
+            max_temp_c: thermal.and_then(|t| t.max_temp_c()).map(f64::from),
+            min_fan_rpm: thermal.and_then(|t| t.min_fan_rpm()).map(f64::from),
+            cpu_freq_mhz: power.and_then(|p| p.cpu_freq_mhz).map(f64::from),
+            undervoltage: power.map(|p| p.throttle.undervoltage),
+            throttled: power.map(|p| p.throttle.throttled || p.throttle.freq_capped),
+            unhealthy_containers: metrics.containers.as_ref().map(|c| c.unhealthy as f64),
+            reported_at: DateTime::now(),
+        }
+    }
 }
 
 impl DeviceDoc {
@@ -140,6 +253,15 @@ impl DeviceDoc {
         format!("device:{}", device_id.to_string())
     }
 
+    /// Orgs the device belongs to or is shared with.
+    pub fn org_ids(&self) -> Vec<String> {
+        std::iter::once(&self.owner_scope)
+            .chain(self.allowed_scopes.iter())
+            .filter_map(|scope| scope.strip_prefix("org:"))
+            .map(str::to_string)
+            .collect()
+    }
+
     pub async fn create_from(db: &Arc<Mongo>, create_body: CreateDeviceBody) -> ServerResult<()> {
         let device_id = match create_body.id {
             Some(id) => ObjectId::parse_str(&id)?,
@@ -174,6 +296,11 @@ impl DeviceDoc {
             last_config_hash: "".to_string(),
             last_deployment_hash: "".to_string(),
             pending_lifecycle_updates: vec![],
+            labels: BTreeMap::new(),
+            last_heartbeat_at: None,
+            vitals: None,
+            pending_api_key_id: None,
+            revoked_at: None,
         };
         let _ = db.devices().insert_one(node.clone()).await?;
         Ok(())
@@ -262,6 +389,19 @@ impl DeviceDoc {
         if !update_fields.is_empty() {
             update_fields.insert("updated_at", DateTime::now());
         }
+        update_fields.insert("last_heartbeat_at", DateTime::now());
+        if let Some(metrics) = &payload.metrics
+            && let Ok(vitals) = to_bson(&DeviceVitals::from_metrics(metrics))
+        {
+            update_fields.insert("vitals", vitals);
+        }
+        if let Some(metrics) = &payload.metrics
+            && !metrics.units.is_empty()
+            && let Err(e) =
+                UnitMetricsDoc::record(db, config, self.id.unwrap(), &metrics.units).await
+        {
+            tracing::warn!("failed to store unit metrics: {:?}", e);
+        }
 
         let _ = db
             .devices()
@@ -275,6 +415,17 @@ impl DeviceDoc {
             )
             .await;
 
+        let new_api_key = match payload.api_key_id.as_deref() {
+            Some(stored_key_id) => self
+                .rotate_api_key(db, config, stored_key_id, claims.api_key_id.as_deref())
+                .await
+                .unwrap_or_else(|e| {
+                    tracing::warn!("device key rotation failed: {:?}", e);
+                    None
+                }),
+            None => None,
+        };
+
         let mut ack_report_hash = None;
         if let Some(deploy_report) = payload.deploy_report {
             let body = CreateDeployReportBody {
@@ -290,6 +441,7 @@ impl DeviceDoc {
                 )),
             };
             let res = DeployReportDoc::create_or_update(db, body).await;
+            metrics::record_deploy_report(&deploy_report, res.is_ok());
             if let Err(err) = res {
                 tracing::error!("Failed to create deploy report: {}", err);
             }
@@ -316,6 +468,8 @@ impl DeviceDoc {
             }
         }
 
+        let log_ack = self.ingest_logs(db, config, payload.log_batch).await;
+
         let ack_hash_list = match ack_report_hash {
             Some(hash) => Some(vec![hash]),
             None => None,
@@ -352,6 +506,8 @@ impl DeviceDoc {
                 received_report_hashes: ack_hash_list,
                 lifecycle_updates: pending_updates.clone(),
                 pending_job_runs: pending_job_runs.clone(),
+                new_api_key,
+                log_ack,
             });
         }
 
@@ -391,10 +547,214 @@ impl DeviceDoc {
             received_report_hashes: ack_hash_list,
             lifecycle_updates: pending_updates,
             pending_job_runs,
+            new_api_key,
+            log_ack,
         };
         Ok(resp)
     }
 
+    /// Store a request that only carries shipped logs. Unlike a heartbeat it
+    /// leaves the device's state, lifecycle updates and job runs alone, and
+    /// echoes the device's instruction hash.
+    pub async fn handle_log_batch(
+        &self,
+        db: &Arc<Mongo>,
+        payload: HeartbeatRequest,
+        config: &Arc<AppConfig>,
+    ) -> HeartbeatResponse {
+        HeartbeatResponse {
+            up_to_date: true,
+            config: None,
+            instruction_hash: payload.last_instruction_hash,
+            target_revision: None,
+            received_report_hashes: None,
+            lifecycle_updates: Vec::new(),
+            pending_job_runs: Vec::new(),
+            new_api_key: None,
+            log_ack: self.ingest_logs(db, config, payload.log_batch).await,
+        }
+    }
+
+    async fn ingest_logs(
+        &self,
+        db: &Arc<Mongo>,
+        config: &Arc<AppConfig>,
+        batch: Option<LogBatch>,
+    ) -> Option<LogBatchAck> {
+        DeviceLogDoc::ingest(db, config, self.id?, batch?)
+            .await
+            .inspect_err(|e| tracing::error!("Failed to store shipped logs: {}", e))
+            .ok()
+    }
+
+    /// One step of the key rotation handshake for a device that has stored
+    /// `stored_key_id` and authenticated with `auth_key_id`. A pending key is
+    /// promoted once the device uses either way to show it has it; otherwise
+    /// it is dropped and, if rotation is due, a fresh one handed out.
+    async fn rotate_api_key(
+        &self,
+        db: &Arc<Mongo>,
+        config: &Arc<AppConfig>,
+        stored_key_id: &str,
+        auth_key_id: Option<&str>,
+    ) -> ServerResult<Option<String>> {
+        let Some(device_id) = self.id else {
+            return Ok(None);
+        };
+
+        if let Some(pending_id) = self.pending_api_key_id {
+            let confirmed = ApiKeyDoc::find_by_id(db, pending_id)
+                .await?
+                .is_some_and(|key| {
+                    key.key_id == stored_key_id || auth_key_id == Some(key.key_id.as_str())
+                });
+            if confirmed {
+                let res = db
+                    .devices()
+                    .update_one(
+                        doc! { "_id": device_id, "pending_api_key_id": pending_id },
+                        doc! { "$set": { "api_key_id": pending_id, "pending_api_key_id": null } },
+                    )
+                    .await?;
+                if res.modified_count == 1 {
+                    ApiKeyDoc::delete_with_roles(db, self.api_key_id).await?;
+                    tracing::info!(device_id = %device_id, "device API key rotated");
+                }
+                return Ok(None);
+            }
+            // The device never stored it, e.g. the response got lost.
+            ApiKeyDoc::delete_with_roles(db, pending_id).await?;
+            db.devices()
+                .update_one(
+                    doc! { "_id": device_id, "pending_api_key_id": pending_id },
+                    doc! { "$set": { "pending_api_key_id": null } },
+                )
+                .await?;
+        }
+
+        if config.device_key_rotation_days == 0 {
+            return Ok(None);
+        }
+        let Some(current) = ApiKeyDoc::find_by_id(db, self.api_key_id).await? else {
+            return Ok(None);
+        };
+        let max_age = Duration::from_hours(24 * config.device_key_rotation_days as u64);
+        let due = current.created_at.is_none_or(|created| {
+            created
+                .to_system_time()
+                .elapsed()
+                .is_ok_and(|age| age >= max_age)
+        });
+        if !due {
+            return Ok(None);
+        }
+
+        let (key_doc, api_key) = ApiKeyDoc::create(
+            db,
+            CreateApiKey {
+                name: current.name.clone(),
+                ttl_secs: None,
+                scopes: vec![(Self::scope_for_device(&device_id), Role::Editor)],
+                owner_id: None,
+                owner_email: None,
+            },
+        )
+        .await?;
+        let Some(new_id) = key_doc.id else {
+            return Ok(None);
+        };
+        let res = db
+            .devices()
+            .update_one(
+                doc! { "_id": device_id, "pending_api_key_id": null },
+                doc! { "$set": { "pending_api_key_id": new_id } },
+            )
+            .await?;
+        if res.modified_count == 0 {
+            ApiKeyDoc::delete_with_roles(db, new_id).await?;
+            return Ok(None);
+        }
+        Ok(Some(api_key))
+    }
+
+    /// Invalidate the device's keys and mark it revoked. Closing its control
+    /// tunnel is up to the relay.
+    pub async fn revoke(&self, db: &Arc<Mongo>) -> ServerResult<()> {
+        let Some(device_id) = self.id else {
+            return Err(ServerError::not_found("Device not found"));
+        };
+        ApiKeyDoc::delete_with_roles(db, self.api_key_id).await?;
+        if let Some(pending_id) = self.pending_api_key_id {
+            ApiKeyDoc::delete_with_roles(db, pending_id).await?;
+        }
+        let now = DateTime::now();
+        db.devices()
+            .update_one(
+                doc! { "_id": device_id },
+                doc! { "$set": {
+                    "revoked_at": now,
+                    "pending_api_key_id": null,
+                    "updated_at": now,
+                } },
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Bind a freshly approved key to an already registered device, keeping
+    /// its history. Returns false if no device with this id exists.
+    pub async fn reinstate(
+        db: &Arc<Mongo>,
+        device_id: ObjectId,
+        api_key_id: ObjectId,
+        system_info: &DeviceSystemInfo,
+    ) -> ServerResult<bool> {
+        let Some(existing) = db.devices().find_one(doc! { "_id": device_id }).await? else {
+            return Ok(false);
+        };
+        let system_info =
+            to_bson(system_info).map_err(|e| ServerError::internal_error(&e.to_string()))?;
+        ApiKeyDoc::delete_with_roles(db, existing.api_key_id).await?;
+        if let Some(pending_id) = existing.pending_api_key_id {
+            ApiKeyDoc::delete_with_roles(db, pending_id).await?;
+        }
+        db.devices()
+            .update_one(
+                doc! { "_id": device_id },
+                doc! { "$set": {
+                    "api_key_id": api_key_id,
+                    "system_info": system_info,
+                    "revoked_at": null,
+                    "pending_api_key_id": null,
+                    "updated_at": DateTime::now(),
+                } },
+            )
+            .await?;
+        Ok(true)
+    }
+
+    /// Those of `short_ids` whose credentials were revoked.
+    pub async fn revoked_short_ids(
+        db: &Arc<Mongo>,
+        short_ids: &[String],
+    ) -> ServerResult<Vec<String>> {
+        if short_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut cursor = db
+            .devices()
+            .find(doc! {
+                "short_id": { "$in": short_ids },
+                "revoked_at": { "$ne": null },
+            })
+            .await?;
+        let mut revoked = Vec::new();
+        while let Some(device) = cursor.next().await {
+            revoked.push(device?.short_id);
+        }
+        Ok(revoked)
+    }
+
     pub async fn get_status(&self, db: &Arc<Mongo>) -> ServerResult<DeviceStatus> {
         let active_revision =
             DeployRevisionDoc::get_active_device_deployment(db, self.id.clone().unwrap()).await?;
@@ -617,6 +977,8 @@ impl DeviceDoc {
             target_version: self.target_version.clone(),
             config: self.config.clone(),
             system_info: self.system_info.clone(),
+            labels: self.labels.clone(),
+            revoked_at: self.revoked_at.and_then(|t| t.try_to_rfc3339_string().ok()),
             role: role.clone(),
         }
     }
@@ -1,6 +1,7 @@
 use std::sync::Arc;
 
 use m87_shared::device::DeviceSystemInfo;
+use m87_shared::org::WebhookEvent;
 use mongodb::bson::{DateTime, doc, oid::ObjectId};
 
 use serde::{Deserialize, Serialize};
@@ -15,6 +16,7 @@ use crate::{
     auth::access_control::AccessControlled,
     db::Mongo,
     response::{ServerError, ServerResult},
+    webhooks,
 };
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -46,13 +48,30 @@ impl DeviceAuthRequestDoc {
         };
         let _ = db
             .device_auth_requests()
-            .insert_one(request)
+            .insert_one(&request)
             .await
             .map_err(|err| {
                 tracing::error!("Failed to create device auth request: {}", err);
                 ServerError::internal_error("Failed to create device auth request")
             })?;
 
+        if let Some(org_id) = request.owner_scope.strip_prefix("org:") {
+            let info = &request.device_info;
+            webhooks::notify_org(
+                db,
+                org_id,
+                WebhookEvent::DeviceAuthRequest,
+                serde_json::json!({
+                    "request_id": &request.request_id,
+                    "device_id": &request.device_id,
+                    "hostname": &info.hostname,
+                    "operating_system": &info.operating_system,
+                    "architecture": &info.architecture,
+                    "public_ip_address": &info.public_ip_address,
+                }),
+            );
+        }
+
         Ok(request_uuid)
     }
 }
@@ -0,0 +1,293 @@
+use std::collections::HashMap;
+use std::io::Read;
+use std::{sync::Arc, time::Duration};
+
+use futures::TryStreamExt;
+use m87_shared::device::{DeviceLogEntry, DeviceLogQuery};
+use m87_shared::heartbeat::{LogBatch, LogBatchAck, ShippedLogLine};
+use mongodb::bson::{DateTime, Document, doc, oid::ObjectId};
+use mongodb::options::FindOptions;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::AppConfig,
+    db::Mongo,
+    metrics,
+    response::{ServerError, ServerResult},
+};
+
+/// Upper bound for a decompressed batch, so a small upload can't expand
+/// into an arbitrary amount of memory.
+const MAX_BATCH_BYTES: u64 = 8 * 1024 * 1024;
+const MAX_LINE_BYTES: usize = 16 * 1024;
+const DEFAULT_QUERY_LIMIT: u32 = 200;
+const MAX_QUERY_LIMIT: u32 = 5000;
+const DAY_SECS: u64 = 24 * 3600;
+
+/// Bytes a device may ship per day; `None` without a quota.
+fn daily_quota_bytes(config: &AppConfig) -> Option<u64> {
+    (config.log_quota_mb_per_day > 0).then(|| config.log_quota_mb_per_day as u64 * 1024 * 1024)
+}
+
+/// One log line shipped by a device.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceLogDoc {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub device_id: ObjectId,
+    pub source: String,
+    pub at: DateTime,
+    pub line: String,
+    pub expires_at: DateTime,
+}
+
+/// Bytes a device shipped on one UTC day, for the daily quota.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceLogUsageDoc {
+    /// `<device id>:<days since epoch>`
+    #[serde(rename = "_id")]
+    pub id: String,
+    pub bytes: i64,
+    pub expires_at: DateTime,
+}
+
+impl DeviceLogDoc {
+    /// Store a batch unless the device is over its daily quota. Batches that
+    /// can't be decoded or are larger than the whole quota are acknowledged
+    /// and dropped, since resending them wouldn't help.
+    pub async fn ingest(
+        db: &Arc<Mongo>,
+        config: &Arc<AppConfig>,
+        device_id: ObjectId,
+        batch: LogBatch,
+    ) -> ServerResult<LogBatchAck> {
+        let mut ack = LogBatchAck {
+            seq: batch.seq,
+            retry_after_secs: None,
+            too_large: false,
+        };
+        let lines = match decode_batch(&batch.data) {
+            Ok(lines) => lines,
+            Err(e) => {
+                tracing::warn!(%device_id, "dropping undecodable log batch: {e}");
+                metrics::record_shipped_logs("invalid", 1);
+                return Ok(ack);
+            }
+        };
+        if lines.is_empty() {
+            return Ok(ack);
+        }
+
+        let bytes: u64 = lines.iter().map(|l| l.line.len() as u64).sum();
+        if daily_quota_bytes(config).is_some_and(|quota| bytes > quota) {
+            tracing::warn!(%device_id, bytes, "dropping log batch larger than the daily quota");
+            ack.too_large = true;
+            metrics::record_shipped_logs("too_large", lines.len() as u64);
+            return Ok(ack);
+        }
+        if !Self::reserve_quota(db, config, device_id, bytes).await? {
+            let now = DateTime::now().timestamp_millis().max(0) as u64 / 1000;
+            ack.retry_after_secs = Some(DAY_SECS - now % DAY_SECS);
+            metrics::record_shipped_logs("over_quota", lines.len() as u64);
+            return Ok(ack);
+        }
+
+        let expires_at = DateTime::from_system_time(
+            DateTime::now().to_system_time()
+                + Duration::from_hours(24 * config.log_retention_days as u64),
+        );
+        let count = lines.len() as u64;
+        let docs: Vec<DeviceLogDoc> = lines
+            .into_iter()
+            .map(|l| DeviceLogDoc {
+                id: None,
+                device_id,
+                source: l.source,
+                at: DateTime::from_millis(l.at_ms.min(i64::MAX as u64) as i64),
+                line: truncate(l.line),
+                expires_at,
+            })
+            .collect();
+        db.device_logs().insert_many(docs).await?;
+        metrics::record_shipped_logs("stored", count);
+        Ok(ack)
+    }
+
+    /// Count `bytes` against today's quota; `false` if they don't fit.
+    async fn reserve_quota(
+        db: &Arc<Mongo>,
+        config: &Arc<AppConfig>,
+        device_id: ObjectId,
+        bytes: u64,
+    ) -> ServerResult<bool> {
+        let Some(quota) = daily_quota_bytes(config) else {
+            return Ok(true);
+        };
+        let quota = quota as i64;
+        let now = DateTime::now();
+        let day = now.timestamp_millis().max(0) as u64 / 1000 / DAY_SECS;
+        let id = format!("{device_id}:{day}");
+        let expires_at =
+            DateTime::from_system_time(now.to_system_time() + Duration::from_secs(2 * DAY_SECS));
+
+        db.device_log_usage()
+            .update_one(
+                doc! { "_id": &id },
+                doc! { "$setOnInsert": { "bytes": 0i64, "expires_at": expires_at } },
+            )
+            .upsert(true)
+            .await?;
+        // Only counts when the batch fits, so a rejected batch doesn't use up
+        // the rest of the day's quota.
+        let res = db
+            .device_log_usage()
+            .update_one(
+                doc! { "_id": &id, "bytes": { "$lte": quota - bytes as i64 } },
+                doc! { "$inc": { "bytes": bytes as i64 } },
+            )
+            .await?;
+        Ok(res.matched_count == 1)
+    }
+
+    /// Newest matching lines of `devices` (id → short id and name), returned
+    /// oldest first.
+    pub async fn query(
+        db: &Arc<Mongo>,
+        devices: &HashMap<ObjectId, (String, String)>,
+        query: &DeviceLogQuery,
+    ) -> ServerResult<Vec<DeviceLogEntry>> {
+        let ids: Vec<ObjectId> = devices.keys().copied().collect();
+        let mut filter = doc! { "device_id": { "$in": ids } };
+        if let Some(source) = &query.source {
+            filter.insert("source", source);
+        }
+        if let Some(grep) = &query.grep {
+            check_grep(grep)?;
+            filter.insert("line", doc! { "$regex": grep });
+        }
+        if query.since_ms.is_some() || query.until_ms.is_some() {
+            let mut at = Document::new();
+            if let Some(since) = query.since_ms {
+                at.insert("$gte", DateTime::from_millis(since as i64));
+            }
+            if let Some(until) = query.until_ms {
+                at.insert("$lte", DateTime::from_millis(until as i64));
+            }
+            filter.insert("at", at);
+        }
+
+        let limit = query
+            .limit
+            .unwrap_or(DEFAULT_QUERY_LIMIT)
+            .clamp(1, MAX_QUERY_LIMIT);
+        let options = FindOptions::builder()
+            .limit(Some(limit as i64))
+            .sort(doc! { "at": -1, "_id": -1 })
+            .max_time(Some(Duration::from_secs(30)))
+            .build();
+        let docs: Vec<DeviceLogDoc> = db
+            .device_logs()
+            .find(filter)
+            .with_options(options)
+            .await?
+            .try_collect()
+            .await?;
+
+        Ok(docs
+            .into_iter()
+            .rev()
+            .map(|d| {
+                let (short_id, name) = devices.get(&d.device_id).cloned().unwrap_or_default();
+                DeviceLogEntry {
+                    device_id: short_id,
+                    device_name: name,
+                    source: d.source,
+                    at_ms: d.at.timestamp_millis().max(0) as u64,
+                    line: d.line,
+                }
+            })
+            .collect())
+    }
+}
+
+fn decode_batch(data: &[u8]) -> std::io::Result<Vec<ShippedLogLine>> {
+    let mut raw = Vec::new();
+    zstd::stream::read::Decoder::new(data)?
+        .take(MAX_BATCH_BYTES + 1)
+        .read_to_end(&mut raw)?;
+    if raw.len() as u64 > MAX_BATCH_BYTES {
+        return Err(std::io::Error::other("batch too large"));
+    }
+    raw.split(|b| *b == b'\n')
+        .filter(|l| !l.is_empty())
+        .map(|l| serde_json::from_slice(l).map_err(std::io::Error::other))
+        .collect()
+}
+
+fn truncate(mut line: String) -> String {
+    if line.len() > MAX_LINE_BYTES {
+        let mut end = MAX_LINE_BYTES;
+        while !line.is_char_boundary(end) {
+            end -= 1;
+        }
+        line.truncate(end);
+    }
+    line
+}
+
+/// The pattern runs against every candidate line, so keep it small.
+fn check_grep(pattern: &str) -> ServerResult<()> {
+    if pattern.len() > 512 {
+        return Err(ServerError::bad_request("grep pattern is too long"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn encode(lines: &[ShippedLogLine]) -> Vec<u8> {
+        let mut enc = zstd::stream::write::Encoder::new(Vec::new(), 3).unwrap();
+        for line in lines {
+            enc.write_all(&serde_json::to_vec(line).unwrap()).unwrap();
+            enc.write_all(b"\n").unwrap();
+        }
+        enc.finish().unwrap()
+    }
+
+    #[test]
+    fn decodes_ndjson_batches() {
+        let lines = vec![
+            ShippedLogLine {
+                at_ms: 1,
+                source: "m87".into(),
+                line: "INFO started".into(),
+            },
+            ShippedLogLine {
+                at_ms: 2,
+                source: "web".into(),
+                line: "segfault".into(),
+            },
+        ];
+        assert_eq!(decode_batch(&encode(&lines)).unwrap(), lines);
+        assert!(decode_batch(b"not zstd").is_err());
+    }
+
+    #[test]
+    fn rejects_batches_that_expand_too_far() {
+        let big = ShippedLogLine {
+            at_ms: 1,
+            source: "m87".into(),
+            line: "x".repeat(MAX_BATCH_BYTES as usize),
+        };
+        assert!(decode_batch(&encode(&[big])).is_err());
+    }
+
+    #[test]
+    fn truncates_on_char_boundary() {
+        let line = format!("{}é", "a".repeat(MAX_LINE_BYTES - 1));
+        assert_eq!(truncate(line).len(), MAX_LINE_BYTES - 1);
+    }
+}
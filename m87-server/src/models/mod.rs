@@ -1,8 +1,14 @@
+pub mod alert;
 pub mod api_key;
 pub mod audit_logs;
 pub mod deploy_spec;
 pub mod device;
 pub mod device_auth_request;
+pub mod device_log;
 pub mod org;
 pub mod roles;
+pub mod session_recording;
+pub mod tunnel_lease;
+pub mod unit_metrics;
 pub mod user;
+pub mod webhook;
@@ -1,8 +1,10 @@
 use std::{collections::HashMap, sync::Arc, time::Duration};
 
 use futures::{StreamExt, TryStreamExt, stream};
-use m87_shared::{device::PublicDevice, roles::Role, users::User};
+use m87_shared::{device::PublicDevice, org::OrgSettings, roles::Role, users::User};
 use mongodb::bson::doc;
+use mongodb::options::{ReplaceOptions, UpdateOptions};
+use serde::{Deserialize, Serialize};
 use tokio::time::timeout;
 
 use crate::{
@@ -144,3 +146,80 @@ pub fn org_scope(org_id: &str) -> String {
 pub fn org_ref(org_id: &str) -> String {
     org_scope(org_id)
 }
+
+/// Per-organization policy, one document per org id. Orgs themselves only
+/// exist as role scopes, so a missing document means all defaults.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OrgSettingsDoc {
+    #[serde(rename = "_id")]
+    pub org_id: String,
+    /// Record `Terminal`/`Exec` sessions on the org's devices.
+    #[serde(default)]
+    pub record_sessions: bool,
+}
+
+impl OrgSettingsDoc {
+    pub async fn get(db: &Arc<Mongo>, org_id: &str) -> ServerResult<Self> {
+        let settings = db
+            .org_settings()
+            .find_one(doc! { "_id": org_id })
+            .await?
+            .unwrap_or_else(|| Self {
+                org_id: org_id.to_string(),
+                ..Default::default()
+            });
+        Ok(settings)
+    }
+
+    pub async fn set_record_sessions(
+        db: &Arc<Mongo>,
+        org_id: &str,
+        record_sessions: bool,
+    ) -> ServerResult<()> {
+        db.org_settings()
+            .update_one(
+                doc! { "_id": org_id },
+                doc! { "$set": { "record_sessions": record_sessions } },
+            )
+            .with_options(UpdateOptions::builder().upsert(true).build())
+            .await?;
+        Ok(())
+    }
+
+    /// Move the settings along with an org rename.
+    pub async fn rename(db: &Arc<Mongo>, old_id: &str, new_id: &str) -> ServerResult<()> {
+        let Some(mut settings) = db.org_settings().find_one(doc! { "_id": old_id }).await? else {
+            return Ok(());
+        };
+        settings.org_id = new_id.to_string();
+        db.org_settings()
+            .replace_one(doc! { "_id": new_id }, &settings)
+            .with_options(ReplaceOptions::builder().upsert(true).build())
+            .await?;
+        db.org_settings().delete_one(doc! { "_id": old_id }).await?;
+        Ok(())
+    }
+
+    pub async fn delete(db: &Arc<Mongo>, org_id: &str) -> ServerResult<()> {
+        db.org_settings().delete_one(doc! { "_id": org_id }).await?;
+        Ok(())
+    }
+
+    /// Whether any of the given orgs requires session recording.
+    pub async fn any_records_sessions(db: &Arc<Mongo>, org_ids: &[String]) -> ServerResult<bool> {
+        if org_ids.is_empty() {
+            return Ok(false);
+        }
+        let found = db
+            .org_settings()
+            .find_one(doc! { "_id": { "$in": org_ids }, "record_sessions": true })
+            .await?;
+        Ok(found.is_some())
+    }
+
+    pub fn to_org_settings(&self) -> OrgSettings {
+        OrgSettings {
+            record_sessions: self.record_sessions,
+        }
+    }
+}
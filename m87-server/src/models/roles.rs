@@ -164,4 +164,11 @@ impl RoleDoc {
             .map_err(|_| ServerError::internal_error("Failed to delete role binding"))?;
         Ok(())
     }
+    pub async fn delete_for_reference(db: &Arc<Mongo>, reference_id: &str) -> ServerResult<()> {
+        db.roles()
+            .delete_many(doc! { "reference_id": reference_id })
+            .await
+            .map_err(|_| ServerError::internal_error("Failed to delete role bindings"))?;
+        Ok(())
+    }
 }
@@ -0,0 +1,104 @@
+use std::{sync::Arc, time::Duration};
+
+use m87_shared::device::SessionRecording;
+use mongodb::bson::{DateTime, doc, oid::ObjectId};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    auth::claims::Claims,
+    config::AppConfig,
+    db::Mongo,
+    response::{ServerError, ServerResult},
+};
+
+/// Asciicast v2 recording of a `Terminal` or `Exec` session, kept alongside
+/// the audit log (and expiring with it).
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct SessionRecordingDoc {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub session_id: String,
+    pub device_id: ObjectId,
+    pub user_id: Option<ObjectId>,
+    pub user_name: String,
+    pub user_mail: String,
+    /// `terminal` or `exec`.
+    pub kind: String,
+    pub command: Option<String>,
+    pub started_at: DateTime,
+    pub ended_at: DateTime,
+    /// Recording stopped early because it hit the size limit.
+    #[serde(default)]
+    pub truncated: bool,
+    pub cast: String,
+    #[serde(default)]
+    pub expires_at: Option<DateTime>,
+}
+
+impl SessionRecordingDoc {
+    #[allow(clippy::too_many_arguments)]
+    pub async fn add(
+        db: &Arc<Mongo>,
+        claims: &Claims,
+        config: &Arc<AppConfig>,
+        session_id: &str,
+        device_id: ObjectId,
+        kind: &str,
+        command: Option<String>,
+        started_at: DateTime,
+        truncated: bool,
+        cast: String,
+    ) -> ServerResult<()> {
+        let expires_at = Some(DateTime::from_system_time(
+            DateTime::now().to_system_time()
+                + Duration::from_hours((config.audit_retention_days * 24) as u64),
+        ));
+
+        let doc = Self {
+            id: None,
+            session_id: session_id.to_string(),
+            device_id,
+            user_id: claims.user_id,
+            user_name: claims.user_name.clone(),
+            user_mail: claims.user_email.clone(),
+            kind: kind.to_string(),
+            command,
+            started_at,
+            ended_at: DateTime::now(),
+            truncated,
+            cast,
+            expires_at,
+        };
+        db.session_recordings()
+            .insert_one(&doc)
+            .await
+            .map_err(|_| ServerError::internal_error("Failed to insert session recording"))?;
+        Ok(())
+    }
+
+    pub async fn find_for_device(
+        db: &Arc<Mongo>,
+        device_id: ObjectId,
+        session_id: &str,
+    ) -> ServerResult<Option<SessionRecordingDoc>> {
+        let doc = db
+            .session_recordings()
+            .find_one(doc! { "device_id": device_id, "session_id": session_id })
+            .await?;
+        Ok(doc)
+    }
+
+    pub fn to_session_recording(&self) -> SessionRecording {
+        SessionRecording {
+            session_id: self.session_id.clone(),
+            kind: self.kind.clone(),
+            command: self.command.clone(),
+            user_name: self.user_name.clone(),
+            user_email: self.user_mail.clone(),
+            started_at: self.started_at.try_to_rfc3339_string().unwrap_or_default(),
+            ended_at: self.ended_at.try_to_rfc3339_string().unwrap_or_default(),
+            truncated: self.truncated,
+            cast: self.cast.clone(),
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use futures::TryStreamExt;
+use mongodb::bson::{DateTime, doc};
+use mongodb::options::{FindOneAndUpdateOptions, ReturnDocument};
+use serde::{Deserialize, Serialize};
+
+use crate::{db::Mongo, response::ServerResult};
+
+/// Which server replica currently holds a device's control tunnel.
+///
+/// One document per device. The owning node refreshes `lease_expires_at`
+/// while the tunnel is up; a lease that was not renewed (node crashed or was
+/// redeployed) is ignored by lookups and eventually removed by a TTL index.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TunnelLeaseDoc {
+    #[serde(rename = "_id")]
+    pub device_short_id: String,
+    pub node_id: String,
+    /// `host:port` of the owning node's QUIC endpoint.
+    pub node_address: String,
+    /// `quinn::Connection::stable_id` of the tunnel on the owning node.
+    pub conn_id: i64,
+    pub lease_expires_at: DateTime,
+}
+
+fn lease_deadline(ttl_secs: i64) -> DateTime {
+    DateTime::from_millis(DateTime::now().timestamp_millis() + ttl_secs * 1000)
+}
+
+impl TunnelLeaseDoc {
+    /// Take ownership of the device's tunnel. The newest connection wins, even
+    /// if another node still holds an unexpired lease. Returns true if there
+    /// was no live lease before, i.e. the device just came online.
+    pub async fn claim(
+        db: &Mongo,
+        device_short_id: &str,
+        node_id: &str,
+        node_address: &str,
+        conn_id: usize,
+        ttl_secs: i64,
+    ) -> ServerResult<bool> {
+        let before = db
+            .tunnel_leases()
+            .find_one_and_update(
+                doc! { "_id": device_short_id },
+                doc! { "$set": {
+                    "node_id": node_id,
+                    "node_address": node_address,
+                    "conn_id": conn_id as i64,
+                    "lease_expires_at": lease_deadline(ttl_secs),
+                }},
+            )
+            .with_options(
+                FindOneAndUpdateOptions::builder()
+                    .upsert(true)
+                    .return_document(ReturnDocument::Before)
+                    .build(),
+            )
+            .await?;
+        Ok(before.is_none_or(|lease| lease.lease_expires_at <= DateTime::now()))
+    }
+
+    /// Extend the leases this node still owns for the given devices.
+    pub async fn renew(
+        db: &Mongo,
+        node_id: &str,
+        device_short_ids: &[String],
+        ttl_secs: i64,
+    ) -> ServerResult<()> {
+        if device_short_ids.is_empty() {
+            return Ok(());
+        }
+        db.tunnel_leases()
+            .update_many(
+                doc! { "_id": { "$in": device_short_ids }, "node_id": node_id },
+                doc! { "$set": { "lease_expires_at": lease_deadline(ttl_secs) } },
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Drop the lease, but only if it still belongs to this node's connection.
+    /// Returns true if it did, i.e. the device just went offline rather than
+    /// moving to another connection.
+    pub async fn release(
+        db: &Mongo,
+        device_short_id: &str,
+        node_id: &str,
+        conn_id: usize,
+    ) -> ServerResult<bool> {
+        let res = db
+            .tunnel_leases()
+            .delete_one(doc! {
+                "_id": device_short_id,
+                "node_id": node_id,
+                "conn_id": conn_id as i64,
+            })
+            .await?;
+        Ok(res.deleted_count > 0)
+    }
+
+    /// Live lease held by a node other than `node_id`.
+    pub async fn find_remote_owner(
+        db: &Mongo,
+        device_short_id: &str,
+        node_id: &str,
+    ) -> ServerResult<Option<Self>> {
+        let lease = db
+            .tunnel_leases()
+            .find_one(doc! {
+                "_id": device_short_id,
+                "node_id": { "$ne": node_id },
+                "lease_expires_at": { "$gt": DateTime::now() },
+            })
+            .await?;
+        Ok(lease)
+    }
+
+    /// Which of the given devices have a live lease on a node other than
+    /// `node_id`, in one query.
+    pub async fn find_remote_owned(
+        db: &Mongo,
+        device_short_ids: &[String],
+        node_id: &str,
+    ) -> ServerResult<Vec<String>> {
+        if device_short_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let leases: Vec<Self> = db
+            .tunnel_leases()
+            .find(doc! {
+                "_id": { "$in": device_short_ids },
+                "node_id": { "$ne": node_id },
+                "lease_expires_at": { "$gt": DateTime::now() },
+            })
+            .await?
+            .try_collect()
+            .await?;
+        Ok(leases.into_iter().map(|l| l.device_short_id).collect())
+    }
+}
@@ -0,0 +1,105 @@
+use std::{sync::Arc, time::Duration};
+
+use futures::TryStreamExt;
+use m87_shared::metrics::{UnitMetrics, UnitMetricsQuery};
+use mongodb::bson::{DateTime, doc, oid::ObjectId, to_bson};
+use mongodb::options::FindOptions;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::AppConfig,
+    db::Mongo,
+    response::{ServerError, ServerResult},
+};
+
+const DEFAULT_QUERY_LIMIT: u32 = 720;
+const MAX_QUERY_LIMIT: u32 = 10_000;
+const DAY_SECS: u64 = 24 * 3600;
+
+/// One run of a unit's `metrics` hook, as reported in a heartbeat.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnitMetricsDoc {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub device_id: ObjectId,
+    pub unit: String,
+    /// `(name, value)` pairs, since names may contain `.` or `$`.
+    pub values: Vec<(String, f64)>,
+    pub collected_at: DateTime,
+    pub expires_at: DateTime,
+}
+
+impl UnitMetricsDoc {
+    /// Store the samples of a heartbeat. Heartbeats repeat the latest sample
+    /// until the hook runs again, so a sample is only inserted once.
+    pub async fn record(
+        db: &Arc<Mongo>,
+        config: &Arc<AppConfig>,
+        device_id: ObjectId,
+        units: &[UnitMetrics],
+    ) -> ServerResult<()> {
+        let retention = Duration::from_secs(config.unit_metrics_retention_days as u64 * DAY_SECS);
+        for sample in units {
+            let values: Vec<(&String, &f64)> = sample.values.iter().collect();
+            let collected_at = DateTime::from_millis(sample.collected_at as i64);
+            let expires_at = DateTime::from_system_time(collected_at.to_system_time() + retention);
+            db.unit_metrics()
+                .update_one(
+                    doc! {
+                        "device_id": device_id,
+                        "unit": &sample.unit,
+                        "collected_at": collected_at,
+                    },
+                    doc! { "$setOnInsert": {
+                        "values": to_bson(&values).map_err(|e| ServerError::internal_error(&e.to_string()))?,
+                        "expires_at": expires_at,
+                    } },
+                )
+                .upsert(true)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Newest samples of `unit`, returned oldest first.
+    pub async fn query(
+        db: &Arc<Mongo>,
+        device_id: ObjectId,
+        unit: &str,
+        query: &UnitMetricsQuery,
+    ) -> ServerResult<Vec<UnitMetrics>> {
+        let mut filter = doc! { "device_id": device_id, "unit": unit };
+        if let Some(since) = query.since {
+            filter.insert(
+                "collected_at",
+                doc! { "$gt": DateTime::from_millis(since as i64) },
+            );
+        }
+        let limit = query
+            .limit
+            .unwrap_or(DEFAULT_QUERY_LIMIT)
+            .clamp(1, MAX_QUERY_LIMIT);
+        let options = FindOptions::builder()
+            .limit(Some(limit as i64))
+            .sort(doc! { "collected_at": -1 })
+            .max_time(Some(Duration::from_secs(30)))
+            .build();
+        let docs: Vec<UnitMetricsDoc> = db
+            .unit_metrics()
+            .find(filter)
+            .with_options(options)
+            .await?
+            .try_collect()
+            .await?;
+
+        Ok(docs
+            .into_iter()
+            .rev()
+            .map(|d| UnitMetrics {
+                unit: d.unit,
+                values: d.values.into_iter().collect(),
+                collected_at: d.collected_at.timestamp_millis().max(0) as u64,
+            })
+            .collect())
+    }
+}
@@ -4,8 +4,13 @@ use crate::{
     db::Mongo,
     response::ServerResult,
 };
-use m87_shared::{roles::Role, users::User};
-use mongodb::bson::{DateTime, doc, oid::ObjectId};
+use futures::TryStreamExt;
+use m87_shared::{
+    roles::Role,
+    users::{AdminUser, User, UserStatus},
+};
+use mongodb::bson::{DateTime, Document, doc, oid::ObjectId};
+use mongodb::options::{FindOneAndUpdateOptions, FindOptions, ReturnDocument};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
@@ -19,6 +24,9 @@ pub struct UserDoc {
     pub sub: String,
 
     pub approved: bool,
+    /// Disabled users are rejected even when approved.
+    #[serde(default)]
+    pub disabled: bool,
 
     #[serde(default)]
     pub created_at: Option<DateTime>,
@@ -66,6 +74,11 @@ impl UserDoc {
             }
             false => true,
         };
+        // Admins must be able to sign in to approve everyone else.
+        let approved = approved
+            || email
+                .as_ref()
+                .is_some_and(|mail| config.admin_emails.contains(mail));
 
         let new_user = UserDoc {
             id: None,
@@ -73,6 +86,7 @@ impl UserDoc {
             email,
             sub: claims.sub.clone(),
             approved,
+            disabled: false,
             created_at: Some(now.clone()),
             last_login: Some(now),
             total_logins: 1,
@@ -105,4 +119,131 @@ impl UserDoc {
             role: role.clone(),
         }
     }
+
+    pub fn status(&self) -> UserStatus {
+        if self.disabled {
+            UserStatus::Disabled
+        } else if !self.approved {
+            UserStatus::Pending
+        } else {
+            UserStatus::Active
+        }
+    }
+
+    pub fn to_admin_user(&self) -> AdminUser {
+        AdminUser {
+            id: self.id.map(|id| id.to_hex()).unwrap_or_default(),
+            email: self.email.clone().unwrap_or(self.sub.clone()),
+            name: self.name.clone(),
+            status: self.status(),
+            created_at: self.created_at.and_then(|t| t.try_to_rfc3339_string().ok()),
+            last_login: self.last_login.and_then(|t| t.try_to_rfc3339_string().ok()),
+            total_logins: self.total_logins,
+        }
+    }
+
+    fn status_filter(status: UserStatus) -> Document {
+        match status {
+            UserStatus::Pending => doc! { "approved": false, "disabled": { "$ne": true } },
+            UserStatus::Active => doc! { "approved": true, "disabled": { "$ne": true } },
+            UserStatus::Disabled => doc! { "disabled": true },
+        }
+    }
+
+    /// All users, newest first, optionally restricted to one status.
+    pub async fn list(db: &Arc<Mongo>, status: Option<UserStatus>) -> ServerResult<Vec<Self>> {
+        let filter = status.map(Self::status_filter).unwrap_or_default();
+        let options = FindOptions::builder()
+            .sort(doc! { "created_at": -1 })
+            .build();
+        let users = db
+            .users()
+            .find(filter)
+            .with_options(options)
+            .await?
+            .try_collect()
+            .await?;
+        Ok(users)
+    }
+
+    /// Look a user up by id or, failing that, by email.
+    pub async fn find_by_id_or_email(db: &Arc<Mongo>, user: &str) -> ServerResult<Option<Self>> {
+        let filter = match ObjectId::parse_str(user) {
+            Ok(id) => doc! { "_id": id },
+            Err(_) => doc! { "email": user },
+        };
+        Ok(db.users().find_one(filter).await?)
+    }
+
+    pub async fn set_access(
+        db: &Arc<Mongo>,
+        id: ObjectId,
+        approved: bool,
+        disabled: bool,
+    ) -> ServerResult<Option<Self>> {
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+        let user = db
+            .users()
+            .find_one_and_update(
+                doc! { "_id": id },
+                doc! { "$set": { "approved": approved, "disabled": disabled } },
+            )
+            .with_options(options)
+            .await?;
+        Ok(user)
+    }
+
+    /// Those of `ids` that may no longer connect (disabled or not approved).
+    pub async fn blocked_ids(db: &Arc<Mongo>, ids: &[ObjectId]) -> ServerResult<Vec<ObjectId>> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let users: Vec<Self> = db
+            .users()
+            .find(doc! {
+                "_id": { "$in": ids },
+                "$or": [{ "disabled": true }, { "approved": false }],
+            })
+            .await?
+            .try_collect()
+            .await?;
+        Ok(users.into_iter().filter_map(|u| u.id).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(approved: bool, disabled: bool) -> UserDoc {
+        UserDoc {
+            id: Some(ObjectId::new()),
+            name: None,
+            email: Some("ada@example.com".into()),
+            sub: "sub".into(),
+            approved,
+            disabled,
+            created_at: None,
+            last_login: None,
+            total_logins: 0,
+        }
+    }
+
+    #[test]
+    fn disabled_wins_over_approval() {
+        assert_eq!(user(false, false).status(), UserStatus::Pending);
+        assert_eq!(user(true, false).status(), UserStatus::Active);
+        assert_eq!(user(true, true).status(), UserStatus::Disabled);
+        assert_eq!(user(false, true).status(), UserStatus::Disabled);
+    }
+
+    #[test]
+    fn disabled_defaults_to_false_for_existing_users() {
+        let doc = doc! { "sub": "sub", "approved": true, "last_login": null, "total_logins": 3 };
+        let user: UserDoc = mongodb::bson::from_document(doc).unwrap();
+        assert!(!user.disabled);
+        assert_eq!(user.status(), UserStatus::Active);
+    }
 }
@@ -0,0 +1,324 @@
+use std::{sync::Arc, time::Duration};
+
+use futures::TryStreamExt;
+use m87_shared::org::{
+    CreateWebhookBody, Webhook, WebhookDelivery, WebhookDeliveryStatus, WebhookEvent,
+};
+use mongodb::bson::{DateTime, doc, oid::ObjectId};
+use mongodb::options::{FindOneAndUpdateOptions, FindOptions, ReturnDocument};
+use rand::{Rng, distributions::Alphanumeric};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::AppConfig,
+    db::Mongo,
+    response::{ServerError, ServerResult},
+    webhooks,
+};
+
+/// How long deliveries stay in the delivery log.
+const DELIVERY_RETENTION: Duration = Duration::from_hours(24 * 7);
+
+/// An org's subscription to platform events.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookDoc {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub org_id: String,
+    pub url: String,
+    /// HMAC key for the `X-M87-Signature` header.
+    pub secret: String,
+    /// Empty means all events.
+    #[serde(default)]
+    pub events: Vec<WebhookEvent>,
+    pub created_at: DateTime,
+}
+
+impl WebhookDoc {
+    pub async fn create(
+        db: &Arc<Mongo>,
+        config: &AppConfig,
+        org_id: &str,
+        body: CreateWebhookBody,
+    ) -> ServerResult<Self> {
+        let url = reqwest::Url::parse(body.url.trim())
+            .map_err(|_| ServerError::bad_request("Invalid webhook url"))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(ServerError::bad_request("Webhook url must be http(s)"));
+        }
+        webhooks::check_target(&url, &config.webhook_allowed_hosts)
+            .map_err(|e| ServerError::bad_request(&e))?;
+
+        let secret: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(40)
+            .map(char::from)
+            .collect();
+
+        let mut events = body.events;
+        events.sort_by_key(|e| e.as_str());
+        events.dedup();
+
+        let doc = Self {
+            id: Some(ObjectId::new()),
+            org_id: org_id.to_string(),
+            url: url.to_string(),
+            secret: format!("whsec_{secret}"),
+            events,
+            created_at: DateTime::now(),
+        };
+        db.webhooks()
+            .insert_one(&doc)
+            .await
+            .map_err(|_| ServerError::internal_error("Failed to create webhook"))?;
+        Ok(doc)
+    }
+
+    pub async fn list_for_org(db: &Arc<Mongo>, org_id: &str) -> ServerResult<Vec<Self>> {
+        let hooks = db
+            .webhooks()
+            .find(doc! { "org_id": org_id })
+            .sort(doc! { "created_at": 1 })
+            .await?
+            .try_collect()
+            .await?;
+        Ok(hooks)
+    }
+
+    pub async fn find(db: &Arc<Mongo>, org_id: &str, id: ObjectId) -> ServerResult<Option<Self>> {
+        let hook = db
+            .webhooks()
+            .find_one(doc! { "_id": id, "org_id": org_id })
+            .await?;
+        Ok(hook)
+    }
+
+    pub async fn find_by_id(db: &Arc<Mongo>, id: ObjectId) -> ServerResult<Option<Self>> {
+        Ok(db.webhooks().find_one(doc! { "_id": id }).await?)
+    }
+
+    /// Webhooks of any of `org_ids` that want `event`.
+    pub async fn subscribed(
+        db: &Arc<Mongo>,
+        org_ids: &[String],
+        event: WebhookEvent,
+    ) -> ServerResult<Vec<Self>> {
+        if org_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let hooks = db
+            .webhooks()
+            .find(doc! {
+                "org_id": { "$in": org_ids },
+                "$or": [
+                    { "events": event.as_str() },
+                    { "events": { "$size": 0 } },
+                ],
+            })
+            .await?
+            .try_collect()
+            .await?;
+        Ok(hooks)
+    }
+
+    pub async fn delete(db: &Arc<Mongo>, org_id: &str, id: ObjectId) -> ServerResult<bool> {
+        let res = db
+            .webhooks()
+            .delete_one(doc! { "_id": id, "org_id": org_id })
+            .await?;
+        if res.deleted_count == 0 {
+            return Ok(false);
+        }
+        db.webhook_deliveries()
+            .delete_many(doc! { "webhook_id": id })
+            .await?;
+        Ok(true)
+    }
+
+    pub async fn delete_for_org(db: &Arc<Mongo>, org_id: &str) -> ServerResult<()> {
+        db.webhooks().delete_many(doc! { "org_id": org_id }).await?;
+        db.webhook_deliveries()
+            .delete_many(doc! { "org_id": org_id })
+            .await?;
+        Ok(())
+    }
+
+    pub async fn rename_org(db: &Arc<Mongo>, old_id: &str, new_id: &str) -> ServerResult<()> {
+        db.webhooks()
+            .update_many(
+                doc! { "org_id": old_id },
+                doc! { "$set": { "org_id": new_id } },
+            )
+            .await?;
+        db.webhook_deliveries()
+            .update_many(
+                doc! { "org_id": old_id },
+                doc! { "$set": { "org_id": new_id } },
+            )
+            .await?;
+        Ok(())
+    }
+
+    pub fn to_webhook(&self) -> Webhook {
+        Webhook {
+            id: self.id.map(|id| id.to_hex()).unwrap_or_default(),
+            org_id: self.org_id.clone(),
+            url: self.url.clone(),
+            events: self.events.clone(),
+            created_at: self.created_at.try_to_rfc3339_string().unwrap_or_default(),
+        }
+    }
+}
+
+/// One event sent (or to be sent) to one webhook, with the outcome of the
+/// latest attempt. The payload is stored as sent so retries are identical.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookDeliveryDoc {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub webhook_id: ObjectId,
+    pub org_id: String,
+    pub event: WebhookEvent,
+    pub payload: String,
+    pub status: WebhookDeliveryStatus,
+    #[serde(default)]
+    pub attempts: u32,
+    /// Due time of the next attempt while pending; pushed forward while an
+    /// attempt is in flight so only one replica sends it.
+    #[serde(default)]
+    pub next_attempt_at: Option<DateTime>,
+    #[serde(default)]
+    pub last_status_code: Option<u16>,
+    #[serde(default)]
+    pub last_error: Option<String>,
+    pub created_at: DateTime,
+    pub updated_at: DateTime,
+    #[serde(default)]
+    pub expires_at: Option<DateTime>,
+}
+
+impl WebhookDeliveryDoc {
+    pub fn new(id: ObjectId, hook: &WebhookDoc, event: WebhookEvent, payload: String) -> Self {
+        let now = DateTime::now();
+        Self {
+            id,
+            webhook_id: hook.id.unwrap_or_default(),
+            org_id: hook.org_id.clone(),
+            event,
+            payload,
+            status: WebhookDeliveryStatus::Pending,
+            attempts: 0,
+            next_attempt_at: Some(now),
+            last_status_code: None,
+            last_error: None,
+            created_at: now,
+            updated_at: now,
+            expires_at: Some(DateTime::from_system_time(
+                now.to_system_time() + DELIVERY_RETENTION,
+            )),
+        }
+    }
+
+    pub async fn insert(&self, db: &Arc<Mongo>) -> ServerResult<()> {
+        db.webhook_deliveries()
+            .insert_one(self)
+            .await
+            .map_err(|_| ServerError::internal_error("Failed to insert webhook delivery"))?;
+        Ok(())
+    }
+
+    /// Take the oldest due delivery (or the given one, if due) for `lease`.
+    pub async fn claim(
+        db: &Arc<Mongo>,
+        id: Option<ObjectId>,
+        lease: Duration,
+    ) -> ServerResult<Option<Self>> {
+        let now = DateTime::now();
+        let mut filter = doc! {
+            "status": "pending",
+            "next_attempt_at": { "$lte": now },
+        };
+        if let Some(id) = id {
+            filter.insert("_id", id);
+        }
+        let until = DateTime::from_system_time(now.to_system_time() + lease);
+        let options = FindOneAndUpdateOptions::builder()
+            .sort(doc! { "next_attempt_at": 1 })
+            .return_document(ReturnDocument::After)
+            .build();
+        let claimed = db
+            .webhook_deliveries()
+            .find_one_and_update(filter, doc! { "$set": { "next_attempt_at": until } })
+            .with_options(options)
+            .await?;
+        Ok(claimed)
+    }
+
+    /// Store the outcome of an attempt. `retry_in` of `None` ends the delivery.
+    pub async fn record_attempt(
+        &self,
+        db: &Arc<Mongo>,
+        status_code: Option<u16>,
+        error: Option<String>,
+        delivered: bool,
+        retry_in: Option<Duration>,
+    ) -> ServerResult<()> {
+        let now = DateTime::now();
+        let (status, next_attempt_at) = match (delivered, retry_in) {
+            (true, _) => (WebhookDeliveryStatus::Delivered, None),
+            (false, Some(wait)) => (
+                WebhookDeliveryStatus::Pending,
+                Some(DateTime::from_system_time(now.to_system_time() + wait)),
+            ),
+            (false, None) => (WebhookDeliveryStatus::Failed, None),
+        };
+        db.webhook_deliveries()
+            .update_one(
+                doc! { "_id": self.id },
+                doc! { "$set": {
+                    "status": status.to_string(),
+                    "attempts": self.attempts as i64 + 1,
+                    "next_attempt_at": next_attempt_at,
+                    "last_status_code": status_code.map(i32::from),
+                    "last_error": error,
+                    "updated_at": now,
+                } },
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Most recent deliveries of a webhook, newest first.
+    pub async fn list_for_webhook(
+        db: &Arc<Mongo>,
+        webhook_id: ObjectId,
+        limit: i64,
+    ) -> ServerResult<Vec<Self>> {
+        let options = FindOptions::builder()
+            .sort(doc! { "created_at": -1 })
+            .limit(Some(limit))
+            .build();
+        let deliveries = db
+            .webhook_deliveries()
+            .find(doc! { "webhook_id": webhook_id })
+            .with_options(options)
+            .await?
+            .try_collect()
+            .await?;
+        Ok(deliveries)
+    }
+
+    pub fn to_webhook_delivery(&self) -> WebhookDelivery {
+        WebhookDelivery {
+            id: self.id.to_hex(),
+            webhook_id: self.webhook_id.to_hex(),
+            event: self.event,
+            status: self.status,
+            attempts: self.attempts,
+            last_status_code: self.last_status_code,
+            last_error: self.last_error.clone(),
+            created_at: self.created_at.try_to_rfc3339_string().unwrap_or_default(),
+            updated_at: self.updated_at.try_to_rfc3339_string().unwrap_or_default(),
+        }
+    }
+}
@@ -1 +1,5 @@
+pub mod peer;
+pub mod recording;
+pub mod registry;
 pub mod relay_state;
+pub mod stream_audit;
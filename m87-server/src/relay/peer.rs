@@ -0,0 +1,232 @@
+//! Node-to-node forwarding between server replicas.
+//!
+//! When a client lands on a replica that does not hold the device's control
+//! tunnel, that replica dials the owning node on its regular QUIC endpoint
+//! with SNI `relay-<device>.<public_address>` and a relay token signed with
+//! the shared `relay_secret`. The owning node then bridges the connection to
+//! the device exactly like a client connection, so to the dialing node the
+//! peer connection stands in for the device connection.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use quinn::{ClientConfig, Endpoint, IdleTimeout, TransportConfig};
+use quinn_proto::crypto::rustls::QuicClientConfig;
+use rustls::{
+    ClientConfig as RustlsClientConfig, DigitallySignedStruct, SignatureScheme,
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    pki_types::{CertificateDer, ServerName, UnixTime},
+};
+use sha2::Sha256;
+use tokio::sync::OnceCell;
+use tokio::time::timeout;
+
+use crate::response::{ServerError, ServerResult};
+
+const TOKEN_PREFIX: &str = "relay:";
+/// Maximum age (and clock skew) accepted for a relay token.
+const TOKEN_MAX_AGE_SECS: u64 = 60;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// `relay:<node_id>:<unix_ts>:<hex hmac>`, bound to the device being forwarded.
+pub fn sign_relay_token(secret: &str, node_id: &str, device_short_id: &str, ts: u64) -> String {
+    let sig = relay_mac(secret, node_id, device_short_id, ts);
+    format!("{TOKEN_PREFIX}{node_id}:{ts}:{}", hex::encode(sig))
+}
+
+/// Validate a relay token for `device_short_id`, returning the dialing node id.
+pub fn verify_relay_token(
+    secret: &str,
+    token: &str,
+    device_short_id: &str,
+    now: u64,
+) -> Option<String> {
+    let rest = token.strip_prefix(TOKEN_PREFIX)?;
+    let (node_id, rest) = rest.split_once(':')?;
+    let (ts, sig) = rest.split_once(':')?;
+    let ts: u64 = ts.parse().ok()?;
+    if now.abs_diff(ts) > TOKEN_MAX_AGE_SECS {
+        return None;
+    }
+    let sig = hex::decode(sig).ok()?;
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).ok()?;
+    mac.update(relay_message(node_id, device_short_id, ts).as_bytes());
+    mac.verify_slice(&sig).ok()?;
+    Some(node_id.to_string())
+}
+
+fn relay_message(node_id: &str, device_short_id: &str, ts: u64) -> String {
+    format!("{node_id}:{device_short_id}:{ts}")
+}
+
+fn relay_mac(secret: &str, node_id: &str, device_short_id: &str, ts: u64) -> Vec<u8> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(relay_message(node_id, device_short_id, ts).as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+pub fn relay_sni(device_short_id: &str, public_address: &str) -> String {
+    format!("relay-{device_short_id}.{public_address}")
+}
+
+/// Replicas reach each other by address, not by the public name on their
+/// certificate (and staging nodes each generate their own self-signed one),
+/// so the peer certificate is not verified. The link is authenticated by the
+/// signed relay token; `node_address` is expected to be on the private network.
+#[derive(Debug)]
+struct PeerCertVerifier;
+
+impl ServerCertVerifier for PeerCertVerifier {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        vec![
+            SignatureScheme::ECDSA_NISTP256_SHA256,
+            SignatureScheme::ECDSA_NISTP384_SHA384,
+            SignatureScheme::ED25519,
+            SignatureScheme::RSA_PSS_SHA256,
+            SignatureScheme::RSA_PKCS1_SHA256,
+        ]
+    }
+}
+
+/// Client endpoint used to dial other replicas, created on first use.
+pub struct PeerDialer {
+    endpoint: OnceCell<Endpoint>,
+}
+
+impl PeerDialer {
+    pub fn new() -> Self {
+        Self {
+            endpoint: OnceCell::new(),
+        }
+    }
+
+    async fn endpoint(&self) -> ServerResult<&Endpoint> {
+        self.endpoint
+            .get_or_try_init(|| async {
+                let mut endpoint = Endpoint::client(SocketAddr::from(([0, 0, 0, 0], 0)))
+                    .map_err(|e| ServerError::internal_error(&format!("bind peer QUIC: {e:?}")))?;
+                endpoint.set_default_client_config(peer_client_config()?);
+                Ok(endpoint)
+            })
+            .await
+    }
+
+    /// Open an authenticated forward to the node owning the device's tunnel.
+    pub async fn connect(
+        &self,
+        node_address: &str,
+        sni: &str,
+        token: &str,
+    ) -> ServerResult<quinn::Connection> {
+        let addr = tokio::net::lookup_host(node_address)
+            .await?
+            .next()
+            .ok_or_else(|| {
+                ServerError::internal_error(&format!("cannot resolve node {node_address}"))
+            })?;
+
+        let connecting = self
+            .endpoint()
+            .await?
+            .connect(addr, sni)
+            .map_err(|e| ServerError::internal_error(&format!("peer connect: {e:?}")))?;
+        let conn = timeout(CONNECT_TIMEOUT, connecting)
+            .await
+            .map_err(|_| ServerError::timeout("peer connect timed out"))??;
+
+        // Same framing as clients use: u16 BE length + token on a uni stream.
+        let mut send = conn.open_uni().await?;
+        send.write_all(&(token.len() as u16).to_be_bytes())
+            .await
+            .map_err(|e| ServerError::internal_error(&format!("peer token: {e}")))?;
+        send.write_all(token.as_bytes())
+            .await
+            .map_err(|e| ServerError::internal_error(&format!("peer token: {e}")))?;
+        let _ = send.finish();
+
+        Ok(conn)
+    }
+}
+
+fn peer_client_config() -> ServerResult<ClientConfig> {
+    let mut tls = RustlsClientConfig::builder()
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(PeerCertVerifier))
+        .with_no_client_auth();
+    tls.alpn_protocols = vec![b"m87-quic".to_vec()];
+
+    let crypto = QuicClientConfig::try_from(tls)
+        .map_err(|e| ServerError::internal_error(&format!("peer quic rustls: {e}")))?;
+    let mut cfg = ClientConfig::new(Arc::new(crypto));
+
+    let mut t = TransportConfig::default();
+    t.keep_alive_interval(Some(Duration::from_secs(10)));
+    t.max_idle_timeout(Some(
+        IdleTimeout::try_from(Duration::from_secs(180)).unwrap(),
+    ));
+    cfg.transport_config(Arc::new(t));
+    Ok(cfg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relay_token_roundtrip() {
+        let token = sign_relay_token("s3cret", "node-a", "dev1", 1000);
+        assert_eq!(
+            verify_relay_token("s3cret", &token, "dev1", 1010).as_deref(),
+            Some("node-a")
+        );
+    }
+
+    #[test]
+    fn relay_token_is_bound_to_device_secret_and_time() {
+        let token = sign_relay_token("s3cret", "node-a", "dev1", 1000);
+        assert!(verify_relay_token("s3cret", &token, "dev2", 1000).is_none());
+        assert!(verify_relay_token("other", &token, "dev1", 1000).is_none());
+        assert!(verify_relay_token("s3cret", &token, "dev1", 1000 + 61).is_none());
+        assert!(verify_relay_token("s3cret", "not-a-relay-token", "dev1", 1000).is_none());
+    }
+}
@@ -0,0 +1,389 @@
+//! Session recording for the audit log (asciicast v2).
+//!
+//! When an org with access to the device has `record_sessions` enabled,
+//! `Terminal` and `Exec` streams are bridged through an [`Asciicast`]
+//! recorder: device output becomes `"o"` events, client input `"i"` events
+//! and resize frames `"r"` events. The stream header is handled by
+//! `stream_audit::forward_header`, which drops the `compression` option of
+//! recorded streams so the device answers in plain bytes; clients already
+//! accept plain replies from older devices.
+
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use mongodb::bson::DateTime;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tracing::{debug, warn};
+
+use crate::models::audit_logs::AuditLogDoc;
+use crate::models::session_recording::SessionRecordingDoc;
+use crate::relay::stream_audit::{ForwardAudit, Traffic, bridge};
+
+/// Keeps a recording well below MongoDB's 16 MiB document limit.
+const MAX_CAST_BYTES: usize = 8 * 1024 * 1024;
+/// `0xFF` + rows (u16 BE) + cols (u16 BE), as sent by `m87 shell` / `exec -t`.
+const RESIZE_FRAME_LEN: usize = 5;
+const RESIZE_MARKER: u8 = 0xFF;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionKind {
+    Terminal,
+    Exec,
+}
+
+impl SessionKind {
+    pub fn from_header(header: &Value) -> Option<Self> {
+        match header.get("type")?.as_str()? {
+            "Terminal" => Some(Self::Terminal),
+            "Exec" => Some(Self::Exec),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Terminal => "terminal",
+            Self::Exec => "exec",
+        }
+    }
+}
+
+/// Bridge a stream while recording it, then store the recording.
+pub async fn bridge_recorded(
+    audit: &ForwardAudit,
+    kind: SessionKind,
+    client_send: Pin<Box<dyn AsyncWrite + Send>>,
+    client_recv: Pin<Box<dyn AsyncRead + Send>>,
+    dev_send: quinn::SendStream,
+    dev_recv: quinn::RecvStream,
+) -> Traffic {
+    let started_at = DateTime::now();
+    let cast = Arc::new(Mutex::new(Asciicast::new(kind)));
+
+    let traffic = bridge(
+        client_send,
+        client_recv,
+        dev_send,
+        dev_recv,
+        Some(cast.clone()),
+    )
+    .await;
+
+    let cast = std::mem::replace(&mut *cast.lock().unwrap(), Asciicast::new(kind));
+    if cast.is_empty() {
+        debug!("recorded stream carried no data, not storing");
+    } else {
+        store(audit, kind, cast, started_at).await;
+    }
+    traffic
+}
+
+async fn store(audit: &ForwardAudit, kind: SessionKind, cast: Asciicast, started_at: DateTime) {
+    let Some(device_id) = audit.device_id else {
+        return;
+    };
+    let session_id = uuid::Uuid::new_v4().to_string();
+    let command = cast.command.clone();
+    let truncated = cast.truncated;
+    let state = &audit.state;
+
+    if let Err(e) = SessionRecordingDoc::add(
+        &state.db,
+        &audit.claims,
+        &state.config,
+        &session_id,
+        device_id,
+        kind.as_str(),
+        command.clone(),
+        started_at,
+        truncated,
+        cast.finish(),
+    )
+    .await
+    {
+        warn!(%device_id, "failed to store session recording: {e:?}");
+        return;
+    }
+
+    let mut details = format!("session={session_id}");
+    if let Some(command) = &command {
+        details.push_str(&format!(" command={command}"));
+    }
+    if truncated {
+        details.push_str(" truncated=true");
+    }
+    let _ = AuditLogDoc::add(
+        &state.db,
+        &audit.claims,
+        &state.config,
+        &format!("Recorded {} session", kind.as_str()),
+        &details,
+        Some(device_id),
+    )
+    .await;
+}
+
+#[derive(Deserialize)]
+struct ExecRequest {
+    command: String,
+    #[serde(default)]
+    rows: Option<u16>,
+    #[serde(default)]
+    cols: Option<u16>,
+}
+
+/// In-memory asciicast v2 recording. Events are timed from creation.
+pub struct Asciicast {
+    kind: SessionKind,
+    started: Instant,
+    timestamp: u64,
+    width: u16,
+    height: u16,
+    command: Option<String>,
+    events: String,
+    truncated: bool,
+    /// Exec streams start with a JSON request line before any stdin.
+    awaiting_request: bool,
+    pending_input: Vec<u8>,
+    pending_output: Vec<u8>,
+}
+
+impl Asciicast {
+    pub fn new(kind: SessionKind) -> Self {
+        Self {
+            kind,
+            started: Instant::now(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            width: 80,
+            height: 24,
+            command: None,
+            events: String::new(),
+            truncated: false,
+            awaiting_request: kind == SessionKind::Exec,
+            pending_input: Vec::new(),
+            pending_output: Vec::new(),
+        }
+    }
+
+    fn elapsed(&self) -> f64 {
+        self.started.elapsed().as_secs_f64()
+    }
+
+    pub fn input(&mut self, data: &[u8]) {
+        let at = self.elapsed();
+        self.input_at(at, data);
+    }
+
+    pub fn output(&mut self, data: &[u8]) {
+        let at = self.elapsed();
+        self.output_at(at, data);
+    }
+
+    fn input_at(&mut self, at: f64, data: &[u8]) {
+        self.pending_input.extend_from_slice(data);
+
+        if self.awaiting_request {
+            let Some(pos) = self.pending_input.iter().position(|b| *b == b'\n') else {
+                return;
+            };
+            let line: Vec<u8> = self.pending_input.drain(..=pos).collect();
+            if let Ok(req) = serde_json::from_slice::<ExecRequest>(&line) {
+                self.command = Some(req.command);
+                if let (Some(rows), Some(cols)) = (req.rows, req.cols) {
+                    self.height = rows;
+                    self.width = cols;
+                }
+            }
+            self.awaiting_request = false;
+        }
+
+        // Split plain input from resize frames. A frame cut across reads
+        // stays pending until the rest arrives.
+        let mut text = Vec::new();
+        let mut i = 0;
+        let pending = std::mem::take(&mut self.pending_input);
+        while i < pending.len() {
+            if pending[i] != RESIZE_MARKER {
+                text.push(pending[i]);
+                i += 1;
+                continue;
+            }
+            if pending.len() - i < RESIZE_FRAME_LEN {
+                self.pending_input = pending[i..].to_vec();
+                break;
+            }
+            self.push_text(at, "i", &mut text);
+            let rows = u16::from_be_bytes([pending[i + 1], pending[i + 2]]);
+            let cols = u16::from_be_bytes([pending[i + 3], pending[i + 4]]);
+            self.resize_at(at, rows, cols);
+            i += RESIZE_FRAME_LEN;
+        }
+        self.push_text(at, "i", &mut text);
+    }
+
+    fn output_at(&mut self, at: f64, data: &[u8]) {
+        let mut bytes = std::mem::take(&mut self.pending_output);
+        bytes.extend_from_slice(data);
+
+        // Keep an incomplete UTF-8 sequence at the end for the next chunk.
+        let cut = match std::str::from_utf8(&bytes) {
+            Ok(_) => bytes.len(),
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(_) => bytes.len(),
+        };
+        self.pending_output = bytes.split_off(cut);
+        self.push_text(at, "o", &mut bytes);
+    }
+
+    fn resize_at(&mut self, at: f64, rows: u16, cols: u16) {
+        if self.events.is_empty() {
+            // Initial size: goes into the header instead of an event.
+            self.height = rows;
+            self.width = cols;
+        } else {
+            self.push_event(at, "r", &format!("{cols}x{rows}"));
+        }
+    }
+
+    fn push_text(&mut self, at: f64, code: &str, bytes: &mut Vec<u8>) {
+        if bytes.is_empty() {
+            return;
+        }
+        let text = String::from_utf8_lossy(bytes).into_owned();
+        bytes.clear();
+        self.push_event(at, code, &text);
+    }
+
+    fn push_event(&mut self, at: f64, code: &str, data: &str) {
+        if self.truncated {
+            return;
+        }
+        let line = json!([(at * 1e6).round() / 1e6, code, data]).to_string();
+        if self.events.len() + line.len() + 1 > MAX_CAST_BYTES {
+            self.truncated = true;
+            return;
+        }
+        self.events.push_str(&line);
+        self.events.push('\n');
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty() && self.command.is_none()
+    }
+
+    /// Header line followed by the event lines.
+    pub fn finish(self) -> String {
+        let mut header = json!({
+            "version": 2,
+            "width": self.width,
+            "height": self.height,
+            "timestamp": self.timestamp,
+        });
+        if let Some(command) = &self.command {
+            header["command"] = json!(command);
+            header["title"] = json!(command);
+        } else if self.kind == SessionKind::Terminal {
+            header["title"] = json!("m87 shell");
+        }
+        let mut out = header.to_string();
+        out.push('\n');
+        out.push_str(&self.events);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(cast: Asciicast) -> Vec<Value> {
+        cast.finish()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn terminal_initial_resize_sets_header_size() {
+        let mut cast = Asciicast::new(SessionKind::Terminal);
+        cast.input_at(0.0, &[0xFF, 0, 40, 0, 120]);
+        cast.output_at(0.5, b"$ ");
+        cast.input_at(1.0, b"ls\r");
+        cast.input_at(2.0, &[0xFF, 0, 50, 0, 132]);
+
+        let lines = lines(cast);
+        assert_eq!(lines[0]["version"], 2);
+        assert_eq!(lines[0]["width"], 120);
+        assert_eq!(lines[0]["height"], 40);
+        assert_eq!(lines[1], json!([0.5, "o", "$ "]));
+        assert_eq!(lines[2], json!([1.0, "i", "ls\r"]));
+        assert_eq!(lines[3], json!([2.0, "r", "132x50"]));
+    }
+
+    #[test]
+    fn resize_frame_split_across_reads() {
+        let mut cast = Asciicast::new(SessionKind::Terminal);
+        cast.output_at(0.0, b"x");
+        cast.input_at(1.0, &[b'a', 0xFF, 0]);
+        cast.input_at(1.5, &[30, 0, 100, b'b']);
+
+        let lines = lines(cast);
+        assert_eq!(lines[2], json!([1.0, "i", "a"]));
+        assert_eq!(lines[3], json!([1.5, "r", "100x30"]));
+        assert_eq!(lines[4], json!([1.5, "i", "b"]));
+    }
+
+    #[test]
+    fn exec_request_line_becomes_command() {
+        let mut cast = Asciicast::new(SessionKind::Exec);
+        cast.input_at(
+            0.0,
+            b"{\"command\":\"htop\",\"tty\":true,\"rows\":30,\"cols\":100}\nq",
+        );
+
+        let lines = lines(cast);
+        assert_eq!(lines[0]["command"], "htop");
+        assert_eq!(lines[0]["width"], 100);
+        assert_eq!(lines[1], json!([0.0, "i", "q"]));
+    }
+
+    #[test]
+    fn output_keeps_split_utf8_sequences_together() {
+        let mut cast = Asciicast::new(SessionKind::Terminal);
+        let snowman = "☃".as_bytes();
+        cast.output_at(0.0, &snowman[..1]);
+        cast.output_at(0.1, &snowman[1..]);
+
+        let lines = lines(cast);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1], json!([0.1, "o", "☃"]));
+    }
+
+    #[test]
+    fn recording_stops_at_size_limit() {
+        let mut cast = Asciicast::new(SessionKind::Terminal);
+        let chunk = vec![b'a'; 1024 * 1024];
+        for i in 0..10 {
+            cast.output_at(i as f64, &chunk);
+        }
+        assert!(cast.truncated);
+        assert!(cast.events.len() <= MAX_CAST_BYTES);
+    }
+
+    #[test]
+    fn only_terminal_and_exec_are_recorded() {
+        let terminal = json!({ "type": "Terminal", "token": "t" });
+        let logs = json!({ "type": "Logs", "token": "t" });
+        assert_eq!(
+            SessionKind::from_header(&terminal),
+            Some(SessionKind::Terminal)
+        );
+        assert_eq!(SessionKind::from_header(&logs), None);
+    }
+}
@@ -0,0 +1,172 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tracing::{debug, warn};
+
+use crate::config::AppConfig;
+use crate::db::Mongo;
+use crate::models::tunnel_lease::TunnelLeaseDoc;
+use crate::relay::peer::{PeerDialer, relay_sni, sign_relay_token, unix_now};
+
+/// How long a lease stays valid without renewal.
+pub const LEASE_TTL_SECS: i64 = 30;
+/// Renew well before expiry so one missed round does not drop the lease.
+pub const LEASE_RENEW_INTERVAL: Duration = Duration::from_secs(10);
+/// How long a batched online lookup is reused for device lists and alerts.
+const ONLINE_CACHE_TTL: Duration = Duration::from_secs(5);
+
+/// Tunnel registry shared by all server replicas (device short_id → node).
+pub struct TunnelRegistry {
+    db: Arc<Mongo>,
+    node_id: String,
+    node_address: String,
+    relay_secret: String,
+    public_address: String,
+    dialer: PeerDialer,
+    /// Remote online state per device, with the time it was looked up.
+    online_cache: Mutex<HashMap<String, (bool, Instant)>>,
+}
+
+impl TunnelRegistry {
+    /// `None` unless both `node_address` and `relay_secret` are configured.
+    pub fn from_config(db: Arc<Mongo>, config: &AppConfig) -> Option<Self> {
+        Some(Self {
+            db,
+            node_id: config.node_id.clone(),
+            node_address: config.node_address.clone()?,
+            relay_secret: config.relay_secret.clone()?,
+            public_address: config.public_address.clone(),
+            dialer: PeerDialer::new(),
+            online_cache: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn node_id(&self) -> &str {
+        &self.node_id
+    }
+
+    pub fn relay_secret(&self) -> &str {
+        &self.relay_secret
+    }
+
+    /// Whether the device came online with this claim across all nodes;
+    /// `None` if the registry could not be updated.
+    pub async fn claim(&self, device_short_id: &str, conn_id: usize) -> Option<bool> {
+        match TunnelLeaseDoc::claim(
+            &self.db,
+            device_short_id,
+            &self.node_id,
+            &self.node_address,
+            conn_id,
+            LEASE_TTL_SECS,
+        )
+        .await
+        {
+            Ok(came_online) => Some(came_online),
+            Err(e) => {
+                warn!(%device_short_id, "failed to claim tunnel lease: {e:?}");
+                None
+            }
+        }
+    }
+
+    /// Whether the device went offline across all nodes with this release;
+    /// `None` if the registry could not be updated.
+    pub async fn release(&self, device_short_id: &str, conn_id: usize) -> Option<bool> {
+        match TunnelLeaseDoc::release(&self.db, device_short_id, &self.node_id, conn_id).await {
+            Ok(went_offline) => Some(went_offline),
+            Err(e) => {
+                warn!(%device_short_id, "failed to release tunnel lease: {e:?}");
+                None
+            }
+        }
+    }
+
+    pub async fn renew(&self, device_short_ids: &[String]) {
+        if let Err(e) =
+            TunnelLeaseDoc::renew(&self.db, &self.node_id, device_short_ids, LEASE_TTL_SECS).await
+        {
+            warn!("failed to renew tunnel leases: {e:?}");
+        }
+    }
+
+    /// Another node holding a live lease for the device, if any.
+    pub async fn remote_owner(&self, device_short_id: &str) -> Option<TunnelLeaseDoc> {
+        match TunnelLeaseDoc::find_remote_owner(&self.db, device_short_id, &self.node_id).await {
+            Ok(owner) => owner,
+            Err(e) => {
+                warn!(%device_short_id, "tunnel registry lookup failed: {e:?}");
+                None
+            }
+        }
+    }
+
+    /// Which of the given devices hold a live lease on another node. Answers
+    /// from the last `ONLINE_CACHE_TTL` are reused; the rest are looked up
+    /// with a single query.
+    pub async fn remote_online(&self, device_short_ids: &[String]) -> HashSet<String> {
+        let now = Instant::now();
+        let mut online = HashSet::new();
+        let mut misses = Vec::new();
+        {
+            let mut cache = self.online_cache.lock().unwrap();
+            cache.retain(|_, (_, at)| now.duration_since(*at) < ONLINE_CACHE_TTL);
+            for id in device_short_ids {
+                match cache.get(id) {
+                    Some((true, _)) => {
+                        online.insert(id.clone());
+                    }
+                    Some((false, _)) => {}
+                    None => misses.push(id.clone()),
+                }
+            }
+        }
+        if misses.is_empty() {
+            return online;
+        }
+
+        let found: HashSet<String> =
+            match TunnelLeaseDoc::find_remote_owned(&self.db, &misses, &self.node_id).await {
+                Ok(found) => found.into_iter().collect(),
+                Err(e) => {
+                    warn!("tunnel registry batch lookup failed: {e:?}");
+                    return online;
+                }
+            };
+        let mut cache = self.online_cache.lock().unwrap();
+        for id in misses {
+            let is_online = found.contains(&id);
+            cache.insert(id.clone(), (is_online, now));
+            if is_online {
+                online.insert(id);
+            }
+        }
+        online
+    }
+
+    /// Dial the owning node; the returned connection stands in for the device.
+    pub async fn connect_to_owner(
+        &self,
+        device_short_id: &str,
+        owner: &TunnelLeaseDoc,
+    ) -> Option<quinn::Connection> {
+        let token = sign_relay_token(
+            &self.relay_secret,
+            &self.node_id,
+            device_short_id,
+            unix_now(),
+        );
+        let sni = relay_sni(device_short_id, &self.public_address);
+        match self.dialer.connect(&owner.node_address, &sni, &token).await {
+            Ok(conn) => {
+                debug!(%device_short_id, node = %owner.node_id, "connected to tunnel owner");
+                Some(conn)
+            }
+            Err(e) => {
+                warn!(%device_short_id, node = %owner.node_id, "failed to reach tunnel owner: {e:?}");
+                None
+            }
+        }
+    }
+}
@@ -1,27 +1,190 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
+use std::time::Duration;
 
+use mongodb::bson::oid::ObjectId;
 use quinn::Connection;
 use tokio::sync::RwLock;
 use tracing::{info, warn};
 
+use crate::db::Mongo;
+use crate::models::device::DeviceDoc;
+use crate::models::tunnel_lease::TunnelLeaseDoc;
+use crate::models::user::UserDoc;
+use crate::relay::registry::{LEASE_RENEW_INTERVAL, TunnelRegistry};
+
+/// Where a device's control tunnel currently lives.
+pub enum TunnelRoute {
+    Local(Connection),
+    /// Held by another replica (only with a shared registry).
+    Remote(TunnelLeaseDoc),
+}
+
 #[derive(Clone)]
 pub struct RelayState {
     tunnels: Arc<RwLock<HashMap<String, Connection>>>,
     lost: Arc<RwLock<HashMap<String, ()>>>, // just a set, we don't need Instant
+    registry: Option<Arc<TunnelRegistry>>,
+    /// Client forward connections per user, keyed by connection stable id.
+    client_sessions: Arc<RwLock<HashMap<ObjectId, HashMap<usize, Connection>>>>,
 }
 
+/// How often each replica drops sessions of users disabled elsewhere.
+const SESSION_SWEEP_INTERVAL: Duration = Duration::from_secs(15);
+
 impl RelayState {
     pub fn new() -> Self {
         Self {
             tunnels: Arc::new(RwLock::new(HashMap::new())),
             lost: Arc::new(RwLock::new(HashMap::new())),
+            registry: None,
+            client_sessions: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Relay that publishes its tunnels to, and resolves foreign ones from,
+    /// the registry shared by all replicas.
+    pub fn with_registry(registry: TunnelRegistry) -> Self {
+        Self {
+            registry: Some(Arc::new(registry)),
+            ..Self::new()
+        }
+    }
+
+    pub fn registry(&self) -> Option<&Arc<TunnelRegistry>> {
+        self.registry.as_ref()
+    }
+
+    /// Keep the leases of all local tunnels alive. No-op without a registry.
+    pub fn spawn_lease_renewal(&self) {
+        let Some(registry) = self.registry.clone() else {
+            return;
+        };
+        let state = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(LEASE_RENEW_INTERVAL);
+            loop {
+                interval.tick().await;
+                let ids = state.local_device_ids().await;
+                registry.renew(&ids).await;
+            }
+        });
+    }
+
+    /// Close local sessions of users that were disabled, and tunnels of
+    /// devices that were revoked, possibly through another replica, since
+    /// they connected.
+    pub fn spawn_session_sweep(&self, db: Arc<Mongo>) {
+        let state = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(SESSION_SWEEP_INTERVAL);
+            loop {
+                interval.tick().await;
+                let ids: Vec<ObjectId> =
+                    state.client_sessions.read().await.keys().copied().collect();
+                match UserDoc::blocked_ids(&db, &ids).await {
+                    Ok(blocked) => {
+                        for user_id in blocked {
+                            state.close_user_sessions(user_id).await;
+                        }
+                    }
+                    Err(e) => warn!("failed to check client sessions: {e:?}"),
+                }
+
+                let short_ids = state.local_device_ids().await;
+                match DeviceDoc::revoked_short_ids(&db, &short_ids).await {
+                    Ok(revoked) => {
+                        for short_id in revoked {
+                            state.close_tunnel(&short_id, b"device-revoked").await;
+                        }
+                    }
+                    Err(e) => warn!("failed to check device tunnels: {e:?}"),
+                }
+            }
+        });
+    }
+
+    /// Close the device's control tunnel if it is held here. Cleanup runs in
+    /// the tunnel handler as for any other disconnect.
+    pub async fn close_tunnel(&self, device_short_id: &str, reason: &[u8]) -> bool {
+        let conn = self.tunnels.read().await.get(device_short_id).cloned();
+        match conn {
+            Some(conn) => {
+                info!("Closing tunnel for device {}", device_short_id);
+                conn.close(0u32.into(), reason);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Track a client forward connection so it can be closed when its user
+    /// loses access.
+    pub async fn add_client_session(&self, user_id: ObjectId, conn: Connection) {
+        let mut sessions = self.client_sessions.write().await;
+        sessions
+            .entry(user_id)
+            .or_default()
+            .insert(conn.stable_id(), conn);
+    }
+
+    pub async fn remove_client_session(&self, user_id: ObjectId, conn_id: usize) {
+        let mut sessions = self.client_sessions.write().await;
+        if let Some(conns) = sessions.get_mut(&user_id) {
+            conns.remove(&conn_id);
+            if conns.is_empty() {
+                sessions.remove(&user_id);
+            }
+        }
+    }
+
+    /// Close all client connections of a user on this node. Returns how many
+    /// were closed.
+    pub async fn close_user_sessions(&self, user_id: ObjectId) -> usize {
+        let conns = self.client_sessions.write().await.remove(&user_id);
+        let conns = conns.unwrap_or_default();
+        for conn in conns.values() {
+            conn.close(0u32.into(), b"user-disabled");
         }
+        if !conns.is_empty() {
+            info!(%user_id, count = conns.len(), "closed client sessions of disabled user");
+        }
+        conns.len()
+    }
+
+    /// Number of active (non-lost) tunnels on this node.
+    pub async fn local_tunnel_count(&self) -> usize {
+        self.local_device_ids().await.len()
+    }
+
+    /// Number of tracked client connections on this node.
+    pub async fn client_session_count(&self) -> usize {
+        self.client_sessions
+            .read()
+            .await
+            .values()
+            .map(HashMap::len)
+            .sum()
+    }
+
+    /// Devices with an active (non-lost) tunnel on this node.
+    async fn local_device_ids(&self) -> Vec<String> {
+        let tunnels = self.tunnels.read().await;
+        let lost = self.lost.read().await;
+        tunnels
+            .keys()
+            .filter(|id| !lost.contains_key(*id))
+            .cloned()
+            .collect()
     }
 
-    /// Insert a new tunnel and close the old one if present.
-    pub async fn replace_tunnel(&self, device_short_id: &str, conn: Connection) {
+    /// Insert a new tunnel and close the old one if present. Returns true if
+    /// the device just came online: with a registry, if it held no live lease
+    /// on any node, so moving between replicas is not reported; otherwise if
+    /// it had no tunnel here.
+    pub async fn replace_tunnel(&self, device_short_id: &str, conn: Connection) -> bool {
         info!("Replacing tunnel for device {}", device_short_id);
+        let conn_id = conn.stable_id();
 
         // Replace old tunnel atomically.
         let old = {
@@ -36,40 +199,102 @@ impl RelayState {
         }
 
         // Clean up old tunnel if there was one
+        let came_online = old.is_none();
         if let Some(old_conn) = old {
             warn!("Closing old tunnel for device {}", device_short_id);
             old_conn.close(0u32.into(), b"replaced-by-new-connection");
         }
+
+        match &self.registry {
+            Some(registry) => registry
+                .claim(device_short_id, conn_id)
+                .await
+                .unwrap_or(came_online),
+            None => came_online,
+        }
     }
 
     /// Remove the tunnel ONLY if this connection is still the active one.
-    pub async fn remove_if_match(&self, device_short_id: &str, conn_id: usize) {
-        let mut tunnels = self.tunnels.write().await;
-
-        if let Some(active) = tunnels.get(device_short_id) {
-            // Connection ID must be compared to ensure we don't remove a newer tunnel
-            if active.stable_id() == conn_id {
-                info!("Removing tunnel for device {} (matched)", device_short_id);
-                tunnels.remove(device_short_id);
-
-                // Mark device lost
-                let mut lost = self.lost.write().await;
-                lost.insert(device_short_id.to_string(), ());
-            } else {
-                warn!(
-                    "Skipping removal for device {} because connection ID does not match (stale close event)",
-                    device_short_id
-                );
+    /// Returns true if the device just went offline: it was, and with a
+    /// registry, no other node has taken over the lease meanwhile.
+    pub async fn remove_if_match(&self, device_short_id: &str, conn_id: usize) -> bool {
+        let removed = {
+            let mut tunnels = self.tunnels.write().await;
+
+            match tunnels.get(device_short_id) {
+                // Connection ID must be compared to ensure we don't remove a newer tunnel
+                Some(active) if active.stable_id() == conn_id => {
+                    info!("Removing tunnel for device {} (matched)", device_short_id);
+                    tunnels.remove(device_short_id);
+
+                    // Mark device lost
+                    let mut lost = self.lost.write().await;
+                    lost.insert(device_short_id.to_string(), ());
+                    true
+                }
+                Some(_) => {
+                    warn!(
+                        "Skipping removal for device {} because connection ID does not match (stale close event)",
+                        device_short_id
+                    );
+                    false
+                }
+                None => false,
             }
+        };
+
+        // Outside the locks: the registry round-trips to Mongo.
+        match &self.registry {
+            Some(registry) if removed => registry
+                .release(device_short_id, conn_id)
+                .await
+                .unwrap_or(removed),
+            _ => removed,
         }
     }
 
-    /// Returns true only if device has an active and *not lost* tunnel.
+    /// Returns true if the device has an active and *not lost* tunnel on this
+    /// node, or a live lease on another node.
+    pub async fn has_tunnel(&self, device_short_id: &str) -> bool {
+        if self.has_local_tunnel(device_short_id).await {
+            return true;
+        }
+        match &self.registry {
+            Some(registry) => registry.remote_owner(device_short_id).await.is_some(),
+            None => false,
+        }
+    }
+
+    /// The subset of `device_short_ids` that is online, like `has_tunnel`
+    /// but with one registry lookup for all devices not connected here.
+    pub async fn online_devices(&self, device_short_ids: &[String]) -> HashSet<String> {
+        let mut online = HashSet::new();
+        let mut elsewhere = Vec::new();
+        {
+            let tunnels = self.tunnels.read().await;
+            let lost = self.lost.read().await;
+            for id in device_short_ids {
+                if tunnels.contains_key(id) && !lost.contains_key(id) {
+                    online.insert(id.clone());
+                } else {
+                    elsewhere.push(id.clone());
+                }
+            }
+        }
+        if let Some(registry) = &self.registry
+            && !elsewhere.is_empty()
+        {
+            online.extend(registry.remote_online(&elsewhere).await);
+        }
+        online
+    }
+
+    /// Returns true only if device has an active and *not lost* tunnel here.
     ///
     /// Locks are acquired `tunnels` before `lost`, matching `remove_if_match`
     /// and `replace_tunnel`. A consistent order across all methods is what
     /// prevents the AB-BA deadlock between the two relay locks.
-    pub async fn has_tunnel(&self, device_short_id: &str) -> bool {
+    pub async fn has_local_tunnel(&self, device_short_id: &str) -> bool {
         let tunnels = self.tunnels.read().await;
         if !tunnels.contains_key(device_short_id) {
             return false;
@@ -81,7 +306,7 @@ impl RelayState {
 
     /// Return active (non-lost) tunnel.
     ///
-    /// Locks are acquired `tunnels` before `lost` (see `has_tunnel`).
+    /// Locks are acquired `tunnels` before `lost` (see `has_local_tunnel`).
     pub async fn get_tunnel(&self, device_short_id: &str) -> Option<Connection> {
         let tunnels = self.tunnels.read().await;
         let conn = tunnels.get(device_short_id).cloned()?;
@@ -92,6 +317,18 @@ impl RelayState {
         }
         Some(conn)
     }
+
+    /// Local tunnel if present, otherwise the node holding it (if any).
+    pub async fn route(&self, device_short_id: &str) -> Option<TunnelRoute> {
+        if let Some(conn) = self.get_tunnel(device_short_id).await {
+            return Some(TunnelRoute::Local(conn));
+        }
+        let registry = self.registry.as_ref()?;
+        registry
+            .remote_owner(device_short_id)
+            .await
+            .map(TunnelRoute::Remote)
+    }
 }
 
 #[cfg(test)]
@@ -103,14 +340,14 @@ mod tests {
     /// Reproduces the AB-BA deadlock between `tunnels` and `lost`.
     ///
     /// `remove_if_match` acquires `tunnels.write()` then `lost.write()`
-    /// (tunnels -> lost), while `has_tunnel`/`get_tunnel` acquire
+    /// (tunnels -> lost), while `has_local_tunnel`/`get_tunnel` acquire
     /// `lost.read()` then `tunnels.read()` (lost -> tunnels). With a reader
     /// in flight, a writer holding `tunnels` can never obtain `lost` and the
     /// whole relay wedges.
     ///
     /// We simulate the writer side by holding `tunnels.write()` (exactly what
     /// `remove_if_match` holds when it reaches `lost.write()`), run the *real*
-    /// `has_tunnel` concurrently, then assert the writer can still acquire
+    /// `has_local_tunnel` concurrently, then assert the writer can still acquire
     /// `lost.write()`. On the pre-fix reader ordering this times out.
     #[tokio::test]
     async fn writer_is_not_deadlocked_by_concurrent_reader() {
@@ -119,9 +356,9 @@ mod tests {
         // Writer side: hold `tunnels.write()`, as `remove_if_match` does.
         let tunnels_guard = state.tunnels.write().await;
 
-        // Reader side: real `has_tunnel`, running concurrently.
+        // Reader side: real `has_local_tunnel`, running concurrently.
         let reader_state = state.clone();
-        let reader = tokio::spawn(async move { reader_state.has_tunnel("device-1").await });
+        let reader = tokio::spawn(async move { reader_state.has_local_tunnel("device-1").await });
 
         // Let the reader reach its first lock acquisition and park on the
         // second one. On current-thread runtime this yields to `reader`.
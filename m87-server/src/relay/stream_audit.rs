@@ -0,0 +1,568 @@
+//! Structured audit events for relayed streams.
+//!
+//! The node a client is connected to reads the header of every stream it
+//! forwards and counts bytes in both directions. When the client connection
+//! ends (`handle_forward_once` returns), one audit entry is written per
+//! stream type and target, e.g. all streams of an `m87 <device> forward
+//! 8080` session become a single `forward` entry for `tcp:127.0.0.1:8080`.
+
+use std::collections::BTreeMap;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::future::{AbortHandle, Abortable};
+use m87_shared::p2p::DirectStreamUsage;
+use mongodb::bson::{DateTime, oid::ObjectId};
+use serde_json::Value;
+use tokio::io::{self, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tracing::warn;
+
+use crate::auth::claims::Claims;
+use crate::models::audit_logs::{AuditLogDoc, StreamAuditFields};
+use crate::models::device::DeviceDoc;
+use crate::models::org::OrgSettingsDoc;
+use crate::relay::recording::{Asciicast, SessionKind};
+use crate::util::app_state::AppState;
+
+const MAX_HEADER_LEN: usize = 64 * 1024;
+
+/// Who is connected to which device, for audit entries and session
+/// recordings made on one client connection.
+pub struct ForwardAudit {
+    pub state: AppState,
+    pub claims: Claims,
+    pub device_id: Option<ObjectId>,
+    /// An org with access to the device has `record_sessions` enabled.
+    pub record_sessions: bool,
+}
+
+impl ForwardAudit {
+    pub async fn for_device(state: &AppState, claims: &Claims, device: &DeviceDoc) -> Arc<Self> {
+        let record_sessions = OrgSettingsDoc::any_records_sessions(&state.db, &device.org_ids())
+            .await
+            .unwrap_or_else(|e| {
+                warn!(device_id = ?device.id, "failed to load org recording policy: {e:?}");
+                false
+            });
+
+        Arc::new(Self {
+            state: state.clone(),
+            claims: claims.clone(),
+            device_id: device.id,
+            record_sessions,
+        })
+    }
+
+    /// Write one entry per stream type/target seen on the connection.
+    pub async fn write_events(&self, usage: Vec<StreamUsage>, close_reason: &str) {
+        for u in usage {
+            let fields = StreamAuditFields {
+                stream_type: u.stream_type,
+                target: u.target,
+                streams: u.streams as i64,
+                started_at: u.started_at,
+                ended_at: u.ended_at,
+                bytes_up: u.bytes_up as i64,
+                bytes_down: u.bytes_down as i64,
+                close_reason: close_reason.to_string(),
+            };
+            if let Err(e) = AuditLogDoc::add_stream_event(
+                &self.state.db,
+                &self.claims,
+                &self.state.config,
+                self.device_id,
+                fields,
+            )
+            .await
+            {
+                warn!(device_id = ?self.device_id, "failed to write stream audit event: {e:?}");
+            }
+        }
+    }
+}
+
+/// Stream type and target as read from a stream header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamInfo {
+    pub stream_type: String,
+    pub target: Option<String>,
+}
+
+impl StreamInfo {
+    fn from_header(header: &Value) -> Option<Self> {
+        let stream_type = header.get("type")?.as_str()?.to_ascii_lowercase();
+        let target = match stream_type.as_str() {
+            "forward" => header.get("target").and_then(forward_target),
+            "serial" | "seriallog" => header
+                .get("name")
+                .and_then(Value::as_str)
+                .map(|name| format!("serial:{name}")),
+            "persistedlogs" => header
+                .get("unit")
+                .and_then(Value::as_str)
+                .map(|unit| format!("unit:{unit}")),
+            "journal" => journal_target(header),
+            "signal" => match (
+                header.get("pid"),
+                header.get("signal").and_then(Value::as_str),
+            ) {
+                (Some(pid), Some(signal)) => Some(format!("pid:{pid}:{signal}")),
+                _ => None,
+            },
+            "p2p" => header
+                .get("candidates")
+                .and_then(Value::as_array)
+                .map(|addrs| {
+                    let addrs: Vec<&str> = addrs.iter().filter_map(Value::as_str).collect();
+                    format!("candidates:{}", addrs.join(","))
+                }),
+            _ => None,
+        };
+        Some(Self {
+            stream_type,
+            target,
+        })
+    }
+}
+
+/// `journal:docker.service,kernel`; `None` for the whole journal.
+fn journal_target(header: &Value) -> Option<String> {
+    let mut sources: Vec<&str> = header
+        .get("units")
+        .and_then(Value::as_array)
+        .map(|units| units.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+    if header.get("kernel").and_then(Value::as_bool) == Some(true) {
+        sources.push("kernel");
+    }
+    (!sources.is_empty()).then(|| format!("journal:{}", sources.join(",")))
+}
+
+/// `{"Tcp": {"remote_host": .., "remote_port": ..}}` → `tcp:host:port`.
+fn forward_target(target: &Value) -> Option<String> {
+    let (kind, t) = target.as_object()?.iter().next()?;
+    let kind = kind.to_ascii_lowercase();
+    let field = |name: &str| match t.get(name)? {
+        Value::String(s) => Some(s.clone()),
+        Value::Null => None,
+        v => Some(v.to_string()),
+    };
+    match kind.as_str() {
+        "tcp" | "udp" => Some(format!(
+            "{kind}:{}:{}",
+            field("remote_host")?,
+            field("remote_port")?
+        )),
+        "socket" => Some(format!("socket:{}", field("remote_path")?)),
+        "vpn" => Some(match field("cidr") {
+            Some(cidr) => format!("vpn:{cidr}"),
+            None => "vpn".to_string(),
+        }),
+        _ => Some(kind),
+    }
+}
+
+/// Header forwarded from client to device, with what the relay learned from it.
+pub struct ForwardedHeader {
+    pub info: Option<StreamInfo>,
+    /// Set if the stream has to be recorded; compression was dropped then.
+    pub session: Option<SessionKind>,
+}
+
+/// Copy the stream header from client to device. Direct-connection requests
+/// are refused when sessions are recorded, since the relay would not see the
+/// traffic of the direct session.
+pub async fn forward_header(
+    client_recv: &mut Pin<Box<dyn AsyncRead + Send>>,
+    dev_send: &mut quinn::SendStream,
+    record_sessions: bool,
+) -> io::Result<ForwardedHeader> {
+    let mut len_buf = [0u8; 4];
+    client_recv.read_exact(&mut len_buf).await?;
+    let len = u32::from_be_bytes(len_buf) as usize;
+    if len > MAX_HEADER_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "stream header too large",
+        ));
+    }
+    let mut buf = vec![0u8; len];
+    client_recv.read_exact(&mut buf).await?;
+
+    let mut forwarded = ForwardedHeader {
+        info: None,
+        session: None,
+    };
+    if let Ok(mut header) = serde_json::from_slice::<Value>(&buf) {
+        forwarded.info = StreamInfo::from_header(&header);
+        if record_sessions
+            && forwarded
+                .info
+                .as_ref()
+                .is_some_and(|i| i.stream_type == "p2p")
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "direct connections are disabled while sessions are recorded",
+            ));
+        }
+        if record_sessions {
+            forwarded.session = SessionKind::from_header(&header);
+        }
+        if forwarded.session.is_some()
+            && let Some(obj) = header.as_object_mut()
+            && obj.remove("compression").is_some()
+        {
+            buf = serde_json::to_vec(&header)?;
+        }
+    }
+
+    dev_send
+        .write_all(&(buf.len() as u32).to_be_bytes())
+        .await?;
+    dev_send.write_all(&buf).await?;
+    Ok(forwarded)
+}
+
+/// Bytes moved by [`bridge`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Traffic {
+    pub bytes_up: u64,
+    pub bytes_down: u64,
+}
+
+/// Copy both directions until one side ends, then stop the other. With a
+/// recorder, input and output are also fed into the asciicast.
+pub async fn bridge(
+    client_send: Pin<Box<dyn AsyncWrite + Send>>,
+    client_recv: Pin<Box<dyn AsyncRead + Send>>,
+    dev_send: quinn::SendStream,
+    dev_recv: quinn::RecvStream,
+    cast: Option<Arc<Mutex<Asciicast>>>,
+) -> Traffic {
+    let up = Arc::new(AtomicU64::new(0));
+    let down = Arc::new(AtomicU64::new(0));
+
+    let (abort_uplink, reg_up) = AbortHandle::new_pair();
+    let (abort_down, reg_dn) = AbortHandle::new_pair();
+
+    let up_cast = cast.clone();
+    let uplink = tokio::spawn(Abortable::new(
+        copy_counted(client_recv, dev_send, up.clone(), move |data| {
+            if let Some(cast) = &up_cast {
+                cast.lock().unwrap().input(data);
+            }
+        }),
+        reg_up,
+    ));
+    let downlink = tokio::spawn(Abortable::new(
+        copy_counted(dev_recv, client_send, down.clone(), move |data| {
+            if let Some(cast) = &cast {
+                cast.lock().unwrap().output(data);
+            }
+        }),
+        reg_dn,
+    ));
+
+    tokio::select! {
+        _ = uplink => abort_down.abort(),
+        _ = downlink => abort_uplink.abort(),
+    }
+
+    Traffic {
+        bytes_up: up.load(Ordering::Relaxed),
+        bytes_down: down.load(Ordering::Relaxed),
+    }
+}
+
+/// How long a `P2p` stream may keep reporting after the client side ended.
+const DIRECT_DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Bridge for `P2p` streams. The stream outlives the negotiation: the
+/// device's first frame (the answer) goes to the client, every later frame is
+/// a [`DirectStreamUsage`] of the direct session, handed to `on_usage` and not
+/// forwarded. Closing the relayed connection ends the stream, and with it the
+/// direct session on the device.
+pub async fn bridge_direct<F>(
+    client_send: Pin<Box<dyn AsyncWrite + Send>>,
+    client_recv: Pin<Box<dyn AsyncRead + Send>>,
+    dev_send: quinn::SendStream,
+    dev_recv: quinn::RecvStream,
+    on_usage: F,
+) -> Traffic
+where
+    F: FnMut(StreamInfo, DateTime, Traffic) + Send + 'static,
+{
+    let up = Arc::new(AtomicU64::new(0));
+    let down = Arc::new(AtomicU64::new(0));
+
+    let mut uplink = tokio::spawn(copy_counted(client_recv, dev_send, up.clone(), |_| {}));
+    let mut downlink = tokio::spawn(relay_direct_reports(
+        dev_recv,
+        client_send,
+        down.clone(),
+        on_usage,
+    ));
+
+    tokio::select! {
+        _ = &mut downlink => uplink.abort(),
+        _ = &mut uplink => {
+            // The device winds the session down and reports its last streams.
+            if tokio::time::timeout(DIRECT_DRAIN_TIMEOUT, &mut downlink).await.is_err() {
+                downlink.abort();
+            }
+        }
+    }
+
+    Traffic {
+        bytes_up: up.load(Ordering::Relaxed),
+        bytes_down: down.load(Ordering::Relaxed),
+    }
+}
+
+async fn relay_direct_reports<F>(
+    mut dev_recv: quinn::RecvStream,
+    mut client_send: Pin<Box<dyn AsyncWrite + Send>>,
+    counter: Arc<AtomicU64>,
+    mut on_usage: F,
+) -> io::Result<()>
+where
+    F: FnMut(StreamInfo, DateTime, Traffic),
+{
+    let result = async {
+        let Some(answer) = read_frame(&mut dev_recv).await? else {
+            return Ok(());
+        };
+        client_send
+            .write_all(&(answer.len() as u32).to_be_bytes())
+            .await?;
+        client_send.write_all(&answer).await?;
+        counter.fetch_add(4 + answer.len() as u64, Ordering::Relaxed);
+
+        while let Some(frame) = read_frame(&mut dev_recv).await? {
+            let usage: DirectStreamUsage = match serde_json::from_slice(&frame) {
+                Ok(usage) => usage,
+                Err(e) => {
+                    warn!("invalid direct stream report: {e}");
+                    continue;
+                }
+            };
+            if let Some(info) = StreamInfo::from_header(&usage.header) {
+                on_usage(
+                    info,
+                    DateTime::from_millis(usage.started_at_ms as i64),
+                    Traffic {
+                        bytes_up: usage.bytes_up,
+                        bytes_down: usage.bytes_down,
+                    },
+                );
+            }
+        }
+        Ok(())
+    }
+    .await;
+    let _ = client_send.shutdown().await;
+    result
+}
+
+/// One length-prefixed frame; `None` at a clean end of stream.
+async fn read_frame(recv: &mut quinn::RecvStream) -> io::Result<Option<Vec<u8>>> {
+    let mut len_buf = [0u8; 4];
+    match recv.read_exact(&mut len_buf).await {
+        Ok(()) => {}
+        Err(quinn::ReadExactError::FinishedEarly(0)) => return Ok(None),
+        Err(e) => return Err(io::Error::other(e)),
+    }
+    let len = u32::from_be_bytes(len_buf) as usize;
+    if len > MAX_HEADER_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "frame too large",
+        ));
+    }
+    let mut buf = vec![0u8; len];
+    recv.read_exact(&mut buf).await.map_err(io::Error::other)?;
+    Ok(Some(buf))
+}
+
+async fn copy_counted<R, W, F>(
+    mut reader: R,
+    mut writer: W,
+    counter: Arc<AtomicU64>,
+    mut on_data: F,
+) -> io::Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+    F: FnMut(&[u8]),
+{
+    let mut buf = [0u8; 8192];
+    let result = loop {
+        let n = match reader.read(&mut buf).await {
+            Ok(0) => break Ok(()),
+            Ok(n) => n,
+            Err(e) => break Err(e),
+        };
+        on_data(&buf[..n]);
+        if let Err(e) = writer.write_all(&buf[..n]).await {
+            break Err(e);
+        }
+        counter.fetch_add(n as u64, Ordering::Relaxed);
+    };
+    let _ = writer.shutdown().await;
+    result
+}
+
+/// Aggregated streams of one type/target.
+#[derive(Debug, Clone)]
+pub struct StreamUsage {
+    pub stream_type: String,
+    pub target: Option<String>,
+    pub streams: u32,
+    pub started_at: DateTime,
+    pub ended_at: DateTime,
+    pub bytes_up: u64,
+    pub bytes_down: u64,
+}
+
+/// Streams seen on one client connection, collected by the stream tasks.
+#[derive(Default)]
+pub struct ConnectionAudit {
+    usage: Mutex<BTreeMap<(String, Option<String>), StreamUsage>>,
+}
+
+impl ConnectionAudit {
+    pub fn record(&self, info: StreamInfo, started_at: DateTime, traffic: Traffic) {
+        let ended_at = DateTime::now();
+        let mut usage = self.usage.lock().unwrap();
+        let entry = usage
+            .entry((info.stream_type.clone(), info.target.clone()))
+            .or_insert_with(|| StreamUsage {
+                stream_type: info.stream_type,
+                target: info.target,
+                streams: 0,
+                started_at,
+                ended_at,
+                bytes_up: 0,
+                bytes_down: 0,
+            });
+        entry.streams += 1;
+        entry.started_at = entry.started_at.min(started_at);
+        entry.ended_at = entry.ended_at.max(ended_at);
+        entry.bytes_up += traffic.bytes_up;
+        entry.bytes_down += traffic.bytes_down;
+    }
+
+    pub fn take(&self) -> Vec<StreamUsage> {
+        std::mem::take(&mut *self.usage.lock().unwrap())
+            .into_values()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn forward_header_target() {
+        let header = json!({
+            "type": "Forward",
+            "token": "secret",
+            "target": { "Tcp": { "remote_host": "127.0.0.1", "remote_port": 8080, "local_port": 9000 } },
+        });
+        assert_eq!(
+            StreamInfo::from_header(&header),
+            Some(StreamInfo {
+                stream_type: "forward".into(),
+                target: Some("tcp:127.0.0.1:8080".into()),
+            })
+        );
+
+        let vpn = json!({ "type": "Forward", "token": "t", "target": { "Vpn": { "cidr": null, "mtu": null } } });
+        assert_eq!(
+            StreamInfo::from_header(&vpn).unwrap().target.as_deref(),
+            Some("vpn")
+        );
+    }
+
+    #[test]
+    fn serial_and_plain_headers() {
+        let serial = json!({ "type": "Serial", "token": "t", "name": "ttyUSB0", "baud": 115200 });
+        assert_eq!(
+            StreamInfo::from_header(&serial).unwrap().target.as_deref(),
+            Some("serial:ttyUSB0")
+        );
+
+        let journal =
+            json!({ "type": "Journal", "token": "t", "units": ["docker.service"], "kernel": true });
+        assert_eq!(
+            StreamInfo::from_header(&journal).unwrap().target.as_deref(),
+            Some("journal:docker.service,kernel")
+        );
+
+        let signal = json!({ "type": "Signal", "token": "t", "pid": 812, "signal": "TERM" });
+        assert_eq!(
+            StreamInfo::from_header(&signal).unwrap().target.as_deref(),
+            Some("pid:812:TERM")
+        );
+
+        let p2p = json!({ "type": "P2p", "token": "t", "candidates": ["192.168.1.5:41000", "203.0.113.7:41000"] });
+        assert_eq!(
+            StreamInfo::from_header(&p2p).unwrap().target.as_deref(),
+            Some("candidates:192.168.1.5:41000,203.0.113.7:41000")
+        );
+
+        let exec = json!({ "type": "Exec", "token": "t", "compression": "zstd" });
+        let info = StreamInfo::from_header(&exec).unwrap();
+        assert_eq!(info.stream_type, "exec");
+        assert_eq!(info.target, None);
+    }
+
+    #[test]
+    fn connection_audit_aggregates_by_type_and_target() {
+        let audit = ConnectionAudit::default();
+        let fwd = StreamInfo {
+            stream_type: "forward".into(),
+            target: Some("tcp:127.0.0.1:80".into()),
+        };
+        let t0 = DateTime::from_millis(1_000);
+        let t1 = DateTime::from_millis(500);
+        audit.record(
+            fwd.clone(),
+            t0,
+            Traffic {
+                bytes_up: 10,
+                bytes_down: 100,
+            },
+        );
+        audit.record(
+            fwd,
+            t1,
+            Traffic {
+                bytes_up: 5,
+                bytes_down: 50,
+            },
+        );
+        audit.record(
+            StreamInfo {
+                stream_type: "terminal".into(),
+                target: None,
+            },
+            t0,
+            Traffic::default(),
+        );
+
+        let usage = audit.take();
+        assert_eq!(usage.len(), 2);
+        let fwd = usage.iter().find(|u| u.stream_type == "forward").unwrap();
+        assert_eq!(fwd.streams, 2);
+        assert_eq!(fwd.bytes_up, 15);
+        assert_eq!(fwd.bytes_down, 150);
+        assert_eq!(fwd.started_at, t1);
+        assert!(audit.take().is_empty());
+    }
+}
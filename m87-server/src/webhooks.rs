@@ -0,0 +1,475 @@
+//! Outbound org webhooks.
+//!
+//! Events are turned into one delivery document per subscribed webhook and
+//! sent right away; failed attempts are retried with backoff by the delivery
+//! worker, which any replica may run. Each request carries
+//!
+//! - `X-M87-Event`: the event name, e.g. `device_offline`
+//! - `X-M87-Delivery`: the delivery id (same across retries)
+//! - `X-M87-Timestamp`: unix seconds of this attempt
+//! - `X-M87-Signature`: `sha256=<hex>` HMAC of `<timestamp>.<body>` keyed
+//!   with the webhook secret
+//!
+//! Webhooks must not reach into the server's own network: urls pointing at
+//! loopback, private or link-local addresses (which includes cloud metadata
+//! endpoints) are rejected when the webhook is created, and every address a
+//! host name resolves to is checked again when connecting. Operators can
+//! allow such hosts with `WEBHOOK_ALLOWED_HOSTS`.
+
+use std::error::Error as _;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use hmac::{Hmac, Mac};
+use m87_shared::org::{WebhookEvent, WebhookPayload};
+use mongodb::bson::{DateTime, oid::ObjectId};
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::{Client, Url};
+use serde_json::Value;
+use sha2::Sha256;
+use tracing::{debug, warn};
+
+use crate::db::Mongo;
+use crate::models::device::DeviceDoc;
+use crate::models::webhook::{WebhookDeliveryDoc, WebhookDoc};
+use crate::relay::peer::unix_now;
+
+/// Wait before retry n (after the n-th failed attempt). The delivery fails
+/// for good once these are used up.
+const RETRY_BACKOFF: [Duration; 5] = [
+    Duration::from_secs(10),
+    Duration::from_secs(60),
+    Duration::from_secs(5 * 60),
+    Duration::from_secs(30 * 60),
+    Duration::from_secs(2 * 60 * 60),
+];
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Longer than `REQUEST_TIMEOUT`, so a claimed delivery isn't sent twice.
+const CLAIM_LEASE: Duration = Duration::from_secs(60);
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+const MAX_ERROR_LEN: usize = 512;
+
+/// `WEBHOOK_ALLOWED_HOSTS`, set when the delivery worker starts.
+static ALLOWED_HOSTS: OnceLock<Vec<String>> = OnceLock::new();
+
+fn allowed_hosts() -> &'static [String] {
+    ALLOWED_HOSTS.get().map_or(&[], Vec::as_slice)
+}
+
+fn http_client() -> &'static Client {
+    static CLIENT: OnceLock<Client> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .redirect(reqwest::redirect::Policy::none())
+            .dns_resolver(Arc::new(PublicOnlyResolver))
+            .build()
+            .expect("failed to build reqwest client")
+    })
+}
+
+/// Resolves host names like the system resolver, but fails for hosts with an
+/// internal address unless they are allowed. Checking the addresses that are
+/// actually connected to means a DNS change after the webhook was created
+/// can't point it into the server's network.
+struct PublicOnlyResolver;
+
+impl Resolve for PublicOnlyResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let host = name.as_str().to_string();
+            let addrs: Vec<SocketAddr> =
+                tokio::net::lookup_host((host.as_str(), 0)).await?.collect();
+            if !is_allowed_host(&host, allowed_hosts())
+                && let Some(addr) = addrs.iter().find(|a| is_internal(a.ip()))
+            {
+                return Err(format!("{host} resolves to internal address {}", addr.ip()).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Loopback, private (RFC 1918, unique local), link-local (169.254.0.0/16,
+/// including the cloud metadata endpoint), shared (100.64.0.0/10),
+/// unspecified and broadcast addresses. IPv4 addresses embedded in IPv6
+/// (`::ffff:0:0/96`, NAT64 `64:ff9b::/96`) are checked as IPv4.
+fn is_internal(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_internal_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped().or_else(|| nat64_embedded(ip)) {
+            Some(v4) => is_internal_v4(v4),
+            None => is_internal_v6(ip),
+        },
+    }
+}
+
+/// The IPv4 address behind a NAT64 well-known prefix address.
+fn nat64_embedded(ip: Ipv6Addr) -> Option<Ipv4Addr> {
+    let octets = ip.octets();
+    let nat64_prefix = [0, 0x64, 0xff, 0x9b, 0, 0, 0, 0, 0, 0, 0, 0];
+    (octets[..12] == nat64_prefix)
+        .then(|| Ipv4Addr::new(octets[12], octets[13], octets[14], octets[15]))
+}
+
+fn is_internal_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || a == 0
+        || (a == 100 && (b & 0xc0) == 64)
+}
+
+fn is_internal_v6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    ip.is_loopback()
+        || ip.is_unspecified()
+        || (first & 0xfe00) == 0xfc00
+        || (first & 0xffc0) == 0xfe80
+}
+
+fn is_allowed_host(host: &str, allowed_hosts: &[String]) -> bool {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    allowed_hosts.iter().any(|h| h.eq_ignore_ascii_case(host))
+}
+
+/// Reject webhook urls whose host is an internal address or a name for the
+/// local machine, unless it is in `allowed_hosts`. Host names are checked
+/// again after resolution when delivering.
+pub fn check_target(url: &Url, allowed_hosts: &[String]) -> Result<(), String> {
+    let host = url.host_str().ok_or("Webhook url has no host")?;
+    if is_allowed_host(host, allowed_hosts) {
+        return Ok(());
+    }
+    let name = host.trim_start_matches('[').trim_end_matches(']');
+    let internal = match name.parse::<IpAddr>() {
+        Ok(ip) => is_internal(ip),
+        Err(_) => {
+            let name = name.trim_end_matches('.').to_ascii_lowercase();
+            name == "localhost"
+                || name.ends_with(".localhost")
+                || name == "metadata.google.internal"
+        }
+    };
+    if internal {
+        return Err(format!(
+            "Webhook url must not target an internal address ({host})"
+        ));
+    }
+    Ok(())
+}
+
+/// Retry deliveries that are due. Runs for the lifetime of the server.
+pub fn spawn_delivery_worker(db: Arc<Mongo>, allowed_hosts: Vec<String>) {
+    let _ = ALLOWED_HOSTS.set(allowed_hosts);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        loop {
+            interval.tick().await;
+            loop {
+                match WebhookDeliveryDoc::claim(&db, None, CLAIM_LEASE).await {
+                    Ok(Some(delivery)) => attempt(&db, delivery).await,
+                    Ok(None) => break,
+                    Err(e) => {
+                        warn!("failed to claim webhook delivery: {e:?}");
+                        break;
+                    }
+                }
+            }
+        }
+    });
+}
+
+/// Fire `event` for the orgs of `device` in the background.
+pub fn notify_device(db: &Arc<Mongo>, device: &DeviceDoc, event: WebhookEvent, data: Value) {
+    let db = db.clone();
+    let org_ids = device.org_ids();
+    let device_id = device.id.map(|id| id.to_hex());
+    let device_name = Some(device.name.clone());
+    tokio::spawn(async move {
+        emit(&db, &org_ids, event, device_id, device_name, data).await;
+    });
+}
+
+/// Like [`notify_device`] when only the device's id is at hand.
+pub fn notify_device_id(db: &Arc<Mongo>, device_id: ObjectId, event: WebhookEvent, data: Value) {
+    let db = db.clone();
+    tokio::spawn(async move {
+        match db
+            .devices()
+            .find_one(mongodb::bson::doc! { "_id": device_id })
+            .await
+        {
+            Ok(Some(device)) => {
+                let device_name = Some(device.name.clone());
+                emit(
+                    &db,
+                    &device.org_ids(),
+                    event,
+                    Some(device_id.to_hex()),
+                    device_name,
+                    data,
+                )
+                .await
+            }
+            Ok(None) => {}
+            Err(e) => warn!(%device_id, "failed to load device for webhook: {e:?}"),
+        }
+    });
+}
+
+/// Fire `event` for a single org in the background.
+pub fn notify_org(db: &Arc<Mongo>, org_id: &str, event: WebhookEvent, data: Value) {
+    let db = db.clone();
+    let org_ids = vec![org_id.to_string()];
+    tokio::spawn(async move {
+        emit(&db, &org_ids, event, None, None, data).await;
+    });
+}
+
+/// Fire a device event for a single org in the background, e.g. an alert of
+/// one org's rule on a device shared with others.
+pub fn notify_org_device(
+    db: &Arc<Mongo>,
+    org_id: &str,
+    device_id: ObjectId,
+    device_name: &str,
+    event: WebhookEvent,
+    data: Value,
+) {
+    let db = db.clone();
+    let org_ids = vec![org_id.to_string()];
+    let device_id = Some(device_id.to_hex());
+    let device_name = Some(device_name.to_string());
+    tokio::spawn(async move {
+        emit(&db, &org_ids, event, device_id, device_name, data).await;
+    });
+}
+
+async fn emit(
+    db: &Arc<Mongo>,
+    org_ids: &[String],
+    event: WebhookEvent,
+    device_id: Option<String>,
+    device_name: Option<String>,
+    data: Value,
+) {
+    let hooks = match WebhookDoc::subscribed(db, org_ids, event).await {
+        Ok(hooks) => hooks,
+        Err(e) => {
+            warn!(%event, "failed to look up webhooks: {e:?}");
+            return;
+        }
+    };
+
+    for hook in hooks {
+        let id = ObjectId::new();
+        let payload = WebhookPayload {
+            id: id.to_hex(),
+            event,
+            org_id: hook.org_id.clone(),
+            created_at: DateTime::now().try_to_rfc3339_string().unwrap_or_default(),
+            device_id: device_id.clone(),
+            device_name: device_name.clone(),
+            data: data.clone(),
+        };
+        let Ok(body) = serde_json::to_string(&payload) else {
+            continue;
+        };
+        let delivery = WebhookDeliveryDoc::new(id, &hook, event, body);
+        if let Err(e) = delivery.insert(db).await {
+            warn!(%event, org_id = %hook.org_id, "failed to queue webhook delivery: {e:?}");
+            continue;
+        }
+
+        // First attempt right away; retries go through the worker.
+        let db = db.clone();
+        tokio::spawn(async move {
+            if let Ok(Some(delivery)) = WebhookDeliveryDoc::claim(&db, Some(id), CLAIM_LEASE).await
+            {
+                attempt(&db, delivery).await;
+            }
+        });
+    }
+}
+
+async fn attempt(db: &Arc<Mongo>, delivery: WebhookDeliveryDoc) {
+    let hook = match WebhookDoc::find_by_id(db, delivery.webhook_id).await {
+        Ok(Some(hook)) => hook,
+        Ok(None) => {
+            let _ = delivery
+                .record_attempt(db, None, Some("webhook deleted".into()), false, None)
+                .await;
+            return;
+        }
+        // Leave it claimed; the lease runs out and the worker retries.
+        Err(e) => {
+            warn!(delivery = %delivery.id, "failed to load webhook: {e:?}");
+            return;
+        }
+    };
+
+    // IP literals bypass the resolver, so their check runs here.
+    if let Err(reason) = Url::parse(&hook.url)
+        .map_err(|e| e.to_string())
+        .and_then(|url| check_target(&url, allowed_hosts()))
+    {
+        let _ = delivery
+            .record_attempt(db, None, Some(reason), false, None)
+            .await;
+        return;
+    }
+
+    let timestamp = unix_now();
+    let res = http_client()
+        .post(&hook.url)
+        .header("Content-Type", "application/json")
+        .header("User-Agent", "m87-webhooks")
+        .header("X-M87-Event", delivery.event.as_str())
+        .header("X-M87-Delivery", delivery.id.to_hex())
+        .header("X-M87-Timestamp", timestamp.to_string())
+        .header(
+            "X-M87-Signature",
+            format!(
+                "sha256={}",
+                sign(&hook.secret, timestamp, &delivery.payload)
+            ),
+        )
+        .body(delivery.payload.clone())
+        .send()
+        .await;
+
+    let (status_code, error) = match res {
+        Ok(r) if r.status().is_success() => (Some(r.status().as_u16()), None),
+        Ok(r) => (
+            Some(r.status().as_u16()),
+            Some(format!("HTTP {}", r.status())),
+        ),
+        Err(e) => (None, Some(truncate(error_chain(&e)))),
+    };
+    let delivered = error.is_none();
+    let retry_in = if delivered {
+        None
+    } else {
+        RETRY_BACKOFF.get(delivery.attempts as usize).copied()
+    };
+    debug!(
+        delivery = %delivery.id,
+        url = %hook.url,
+        ?status_code,
+        delivered,
+        "webhook attempt {}",
+        delivery.attempts + 1
+    );
+
+    if let Err(e) = delivery
+        .record_attempt(db, status_code, error, delivered, retry_in)
+        .await
+    {
+        warn!(delivery = %delivery.id, "failed to record webhook attempt: {e:?}");
+    }
+}
+
+fn sign(secret: &str, timestamp: u64, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// `reqwest` errors only name the request; the cause (e.g. a refused
+/// address) is in their sources.
+fn error_chain(e: &reqwest::Error) -> String {
+    let mut text = e.to_string();
+    let mut source = e.source();
+    while let Some(cause) = source {
+        text.push_str(": ");
+        text.push_str(&cause.to_string());
+        source = cause.source();
+    }
+    text
+}
+
+fn truncate(mut s: String) -> String {
+    if s.len() > MAX_ERROR_LEN {
+        let mut cut = MAX_ERROR_LEN;
+        while !s.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        s.truncate(cut);
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signature_covers_timestamp_and_body() {
+        let sig = sign("whsec_test", 1_700_000_000, r#"{"event":"device_online"}"#);
+        assert_eq!(sig.len(), 64);
+        assert_eq!(
+            sig,
+            sign("whsec_test", 1_700_000_000, r#"{"event":"device_online"}"#)
+        );
+        assert_ne!(
+            sig,
+            sign("whsec_test", 1_700_000_001, r#"{"event":"device_online"}"#)
+        );
+        assert_ne!(
+            sig,
+            sign("whsec_other", 1_700_000_000, r#"{"event":"device_online"}"#)
+        );
+    }
+
+    #[test]
+    fn internal_targets_are_rejected_unless_allowed() {
+        let check = |url: &str, allowed: &[&str]| {
+            let allowed: Vec<String> = allowed.iter().map(|h| h.to_string()).collect();
+            check_target(&Url::parse(url).unwrap(), &allowed)
+        };
+
+        for url in [
+            "http://127.0.0.1/hook",
+            "http://10.1.2.3/hook",
+            "http://172.16.0.1/hook",
+            "http://192.168.1.10:8080/hook",
+            "http://169.254.169.254/latest/meta-data",
+            "http://100.64.0.1/hook",
+            "http://0.0.0.0/hook",
+            "http://[::1]/hook",
+            "http://[fd00:ec2::254]/hook",
+            "http://[fe80::1]/hook",
+            "http://[::ffff:10.0.0.1]/hook",
+            "http://[64:ff9b::a9fe:a9fe]/latest/meta-data",
+            "http://[64:ff9b::127.0.0.1]/hook",
+            "http://localhost:3000/hook",
+            "http://api.localhost/hook",
+            "http://metadata.google.internal/computeMetadata/v1/",
+        ] {
+            assert!(check(url, &[]).is_err(), "{url} should be rejected");
+        }
+
+        assert!(check("https://hooks.example.com/m87", &[]).is_ok());
+        assert!(check("https://8.8.8.8/hook", &[]).is_ok());
+        assert!(check("https://[64:ff9b::808:808]/hook", &[]).is_ok());
+        assert!(check("http://10.0.0.12/hook", &["10.0.0.12"]).is_ok());
+        assert!(check("http://localhost:3000/hook", &["localhost"]).is_ok());
+        assert!(check("http://[::1]/hook", &["::1"]).is_ok());
+    }
+
+    #[test]
+    fn error_text_is_cut_on_char_boundary() {
+        let s = "é".repeat(MAX_ERROR_LEN);
+        let cut = truncate(s);
+        assert!(cut.len() <= MAX_ERROR_LEN);
+        assert!(cut.chars().all(|c| c == 'é'));
+    }
+}
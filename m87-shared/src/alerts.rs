@@ -0,0 +1,323 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+/// Heartbeat metrics an alert rule can compare against a threshold.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum MetricField {
+    #[serde(rename = "cpu.usage_percent")]
+    CpuUsagePercent,
+    #[serde(rename = "memory.usage_percent")]
+    MemoryUsagePercent,
+    #[serde(rename = "disk.usage_percent")]
+    DiskUsagePercent,
+    /// Hottest thermal zone.
+    #[serde(rename = "thermal.max_temp_c")]
+    ThermalMaxTempC,
+    /// Slowest fan, to catch a stalled one.
+    #[serde(rename = "thermal.min_fan_rpm")]
+    ThermalMinFanRpm,
+    #[serde(rename = "cpu.freq_mhz")]
+    CpuFreqMhz,
+    /// 1 while the supply voltage is too low, else 0.
+    #[serde(rename = "power.undervoltage")]
+    PowerUndervoltage,
+    /// 1 while the CPU is throttled or frequency-capped, else 0.
+    #[serde(rename = "power.throttled")]
+    PowerThrottled,
+    /// Containers whose health check fails.
+    #[serde(rename = "containers.unhealthy")]
+    ContainersUnhealthy,
+}
+
+impl MetricField {
+    pub const ALL: [MetricField; 9] = [
+        MetricField::CpuUsagePercent,
+        MetricField::MemoryUsagePercent,
+        MetricField::DiskUsagePercent,
+        MetricField::ThermalMaxTempC,
+        MetricField::ThermalMinFanRpm,
+        MetricField::CpuFreqMhz,
+        MetricField::PowerUndervoltage,
+        MetricField::PowerThrottled,
+        MetricField::ContainersUnhealthy,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MetricField::CpuUsagePercent => "cpu.usage_percent",
+            MetricField::MemoryUsagePercent => "memory.usage_percent",
+            MetricField::DiskUsagePercent => "disk.usage_percent",
+            MetricField::ThermalMaxTempC => "thermal.max_temp_c",
+            MetricField::ThermalMinFanRpm => "thermal.min_fan_rpm",
+            MetricField::CpuFreqMhz => "cpu.freq_mhz",
+            MetricField::PowerUndervoltage => "power.undervoltage",
+            MetricField::PowerThrottled => "power.throttled",
+            MetricField::ContainersUnhealthy => "containers.unhealthy",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    #[serde(rename = ">")]
+    Gt,
+    #[serde(rename = ">=")]
+    Ge,
+    #[serde(rename = "<")]
+    Lt,
+    #[serde(rename = "<=")]
+    Le,
+}
+
+impl Comparison {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Comparison::Gt => ">",
+            Comparison::Ge => ">=",
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+        }
+    }
+
+    pub fn holds(&self, value: f64, threshold: f64) -> bool {
+        match self {
+            Comparison::Gt => value > threshold,
+            Comparison::Ge => value >= threshold,
+            Comparison::Lt => value < threshold,
+            Comparison::Le => value <= threshold,
+        }
+    }
+}
+
+/// What an alert rule checks on each device it selects.
+///
+/// Written on the command line as
+/// - `offline`
+/// - `version_mismatch` (runtime version differs from a pinned target version)
+/// - `unhealthy:<checks>` or `unhealthy:<unit>:<checks>` (consecutive failed
+///   health checks of any / one unit)
+/// - `<metric><op><threshold>`, e.g. `disk.usage_percent>90`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AlertCondition {
+    Offline,
+    VersionMismatch,
+    Unhealthy {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        unit: Option<String>,
+        consecutive_checks: u32,
+    },
+    Metric {
+        metric: MetricField,
+        op: Comparison,
+        threshold: f64,
+    },
+}
+
+impl Display for AlertCondition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AlertCondition::Offline => write!(f, "offline"),
+            AlertCondition::VersionMismatch => write!(f, "version_mismatch"),
+            AlertCondition::Unhealthy {
+                unit: Some(unit),
+                consecutive_checks,
+            } => write!(f, "unhealthy:{unit}:{consecutive_checks}"),
+            AlertCondition::Unhealthy {
+                unit: None,
+                consecutive_checks,
+            } => write!(f, "unhealthy:{consecutive_checks}"),
+            AlertCondition::Metric {
+                metric,
+                op,
+                threshold,
+            } => write!(f, "{}{}{}", metric.as_str(), op.as_str(), threshold),
+        }
+    }
+}
+
+impl FromStr for AlertCondition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        match s {
+            "offline" => return Ok(AlertCondition::Offline),
+            "version_mismatch" => return Ok(AlertCondition::VersionMismatch),
+            _ => {}
+        }
+
+        if let Some(rest) = s.strip_prefix("unhealthy:") {
+            let (unit, checks) = match rest.rsplit_once(':') {
+                Some((unit, checks)) => (Some(unit.to_string()), checks),
+                None => (None, rest),
+            };
+            let consecutive_checks: u32 = checks
+                .parse()
+                .map_err(|_| format!("invalid check count '{checks}'"))?;
+            if consecutive_checks == 0 {
+                return Err("check count must be at least 1".to_string());
+            }
+            return Ok(AlertCondition::Unhealthy {
+                unit,
+                consecutive_checks,
+            });
+        }
+
+        for metric in MetricField::ALL {
+            let Some(rest) = s.strip_prefix(metric.as_str()) else {
+                continue;
+            };
+            // Two-character operators first so `>=` isn't read as `>`.
+            let (op, value) = [
+                Comparison::Ge,
+                Comparison::Le,
+                Comparison::Gt,
+                Comparison::Lt,
+            ]
+            .into_iter()
+            .find_map(|op| rest.strip_prefix(op.as_str()).map(|v| (op, v)))
+            .ok_or_else(|| format!("expected >, >=, < or <= after {}", metric.as_str()))?;
+            let threshold: f64 = value
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid threshold '{}'", value.trim()))?;
+            return Ok(AlertCondition::Metric {
+                metric,
+                op,
+                threshold,
+            });
+        }
+
+        Err(format!(
+            "unknown condition '{s}' (expected offline, version_mismatch, \
+             unhealthy:[<unit>:]<checks> or <metric><op><threshold> with metric one of {})",
+            MetricField::ALL.map(|m| m.as_str()).join(", ")
+        ))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AlertRule {
+    pub id: String,
+    pub org_id: String,
+    pub name: String,
+    /// Device labels that must all match; empty selects every org device.
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    pub condition: AlertCondition,
+    /// How long the condition must hold before the alert fires.
+    pub for_secs: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub silenced_until: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CreateAlertRuleBody {
+    pub name: String,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    pub condition: AlertCondition,
+    #[serde(default)]
+    pub for_secs: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SilenceAlertRuleBody {
+    /// Silence notifications for this long; `None` lifts the silence.
+    #[serde(default)]
+    pub duration_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ListAlertsQuery {
+    /// Include resolved alerts, not only firing ones.
+    #[serde(default)]
+    pub all: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertState {
+    Firing,
+    Resolved,
+}
+
+impl Display for AlertState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AlertState::Firing => write!(f, "firing"),
+            AlertState::Resolved => write!(f, "resolved"),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Alert {
+    pub id: String,
+    pub rule_id: String,
+    pub rule_name: String,
+    pub org_id: String,
+    pub device_id: String,
+    pub device_name: String,
+    /// Unit name for `unhealthy` rules, empty otherwise.
+    #[serde(default)]
+    pub subject: String,
+    pub condition: String,
+    pub state: AlertState,
+    /// Last observed value, e.g. `93.2` for a metric rule.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    pub fired_at: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolved_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub acked_by: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub acked_at: Option<String>,
+    #[serde(default)]
+    pub silenced: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conditions_round_trip_through_cli_syntax() {
+        for s in [
+            "offline",
+            "version_mismatch",
+            "unhealthy:3",
+            "unhealthy:web-server:5",
+            "disk.usage_percent>90",
+            "memory.usage_percent>=85.5",
+            "cpu.usage_percent<5",
+            "thermal.max_temp_c>80",
+            "power.undervoltage>=1",
+        ] {
+            let cond: AlertCondition = s.parse().unwrap();
+            assert_eq!(cond.to_string(), s);
+        }
+    }
+
+    #[test]
+    fn metric_operators_are_not_confused() {
+        let cond: AlertCondition = "disk.usage_percent>=90".parse().unwrap();
+        assert_eq!(
+            cond,
+            AlertCondition::Metric {
+                metric: MetricField::DiskUsagePercent,
+                op: Comparison::Ge,
+                threshold: 90.0,
+            }
+        );
+        assert!("disk.usage_percent=90".parse::<AlertCondition>().is_err());
+        assert!("unhealthy:0".parse::<AlertCondition>().is_err());
+        assert!("load>1".parse::<AlertCondition>().is_err());
+    }
+}
@@ -1,6 +1,7 @@
 use serde::{Deserialize, Serialize};
 
 use crate::device::DeviceSystemInfo;
+use crate::roles::Role;
 
 #[derive(Serialize, Deserialize)]
 pub struct DeviceAuthRequestBody {
@@ -32,3 +33,34 @@ pub struct DeviceAuthRequest {
     pub device_info: DeviceSystemInfo,
     pub created_at: String,
 }
+
+/// Create a personal or service API key. Without `org_id` or `device_id` the
+/// key acts within the caller's own scope.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CreateApiKeyBody {
+    pub name: String,
+    pub role: Role,
+    #[serde(default)]
+    pub org_id: Option<String>,
+    #[serde(default)]
+    pub device_id: Option<String>,
+    #[serde(default)]
+    pub ttl_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ApiKeyInfo {
+    pub key_id: String,
+    pub name: String,
+    pub scope: String,
+    pub role: Role,
+    pub created_at: Option<String>,
+    pub expires_at: Option<String>,
+}
+
+/// Returned once on creation; the secret can't be retrieved later.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CreatedApiKey {
+    pub key: ApiKeyInfo,
+    pub api_key: String,
+}
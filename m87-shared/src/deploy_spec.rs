@@ -1101,12 +1101,159 @@ pub struct ObserveSpec {
     pub liveness: Option<ObserveHooks>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub health: Option<ObserveHooks>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metrics: Option<MetricsHook>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LogSpec {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub follow: Option<CommandSpec>,
+    /// Follow the systemd journal instead of a command; ignored when
+    /// `follow` is set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub journal: Option<JournalSpec>,
+    /// Keep `follow` running at all times and store its output on the device.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub persist: Option<LogPersistSpec>,
+}
+
+impl LogSpec {
+    /// The command whose output are the unit's logs.
+    pub fn follow_command(&self) -> Option<CommandSpec> {
+        self.follow
+            .clone()
+            .or_else(|| self.journal.as_ref().map(JournalSpec::follow_command))
+    }
+}
+
+/// Journal entries to follow, as `journalctl` matches them: any of `units`,
+/// plus kernel messages with `kernel`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournalSpec {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub units: Vec<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub kernel: bool,
+    /// Only entries at this priority or more severe.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<JournalPriority>,
+}
+
+impl JournalSpec {
+    /// `journalctl` arguments selecting the entries.
+    pub fn filter_args(&self) -> Vec<String> {
+        let mut args: Vec<String> = self.units.iter().map(|u| format!("--unit={u}")).collect();
+        if self.kernel {
+            args.push("--dmesg".into());
+        }
+        if let Some(priority) = self.priority {
+            args.push(format!("--priority={}", priority as u8));
+        }
+        args
+    }
+
+    /// New entries only, one `identifier[pid]: message` line each, since the
+    /// log manager adds its own timestamps.
+    pub fn follow_command(&self) -> CommandSpec {
+        let mut argv: Vec<String> = [
+            "journalctl",
+            "--follow",
+            "--lines=0",
+            "--no-pager",
+            "--quiet",
+            "--output=short",
+        ]
+        .map(String::from)
+        .into();
+        argv.extend(self.filter_args());
+        CommandSpec::Argv(argv)
+    }
+}
+
+/// syslog priorities, most severe first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JournalPriority {
+    Emerg = 0,
+    Alert = 1,
+    Crit = 2,
+    Err = 3,
+    Warning = 4,
+    Notice = 5,
+    Info = 6,
+    Debug = 7,
+}
+
+impl JournalPriority {
+    pub const ALL: [JournalPriority; 8] = [
+        JournalPriority::Emerg,
+        JournalPriority::Alert,
+        JournalPriority::Crit,
+        JournalPriority::Err,
+        JournalPriority::Warning,
+        JournalPriority::Notice,
+        JournalPriority::Info,
+        JournalPriority::Debug,
+    ];
+
+    pub fn from_level(level: u8) -> Option<Self> {
+        Self::ALL.get(level as usize).copied()
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JournalPriority::Emerg => "emerg",
+            JournalPriority::Alert => "alert",
+            JournalPriority::Crit => "crit",
+            JournalPriority::Err => "err",
+            JournalPriority::Warning => "warning",
+            JournalPriority::Notice => "notice",
+            JournalPriority::Info => "info",
+            JournalPriority::Debug => "debug",
+        }
+    }
+}
+
+impl std::str::FromStr for JournalPriority {
+    type Err = String;
+
+    /// A name like `err`, or a level from 0 to 7.
+    fn from_str(s: &str) -> Result<Self, String> {
+        if let Ok(level) = s.parse::<u8>() {
+            return Self::from_level(level)
+                .ok_or_else(|| format!("invalid priority '{s}': levels are 0-7"));
+        }
+        let s = match s {
+            "error" => "err",
+            "warn" => "warning",
+            other => other,
+        };
+        Self::ALL
+            .into_iter()
+            .find(|p| p.as_str() == s)
+            .ok_or_else(|| format!("invalid priority '{s}'"))
+    }
+}
+
+fn default_log_persist_max_size() -> u64 {
+    16 * 1024 * 1024
+}
+
+/// Retention of persisted unit logs; whichever limit is reached first drops
+/// the oldest lines. The follow command is restarted whenever it exits, so
+/// it should not replay old output (e.g. `docker logs -f --tail 0`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogPersistSpec {
+    /// Disk budget, as bytes or a size like `50MB` or `512KiB`.
+    #[serde(default = "default_log_persist_max_size", with = "byte_size_human")]
+    pub max_size: u64,
+    #[serde(
+        default,
+        with = "option_duration_human",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub max_age: Option<Duration>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -1155,6 +1302,22 @@ impl Default for ObserveHooks {
     }
 }
 
+/// Application metrics of a unit: `observe` prints Prometheus text
+/// (`queue_depth 12`) or a JSON object of numbers (`{"fps": 29.7}`) every
+/// `every`. The values never fail the unit.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricsHook {
+    #[serde(with = "duration_human")]
+    pub every: Duration,
+    pub observe: CommandSpec,
+    #[serde(
+        default,
+        with = "option_duration_human",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub observe_timeout: Option<Duration>,
+}
+
 // ---------------------------------------------------------------------------
 // Workdir
 // ---------------------------------------------------------------------------
@@ -1315,7 +1478,7 @@ pub enum ObserveKind {
     Healthy,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Hash)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RunState {
     pub run_id: String,
     pub revision_id: String,
@@ -1324,6 +1487,10 @@ pub struct RunState {
     pub report_time: u64,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub log_tail: Option<String>,
+    /// Values of the unit's `metrics` hook; set on the report sent when they
+    /// change.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metrics: BTreeMap<String, f64>,
 }
 
 impl RunState {
@@ -1344,7 +1511,7 @@ pub struct JobRunReport {
     pub error: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Hash)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", content = "data")]
 pub enum DeployReportKind {
     DeploymentRevisionReport(DeploymentRevisionReport),
@@ -1489,12 +1656,75 @@ fn parse_duration(s: &str) -> Result<Duration, String> {
             .map(|v| Duration::from_secs(v * 3600))
             .map_err(|e| format!("invalid duration '{}': {}", s, e));
     }
+    if let Some(n) = s.strip_suffix('d') {
+        return n
+            .trim()
+            .parse::<u64>()
+            .map(|v| Duration::from_secs(v * 86400))
+            .map_err(|e| format!("invalid duration '{}': {}", s, e));
+    }
     // bare integer → seconds
     s.parse::<u64>()
         .map(Duration::from_secs)
         .map_err(|e| format!("invalid duration '{}': {}", s, e))
 }
 
+// ---------------------------------------------------------------------------
+// Byte size serde helper
+// ---------------------------------------------------------------------------
+
+/// Sizes as plain byte counts or strings like `512KiB`, `50MB`, `1G`.
+/// Decimal (`KB`, `MB`, `GB`) and binary (`KiB`, `MiB`, `GiB`, or just
+/// `K`, `M`, `G`) units are accepted. Serialized as a byte count.
+pub mod byte_size_human {
+    use serde::{Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &u64, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_u64(*bytes)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<u64, D::Error> {
+        struct V;
+        impl<'de> serde::de::Visitor<'de> for V {
+            type Value = u64;
+            fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(f, "a byte count or a size like '512KiB', '50MB'")
+            }
+            fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<u64, E> {
+                Ok(v)
+            }
+            fn visit_i64<E: serde::de::Error>(self, v: i64) -> Result<u64, E> {
+                u64::try_from(v).map_err(E::custom)
+            }
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<u64, E> {
+                super::parse_byte_size(v).map_err(E::custom)
+            }
+        }
+        d.deserialize_any(V)
+    }
+}
+
+pub fn parse_byte_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (n, unit) = s.split_at(split);
+    let n: u64 = n
+        .parse()
+        .map_err(|e| format!("invalid size '{}': {}", s, e))?;
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "kb" => 1000,
+        "mb" => 1000 * 1000,
+        "gb" => 1000 * 1000 * 1000,
+        "k" | "kib" => 1 << 10,
+        "m" | "mib" => 1 << 20,
+        "g" | "gib" => 1 << 30,
+        _ => return Err(format!("invalid size '{}': unknown unit", s)),
+    };
+    n.checked_mul(multiplier)
+        .ok_or_else(|| format!("invalid size '{}': too large", s))
+}
+
 // ---------------------------------------------------------------------------
 // Instruction hash (used in heartbeat to detect staleness)
 // ---------------------------------------------------------------------------
@@ -1934,6 +2164,76 @@ observers:
         assert_eq!(liveness.every, Duration::from_secs(45));
     }
 
+    #[test]
+    fn metrics_hook_parses() {
+        let yaml = r#"
+services:
+  - id: detector
+    steps: []
+    observe:
+      metrics:
+        every: 15s
+        observe: curl -sf localhost:9100/metrics
+        observe_timeout: 2s
+"#;
+        let rev = DeploymentRevision::from_yaml(yaml).unwrap();
+        let observe = rev.services[0].observe.as_ref().unwrap();
+        let hook = observe.metrics.as_ref().unwrap();
+        assert_eq!(hook.every, Duration::from_secs(15));
+        assert_eq!(hook.observe_timeout, Some(Duration::from_secs(2)));
+        assert!(observe.liveness.is_none());
+    }
+
+    #[test]
+    fn log_persist_spec_parses_sizes_and_ages() {
+        let yaml = r#"
+services:
+  - id: web
+    steps: []
+    observe:
+      logs:
+        follow: docker logs -f --tail 0 web
+        persist:
+          max_size: 50MB
+          max_age: 7d
+"#;
+        let rev = DeploymentRevision::from_yaml(yaml).unwrap();
+        let logs = rev.services[0].observe.as_ref().unwrap().logs.as_ref();
+        let persist = logs.unwrap().persist.clone().unwrap();
+        assert_eq!(persist.max_size, 50_000_000);
+        assert_eq!(persist.max_age, Some(Duration::from_secs(7 * 86400)));
+
+        assert_eq!(parse_byte_size("512KiB").unwrap(), 512 * 1024);
+        assert_eq!(parse_byte_size("4096").unwrap(), 4096);
+        assert!(parse_byte_size("5 parsecs").is_err());
+    }
+
+    #[test]
+    fn journal_log_spec_follows_journalctl() {
+        let yaml = r#"
+observers:
+  - id: network
+    observe:
+      logs:
+        journal:
+          units: [NetworkManager.service]
+          priority: warning
+        persist: {}
+"#;
+        let rev = DeploymentRevision::from_yaml(yaml).unwrap();
+        let logs = rev.observers[0].observe.as_ref().unwrap().logs.as_ref();
+        let Some(CommandSpec::Argv(argv)) = logs.unwrap().follow_command() else {
+            panic!("expected a journalctl argv");
+        };
+        assert_eq!(argv[0], "journalctl");
+        assert!(argv.contains(&"--unit=NetworkManager.service".to_string()));
+        assert!(argv.contains(&"--priority=4".to_string()));
+
+        assert_eq!("error".parse(), Ok(JournalPriority::Err));
+        assert_eq!("3".parse(), Ok(JournalPriority::Err));
+        assert!("8".parse::<JournalPriority>().is_err());
+    }
+
     #[test]
     fn lifecycle_default_is_running() {
         let spec: ServiceSpec = serde_yaml::from_str("id: x\nsteps: []").unwrap();
@@ -1,4 +1,4 @@
-use std::{fmt::Display, hash::Hash};
+use std::{collections::BTreeMap, fmt::Display, hash::Hash};
 
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
@@ -29,6 +29,11 @@ pub struct PublicDevice {
     #[serde(default)]
     pub config: DeviceClientConfig,
     pub system_info: DeviceSystemInfo,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    /// Set once the device's credentials were revoked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revoked_at: Option<String>,
     #[serde(default)]
     pub role: Role, // the role of the requestor
 }
@@ -87,6 +92,15 @@ pub struct UpdateDeviceBody {
     pub config: Option<DeviceClientConfig>,
 }
 
+/// Labels to add or overwrite, then keys to drop.
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+pub struct UpdateDeviceLabelsBody {
+    #[serde(default)]
+    pub set: BTreeMap<String, String>,
+    #[serde(default)]
+    pub remove: Vec<String>,
+}
+
 #[derive(Deserialize, Serialize, Default, Clone)]
 pub struct ObserveStatus {
     pub name: String,
@@ -124,6 +138,77 @@ pub struct AuditLog {
     pub action: String,
     pub details: String,
     pub device_id: Option<String>,
+    /// Set for entries describing traffic relayed to the device.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream: Option<StreamAuditEvent>,
+}
+
+/// A log line shipped by a device and kept by the server (`m87 logs --org`).
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct DeviceLogEntry {
+    pub device_id: String,
+    pub device_name: String,
+    /// Unit id, or `m87` for the runtime's own log.
+    pub source: String,
+    pub at_ms: u64,
+    pub line: String,
+}
+
+/// Filters for a shipped-log query. `grep` is a regex matched by the server.
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct DeviceLogQuery {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grep: Option<String>,
+    /// Device short id.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub since_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub until_ms: Option<u64>,
+    /// Newest lines to return.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u32>,
+}
+
+/// Relayed streams of one type and target on one client connection,
+/// written when the connection ends.
+#[derive(Deserialize, Serialize, Default, Clone, Debug)]
+pub struct StreamAuditEvent {
+    /// Lowercase stream type from the stream header: `terminal`, `exec`,
+    /// `forward`, `logs`, `serial`, ...
+    pub stream_type: String,
+    /// What the streams were opened for, e.g. `tcp:127.0.0.1:8080` or
+    /// `serial:ttyUSB0`.
+    #[serde(default)]
+    pub target: Option<String>,
+    pub streams: u32,
+    pub started_at: String,
+    pub ended_at: String,
+    pub duration_ms: u64,
+    /// Client -> device.
+    pub bytes_up: u64,
+    /// Device -> client.
+    pub bytes_down: u64,
+    /// `client_closed` or `device_closed`.
+    pub close_reason: String,
+}
+
+/// A recorded `Terminal`/`Exec` session; `cast` is asciicast v2.
+#[derive(Deserialize, Serialize, Default)]
+pub struct SessionRecording {
+    pub session_id: String,
+    pub kind: String,
+    pub command: Option<String>,
+    pub user_name: String,
+    pub user_email: String,
+    pub started_at: String,
+    pub ended_at: String,
+    #[serde(default)]
+    pub truncated: bool,
+    pub cast: String,
 }
 
 #[derive(Deserialize, Serialize, Default)]
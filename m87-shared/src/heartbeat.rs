@@ -22,6 +22,38 @@ pub struct HeartbeatRequest {
     /// `2`         = new format (`services` / `observers` / `job_defs`).
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub supported_revision_format: Option<u8>,
+    /// Id of the API key the device has persisted. Only sent by devices that
+    /// take part in key rotation; confirms a key handed out in `new_api_key`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key_id: Option<String>,
+    /// Shipped log lines, sent by devices with log shipping enabled. At most
+    /// one batch is unacknowledged at a time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_batch: Option<LogBatch>,
+}
+
+impl HeartbeatRequest {
+    /// Carries nothing but shipped logs. Devices send these between
+    /// heartbeats, so they don't count as one.
+    pub fn is_log_batch_only(&self) -> bool {
+        let Self {
+            last_instruction_hash: _,
+            system_info,
+            client_version,
+            metrics,
+            active_revision: _,
+            deploy_report,
+            supported_revision_format: _,
+            api_key_id,
+            log_batch,
+        } = self;
+        log_batch.is_some()
+            && system_info.is_none()
+            && client_version.is_none()
+            && metrics.is_none()
+            && deploy_report.is_none()
+            && api_key_id.is_none()
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -43,4 +75,55 @@ pub struct HeartbeatResponse {
     /// Job runs that are `Queued` and waiting to be executed on this device.
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub pending_job_runs: Vec<JobRun>,
+    /// Replacement API key while rotating. The old key stays valid until the
+    /// device confirms the new one via `api_key_id`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new_api_key: Option<String>,
+    /// Answer to the `log_batch` of the request.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_ack: Option<LogBatchAck>,
+}
+
+/// zstd-compressed NDJSON of [`ShippedLogLine`]s.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LogBatch {
+    /// Increases per batch; echoed in the ack.
+    pub seq: u64,
+    #[serde(with = "serde_bytes_base64")]
+    pub data: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ShippedLogLine {
+    pub at_ms: u64,
+    /// Unit id, or `m87` for the runtime's own log.
+    pub source: String,
+    pub line: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LogBatchAck {
+    pub seq: u64,
+    /// Set when the batch was not stored because the device is over its
+    /// daily quota; the device keeps it and retries after this many seconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_after_secs: Option<u64>,
+    /// Set when the batch alone exceeds the daily quota, so it would never
+    /// be stored; the device drops it instead of retrying.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub too_large: bool,
+}
+
+mod serde_bytes_base64 {
+    use base64::{Engine, engine::general_purpose::STANDARD};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<u8>, D::Error> {
+        let s = String::deserialize(d)?;
+        STANDARD.decode(s).map_err(serde::de::Error::custom)
+    }
 }
@@ -1,3 +1,4 @@
+pub mod alerts;
 pub mod auth;
 pub mod config;
 pub mod deploy_spec;
@@ -5,6 +6,7 @@ pub mod device;
 pub mod heartbeat;
 pub mod metrics;
 pub mod org;
+pub mod p2p;
 pub mod pagination;
 pub mod roles;
 pub mod users;
@@ -1,3 +1,5 @@
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -12,9 +14,32 @@ pub struct SystemMetrics {
     pub disk: DiskMetrics,
     pub network: NetworkMetrics,
     pub gpu: Vec<GpuMetrics>,
+    /// Temperatures and fans, on devices that expose them in sysfs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thermal: Option<ThermalMetrics>,
+    /// CPU frequency and throttle / undervoltage state.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub power: Option<PowerMetrics>,
+    /// Docker containers, when the runtime can reach the Docker socket.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub containers: Option<ContainerMetrics>,
+    /// Values of units with a `metrics` observe hook.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub units: Vec<UnitMetrics>,
     pub timestamp: u64,
 }
 
+impl SystemMetrics {
+    /// What goes into a heartbeat: container counts without the
+    /// per-container list.
+    pub fn for_heartbeat(mut self) -> Self {
+        if let Some(containers) = &mut self.containers {
+            containers.containers.clear();
+        }
+        self
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct CpuMetrics {
     /// existing fields
@@ -70,3 +95,169 @@ pub struct GpuMetrics {
     pub memory_used_mb: u64,
     pub memory_total_mb: u64,
 }
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ThermalMetrics {
+    pub zones: Vec<ThermalZoneMetrics>,
+    #[serde(default)]
+    pub fans: Vec<FanMetrics>,
+}
+
+impl ThermalMetrics {
+    /// The hottest zone.
+    pub fn max_temp_c(&self) -> Option<f32> {
+        self.zones.iter().map(|z| z.temp_c).reduce(f32::max)
+    }
+
+    /// The slowest fan, which is the one that's about to fail.
+    pub fn min_fan_rpm(&self) -> Option<u32> {
+        self.fans.iter().map(|f| f.rpm).min()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ThermalZoneMetrics {
+    /// Zone type, e.g. `cpu-thermal` or `GPU-therm`.
+    pub name: String,
+    pub temp_c: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FanMetrics {
+    /// hwmon chip and fan, e.g. `pwmfan/fan1`.
+    pub name: String,
+    pub rpm: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PowerMetrics {
+    /// Average current frequency over all cores.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_freq_mhz: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_max_freq_mhz: Option<u32>,
+    pub throttle: ThrottleFlags,
+    /// Sticky flags since boot, where the firmware keeps them (Raspberry Pi).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub throttle_since_boot: Option<ThrottleFlags>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ThrottleFlags {
+    #[serde(default)]
+    pub undervoltage: bool,
+    #[serde(default)]
+    pub freq_capped: bool,
+    #[serde(default)]
+    pub throttled: bool,
+    #[serde(default)]
+    pub soft_temp_limit: bool,
+}
+
+impl ThrottleFlags {
+    pub fn any(&self) -> bool {
+        self.undervoltage || self.freq_capped || self.throttled || self.soft_temp_limit
+    }
+
+    /// Names of the set flags, e.g. `["undervoltage", "throttled"]`.
+    pub fn names(&self) -> Vec<&'static str> {
+        [
+            (self.undervoltage, "undervoltage"),
+            (self.freq_capped, "freq_capped"),
+            (self.throttled, "throttled"),
+            (self.soft_temp_limit, "soft_temp_limit"),
+        ]
+        .into_iter()
+        .filter_map(|(set, name)| set.then_some(name))
+        .collect()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ContainerMetrics {
+    pub total: u32,
+    pub running: u32,
+    /// Containers whose health check fails.
+    pub unhealthy: u32,
+    /// Restarts of all containers, as counted by Docker.
+    pub restarts: u64,
+    /// Per-container details; left out of heartbeats.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub containers: Vec<ContainerInfo>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ContainerInfo {
+    /// Short id, like `docker ps` shows it.
+    pub id: String,
+    pub name: String,
+    pub image: String,
+    /// Registry digest of the image, e.g. `sha256:4f3c...`; `None` for
+    /// images that were built locally.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_digest: Option<String>,
+    /// `running`, `exited`, `restarting`, ...
+    pub state: String,
+    /// `healthy`, `unhealthy` or `starting`, for containers with a health check.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health: Option<String>,
+    pub restart_count: u64,
+    /// Of one core, like `docker stats`.
+    pub cpu_percent: f32,
+    pub memory_bytes: u64,
+    pub memory_limit_bytes: u64,
+    /// Id of the deployed service whose workdir started the container.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub service: Option<String>,
+}
+
+/// The latest values of a unit's `metrics` hook, e.g. `queue_depth` or
+/// `inference_fps`. Also one sample of the server's history.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct UnitMetrics {
+    pub unit: String,
+    pub values: BTreeMap<String, f64>,
+    /// Unix millis of the hook run that produced the values.
+    pub collected_at: u64,
+}
+
+/// Query of `GET /device/{id}/units/{unit}/metrics`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct UnitMetricsQuery {
+    /// Unix millis; only samples collected after this.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub since: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u32>,
+}
+
+/// One snapshot of a `Processes` stream (`m87 <device> ps` / `top`).
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ProcessList {
+    pub timestamp: u64,
+    pub total_memory_bytes: u64,
+    pub processes: Vec<ProcessInfo>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ProcessInfo {
+    pub pid: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_pid: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    pub name: String,
+    /// Command line; empty for kernel threads.
+    pub cmd: String,
+    /// Of one core, like `top`: a busy multi-threaded process can exceed 100.
+    pub cpu_percent: f32,
+    pub rss_bytes: u64,
+    pub status: String,
+    /// Unix seconds.
+    pub started_at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cgroup: Option<String>,
+    /// Short id of the container the process runs in.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub container: Option<String>,
+}
@@ -43,3 +43,161 @@ pub struct InviteMemberBody {
 pub struct AddDeviceBody {
     pub device_id: String,
 }
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct OrgSettings {
+    /// Record shell and `exec` sessions on the org's devices for the audit log.
+    #[serde(default)]
+    pub record_sessions: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct UpdateOrgSettingsBody {
+    #[serde(default)]
+    pub record_sessions: Option<bool>,
+}
+
+/// Events an org webhook can subscribe to.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    /// A device opened its control tunnel.
+    DeviceOnline,
+    /// A device's control tunnel closed.
+    DeviceOffline,
+    /// An observed unit reported unhealthy after being healthy.
+    ObserveUnhealthy,
+    /// A deployment step failed on a device.
+    StepFailed,
+    /// A job run failed on a device.
+    JobFailed,
+    /// A new device asked to be registered with the org.
+    DeviceAuthRequest,
+    /// An alert rule started firing for a device.
+    AlertFiring,
+    /// A firing alert's condition cleared.
+    AlertResolved,
+}
+
+impl WebhookEvent {
+    pub const ALL: [WebhookEvent; 8] = [
+        WebhookEvent::DeviceOnline,
+        WebhookEvent::DeviceOffline,
+        WebhookEvent::ObserveUnhealthy,
+        WebhookEvent::StepFailed,
+        WebhookEvent::JobFailed,
+        WebhookEvent::DeviceAuthRequest,
+        WebhookEvent::AlertFiring,
+        WebhookEvent::AlertResolved,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEvent::DeviceOnline => "device_online",
+            WebhookEvent::DeviceOffline => "device_offline",
+            WebhookEvent::ObserveUnhealthy => "observe_unhealthy",
+            WebhookEvent::StepFailed => "step_failed",
+            WebhookEvent::JobFailed => "job_failed",
+            WebhookEvent::DeviceAuthRequest => "device_auth_request",
+            WebhookEvent::AlertFiring => "alert_firing",
+            WebhookEvent::AlertResolved => "alert_resolved",
+        }
+    }
+}
+
+impl std::fmt::Display for WebhookEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for WebhookEvent {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        WebhookEvent::ALL
+            .into_iter()
+            .find(|e| e.as_str() == s)
+            .ok_or_else(|| {
+                let valid: Vec<&str> = WebhookEvent::ALL.iter().map(|e| e.as_str()).collect();
+                format!(
+                    "unknown event '{s}' (expected one of: {})",
+                    valid.join(", ")
+                )
+            })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Webhook {
+    pub id: String,
+    pub org_id: String,
+    pub url: String,
+    /// Empty means all events.
+    pub events: Vec<WebhookEvent>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CreateWebhookBody {
+    pub url: String,
+    #[serde(default)]
+    pub events: Vec<WebhookEvent>,
+}
+
+/// Returned once on creation; the secret is not retrievable afterwards.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CreatedWebhook {
+    pub webhook: Webhook,
+    /// Key for the `X-M87-Signature` HMAC-SHA256 over `<timestamp>.<body>`.
+    pub secret: String,
+}
+
+/// Body POSTed to a webhook URL.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WebhookPayload {
+    /// Delivery id, stable across retries.
+    pub id: String,
+    pub event: WebhookEvent,
+    pub org_id: String,
+    pub created_at: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device_name: Option<String>,
+    #[serde(default)]
+    pub data: serde_json::Value,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookDeliveryStatus {
+    Pending,
+    Delivered,
+    Failed,
+}
+
+impl std::fmt::Display for WebhookDeliveryStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WebhookDeliveryStatus::Pending => write!(f, "pending"),
+            WebhookDeliveryStatus::Delivered => write!(f, "delivered"),
+            WebhookDeliveryStatus::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WebhookDelivery {
+    pub id: String,
+    pub webhook_id: String,
+    pub event: WebhookEvent,
+    pub status: WebhookDeliveryStatus,
+    pub attempts: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_status_code: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
@@ -0,0 +1,16 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// One finished stream of a direct client↔device session. The device sends
+/// these on the relayed `P2p` stream the session was negotiated on, so the
+/// server can audit direct streams like relayed ones.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DirectStreamUsage {
+    /// Stream header as the client sent it, without the token.
+    pub header: Value,
+    pub started_at_ms: u64,
+    /// Client -> device bytes.
+    pub bytes_up: u64,
+    /// Device -> client bytes.
+    pub bytes_down: u64,
+}
@@ -7,3 +7,55 @@ pub struct User {
     pub email: String,
     pub role: Role,
 }
+
+/// Account state as seen by server admins.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum UserStatus {
+    /// Signed in but waiting for approval (`users_need_approval`).
+    Pending,
+    Active,
+    Disabled,
+}
+
+impl std::fmt::Display for UserStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UserStatus::Pending => write!(f, "pending"),
+            UserStatus::Active => write!(f, "active"),
+            UserStatus::Disabled => write!(f, "disabled"),
+        }
+    }
+}
+
+impl std::str::FromStr for UserStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pending" => Ok(UserStatus::Pending),
+            "active" => Ok(UserStatus::Active),
+            "disabled" => Ok(UserStatus::Disabled),
+            _ => Err(format!(
+                "unknown status '{s}' (expected one of: pending, active, disabled)"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AdminUser {
+    pub id: String,
+    pub email: String,
+    pub name: Option<String>,
+    pub status: UserStatus,
+    pub created_at: Option<String>,
+    pub last_login: Option<String>,
+    pub total_logins: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ListUsersQuery {
+    #[serde(default)]
+    pub status: Option<UserStatus>,
+}